@@ -0,0 +1,50 @@
+//! Embeds what the engine was built from, for `version`: the git revision, the build profile, the
+//! enabled features and the instruction set extensions. Also turns on the reference checks of the
+//! evaluation in debug builds.
+
+use std::env;
+use std::process::Command;
+
+fn main()
+{
+    // Not every build is of a git checkout, for example builds of a source package
+    let describe = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--tags"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(describe) = describe {
+        println!("cargo:rustc-env=ENGINE_GIT_DESCRIBE={}", describe.trim());
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    let profile = env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string());
+    println!("cargo:rustc-env=ENGINE_BUILD_PROFILE={profile}");
+
+    // The slow checks against `evaluation::reference` are for development builds only. The release
+    // profile keeps debug assertions on, so they can't tell the two apart.
+    println!("cargo:rustc-check-cfg=cfg(reference_checks)");
+    if profile == "debug" {
+        println!("cargo:rustc-cfg=reference_checks");
+    }
+
+    let mut features = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_ascii_lowercase().replace('_', "-")))
+        // Every engine binary has std-threads, which is all that `default` stands for
+        .filter(|feature| feature != "default" && feature != "std-threads")
+        .collect::<Vec<_>>();
+    features.sort();
+    let features = if features.is_empty() { "none".to_string() } else { features.join(" ") };
+    println!("cargo:rustc-env=ENGINE_FEATURES={features}");
+
+    // The instruction set extensions that matter for bitboards, which builds for a native CPU enable
+    let target_features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let cpu_features = ["popcnt", "bmi2", "avx2"]
+        .into_iter()
+        .filter(|feature| target_features.split(',').any(|f| f == *feature))
+        .collect::<Vec<_>>();
+    let cpu_features = if cpu_features.is_empty() { "none".to_string() } else { cpu_features.join(" ") };
+    println!("cargo:rustc-env=ENGINE_CPU_FEATURES={cpu_features}");
+}
@@ -0,0 +1,251 @@
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use chess::Board;
+use crate::evaluation::Evaluator;
+use crate::search::{Depth, Searcher};
+use crate::stopconditions::StopConditions;
+
+pub mod dispatch;
+pub mod gate;
+pub mod hanging;
+pub mod inserts;
+pub mod prefetch;
+
+pub const DEFAULT_BENCH_DEPTH: Depth = 5;
+
+/// Node count changes larger than this are highlighted when comparing benches
+pub const COMPARE_HIGHLIGHT_PERCENT: u64 = 5;
+
+/// Positions of the bench, from the opening to the endgame. New positions go at the end, so that the
+/// indices of recorded benches and of the gate's `TIME_TO_DEPTH_POSITIONS` stay the same.
+pub const BENCH_POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 12",
+    "4kb1r/p2n1ppp/4q3/4p1B1/4P3/1Q6/PPP2PPP/2KR4 w k - 1 1",
+    "8/5k2/8/4P3/8/8/5K2/8 w - - 0 1",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/8 b - - 0 1",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+    "8/8/8/5N2/8/p7/8/2NK3k w - - 0 1",
+    "8/3k4/8/8/8/4B3/4KB2/2B5 w - - 0 1",
+    "8/8/1P6/5pr1/8/4R3/7k/2K5 w - - 0 1",
+    "8/2p4P/8/kr6/6R1/8/8/1K6 w - - 0 1",
+    "8/8/3P3k/8/1p6/8/1P6/1K3n2 b - - 0 1",
+    "8/R7/2q5/8/6k1/8/1P5p/K6R w - - 0 124",
+    "6k1/3b3r/1p1p4/p1n2p2/1PPNpP1q/P3Q1p1/1R1RB1P1/5K2 b - - 0 1",
+    "r2r1n2/pp2bk2/2p1p2p/3q4/3PN1QP/2P3R1/P4PP1/5RK1 w - - 0 1",
+    "5rk1/q6p/2p3bR/1pPp1rP1/1P1Pp3/P3B1Q1/1K3P2/R7 w - - 93 90",
+    "4rrk1/1p1nq3/p7/2p1P1pp/3P2bp/3Q1Bn1/PPPB4/1K2R1NR w - - 40 21",
+    "r3k2r/3nnpbp/q2pp1p1/p7/Pp1PPPP1/4BNN1/1P5P/R2Q1RK1 w kq - 0 16",
+    "3Qb1k1/1r2ppb1/pN1n2q1/Pp1Pp1Pr/4P2p/4BP2/4B1R1/1R5K b - - 11 40",
+    "4k3/3q1r2/1N2r1b1/3ppN2/2nPP3/1B1R2n1/2R1Q3/3K4 w - - 5 1",
+    "1r6/1P4bk/3qr1p1/N6p/3pp2P/6R1/3Q1PP1/1R4K1 w - - 1 42",
+    "3r1k2/4npp1/1ppr3p/p6P/P2PPPP1/1NR5/5K2/2R5 w - - 0 1",
+    "8/1pk5/p1p5/P1P1p2p/4P2P/3K4/8/8 w - - 0 1",
+    "rnbqkb1r/pp1p1ppp/4pn2/2p5/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 0 4",
+];
+
+/// Nodes and time of one iteration of one bench position
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BenchRecord
+{
+    pub position: usize,
+    pub depth: Depth,
+    pub nodes: u64,
+    pub time: u64,
+}
+
+/// A difference in node count between two benches, or an iteration only present in one of them
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NodeDifference
+{
+    pub position: usize,
+    pub depth: Depth,
+    pub old_nodes: Option<u64>,
+    pub new_nodes: Option<u64>,
+}
+
+impl NodeDifference
+{
+    /// Whether the difference is large enough to be highlighted
+    pub fn is_significant(&self) -> bool
+    {
+        match (self.old_nodes, self.new_nodes)
+        {
+            (Some(old), Some(new)) => 100 * old.abs_diff(new) > COMPARE_HIGHLIGHT_PERCENT * old,
+            _ => true,
+        }
+    }
+}
+
+/// Search every bench position to a fixed depth, with a fresh hash table for each
+///
+/// No time limits are involved, so the node counts are deterministic.
+pub fn run_bench(depth: Depth) -> Vec<BenchRecord>
+{
+    run_bench_with(depth, |stop_conditions| Searcher::new(stop_conditions))
+}
+
+/// Run the bench like `run_bench`, with searchers from `new_searcher`
+pub fn run_bench_with<E: Evaluator + Clone + Send>(depth: Depth, new_searcher: impl Fn(&StopConditions) -> Searcher<'_, E>) -> Vec<BenchRecord>
+{
+    let mut records = Vec::new();
+
+    for (position_idx, fen) in BENCH_POSITIONS.iter().enumerate()
+    {
+        let position = Board::from_str(fen).expect("invalid bench position");
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(depth, Ordering::Relaxed);
+        let mut searcher = new_searcher(&stop_conditions);
+
+        let result = searcher.search(position, 0);
+        for iteration in result.iterations
+        {
+            records.push(BenchRecord {
+                position: position_idx,
+                depth: iteration.depth,
+                nodes: iteration.nodes,
+                time: iteration.time,
+            });
+        }
+    }
+
+    records
+}
+
+/// The last iteration of every position, which has the nodes and time of the whole search of it
+pub fn last_iterations(records: &[BenchRecord]) -> impl Iterator<Item = &BenchRecord>
+{
+    records.iter()
+        .enumerate()
+        .filter(|(i, r)| records.get(i + 1).map_or(true, |next| next.position != r.position))
+        .map(|(_, r)| r)
+}
+
+/// Total number of nodes of the bench, counting the last iteration of every position
+///
+/// This is the signature of the bench: changes that leave the search functionally the same keep it.
+pub fn total_nodes(records: &[BenchRecord]) -> u64
+{
+    last_iterations(records).map(|r| r.nodes).sum()
+}
+
+pub fn to_csv(records: &[BenchRecord]) -> String
+{
+    let mut result = String::from("position,depth,nodes,time\n");
+    for r in records {
+        result.push_str(&format!("{},{},{},{}\n", r.position, r.depth, r.nodes, r.time));
+    }
+    result
+}
+
+pub fn from_csv(text: &str) -> Result<Vec<BenchRecord>, String>
+{
+    let mut records = Vec::new();
+
+    // Skip the header
+    for (line_idx, line) in text.lines().enumerate().skip(1)
+    {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = line.split(',').map(|f| f.trim()).collect::<Vec<_>>();
+        let parse_error = || format!("Invalid bench record on line {}: \"{line}\"", line_idx + 1);
+        if fields.len() != 4 {
+            return Err(parse_error());
+        }
+
+        records.push(BenchRecord {
+            position: usize::from_str(fields[0]).map_err(|_| parse_error())?,
+            depth: Depth::from_str(fields[1]).map_err(|_| parse_error())?,
+            nodes: u64::from_str(fields[2]).map_err(|_| parse_error())?,
+            time: u64::from_str(fields[3]).map_err(|_| parse_error())?,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Find all iterations whose node counts differ between two benches
+///
+/// Times are not compared, since they are never reproducible.
+pub fn compare(old: &[BenchRecord], new: &[BenchRecord]) -> Vec<NodeDifference>
+{
+    let find = |records: &[BenchRecord], position, depth| {
+        records.iter()
+            .find(|r| r.position == position && r.depth == depth)
+            .map(|r| r.nodes)
+    };
+
+    let mut keys = old.iter().chain(new)
+        .map(|r| (r.position, r.depth))
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+
+    keys.into_iter()
+        .map(|(position, depth)| NodeDifference {
+            position,
+            depth,
+            old_nodes: find(old, position, depth),
+            new_nodes: find(new, position, depth),
+        })
+        .filter(|d| d.old_nodes != d.new_nodes)
+        .collect()
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_bench_compare_with_itself()
+    {
+        let records = run_bench(2);
+        assert_eq!(records.len(), 2 * BENCH_POSITIONS.len());
+
+        let recorded = from_csv(&to_csv(&records)).unwrap();
+        assert_eq!(recorded, records);
+
+        let rerun = run_bench(2);
+        assert!(compare(&recorded, &rerun).is_empty());
+        assert_eq!(total_nodes(&recorded), total_nodes(&rerun));
+    }
+
+    #[test]
+    fn test_compare_differences()
+    {
+        let record = |position, depth, nodes| BenchRecord { position, depth, nodes, time: 0 };
+        let old = [record(0, 1, 20), record(0, 2, 400), record(1, 1, 30)];
+        let new = [record(0, 1, 20), record(0, 2, 410), record(1, 1, 30), record(1, 2, 900)];
+
+        let differences = compare(&old, &new);
+        assert_eq!(differences, [
+            NodeDifference { position: 0, depth: 2, old_nodes: Some(400), new_nodes: Some(410) },
+            NodeDifference { position: 1, depth: 2, old_nodes: None, new_nodes: Some(900) },
+        ]);
+        assert!(!differences[0].is_significant());
+        assert!(differences[1].is_significant());
+        assert_eq!(total_nodes(&new), 410 + 900);
+        assert_eq!(last_iterations(&new).collect::<Vec<_>>(), [&new[1], &new[3]]);
+    }
+}
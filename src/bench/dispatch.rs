@@ -0,0 +1,116 @@
+//! Measures what calling the evaluation through the `Evaluator` trait costs the search, against the
+//! classical evaluation called directly, as searches without a plugged in evaluator do
+//!
+//! The bench is searched with the classical evaluation called three ways: directly, through an
+//! evaluator whose type the searcher is compiled for, and through `dyn`, as an evaluator chosen at run
+//! time is. All three evaluate the same, so they search the same nodes, and only the times differ.
+
+use std::sync::Arc;
+
+use crate::evaluation::{Classical, SharedEvaluator};
+use crate::search::{Depth, Searcher};
+use super::{last_iterations, run_bench_with, total_nodes, BenchRecord};
+
+/// Each way is measured this many times, taking turns, and the fastest time counts, since noise only
+/// ever adds time
+const ROUNDS: usize = 3;
+
+/// How the search calls the evaluation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dispatch
+{
+    /// `evaluation::evaluate`, without a plugged in evaluator
+    Direct,
+    /// `Searcher<Classical>`, where the compiler knows which `evaluate` is called
+    Static,
+    /// `Searcher<SharedEvaluator>`, calling through the vtable
+    Dynamic,
+}
+
+impl Dispatch
+{
+    pub const ALL: [Dispatch; 3] = [Dispatch::Direct, Dispatch::Static, Dispatch::Dynamic];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Dispatch::Direct => "direct",
+            Dispatch::Static => "static",
+            Dispatch::Dynamic => "dyn",
+        }
+    }
+
+    fn run_bench(self, depth: Depth) -> Vec<BenchRecord>
+    {
+        match self
+        {
+            Dispatch::Direct => run_bench_with(depth, |stop_conditions| Searcher::new(stop_conditions)),
+            Dispatch::Static => run_bench_with(depth, |stop_conditions| Searcher::with_evaluator(stop_conditions, Classical::default())),
+            Dispatch::Dynamic => {
+                let evaluator: SharedEvaluator = Arc::new(Classical::default());
+                run_bench_with(depth, |stop_conditions| Searcher::with_evaluator(stop_conditions, Arc::clone(&evaluator)))
+            }
+        }
+    }
+}
+
+/// The nodes and the fastest time of the bench with one way of calling the evaluation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DispatchTiming
+{
+    pub dispatch: Dispatch,
+    pub nodes: u64,
+    /// In milliseconds, searching only, without setting up the hash tables
+    pub time: u64,
+}
+
+impl DispatchTiming
+{
+    pub fn nps(&self) -> u64
+    {
+        if self.time != 0 { 1000 * self.nodes / self.time } else { 0 }
+    }
+
+    /// How many percent more time than `baseline` this took, negative if less
+    pub fn extra_time_percent(&self, baseline: &DispatchTiming) -> f64
+    {
+        if baseline.time == 0 {
+            return 0.0;
+        }
+        100.0 * (self.time as f64 - baseline.time as f64) / baseline.time as f64
+    }
+}
+
+/// Run the bench to `depth` with every way of calling the evaluation, in the order of `Dispatch::ALL`
+pub fn measure(depth: Depth) -> Vec<DispatchTiming>
+{
+    let mut timings: Vec<Option<DispatchTiming>> = vec![None; Dispatch::ALL.len()];
+    for _ in 0..ROUNDS
+    {
+        for (timing, dispatch) in timings.iter_mut().zip(Dispatch::ALL)
+        {
+            let records = dispatch.run_bench(depth);
+            let time = last_iterations(&records).map(|r| r.time).sum();
+            let nodes = total_nodes(&records);
+            debug_assert!(timing.map_or(true, |timing| timing.nodes == nodes));
+            if timing.map_or(true, |timing| time < timing.time) {
+                *timing = Some(DispatchTiming { dispatch, nodes, time });
+            }
+        }
+    }
+    timings.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_same_nodes_however_called()
+    {
+        let nodes = Dispatch::ALL.map(|dispatch| total_nodes(&dispatch.run_bench(3)));
+        assert_eq!(nodes, [nodes[0]; 3]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Regression gate for search changes: the bench, a set of tactics searched to a fixed number of
+//! nodes, and the time to reach a depth in a few long positions, compared against a baseline that an
+//! earlier build recorded
+//!
+//! The node count and the solved tactics are deterministic, since those searches have no time
+//! limits. The times are not, so they get a generous tolerance.
+
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use chess::Board;
+use crate::epd;
+use crate::search::{Depth, Searcher};
+use crate::stopconditions::StopConditions;
+use super::{run_bench, total_nodes, BENCH_POSITIONS, DEFAULT_BENCH_DEPTH};
+
+/// Tactics from Win at Chess, and a mate in two, as EPD with the best move in the `bm` opcode
+pub const TACTICS: &[&str] = &[
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6;",
+    "8/7p/5k2/5p2/p1p2P2/Pr1pPK2/1P1R3P/8 b - - bm Rxb2;",
+    "5rk1/1ppb3p/p1pb4/6q1/3P1p1r/2P1R2P/PP1BQ1P1/5RKN w - - bm Rg3;",
+    "r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - - bm Qxh7+;",
+    "5k2/6pp/p1qN4/1p1p4/3P4/2PKP2Q/PP3r2/3R4 b - - bm Qc4+;",
+    "rnbqkb1r/pppp1ppp/8/4P3/6n1/7P/PPPNPPP1/R1BQKBNR b KQkq - bm Ne3;",
+    "r4q1k/p2bR1rp/2p2Q1N/5p2/5p2/2P5/PP3PPP/R5K1 w - - bm Rf7;",
+    "3q1rk1/p4pp1/2pb3p/3p4/6Pr/1PNQ4/P1PB1PP1/4RRK1 b - - bm Bh2+;",
+    "2br2k1/2q3rn/p2NppQ1/2p1P3/Pp5R/4P3/1P3PPP/3R2K1 w - - bm Rxh7;",
+    "4kb1r/p2n1ppp/4q3/4p1B1/4P3/1Q6/PPP2PPP/2KR4 w k - bm Qb8+;",
+];
+
+/// Bench positions that take long enough to search deep for their times to mean something
+const TIME_TO_DEPTH_POSITIONS: [usize; 3] = [1, 3, 5];
+
+const DEFAULT_TACTICS_NODES: u64 = 200_000;
+const DEFAULT_TIME_TO_DEPTH: Depth = 7;
+
+/// The bench may take this many percent more nodes before it counts as a regression
+const NODES_TOLERANCE_PERCENT: u64 = 10;
+/// This many fewer solved tactics count as a regression
+const TACTICS_TOLERANCE: usize = 1;
+/// A time to depth may grow by this many percent, plus `TIME_SLACK` milliseconds for the noise of
+/// short searches, before it counts as a regression
+const TIME_TOLERANCE_PERCENT: u64 = 25;
+const TIME_SLACK: u64 = 50;
+
+/// How deep and how long the gate searches. A baseline records the settings it was measured with,
+/// and a gate against it measures with the same settings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GateSettings
+{
+    pub bench_depth: Depth,
+    pub tactics_nodes: u64,
+    pub time_to_depth: Depth,
+}
+
+impl Default for GateSettings
+{
+    fn default() -> Self
+    {
+        GateSettings {
+            bench_depth: DEFAULT_BENCH_DEPTH,
+            tactics_nodes: DEFAULT_TACTICS_NODES,
+            time_to_depth: DEFAULT_TIME_TO_DEPTH,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GateMetrics
+{
+    pub settings: GateSettings,
+    /// Total nodes of the bench, see `total_nodes`
+    pub bench_nodes: u64,
+    /// Number of `TACTICS` whose best move was found
+    pub tactics_solved: usize,
+    /// Milliseconds to complete the iteration of `settings.time_to_depth`, for each of
+    /// `TIME_TO_DEPTH_POSITIONS`
+    pub time_to_depth: Vec<u64>,
+}
+
+/// Run all the searches of the gate
+pub fn measure(settings: GateSettings) -> GateMetrics
+{
+    let bench_nodes = total_nodes(&run_bench(settings.bench_depth));
+    let tactics_solved = TACTICS.iter()
+        .filter(|epd| solves_tactic(epd, settings.tactics_nodes))
+        .count();
+    let time_to_depth = TIME_TO_DEPTH_POSITIONS.iter()
+        .map(|&position_idx| time_to_depth(BENCH_POSITIONS[position_idx], settings.time_to_depth))
+        .collect();
+
+    GateMetrics {
+        settings,
+        bench_nodes,
+        tactics_solved,
+        time_to_depth,
+    }
+}
+
+/// Describe every metric that is worse than in the baseline by more than its tolerance
+pub fn regressions(baseline: &GateMetrics, current: &GateMetrics) -> Vec<String>
+{
+    debug_assert_eq!(baseline.settings, current.settings);
+    let mut result = Vec::new();
+
+    if 100 * current.bench_nodes > (100 + NODES_TOLERANCE_PERCENT) * baseline.bench_nodes {
+        result.push(format!("bench nodes {} -> {}", baseline.bench_nodes, current.bench_nodes));
+    }
+
+    if current.tactics_solved + TACTICS_TOLERANCE < baseline.tactics_solved {
+        result.push(format!("tactics solved {} -> {} of {}", baseline.tactics_solved, current.tactics_solved, TACTICS.len()));
+    }
+
+    for (i, (&old, &new)) in baseline.time_to_depth.iter().zip(&current.time_to_depth).enumerate()
+    {
+        if new > old * (100 + TIME_TOLERANCE_PERCENT) / 100 + TIME_SLACK {
+            let position = TIME_TO_DEPTH_POSITIONS[i];
+            result.push(format!("time to depth {} of position {position}: {old} -> {new} ms", baseline.settings.time_to_depth));
+        }
+    }
+
+    result
+}
+
+/// Whether a search of `nodes` nodes plays the best move of a line of `TACTICS`
+fn solves_tactic(line: &str, nodes: u64) -> bool
+{
+    let epd = epd::parse(line).expect("invalid tactic");
+
+    let stop_conditions = StopConditions::new();
+    stop_conditions.depth.store(Depth::MAX, Ordering::Relaxed);
+    stop_conditions.nodes.store(nodes, Ordering::Relaxed);
+    let mut searcher = Searcher::new(&stop_conditions);
+    let result = searcher.search(epd.position, 0);
+
+    result.best_move.is_some_and(|m| epd.best_moves.contains(&m))
+}
+
+/// Milliseconds for a search with a fresh hash table to complete the iteration of `depth`
+fn time_to_depth(fen: &str, depth: Depth) -> u64
+{
+    let position = Board::from_str(fen).expect("invalid bench position");
+    let stop_conditions = StopConditions::new();
+    stop_conditions.depth.store(depth, Ordering::Relaxed);
+    let mut searcher = Searcher::new(&stop_conditions);
+
+    let result = searcher.search(position, 0);
+    result.iterations.last().map_or(0, |iteration| iteration.time)
+}
+
+/// Format the metrics as a JSON object
+pub fn to_json(metrics: &GateMetrics) -> String
+{
+    let times = metrics.time_to_depth.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+    format!(
+        "{{\n  \"bench_depth\": {},\n  \"tactics_nodes\": {},\n  \"time_to_depth_depth\": {},\n  \
+            \"bench_nodes\": {},\n  \"tactics_solved\": {},\n  \"time_to_depth\": [{}]\n}}\n",
+        metrics.settings.bench_depth,
+        metrics.settings.tactics_nodes,
+        metrics.settings.time_to_depth,
+        metrics.bench_nodes,
+        metrics.tactics_solved,
+        times.join(", "),
+    )
+}
+
+/// Read metrics written by `to_json`
+///
+/// Only a flat object of numbers and arrays of numbers is understood, which is all that `to_json`
+/// writes. The keys may come in any order, and unknown keys are ignored.
+pub fn from_json(text: &str) -> Result<GateMetrics, String>
+{
+    let body = text.trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or("Expected a JSON object")?;
+
+    let mut values = Vec::new();
+    let mut rest = body.trim();
+    while !rest.is_empty()
+    {
+        let (key, after_key) = rest.strip_prefix('"')
+            .and_then(|r| r.split_once('"'))
+            .ok_or_else(|| format!("Expected a key at \"{rest}\""))?;
+        let after_colon = after_key.trim_start()
+            .strip_prefix(':')
+            .ok_or_else(|| format!("Expected ':' after \"{key}\""))?
+            .trim_start();
+
+        let value_end = if after_colon.starts_with('[') {
+            after_colon.find(']').map(|i| i + 1).ok_or_else(|| format!("Unterminated array for \"{key}\""))?
+        } else {
+            after_colon.find(',').unwrap_or(after_colon.len())
+        };
+        values.push((key, after_colon[..value_end].trim()));
+
+        rest = after_colon[value_end..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    let value = |key: &str| {
+        values.iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| format!("Missing \"{key}\""))
+    };
+    fn number<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+    {
+        T::from_str(value).map_err(|_| format!("Invalid \"{key}\": {value}"))
+    }
+
+    let times = value("time_to_depth")?;
+    let time_to_depth = times.strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or_else(|| format!("Invalid \"time_to_depth\": {times}"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| number("time_to_depth", t))
+        .collect::<Result<Vec<u64>, _>>()?;
+    if time_to_depth.len() != TIME_TO_DEPTH_POSITIONS.len() {
+        return Err(format!("Expected {} times in \"time_to_depth\"", TIME_TO_DEPTH_POSITIONS.len()));
+    }
+
+    Ok(GateMetrics {
+        settings: GateSettings {
+            bench_depth: number("bench_depth", value("bench_depth")?)?,
+            tactics_nodes: number("tactics_nodes", value("tactics_nodes")?)?,
+            time_to_depth: number("time_to_depth_depth", value("time_to_depth_depth")?)?,
+        },
+        bench_nodes: number("bench_nodes", value("bench_nodes")?)?,
+        tactics_solved: number("tactics_solved", value("tactics_solved")?)?,
+        time_to_depth,
+    })
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_tactics_are_valid()
+    {
+        for line in TACTICS
+        {
+            // Parsing checks that the best moves are legal
+            let epd = epd::parse(line).unwrap_or_else(|e| panic!("{e}"));
+            assert!(!epd.best_moves.is_empty(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_gate_against_own_baseline()
+    {
+        let settings = GateSettings { bench_depth: 2, tactics_nodes: 2000, time_to_depth: 3 };
+        let baseline = from_json(&to_json(&measure(settings))).unwrap();
+        assert_eq!(baseline.settings, settings);
+
+        let current = measure(baseline.settings);
+        assert_eq!(current.bench_nodes, baseline.bench_nodes);
+        assert_eq!(current.tactics_solved, baseline.tactics_solved);
+        assert_eq!(regressions(&baseline, &current), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_regressions()
+    {
+        let baseline = GateMetrics {
+            settings: GateSettings::default(),
+            bench_nodes: 100_000,
+            tactics_solved: 8,
+            time_to_depth: vec![1000, 2000, 10],
+        };
+
+        let mut current = baseline.clone();
+        current.bench_nodes = 109_000;
+        current.tactics_solved = 7;
+        current.time_to_depth = vec![1200, 2000, 50];
+        assert!(regressions(&baseline, &current).is_empty());
+
+        current.bench_nodes = 111_000;
+        current.tactics_solved = 6;
+        current.time_to_depth = vec![1400, 1000, 70];
+        assert_eq!(regressions(&baseline, &current), [
+            "bench nodes 100000 -> 111000",
+            "tactics solved 8 -> 6 of 10",
+            "time to depth 7 of position 1: 1000 -> 1400 ms",
+            "time to depth 7 of position 5: 10 -> 70 ms",
+        ]);
+
+        assert!(from_json("{}").is_err());
+        assert!(from_json("{\"bench_depth\": x}").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Measures what the hanging piece term, see `EvalTerms::hanging_piece`, adds to the time of the static
+//! evaluation
+//!
+//! The term computes the attack maps of both sides from scratch, so it is the most expensive part of
+//! the evaluation. The bench positions are evaluated many times over without the term and with it.
+
+use std::str::FromStr;
+use std::time;
+
+use chess::Board;
+use crate::evaluation::{evaluate, EvalTerms};
+use crate::params::EngineParams;
+use super::BENCH_POSITIONS;
+
+/// Each setting is measured this many times, taking turns, and the fastest time counts, since noise
+/// only ever adds time
+const ROUNDS: usize = 3;
+
+/// Every bench position is evaluated this many times per measurement, to take long enough to time
+const REPETITIONS: usize = 1000;
+
+/// The fastest times of evaluating the bench positions without and with the hanging piece term
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HangingTiming
+{
+    /// The number of evaluations with each setting
+    pub evaluations: usize,
+    /// In nanoseconds, for all the evaluations without the term
+    pub without: u64,
+    /// In nanoseconds, for all the evaluations with the term
+    pub with: u64,
+}
+
+impl HangingTiming
+{
+    /// How many percent more time the evaluations took with the term
+    pub fn extra_time_percent(&self) -> f64
+    {
+        if self.without == 0 {
+            return 0.0;
+        }
+        100.0 * (self.with as f64 - self.without as f64) / self.without as f64
+    }
+}
+
+fn positions() -> Vec<Board>
+{
+    BENCH_POSITIONS.iter().map(|fen| Board::from_str(fen).expect("invalid bench position")).collect()
+}
+
+/// Evaluate each of `positions` `REPETITIONS` times with `terms`, and return how long it took in
+/// nanoseconds
+fn time_evaluations(positions: &[Board], terms: EvalTerms) -> u64
+{
+    let params = EngineParams::default();
+    let starttime = time::Instant::now();
+    for _ in 0..REPETITIONS
+    {
+        for position in positions {
+            std::hint::black_box(evaluate(std::hint::black_box(position), terms, &params));
+        }
+    }
+    starttime.elapsed().as_nanos() as u64
+}
+
+/// Time the evaluation of the bench positions without and with the hanging piece term
+pub fn measure() -> HangingTiming
+{
+    let positions = positions();
+    let mut timing = HangingTiming { evaluations: REPETITIONS * positions.len(), without: u64::MAX, with: u64::MAX };
+    for _ in 0..ROUNDS
+    {
+        timing.without = timing.without.min(time_evaluations(&positions, EvalTerms::default()));
+        timing.with = timing.with.min(time_evaluations(&positions, EvalTerms { hanging_piece: true }));
+    }
+    timing
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_term_applies_to_bench_positions()
+    {
+        // Otherwise the bench would time a term that never gets past its early returns
+        let params = EngineParams::default();
+        let changed = positions().iter()
+            .filter(|position| evaluate(position, EvalTerms::default(), &params) != evaluate(position, EvalTerms { hanging_piece: true }, &params))
+            .count();
+        assert!(changed > 0);
+    }
+}
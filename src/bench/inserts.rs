@@ -0,0 +1,103 @@
+//! Measures what storing into a full hash table costs, where every store of a new position has to purge
+//! an entry, against storing into free slots
+//!
+//! Both are timed on tables of the same size, so that they fit the caches of the processor alike. The
+//! same number of new positions is stored into an empty table, where nearly every one finds a free
+//! slot, and into a table filled with entries of the current generation, as in a long search.
+
+use std::time;
+
+use crate::hash::{HashEntry, HashMap};
+use crate::score::{BoardScore, BoundedScore};
+use crate::timeman::xorshift;
+
+/// Each case is measured this many times, taking turns, and the fastest time counts, since noise only
+/// ever adds time
+const ROUNDS: usize = 3;
+
+/// The timed stores are this fraction of the capacity of the table, so that nearly all of them find a
+/// free slot in the empty table
+const INSERT_FRACTION: usize = 16;
+
+/// Seeds of the hashes of the timed stores and of the entries that fill the table, which differ so that
+/// the timed stores are of new positions
+const TIMED_SEED: u64 = 0x2545f4914f6cdd1d;
+const FILLING_SEED: u64 = 0x9e3779b97f4a7c15;
+
+/// The fastest times of storing into an empty and into a full table of one size
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InsertTiming
+{
+    /// The number of new positions stored in each case
+    pub inserts: usize,
+    /// In nanoseconds, for all the stores into the empty table
+    pub free: u64,
+    /// In nanoseconds, for all the stores into the full table
+    pub purging: u64,
+}
+
+impl InsertTiming
+{
+    /// How many percent more time purging took than storing into free slots, negative if less
+    pub fn extra_time_percent(&self) -> f64
+    {
+        if self.free == 0 {
+            return 0.0;
+        }
+        100.0 * (self.purging as f64 - self.free as f64) / self.free as f64
+    }
+}
+
+/// Pseudo-random hashes, the same for every run
+fn hashes(count: usize, seed: u64) -> Vec<u64>
+{
+    std::iter::successors(Some(seed), |&state| Some(xorshift(state))).take(count).collect()
+}
+
+/// Store an entry for each of `hashes` into `hashmap`, and return how long it took in nanoseconds
+fn time_inserts(hashmap: &HashMap, hashes: &[u64]) -> u64
+{
+    let starttime = time::Instant::now();
+    for (i, &hash) in hashes.iter().enumerate()
+    {
+        let entry = HashEntry::with_contents(std::hint::black_box(hash), None, BoundedScore::Exact(BoardScore::EVEN), (i % 16) as u8);
+        hashmap.insert_entry(entry);
+    }
+    starttime.elapsed().as_nanos() as u64
+}
+
+/// Time the stores into an empty and into a full table of `megabytes`
+pub fn measure(megabytes: usize) -> InsertTiming
+{
+    let mut hashmap = HashMap::new(megabytes);
+    let inserts = hashmap.capacity() / INSERT_FRACTION;
+    let timed = hashes(inserts, TIMED_SEED);
+    // Twice the capacity leaves few buckets with a free slot
+    let filling = hashes(2 * hashmap.capacity(), FILLING_SEED);
+
+    let mut timing = InsertTiming { inserts, free: u64::MAX, purging: u64::MAX };
+    for _ in 0..ROUNDS
+    {
+        hashmap.clear();
+        timing.free = timing.free.min(time_inserts(&hashmap, &timed));
+
+        hashmap.clear();
+        time_inserts(&hashmap, &filling);
+        timing.purging = timing.purging.min(time_inserts(&hashmap, &timed));
+    }
+    timing
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_filling_leaves_few_free_slots()
+    {
+        let hashmap = HashMap::new(1);
+        time_inserts(&hashmap, &hashes(2 * hashmap.capacity(), FILLING_SEED));
+        assert!(hashmap.filled() >= hashmap.capacity() * 9 / 10, "{} of {} slots filled", hashmap.filled(), hashmap.capacity());
+    }
+}
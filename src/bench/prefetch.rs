@@ -0,0 +1,91 @@
+//! Measures the speed of the search with and without the speculative prefetch of the hash entries of
+//! the next moves at a node, see `SPECULATIVE_PREFETCH_MOVES`
+//!
+//! The prefetch can only pay off when the table is far larger than the caches of the processor, such
+//! as 4096 MB. Allocating and clearing such a table for every bench position would take longer than
+//! the searches, so the positions are searched one after another into one table, the way the moves of
+//! a game are. The prefetch doesn't change what the search does, so it searches the same nodes either
+//! way.
+
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use chess::Board;
+use crate::search::{Depth, Searcher, SPECULATIVE_PREFETCH_MOVES};
+use crate::stopconditions::StopConditions;
+use super::BENCH_POSITIONS;
+
+/// Each setting is measured this many times, taking turns, and the fastest time counts, since noise
+/// only ever adds time
+const ROUNDS: usize = 3;
+
+/// The nodes and the fastest time of the bench with one setting of the prefetch
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PrefetchTiming
+{
+    /// How many first moves at a node prefetch the next, 0 for none
+    pub prefetch_moves: usize,
+    pub nodes: u64,
+    /// In milliseconds, searching only, without setting up the hash table
+    pub time: u64,
+}
+
+impl PrefetchTiming
+{
+    pub fn nps(&self) -> u64
+    {
+        if self.time != 0 { 1000 * self.nodes / self.time } else { 0 }
+    }
+}
+
+/// Search the bench positions to `depth` into a table of `megabytes`, and return the nodes and the
+/// time of the searches
+fn run(depth: Depth, megabytes: usize, prefetch_moves: usize) -> (u64, u64)
+{
+    let stop_conditions = StopConditions::new();
+    stop_conditions.depth.store(depth, Ordering::Relaxed);
+    let mut searcher = Searcher::new(&stop_conditions);
+    searcher.resize_hash(megabytes);
+    searcher.set_prefetch_moves(prefetch_moves);
+
+    let (mut nodes, mut time) = (0, 0);
+    for fen in BENCH_POSITIONS
+    {
+        let result = searcher.search(Board::from_str(fen).expect("invalid bench position"), 0);
+        let last = result.iterations.last().expect("the search completes an iteration");
+        nodes += last.nodes;
+        time += last.time;
+    }
+    (nodes, time)
+}
+
+/// Run the bench to `depth` with a table of `megabytes`, without the prefetch and with it
+pub fn measure(depth: Depth, megabytes: usize) -> [PrefetchTiming; 2]
+{
+    let mut timings = [0, SPECULATIVE_PREFETCH_MOVES].map(|prefetch_moves| PrefetchTiming { prefetch_moves, nodes: 0, time: u64::MAX });
+    for _ in 0..ROUNDS
+    {
+        for timing in &mut timings
+        {
+            let (nodes, time) = run(depth, megabytes, timing.prefetch_moves);
+            debug_assert!(timing.time == u64::MAX || timing.nodes == nodes);
+            timing.nodes = nodes;
+            timing.time = timing.time.min(time);
+        }
+    }
+    timings
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_same_nodes_with_and_without_prefetch()
+    {
+        let (without, _) = run(3, 1, 0);
+        let (with, _) = run(3, 1, SPECULATIVE_PREFETCH_MOVES);
+        assert_eq!(with, without);
+    }
+}
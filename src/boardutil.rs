@@ -0,0 +1,201 @@
+//! Questions about a position and a move in it, answered in one place for the search, the notation
+//! and the tracking of the game history
+//!
+//! The chess crate mostly tells what a move does by making it. Where it can, this answers from the
+//! position before the move instead.
+
+use chess::{Board, ChessMove, Piece};
+
+pub trait PositionExt
+{
+    /// Whether the side to move may pass. Not when in check, since the king would stay in check.
+    fn can_null_move(&self) -> bool;
+
+    /// Whether the side to move has any knight, bishop, rook or queen, without which zugzwang is common
+    fn has_pieces_besides_pawns(&self) -> bool;
+
+    /// Whether a move captures a piece, including en passant
+    fn is_capture(&self, chess_move: ChessMove) -> bool;
+
+    /// Whether a move castles, which the chess crate represents as a king move of two squares
+    fn is_castling(&self, chess_move: ChessMove) -> bool;
+
+    /// Whether a move gives check, directly or by discovery
+    fn gives_check(&self, chess_move: ChessMove) -> bool;
+
+    /// Whether a move resets the fifty-move counter, that is, whether it is a pawn move or a capture
+    fn resets_halfmove_clock(&self, chess_move: ChessMove) -> bool;
+
+    /// Whether no position from before a move can occur again after it: it resets the fifty-move
+    /// counter, or it takes castling rights away from either side
+    fn is_irreversible(&self, chess_move: ChessMove) -> bool;
+}
+
+impl PositionExt for Board
+{
+    fn can_null_move(&self) -> bool
+    {
+        *self.checkers() == chess::EMPTY
+    }
+
+    fn has_pieces_besides_pawns(&self) -> bool
+    {
+        let pawns_and_kings = self.pieces(Piece::Pawn) | self.pieces(Piece::King);
+        (self.color_combined(self.side_to_move()) & !pawns_and_kings) != chess::EMPTY
+    }
+
+    fn is_capture(&self, chess_move: ChessMove) -> bool
+    {
+        let source = chess_move.get_source();
+        let dest = chess_move.get_dest();
+        self.piece_on(dest).is_some() ||
+            (self.piece_on(source) == Some(Piece::Pawn) && source.get_file() != dest.get_file())
+    }
+
+    fn is_castling(&self, chess_move: ChessMove) -> bool
+    {
+        let source = chess_move.get_source();
+        let dest = chess_move.get_dest();
+        self.piece_on(source) == Some(Piece::King) && source.get_file().to_index().abs_diff(dest.get_file().to_index()) == 2
+    }
+
+    fn gives_check(&self, chess_move: ChessMove) -> bool
+    {
+        // Making the move has the chess crate work out the checkers from its attack tables, which
+        // covers discovered checks, castling and en passant without special cases here
+        *self.make_move_new(chess_move).checkers() != chess::EMPTY
+    }
+
+    fn resets_halfmove_clock(&self, chess_move: ChessMove) -> bool
+    {
+        self.piece_on(chess_move.get_source()) == Some(Piece::Pawn) || self.is_capture(chess_move)
+    }
+
+    fn is_irreversible(&self, chess_move: ChessMove) -> bool
+    {
+        if self.resets_halfmove_clock(chess_move) {
+            return true;
+        }
+        if chess::ALL_COLORS.iter().all(|&color| self.castle_rights(color) == chess::CastleRights::NoRights) {
+            return false;
+        }
+
+        let after = self.make_move_new(chess_move);
+        chess::ALL_COLORS.iter().any(|&color| after.castle_rights(color) != self.castle_rights(color))
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+    use chess::{BitBoard, MoveGen};
+
+    use super::*;
+    use crate::bench::BENCH_POSITIONS;
+
+    /// The bench positions and every position one move later, which between them have castling,
+    /// en passant, promotions and checks
+    fn fixture_positions() -> Vec<Board>
+    {
+        let mut positions = Vec::new();
+        for fen in BENCH_POSITIONS
+        {
+            let position = Board::from_str(fen).unwrap();
+            positions.extend(MoveGen::new_legal(&position).map(|m| position.make_move_new(m)));
+            positions.push(position);
+        }
+        positions
+    }
+
+    /// Whether any piece of the side that just moved attacks the enemy king, found by looking outwards
+    /// from the king
+    fn is_attacking_king(after: &Board) -> bool
+    {
+        let them = after.side_to_move();
+        let king = after.king_square(them);
+        let occupied = *after.combined();
+        let ours = |piece| after.pieces(piece) & after.color_combined(!them);
+        let diagonal = ours(Piece::Bishop) | ours(Piece::Queen);
+        let straight = ours(Piece::Rook) | ours(Piece::Queen);
+
+        let attackers = chess::get_pawn_attacks(king, them, ours(Piece::Pawn)) |
+            (chess::get_knight_moves(king) & ours(Piece::Knight)) |
+            (chess::get_bishop_moves(king, occupied) & diagonal) |
+            (chess::get_rook_moves(king, occupied) & straight);
+        attackers != chess::EMPTY
+    }
+
+    #[test]
+    fn test_against_brute_force()
+    {
+        for position in fixture_positions()
+        {
+            assert_eq!(position.can_null_move(), position.null_move().is_some(), "{position}");
+
+            let us = position.side_to_move();
+            let own_rooks = |board: &Board| board.pieces(Piece::Rook) & board.color_combined(us);
+            for chess_move in MoveGen::new_legal(&position)
+            {
+                let after = position.make_move_new(chess_move);
+                let context = format!("{chess_move} in {position}");
+
+                let is_capture = after.color_combined(!us).popcnt() < position.color_combined(!us).popcnt();
+                assert_eq!(position.is_capture(chess_move), is_capture, "{context}");
+
+                // Only castling moves a rook along with the king
+                let is_king_move = position.piece_on(chess_move.get_source()) == Some(Piece::King);
+                let is_castling = is_king_move && own_rooks(&after) != own_rooks(&position);
+                assert_eq!(position.is_castling(chess_move), is_castling, "{context}");
+
+                assert_eq!(position.gives_check(chess_move), is_attacking_king(&after), "{context}");
+
+                let is_pawn_move = *position.pieces(Piece::Pawn) & BitBoard::from_square(chess_move.get_source()) != chess::EMPTY;
+                assert_eq!(position.resets_halfmove_clock(chess_move), is_pawn_move || is_capture, "{context}");
+
+                let loses_rights = chess::ALL_COLORS.iter().any(|&c| after.castle_rights(c) != position.castle_rights(c));
+                assert_eq!(position.is_irreversible(chess_move), is_pawn_move || is_capture || loses_rights, "{context}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_resets_halfmove_clock()
+    {
+        let position = Board::from_str("4k3/8/8/3pP3/8/8/8/R3K3 w - d6 0 1").unwrap();
+        let resets = |m| position.resets_halfmove_clock(ChessMove::from_str(m).unwrap());
+
+        assert!(resets("e5e6"));
+        assert!(resets("e5d6"));
+        assert!(!resets("a1a7"));
+        assert!(!resets("e1d2"));
+    }
+
+    #[test]
+    fn test_is_irreversible()
+    {
+        let position = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let irreversible = |m| position.is_irreversible(ChessMove::from_str(m).unwrap());
+
+        // Castling and moving the king or a rook give up castling rights
+        assert!(irreversible("e1g1"));
+        assert!(irreversible("e1f1"));
+        assert!(irreversible("a1b1"));
+        assert!(irreversible("h1h5"));
+
+        // Without castling rights, only pawn moves and captures count
+        let position = Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        assert!(!position.is_irreversible(ChessMove::from_str("e1f1").unwrap()));
+    }
+
+    #[test]
+    fn test_has_pieces_besides_pawns()
+    {
+        assert!(Board::default().has_pieces_besides_pawns());
+
+        // Only the side to move counts
+        let position = Board::from_str("4k3/pppp4/8/8/8/8/PPPP4/3NK3 b - - 0 1").unwrap();
+        assert!(!position.has_pieces_besides_pawns());
+        assert!(position.null_move().unwrap().has_pieces_besides_pawns());
+    }
+}
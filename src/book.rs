@@ -0,0 +1,390 @@
+//! Book learning: weights for the opening moves of finished games, nudged by their results, in a file
+//! that persists from game to game
+//!
+//! After a game, `learn` raises the weights of the opening moves of the side that won, and lowers those
+//! of the side that lost. Draws leave the weights as they are. A move starts out at `DEFAULT_WEIGHT`,
+//! and stays within `MIN_WEIGHT..=MAX_WEIGHT`, so that no line is given up on for good after a run of
+//! bad luck.
+//!
+//! The book file is a Polyglot book, see `polyglot`, so that other engines and GUIs can play from the
+//! learned weights, and a Polyglot book from elsewhere can be learned into. Its weights are brought
+//! within bounds as its moves are learned from.
+//!
+//! Several engine instances may be playing at once and learn into the same book. An update holds a
+//! lock file next to the book while it reads and rewrites it, and writes the new book to a temporary
+//! file that replaces the old one once it is complete.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chess::{Board, ChessMove, Color};
+use crate::gameresult::GameResult;
+use crate::hash::snapshot;
+
+mod polyglot;
+
+use polyglot::ENTRY_SIZE;
+
+/// Only this many plies from the start of a game are learned from. Later moves are not book moves.
+pub const LEARN_MAX_PLIES: usize = 20;
+
+/// Weight of a move that has not been learned from yet
+const DEFAULT_WEIGHT: u16 = 100;
+const MIN_WEIGHT: u16 = 1;
+const MAX_WEIGHT: u16 = 1000;
+
+/// How much the weight of a move changes with a won or lost game
+const WEIGHT_STEP: u16 = 10;
+
+/// How long to wait for another instance to finish its update of the book
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A lock file older than this was left behind by an instance that died during its update
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// A move of a game, and the position it was played in
+#[derive(Clone, Copy, Debug)]
+pub struct PlayedMove
+{
+    pub position: Board,
+    pub chess_move: ChessMove,
+}
+
+/// The weights of the moves of a book, by Polyglot key and move
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Book
+{
+    weights: BTreeMap<(u64, u16), u16>,
+}
+
+impl Book
+{
+    /// The weight of a move in `position`
+    // Nothing plays from the book yet, see "Book" in TODO.md
+    #[allow(dead_code)]
+    pub fn weight(&self, position: &Board, chess_move: ChessMove) -> u16
+    {
+        let key = (polyglot::key(position), polyglot::encode_move(position, chess_move));
+        self.weights.get(&key).copied().unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    /// Learn from a game with `result`, given the moves from its start, and return the number of moves
+    /// whose weights were updated
+    pub fn learn_game(&mut self, moves: &[PlayedMove], result: GameResult) -> usize
+    {
+        let winner = match result
+        {
+            GameResult::WhiteWins => Color::White,
+            GameResult::BlackWins => Color::Black,
+            GameResult::Draw(_) => return 0,
+        };
+
+        let opening = &moves[..moves.len().min(LEARN_MAX_PLIES)];
+        for played in opening
+        {
+            let key = (polyglot::key(&played.position), polyglot::encode_move(&played.position, played.chess_move));
+            let weight = self.weights.entry(key).or_insert(DEFAULT_WEIGHT);
+            *weight = if played.position.side_to_move() == winner {
+                weight.saturating_add(WEIGHT_STEP).clamp(MIN_WEIGHT, MAX_WEIGHT)
+            } else {
+                weight.saturating_sub(WEIGHT_STEP).clamp(MIN_WEIGHT, MAX_WEIGHT)
+            };
+        }
+        opening.len()
+    }
+
+    /// Read the Polyglot book at `path`. A book that doesn't exist yet is empty.
+    ///
+    /// A damaged book, with an incomplete entry, an invalid move or entries out of order, gives an
+    /// `InvalidData` error.
+    pub fn read(path: &Path) -> io::Result<Book>
+    {
+        let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut input = match File::open(path)
+        {
+            Ok(file) => BufReader::new(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Book::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut book = Book::default();
+        let mut previous_key = 0;
+        while !input.fill_buf()?.is_empty()
+        {
+            let mut entry = [0; ENTRY_SIZE];
+            match input.read_exact(&mut entry)
+            {
+                Ok(()) => { },
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(invalid_data("book ends in the middle of an entry")),
+                Err(e) => return Err(e),
+            }
+
+            // The learning data in the last 4 bytes is left out
+            let key = u64::from_be_bytes(entry[0..8].try_into().unwrap());
+            let encoded_move = u16::from_be_bytes(entry[8..10].try_into().unwrap());
+            let weight = u16::from_be_bytes(entry[10..12].try_into().unwrap());
+            if !polyglot::is_valid_move(encoded_move) {
+                return Err(invalid_data("invalid move in book"));
+            }
+            if key < previous_key {
+                return Err(invalid_data("book is not sorted"));
+            }
+            previous_key = key;
+            book.weights.insert((key, encoded_move), weight);
+        }
+        Ok(book)
+    }
+
+    /// Write the book to `path` as a Polyglot book, replacing the previous one only once it is complete
+    pub fn write(&self, path: &Path) -> io::Result<()>
+    {
+        let temp_path = snapshot::path_with_suffix(path, ".tmp");
+        let result = (|| {
+            let mut output = BufWriter::new(File::create(&temp_path)?);
+            for (&(key, encoded_move), &weight) in &self.weights
+            {
+                output.write_all(&key.to_be_bytes())?;
+                output.write_all(&encoded_move.to_be_bytes())?;
+                output.write_all(&weight.to_be_bytes())?;
+                output.write_all(&0u32.to_be_bytes())?;
+            }
+            output.into_inner().map_err(|e| e.into_error())?.sync_data()
+        })();
+
+        match result
+        {
+            Ok(()) => fs::rename(&temp_path, path),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Learn from a game with `result` into the book at `path`, creating it if needed, and return the
+/// number of moves whose weights were updated
+pub fn learn(path: &Path, moves: &[PlayedMove], result: GameResult) -> io::Result<usize>
+{
+    let _lock = BookLock::acquire(path)?;
+    let mut book = Book::read(path)?;
+    let learned = book.learn_game(moves, result);
+    book.write(path)?;
+    Ok(learned)
+}
+
+/// The lock file of a book, held for as long as this lives
+struct BookLock
+{
+    path: PathBuf,
+}
+
+impl BookLock
+{
+    fn acquire(book_path: &Path) -> io::Result<Self>
+    {
+        let path = snapshot::path_with_suffix(book_path, ".lock");
+        let start = Instant::now();
+        loop
+        {
+            match OpenOptions::new().write(true).create_new(true).open(&path)
+            {
+                Ok(_) => return Ok(BookLock { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => { },
+                Err(e) => return Err(e),
+            }
+
+            let age = fs::metadata(&path).and_then(|metadata| metadata.modified()).map(|time| time.elapsed().unwrap_or_default());
+            if age.is_ok_and(|age| age > STALE_LOCK_AGE) {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if start.elapsed() > LOCK_TIMEOUT {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("\"{}\" is held by another instance", path.display())));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for BookLock
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use crate::gameresult::DrawReason;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("book-test-{name}-{}.book", std::process::id()))
+    }
+
+    /// The moves of a game from the starting position, in UCI notation
+    fn game(moves: &str) -> Vec<PlayedMove>
+    {
+        let mut board = Board::default();
+        let mut played = Vec::new();
+        for word in moves.split_ascii_whitespace()
+        {
+            let chess_move = ChessMove::from_str(word).unwrap();
+            played.push(PlayedMove { position: board, chess_move });
+            board = board.make_move_new(chess_move);
+        }
+        played
+    }
+
+    fn start_weight(book: &Book, chess_move: &str) -> u16
+    {
+        book.weight(&Board::default(), ChessMove::from_str(chess_move).unwrap())
+    }
+
+    #[test]
+    fn test_weights_follow_results()
+    {
+        let mut book = Book::default();
+        for _ in 0..3 {
+            assert_eq!(book.learn_game(&game("e2e4 e7e5 g1f3"), GameResult::WhiteWins), 3);
+        }
+        for _ in 0..2 {
+            book.learn_game(&game("e2e4 c7c5"), GameResult::BlackWins);
+        }
+        assert_eq!(book.learn_game(&game("d2d4 d7d5"), GameResult::Draw(DrawReason::Threefold)), 0);
+
+        // e2e4 won three games and lost two
+        assert_eq!(start_weight(&book, "e2e4"), DEFAULT_WEIGHT + WEIGHT_STEP);
+        assert_eq!(start_weight(&book, "d2d4"), DEFAULT_WEIGHT);
+
+        // Against e2e4, the Sicilian did better than e7e5
+        let after_e4 = Board::default().make_move_new(ChessMove::from_str("e2e4").unwrap());
+        let sicilian = book.weight(&after_e4, ChessMove::from_str("c7c5").unwrap());
+        let open_game = book.weight(&after_e4, ChessMove::from_str("e7e5").unwrap());
+        assert!(sicilian > DEFAULT_WEIGHT && open_game < DEFAULT_WEIGHT, "c7c5 {sicilian}, e7e5 {open_game}");
+
+        // Only the opening is learned from
+        let long_game = "g1f3 g8f6 f3g1 f6g8 ".repeat(10);
+        assert_eq!(book.learn_game(&game(&long_game), GameResult::BlackWins), LEARN_MAX_PLIES);
+    }
+
+    #[test]
+    fn test_weights_are_bounded()
+    {
+        let mut book = Book::default();
+        for _ in 0..200 {
+            book.learn_game(&game("e2e4"), GameResult::WhiteWins);
+            book.learn_game(&game("d2d4"), GameResult::BlackWins);
+        }
+        assert_eq!(start_weight(&book, "e2e4"), MAX_WEIGHT);
+        assert_eq!(start_weight(&book, "d2d4"), MIN_WEIGHT);
+
+        // A line at the bottom can still recover
+        book.learn_game(&game("d2d4"), GameResult::WhiteWins);
+        assert_eq!(start_weight(&book, "d2d4"), MIN_WEIGHT + WEIGHT_STEP);
+    }
+
+    #[test]
+    fn test_book_file()
+    {
+        let path = temp_path("file");
+        let _ = fs::remove_file(&path);
+        assert_eq!(Book::read(&path).unwrap(), Book::default());
+
+        learn(&path, &game("e2e4 e7e5 g1f3 b8c6"), GameResult::WhiteWins).unwrap();
+        learn(&path, &game("e2e4 c7c5 g1f3"), GameResult::BlackWins).unwrap();
+        let mut expected = Book::default();
+        expected.learn_game(&game("e2e4 e7e5 g1f3 b8c6"), GameResult::WhiteWins);
+        expected.learn_game(&game("e2e4 c7c5 g1f3"), GameResult::BlackWins);
+
+        let book = Book::read(&path);
+        let lock_left = snapshot::path_with_suffix(&path, ".lock").exists();
+        let length = fs::metadata(&path).unwrap().len() as usize;
+
+        // A damaged book is refused rather than learned into
+        let contents = fs::read(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() - 1]).unwrap();
+        let truncated = Book::read(&path);
+        fs::write(&path, b"not a book").unwrap();
+        let not_a_book = learn(&path, &game("e2e4"), GameResult::WhiteWins);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(book.unwrap(), expected);
+        assert!(!lock_left, "the lock file should be gone after learning");
+        assert_eq!(length, 6 * ENTRY_SIZE);
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(not_a_book.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_polyglot_entries()
+    {
+        let path = temp_path("polyglot");
+        let mut book = Book::default();
+        book.learn_game(&game("e2e4 e7e5 e1e2"), GameResult::WhiteWins);
+        book.write(&path).unwrap();
+        let contents = fs::read(&path).unwrap();
+
+        // Entries out of order are refused
+        let mut swapped = contents[ENTRY_SIZE..2 * ENTRY_SIZE].to_vec();
+        swapped.extend_from_slice(&contents[..ENTRY_SIZE]);
+        swapped.extend_from_slice(&contents[2 * ENTRY_SIZE..]);
+        fs::write(&path, &swapped).unwrap();
+        let out_of_order = Book::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        let entries = contents.chunks(ENTRY_SIZE)
+            .map(|entry| (
+                u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+                u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            ))
+            .collect::<Vec<_>>();
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0), "{entries:x?}");
+        // e2e4 in the starting position, with the key and the move that any Polyglot reader looks up
+        let e2e4 = (0x463b96181691fc9c, 28 | 12 << 6, DEFAULT_WEIGHT + WEIGHT_STEP, 0);
+        assert!(entries.contains(&e2e4), "{entries:x?}");
+        assert_eq!(out_of_order.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_instances_learning_at_once()
+    {
+        const INSTANCES: usize = 4;
+        const GAMES: usize = 5;
+
+        let path = temp_path("instances");
+        let _ = fs::remove_file(&path);
+        let instances = (0..INSTANCES)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for _ in 0..GAMES {
+                        learn(&path, &game("e2e4 e7e5"), GameResult::WhiteWins).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for instance in instances {
+            instance.join().unwrap();
+        }
+
+        let book = Book::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        // No update got lost
+        assert_eq!(start_weight(&book.unwrap(), "e2e4"), DEFAULT_WEIGHT + (INSTANCES * GAMES) as u16 * WEIGHT_STEP);
+    }
+}
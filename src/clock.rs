@@ -0,0 +1,103 @@
+//! The clock that the search holds its time limits against
+//!
+//! Natively this is the monotonic clock of the standard library. On WebAssembly in the browser or
+//! Node.js, `std::time::Instant` panics, and the host supplies the time instead: the `wasm` module
+//! reads `performance.now()`. Other embeddings can plug in a clock of their own with `set_clock`, as
+//! long as they do so before the first search.
+
+use std::ops::Add;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// A source of monotonic time
+pub trait Clock: Send + Sync
+{
+    /// The time since some fixed point, which must never go backwards
+    fn now(&self) -> Duration;
+}
+
+static CLOCK: OnceLock<&'static dyn Clock> = OnceLock::new();
+
+/// Read the time from `clock` from now on. Returns false if the clock has been read or set already,
+/// since instants of different clocks can't be compared.
+pub fn set_clock(clock: &'static dyn Clock) -> bool
+{
+    CLOCK.set(clock).is_ok()
+}
+
+fn clock() -> &'static dyn Clock
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    let default: &'static dyn Clock = &SystemClock;
+    #[cfg(target_arch = "wasm32")]
+    let default: &'static dyn Clock = &crate::wasm::PerformanceClock;
+    *CLOCK.get_or_init(|| default)
+}
+
+/// The monotonic clock of the standard library, counted from the first time it is read
+#[cfg(not(target_arch = "wasm32"))]
+struct SystemClock;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clock for SystemClock
+{
+    fn now(&self) -> Duration
+    {
+        static ORIGIN: OnceLock<std::time::Instant> = OnceLock::new();
+        ORIGIN.get_or_init(std::time::Instant::now).elapsed()
+    }
+}
+
+/// A point in time of the clock, like `std::time::Instant`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Instant(Duration);
+
+impl Instant
+{
+    pub fn now() -> Self
+    {
+        Instant(clock().now())
+    }
+
+    pub fn elapsed(&self) -> Duration
+    {
+        Instant::now().duration_since(*self)
+    }
+
+    /// The time from `earlier` to this instant, or zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration
+    {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl Add<Duration> for Instant
+{
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant
+    {
+        Instant(self.0 + duration)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_instant()
+    {
+        let start = Instant::now();
+        let later = start + Duration::from_millis(5);
+        assert!(later > start);
+        assert_eq!(later.duration_since(start), Duration::from_millis(5));
+        assert_eq!(start.duration_since(later), Duration::ZERO);
+
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(start.elapsed() >= Duration::from_millis(2));
+        // The clock has been read, so it is too late for another one
+        assert!(!set_clock(&SystemClock));
+    }
+}
@@ -0,0 +1,175 @@
+//! Timing of the commands from the GUI, for diagnosing reports of the engine freezing
+//!
+//! The UCI thread handles every command through `run`, which times it. A command that takes longer than
+//! `SLOW_COMMAND_MILLIS`, and isn't one that waits on purpose, gets a diagnostic that the UCI client
+//! sends in debug mode. The last `LOG_LENGTH` commands are kept, with when they were received and how
+//! long they took, and printed by the `dumplog` command, or to standard error when a thread panics, see
+//! `install_panic_hook`.
+
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// Commands that take longer than this many milliseconds are slow
+pub const SLOW_COMMAND_MILLIS: u128 = 50;
+
+/// Number of commands kept in the log
+pub const LOG_LENGTH: usize = 50;
+
+/// Longer command lines, like positions with long move lists, are cut to this many bytes in the log
+const MAX_LOGGED_LENGTH: usize = 120;
+
+/// The log is global rather than owned by the UCI client, so that the panic hook can get at it
+static LOG: Mutex<CommandLog> = Mutex::new(CommandLog::new());
+
+struct LoggedCommand
+{
+    line: String,
+    received: Instant,
+    /// How long handling the command took, or `None` while it is being handled
+    millis: Option<u128>,
+}
+
+struct CommandLog
+{
+    commands: VecDeque<LoggedCommand>,
+}
+
+impl CommandLog
+{
+    const fn new() -> Self
+    {
+        CommandLog { commands: VecDeque::new() }
+    }
+
+    fn start(&mut self, line: &str, received: Instant)
+    {
+        if self.commands.len() == LOG_LENGTH {
+            self.commands.pop_front();
+        }
+        self.commands.push_back(LoggedCommand { line: shortened(line).to_owned(), received, millis: None });
+    }
+
+    fn finish(&mut self, millis: u128)
+    {
+        if let Some(command) = self.commands.back_mut() {
+            command.millis = Some(millis);
+        }
+    }
+
+    /// The commands, oldest first, as info strings
+    fn lines(&self, now: Instant) -> Vec<String>
+    {
+        self.commands.iter()
+            .map(|command| {
+                let ago = now.duration_since(command.received).as_millis();
+                match command.millis
+                {
+                    Some(millis) => format!("info string log {ago} ms ago \"{}\" took {millis} ms", command.line),
+                    None => format!("info string log {ago} ms ago \"{}\" still running", command.line),
+                }
+            })
+            .collect()
+    }
+}
+
+/// `line` cut to `MAX_LOGGED_LENGTH` bytes, at a character boundary
+fn shortened(line: &str) -> &str
+{
+    let mut end = line.len().min(MAX_LOGGED_LENGTH);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+fn lock() -> MutexGuard<'static, CommandLog>
+{
+    // The log is only ever appended to, so it stays usable after a panic
+    LOG.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Handle the command `line` with `handler`, logging and timing it
+///
+/// Returns what `handler` returns, and the diagnostic for a slow command if `handler` took too long,
+/// unless the command `may_block`.
+pub fn run<T>(line: &str, may_block: bool, handler: impl FnOnce() -> T) -> (T, Option<String>)
+{
+    let received = Instant::now();
+    lock().start(line, received);
+    let result = handler();
+    let millis = received.elapsed().as_millis();
+    lock().finish(millis);
+
+    let slow = (!may_block && millis > SLOW_COMMAND_MILLIS)
+        .then(|| format!("info string slow command \"{}\" took {millis} ms", shortened(line)));
+    (result, slow)
+}
+
+/// The logged commands, oldest first, as info strings
+pub fn dump() -> Vec<String>
+{
+    lock().lines(Instant::now())
+}
+
+/// Print the logged commands to standard error whenever a thread panics, after the panic message
+pub fn install_panic_hook()
+{
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // A panic while the log is locked leaves it locked until the panic has unwound
+        if let Ok(log) = LOG.try_lock()
+        {
+            eprintln!("Last commands before the panic:");
+            for line in log.lines(Instant::now()) {
+                eprintln!("{line}");
+            }
+        }
+    }));
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_slow_commands()
+    {
+        let (result, slow) = run("isready", false, || 7);
+        assert_eq!((result, slow), (7, None));
+
+        let slowed = || thread::sleep(Duration::from_millis(SLOW_COMMAND_MILLIS as u64 + 20));
+        let (_, slow) = run("setoption name Hash value 16", false, slowed);
+        let slow = slow.expect("no diagnostic for a slow command");
+        assert!(slow.starts_with("info string slow command \"setoption name Hash value 16\" took "), "{slow}");
+
+        // Commands that wait on purpose are never slow
+        assert_eq!(run("bench 1", true, slowed).1, None);
+    }
+
+    #[test]
+    fn test_log_keeps_the_last_commands()
+    {
+        // The global log is shared with the other tests, so this one keeps a log of its own
+        let mut log = CommandLog::new();
+        let start = Instant::now();
+        for number in 0..LOG_LENGTH + 5
+        {
+            log.start(&format!("position startpos moves {number}"), start);
+            log.finish(1);
+        }
+        log.start(&"e2e4".repeat(100), start);
+
+        let lines = log.lines(start);
+        assert_eq!(lines.len(), LOG_LENGTH);
+        assert_eq!(lines[0], "info string log 0 ms ago \"position startpos moves 6\" took 1 ms");
+        assert!(lines[LOG_LENGTH - 1].ends_with("e2e4\" still running"), "{}", lines[LOG_LENGTH - 1]);
+        assert!(lines[LOG_LENGTH - 1].len() < MAX_LOGGED_LENGTH + 50);
+    }
+}
@@ -0,0 +1,149 @@
+//! Positions in EPD, as test suites of puzzles come: the first four fields of a FEN, followed by
+//! operations such as `bm Qg6; id "WAC.001";`, and a runner that searches each position of a batch
+//! and checks the move it finds against the best moves of the `bm` opcode
+//!
+//! The runner searches with the limits of `go depth <D> mintime <T> maxtime <H>`, see
+//! `StopConditions::min_searchtime`: a position is done once the search has completed depth D and
+//! searched for T milliseconds, so easy puzzles finish early, and after H milliseconds at most, so hard
+//! ones are neither cut short at a fixed depth nor allowed to take the whole batch.
+
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use chess::{Board, ChessMove};
+use crate::clock::Instant;
+use crate::san;
+use crate::search::{Depth, Searcher};
+use crate::stopconditions::StopConditions;
+
+/// A position of a test suite, and the moves that solve it
+#[derive(Clone, Debug)]
+pub struct EpdPosition
+{
+    pub position: Board,
+    /// The `id` opcode, which names the position within its suite
+    pub id: Option<String>,
+    /// The moves of the `bm` opcode, any one of which solves the position
+    pub best_moves: Vec<ChessMove>,
+}
+
+/// The limits of the search of each position, as those of `go depth <D> mintime <T> maxtime <H>`.
+/// Zero means no limit, and a depth of `Depth::MAX` means none.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EpdLimits
+{
+    pub depth: Depth,
+    pub min_searchtime: u32,
+    pub max_searchtime: u32,
+}
+
+/// How the search of one position went
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Solution
+{
+    pub best_move: Option<ChessMove>,
+    pub solved: bool,
+    /// The last completed iteration
+    pub depth: Depth,
+    /// In milliseconds
+    pub time: u64,
+}
+
+/// Parse a line of EPD. Positions without a `bm` opcode are refused, since there is nothing to check
+/// the search against.
+pub fn parse(line: &str) -> Result<EpdPosition, String>
+{
+    let fields = line.split_ascii_whitespace().collect::<Vec<_>>();
+    if fields.len() < 4 {
+        return Err(format!("Expected a position in \"{line}\""));
+    }
+    // EPD leaves out the move counters, which don't matter to a puzzle
+    let fen = format!("{} 0 1", fields[..4].join(" "));
+    let position = Board::from_str(&fen).map_err(|e| format!("Invalid position \"{}\": {e}", fields[..4].join(" ")))?;
+
+    let mut id = None;
+    let mut best_moves = Vec::new();
+    for operation in fields[4..].join(" ").split(';')
+    {
+        let operation = operation.trim();
+        let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+        match opcode
+        {
+            "bm" => {
+                for operand in operands.split_ascii_whitespace()
+                {
+                    best_moves.push(san::from_san(&position, operand)?);
+                }
+            }
+            "id" => id = Some(operands.trim().trim_matches('"').to_owned()),
+            _ => { },
+        }
+    }
+
+    if best_moves.is_empty() {
+        return Err(format!("No best move in \"{line}\""));
+    }
+    Ok(EpdPosition { position, id, best_moves })
+}
+
+/// Search `epd` with `limits`, and check the move found against its best moves
+pub fn solve(epd: &EpdPosition, limits: EpdLimits) -> Solution
+{
+    let stop_conditions = StopConditions::new();
+    stop_conditions.depth.store(limits.depth, Ordering::Relaxed);
+    stop_conditions.min_searchtime.store(limits.min_searchtime, Ordering::Relaxed);
+    stop_conditions.movetime.store(limits.max_searchtime, Ordering::Relaxed);
+    let mut searcher = Searcher::new(&stop_conditions);
+
+    let starttime = Instant::now();
+    let result = searcher.search(epd.position, 0);
+    let time = starttime.elapsed().as_millis() as u64;
+
+    Solution {
+        best_move: result.best_move,
+        solved: result.best_move.is_some_and(|m| epd.best_moves.contains(&m)),
+        depth: result.iterations.last().map_or(0, |iteration| iteration.depth),
+        time,
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_parse()
+    {
+        let epd = parse("r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - - bm Qxh7+; id \"WAC.004\";").unwrap();
+        assert_eq!(epd.id.as_deref(), Some("WAC.004"));
+        assert_eq!(epd.best_moves, vec![ChessMove::from_str("h6h7").unwrap()]);
+        assert_eq!(epd.position.get_hash(), Board::from_str("r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - - 0 1").unwrap().get_hash());
+
+        // Several best moves, and no id
+        let epd = parse("4k3/8/8/8/8/8/8/R3K2R w KQ - bm O-O Rh8+;").unwrap();
+        assert_eq!(epd.id, None);
+        assert_eq!(epd.best_moves, vec![ChessMove::from_str("e1g1").unwrap(), ChessMove::from_str("h1h8").unwrap()]);
+
+        assert!(parse("").is_err());
+        assert!(parse("4k3/8/8/8/8/8/8/4K3 w - -").is_err());
+        assert!(parse("4k3/8/8/8/8/8/8/4K3 w - - id \"no best move\";").is_err());
+        assert!(parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;").is_err());
+        assert!(parse("4k3/8/8/8/8/8/8/4K3 x - - bm Kd1;").is_err());
+    }
+
+    #[test]
+    fn test_solve_stops_at_minimums()
+    {
+        // A mate in one is found at depth 1, and the minimums stop the search long before the maximum
+        let epd = parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#;").unwrap();
+        let limits = EpdLimits { depth: 3, min_searchtime: 1, max_searchtime: 60_000 };
+        let solution = solve(&epd, limits);
+        assert!(solution.solved, "{solution:?}");
+        assert!(solution.time < 10_000, "{solution:?}");
+
+        // A wrong best move is reported as unsolved
+        let epd = parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Kf1;").unwrap();
+        assert!(!solve(&epd, limits).solved);
+    }
+}
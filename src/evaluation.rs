@@ -1,12 +1,207 @@
+use std::sync::Arc;
+
 use chess::{Board, Piece};
+use crate::params::EngineParams;
 use crate::score::BoardScore;
 
+mod attacks;
+mod batteries;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+mod pawns;
+#[cfg(any(reference_checks, test))]
+mod reference;
+mod scaling;
+mod threats;
+mod trapped;
+
+use attacks::AttackMaps;
+
+/// The last move number that can count as the opening
+const OPENING_LAST_FULLMOVE: u32 = 10;
+
+/// The opening ends early if pieces get traded below this phase value
+const OPENING_MIN_PHASE: u8 = 20;
+
+/// Positions at or below this phase value count as endgames
+const ENDGAME_MAX_PHASE: u8 = 8;
+
+/// Evaluation terms that can be switched on and off, to compare the evaluation with and without them
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct EvalTerms
+{
+    /// Credit the side to move with part of the value of the most valuable enemy piece it can win.
+    /// Overlaps with what the quiescence search finds, so it is off by default.
+    pub hanging_piece: bool,
+}
+
+/// The phase value of a position with all pieces on the board, see `phase_value`
+const MAX_PHASE: u8 = 24;
+
+/// Coarse classification of a position, for adapting the engine's behaviour to the stage of the game
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GamePhase
+{
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+/// How much non-pawn material is left, from 0 (only kings and pawns) to 24 (all pieces on the board)
+///
+/// Knights and bishops count as 1, rooks as 2, and queens as 4. Promotions could take the sum above
+/// 24, so it is capped there.
+pub fn phase_value(board: &Board) -> u8
+{
+    let count = |piece| board.pieces(piece).popcnt();
+    let phase = count(Piece::Knight) + count(Piece::Bishop) + 2 * count(Piece::Rook) + 4 * count(Piece::Queen);
+    phase.min(MAX_PHASE as u32) as u8
+}
+
+/// Blend a (middlegame, endgame) pair of values by the phase value of the position
+fn taper((middlegame, endgame): (i16, i16), phase: u8) -> i16
+{
+    let (phase, max_phase) = (phase as i32, MAX_PHASE as i32);
+    ((middlegame as i32 * phase + endgame as i32 * (max_phase - phase)) / max_phase) as i16
+}
+
+/// Classify the position as opening, middlegame, or endgame, from the material left on the board and
+/// the move number
+pub fn game_phase(board: &Board, fullmove_number: u32) -> GamePhase
+{
+    let phase = phase_value(board);
+    if phase <= ENDGAME_MAX_PHASE {
+        GamePhase::Endgame
+    } else if fullmove_number <= OPENING_LAST_FULLMOVE && phase >= OPENING_MIN_PHASE {
+        GamePhase::Opening
+    } else {
+        GamePhase::Middlegame
+    }
+}
+
+/// A static evaluation that the search can use instead of the classical one, see
+/// `Searcher::with_evaluator`
+///
+/// Scores are from the point of view of the side to move, like those of `evaluate`. The evaluator is
+/// moved to the search thread, so it must also be `Send` to be plugged in.
+pub trait Evaluator
+{
+    fn evaluate(&self, board: &Board) -> BoardScore;
+
+    /// The evaluation broken down into its terms, one per line, for looking into what the evaluator
+    /// thinks of a position. `None` if it doesn't tell.
+    fn trace(&self, _board: &Board) -> Option<String>
+    {
+        None
+    }
+}
+
+/// An evaluator chosen at run time, which the search calls through `dyn`
+pub type SharedEvaluator = Arc<dyn Evaluator + Send + Sync>;
+
+impl<T: Evaluator + ?Sized> Evaluator for Arc<T>
+{
+    fn evaluate(&self, board: &Board) -> BoardScore
+    {
+        (**self).evaluate(board)
+    }
+
+    fn trace(&self, board: &Board) -> Option<String>
+    {
+        (**self).trace(board)
+    }
+}
+
+/// The classical evaluation as an `Evaluator`, for measuring what calling it through the trait costs
+/// against calling `evaluate` directly, see `bench::dispatch`
+#[derive(Clone, Copy, Default)]
+pub struct Classical
+{
+    pub terms: EvalTerms,
+    pub params: EngineParams,
+}
+
+impl Evaluator for Classical
+{
+    fn evaluate(&self, board: &Board) -> BoardScore
+    {
+        evaluate(board, self.terms, &self.params)
+    }
+}
+
 pub fn _evaluate_always_zero(_: &Board) -> BoardScore
 {
     BoardScore::EVEN
 }
 
-pub fn evaluate_piece_values(board: &Board) -> BoardScore
+/// The evaluation of a position broken down into the terms that `evaluate` adds up
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct EvalBreakdown
+{
+    /// The name and centipawns of each term, from the point of view of the side to move, in the order
+    /// they are added up. Terms that are switched off are left out.
+    pub terms: Vec<(&'static str, i16)>,
+    /// How many percent of the sum of the terms is kept, see `scaling::scale_percent`
+    pub scale_percent: i16,
+    /// The evaluation, as `evaluate` returns it
+    pub score: BoardScore,
+}
+
+/// Evaluate the position from the point of view of the side to move, with the piece values of `params`
+pub fn evaluate(board: &Board, terms: EvalTerms, params: &EngineParams) -> BoardScore
+{
+    add_up_terms(board, terms, params, |_, _| ()).0
+}
+
+/// Evaluate the position like `evaluate`, keeping the value of each term
+pub fn breakdown(board: &Board, terms: EvalTerms, params: &EngineParams) -> EvalBreakdown
+{
+    let mut term_values = Vec::new();
+    let (score, scale_percent) = add_up_terms(board, terms, params, |name, value| term_values.push((name, value)));
+    EvalBreakdown { terms: term_values, scale_percent, score }
+}
+
+/// The evaluation and the scale percentage, passing the name and value of each term to `record` as it
+/// is added. `evaluate` records nothing, which compiles down to adding up the terms.
+fn add_up_terms(board: &Board, terms: EvalTerms, params: &EngineParams, mut record: impl FnMut(&'static str, i16)) -> (BoardScore, i16)
+{
+    let up = board.side_to_move();
+    let mut evaluation = 0;
+    let mut add = |name, value: i16| {
+        record(name, value);
+        evaluation += value;
+    };
+
+    let material = piece_values(board, params);
+    add("material", material);
+
+    // Trapped pieces are worth much less than their material value
+    add("trapped pieces", trapped::penalty(board, !up) - trapped::penalty(board, up));
+
+    // Pawn majorities and candidate passed pawns, which can turn into passed pawns later on
+    add("pawn structure", pawns::structure_bonus(board, up) - pawns::structure_bonus(board, !up));
+
+    // Rooks and queens lined up on files, and queens backed up by bishops, against the enemy king
+    add("batteries", batteries::bonus(board, up) - batteries::bonus(board, !up));
+
+    if terms.hanging_piece
+    {
+        let attacks = AttackMaps::new(board);
+        add("hanging piece", threats::hanging_piece_bonus(board, &attacks));
+    }
+
+    // Endgames that are hard to win count for less than their material
+    let strong = if evaluation >= 0 { up } else { !up };
+    #[cfg(reference_checks)]
+    reference::verify_evaluation(board, params, material, strong);
+    let scale_percent = scaling::scale_percent(board, strong);
+    let evaluation = evaluation as i32 * scale_percent as i32 / 100;
+
+    (BoardScore::evaluation(evaluation as i16), scale_percent)
+}
+
+fn piece_values(board: &Board, params: &EngineParams) -> i16
 {
     let mut evaluation = 0;
 
@@ -21,18 +216,144 @@ pub fn evaluate_piece_values(board: &Board) -> BoardScore
         (nbr_red_pieces as i16) - (nbr_blue_pieces as i16)
     };
 
-    // Queens are worth 900 centipawns
-    evaluation += 900 * piece_balance(Piece::Queen);
+    for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn] {
+        evaluation += params.piece_value(piece) * piece_balance(piece);
+    }
+
+    evaluation
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::search::Searcher;
+    use crate::stopconditions::StopConditions;
+
+    #[test]
+    #[cfg_attr(not(reference_checks), ignore = "the reference checks are only in debug builds")]
+    fn test_search_passes_reference_checks()
+    {
+        // The perft positions, with castling, en passant and promotions. The search checks every
+        // position that it evaluates against the reference implementations.
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in positions
+        {
+            let stop_conditions = StopConditions::new();
+            *stop_conditions.depth.get_mut() = 3;
+            let mut searcher = Searcher::new(&stop_conditions);
+            let result = searcher.search(Board::from_str(fen).unwrap(), 0);
+            assert!(result.best_move.is_some(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_evaluate_includes_trapped_pieces()
+    {
+        let terms = EvalTerms::default();
+        assert_eq!(evaluate(&Board::default(), terms, &EngineParams::default()), BoardScore::EVEN);
+
+        // White is a bishop for two pawns up, but the bishop on a7 is trapped
+        let position = Board::from_str("4k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(piece_values(&position, &EngineParams::default()), 100);
+        assert_eq!(evaluate(&position, terms, &EngineParams::default()), BoardScore::evaluation(-20));
+
+        let position = Board::from_str("4k3/B1p5/1p6/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(evaluate(&position, terms, &EngineParams::default()), BoardScore::evaluation(20));
+    }
+
+    #[test]
+    fn test_material_follows_params()
+    {
+        // White is a knight up, and tuning its value changes the evaluation by as much
+        let position = Board::from_str("4k3/pppp4/8/8/8/8/PPPP4/3NK3 w - - 0 1").unwrap();
+        let params = EngineParams::default();
+        let tuned = EngineParams { knight_value: params.knight_value + 25, ..params };
+        let difference = evaluate(&position, EvalTerms::default(), &tuned).raw() - evaluate(&position, EvalTerms::default(), &params).raw();
+        assert_eq!(difference, 25);
+        assert_eq!(breakdown(&position, EvalTerms::default(), &tuned).terms[0], ("material", tuned.knight_value));
+    }
+
+    #[test]
+    fn test_hanging_piece_term()
+    {
+        let with_term = EvalTerms { hanging_piece: true };
+
+        // The rook on d1 attacks the undefended queen on d5. The pawns keep the queen against rook
+        // from being scaled as an endgame.
+        let position = Board::from_str("4k3/7p/8/3q4/8/8/7P/3RK3 w - - 0 1").unwrap();
+        assert_eq!(evaluate(&position, EvalTerms::default(), &EngineParams::default()), BoardScore::evaluation(-400));
+        assert_eq!(evaluate(&position, with_term, &EngineParams::default()), BoardScore::evaluation(50));
+
+        // Nothing hangs in the start position, so the term changes nothing
+        assert_eq!(evaluate(&Board::default(), with_term, &EngineParams::default()), BoardScore::EVEN);
+    }
+
+    #[test]
+    fn test_evaluate_scales_endgames()
+    {
+        let terms = EvalTerms::default();
+
+        // Two knights can't force mate, from the point of view of either side
+        let position = Board::from_str("8/8/4k3/8/8/2NN4/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(evaluate(&position, terms, &EngineParams::default()), BoardScore::evaluation(30));
+        let position = Board::from_str("8/8/4k3/8/8/2NN4/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(evaluate(&position, terms, &EngineParams::default()), BoardScore::evaluation(-30));
+
+        // A pawn more makes it winnable
+        let position = Board::from_str("8/8/4k3/8/8/2NN4/P7/4K3 w - - 0 1").unwrap();
+        assert_eq!(evaluate(&position, terms, &EngineParams::default()), BoardScore::evaluation(700));
+    }
+
+    #[test]
+    fn test_breakdown_adds_up()
+    {
+        for terms in [EvalTerms::default(), EvalTerms { hanging_piece: true }]
+        {
+            for fen in crate::bench::BENCH_POSITIONS
+            {
+                let position = Board::from_str(fen).unwrap();
+                let breakdown = breakdown(&position, terms, &EngineParams::default());
+                let sum = breakdown.terms.iter().map(|&(_, value)| value as i32).sum::<i32>();
+                assert_eq!(breakdown.score, evaluate(&position, terms, &EngineParams::default()), "{fen}");
+                assert_eq!(breakdown.score, BoardScore::evaluation((sum * breakdown.scale_percent as i32 / 100) as i16), "{fen}");
+                assert_eq!(breakdown.terms.len(), if terms.hanging_piece { 5 } else { 4 });
+            }
+        }
+
+        // The bishop on a7 is trapped, and the two knights are scaled down
+        let trapped_bishop = breakdown(&Board::from_str("4k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1").unwrap(), EvalTerms::default(), &EngineParams::default());
+        assert_eq!(trapped_bishop.terms[0], ("material", 100));
+        assert_eq!(trapped_bishop.scale_percent, 100);
+        let two_knights = breakdown(&Board::from_str("8/8/4k3/8/8/2NN4/8/4K3 w - - 0 1").unwrap(), EvalTerms::default(), &EngineParams::default());
+        assert_eq!(two_knights.scale_percent, 5);
+    }
 
-    // Rooks are worth 500 centipawns
-    evaluation += 500 * piece_balance(Piece::Rook);
+    #[test]
+    fn test_game_phase()
+    {
+        let start_position = Board::default();
+        assert_eq!(phase_value(&start_position), 24);
+        assert_eq!(game_phase(&start_position, 1), GamePhase::Opening);
 
-    // Knights and bishops are worth 300 centipawns
-    evaluation += 300 * piece_balance(Piece::Knight);
-    evaluation += 300 * piece_balance(Piece::Bishop);
+        // Full material, but well past the opening moves
+        assert_eq!(game_phase(&start_position, 25), GamePhase::Middlegame);
 
-    // Pawns are worth 100 centipawns
-    evaluation += 100 * piece_balance(Piece::Pawn);
+        let middlegame = Board::from_str("r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 12").unwrap();
+        assert_eq!(phase_value(&middlegame), 22);
+        assert_eq!(game_phase(&middlegame, 12), GamePhase::Middlegame);
 
-    BoardScore::evaluation(evaluation)
+        let pawn_endgame = Board::from_str("8/5k2/8/4P3/8/8/5K2/8 w - - 0 1").unwrap();
+        assert_eq!(phase_value(&pawn_endgame), 0);
+        assert_eq!(game_phase(&pawn_endgame, 60), GamePhase::Endgame);
+    }
 }
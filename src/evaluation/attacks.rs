@@ -0,0 +1,103 @@
+//! Squares attacked by each side, computed once per evaluation and shared by the terms that need them
+//!
+//! Pins are not taken into account: a pinned piece still counts as attacking the squares it could
+//! move to if it weren't pinned.
+
+use chess::{BitBoard, Board, Color, Piece};
+
+pub struct AttackMaps
+{
+    /// Squares attacked by the pieces of each type, indexed by color and then piece
+    by_piece: [[BitBoard; chess::NUM_PIECES]; chess::NUM_COLORS],
+    /// Squares attacked by any piece, indexed by color
+    all: [BitBoard; chess::NUM_COLORS],
+    /// Squares attacked by at least two pieces, indexed by color
+    double: [BitBoard; chess::NUM_COLORS],
+}
+
+impl AttackMaps
+{
+    pub fn new(board: &Board) -> Self
+    {
+        let mut maps = AttackMaps {
+            by_piece: [[chess::EMPTY; chess::NUM_PIECES]; chess::NUM_COLORS],
+            all: [chess::EMPTY; chess::NUM_COLORS],
+            double: [chess::EMPTY; chess::NUM_COLORS],
+        };
+
+        let occupied = *board.combined();
+        for color in chess::ALL_COLORS
+        {
+            let own = board.color_combined(color);
+            for piece in chess::ALL_PIECES
+            {
+                for square in board.pieces(piece) & own
+                {
+                    let attacks = match piece
+                    {
+                        Piece::Pawn => chess::get_pawn_attacks(square, color, !chess::EMPTY),
+                        Piece::Knight => chess::get_knight_moves(square),
+                        Piece::Bishop => chess::get_bishop_moves(square, occupied),
+                        Piece::Rook => chess::get_rook_moves(square, occupied),
+                        Piece::Queen => chess::get_bishop_moves(square, occupied) | chess::get_rook_moves(square, occupied),
+                        Piece::King => chess::get_king_moves(square),
+                    };
+
+                    maps.double[color.to_index()] |= maps.all[color.to_index()] & attacks;
+                    maps.all[color.to_index()] |= attacks;
+                    maps.by_piece[color.to_index()][piece.to_index()] |= attacks;
+                }
+            }
+        }
+
+        maps
+    }
+
+    /// Squares attacked by any piece of `color`
+    pub fn attacked(&self, color: Color) -> BitBoard
+    {
+        self.all[color.to_index()]
+    }
+
+    /// Squares attacked by at least two pieces of `color`
+    pub fn attacked_twice(&self, color: Color) -> BitBoard
+    {
+        self.double[color.to_index()]
+    }
+
+    /// Squares attacked by the pieces of type `piece` and color `color`
+    pub fn attacked_by(&self, color: Color, piece: Piece) -> BitBoard
+    {
+        self.by_piece[color.to_index()][piece.to_index()]
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+    use chess::Square;
+
+    use super::*;
+
+    #[test]
+    fn test_attack_maps()
+    {
+        // The e5 pawn is attacked by the knight on f3 and the rook on e1, and defended by the d6 pawn
+        let board = Board::from_str("4k3/8/3p4/4p3/8/5N2/8/4RK2 w - - 0 1").unwrap();
+        let maps = AttackMaps::new(&board);
+        let e5 = BitBoard::from_square(Square::E5);
+
+        assert_ne!(maps.attacked(Color::White) & e5, chess::EMPTY);
+        assert_ne!(maps.attacked_twice(Color::White) & e5, chess::EMPTY);
+        assert_ne!(maps.attacked_by(Color::White, Piece::Knight) & e5, chess::EMPTY);
+        assert_ne!(maps.attacked_by(Color::White, Piece::Rook) & e5, chess::EMPTY);
+        assert_eq!(maps.attacked_by(Color::White, Piece::King) & e5, chess::EMPTY);
+
+        assert_ne!(maps.attacked_by(Color::Black, Piece::Pawn) & e5, chess::EMPTY);
+        assert_eq!(maps.attacked_twice(Color::Black) & e5, chess::EMPTY);
+
+        // Sliders stop at the first piece in the way
+        assert_eq!(maps.attacked_by(Color::White, Piece::Rook) & BitBoard::from_square(Square::E6), chess::EMPTY);
+    }
+}
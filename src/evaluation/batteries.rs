@@ -0,0 +1,179 @@
+//! Pressure of heavy pieces lined up against the enemy
+//!
+//! Two rooks, or a rook and a queen, that stand on the same file without anything in between form a
+//! battery. On a file without own pawns it is ready to break through, and more so when the file leads
+//! into the enemy king's zone. A queen backed up by a bishop on a diagonal that reaches the enemy
+//! king's zone is a mating threat of its own.
+//!
+//! The attacks that matter come before the endgame, so the bonuses are tapered towards the middlegame.
+
+use chess::{BitBoard, Board, Color, Piece, Square};
+use crate::geometry;
+use super::{phase_value, taper};
+
+/// Bonus for two heavy pieces on the same file without own pawns, as (middlegame, endgame)
+const FILE_BATTERY_BONUS: (i16, i16) = (20, 5);
+
+/// Extra bonus for a file battery whose file passes through the enemy king's zone
+const KING_FILE_BONUS: (i16, i16) = (20, 0);
+
+/// Bonus for a queen and a bishop on a diagonal that reaches the enemy king's zone
+const QUEEN_BISHOP_BONUS: (i16, i16) = (15, 0);
+
+/// The bonus in centipawns for the batteries of `color`
+pub fn bonus(board: &Board, color: Color) -> i16
+{
+    let (file_batteries, king_files) = file_batteries(board, color);
+    let queen_bishop_batteries = queen_bishop_batteries(board, color);
+    if file_batteries == 0 && queen_bishop_batteries == 0 {
+        return 0;
+    }
+
+    let phase = phase_value(board);
+    file_batteries as i16 * taper(FILE_BATTERY_BONUS, phase)
+        + king_files as i16 * taper(KING_FILE_BONUS, phase)
+        + queen_bishop_batteries as i16 * taper(QUEEN_BISHOP_BONUS, phase)
+}
+
+/// The number of file batteries of `color`, and how many of them are on files through the enemy
+/// king's zone
+fn file_batteries(board: &Board, color: Color) -> (u32, u32)
+{
+    let own = board.color_combined(color);
+    let heavy_pieces = (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) & own;
+    if heavy_pieces.popcnt() < 2 {
+        return (0, 0);
+    }
+
+    let own_pawns = board.pieces(Piece::Pawn) & own;
+    let enemy_king_zone = geometry::king_zone(board.king_square(!color));
+    let occupied = *board.combined();
+
+    let (mut batteries, mut king_files) = (0, 0);
+    for piece in heavy_pieces
+    {
+        let file = geometry::file_mask(piece);
+        if own_pawns & file != chess::EMPTY {
+            continue;
+        }
+
+        // Each pair is counted from the piece on the lower square
+        let partners = chess::get_rook_moves(piece, occupied) & file & heavy_pieces;
+        for partner in partners
+        {
+            if partner.to_index() < piece.to_index() {
+                continue;
+            }
+            batteries += 1;
+            if file & enemy_king_zone != chess::EMPTY {
+                king_files += 1;
+            }
+        }
+    }
+    (batteries, king_files)
+}
+
+/// The number of queens of `color` backed up by a bishop on a diagonal that reaches the enemy king's
+/// zone, through whatever stands in front of the battery
+fn queen_bishop_batteries(board: &Board, color: Color) -> u32
+{
+    let own = board.color_combined(color);
+    let queens = board.pieces(Piece::Queen) & own;
+    let bishops = board.pieces(Piece::Bishop) & own;
+    let enemy_king_zone = geometry::king_zone(board.king_square(!color));
+    let occupied = *board.combined();
+
+    let mut batteries = 0;
+    for queen in queens
+    {
+        for bishop in chess::get_bishop_moves(queen, occupied) & bishops
+        {
+            // Each piece sees along the diagonal through the other, as far as the first piece beyond
+            let line = diagonal_through(queen, bishop);
+            let reach = chess::get_bishop_moves(queen, occupied ^ BitBoard::from_square(bishop))
+                | chess::get_bishop_moves(bishop, occupied ^ BitBoard::from_square(queen));
+            if reach & line & enemy_king_zone != chess::EMPTY {
+                batteries += 1;
+            }
+        }
+    }
+    batteries
+}
+
+/// The diagonal that both squares are on, which they must share
+fn diagonal_through(a: Square, b: Square) -> BitBoard
+{
+    let diagonal = geometry::diagonal_mask(a);
+    if diagonal & BitBoard::from_square(b) != chess::EMPTY {
+        diagonal
+    } else {
+        geometry::anti_diagonal_mask(a)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn white_and_black(fen: &str) -> (i16, i16)
+    {
+        let board = Board::from_str(fen).unwrap();
+        (bonus(&board, Color::White), bonus(&board, Color::Black))
+    }
+
+    #[test]
+    fn test_file_batteries()
+    {
+        // Doubled rooks on the open d-file
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1").unwrap();
+        assert_eq!(file_batteries(&board, Color::White), (1, 0));
+        assert_eq!(file_batteries(&board, Color::Black), (0, 0));
+        assert!(bonus(&board, Color::White) > 0);
+
+        // A queen in front of a rook on the half-open g-file, which leads to the king
+        let board = Board::from_str("6k1/5p1p/6p1/8/8/8/5PQP/6RK w - - 0 1").unwrap();
+        assert_eq!(file_batteries(&board, Color::White), (1, 1));
+
+        // The same battery away from the king counts for less
+        let (at_king, _) = white_and_black("6k1/5p1p/6p1/8/8/8/5PQP/6RK w - - 0 1");
+        let (away, _) = white_and_black("6k1/5p1p/6p1/8/8/8/3Q1P1P/3R3K w - - 0 1");
+        assert!(at_king > away && away > 0, "{at_king} at the king, {away} away from it");
+
+        // Three heavy pieces in a row make two batteries
+        let board = Board::from_str("6k1/5ppp/8/8/3Q4/8/3R1PPP/3R2K1 w - - 0 1").unwrap();
+        assert_eq!(file_batteries(&board, Color::White), (2, 0));
+    }
+
+    #[test]
+    fn test_queen_bishop_battery()
+    {
+        // The bishop on c2 backs up the queen on d3, aiming at h7
+        let board = Board::from_str("6k1/5ppp/8/8/8/3Q4/2B2PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(queen_bishop_batteries(&board, Color::White), 1);
+        assert!(bonus(&board, Color::White) > 0);
+
+        // With the queen behind the bishop, it's a battery all the same
+        let board = Board::from_str("6k1/5ppp/8/8/4B3/3Q4/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(queen_bishop_batteries(&board, Color::White), 1);
+
+        // On a diagonal that misses the king, it's not
+        let board = Board::from_str("6k1/5ppp/8/8/8/3Q4/4BPPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(queen_bishop_batteries(&board, Color::White), 0);
+    }
+
+    #[test]
+    fn test_blocked_by_own_pawn()
+    {
+        // The pawn on d2 stands between the rook and the queen
+        assert_eq!(white_and_black("6k1/5ppp/8/8/8/3Q4/3P1PPP/3R2K1 w - - 0 1"), (0, 0));
+
+        // The pawn on e4 blocks the diagonal to the king
+        assert_eq!(white_and_black("6k1/5ppp/8/8/4P3/3Q4/2B2PPP/6K1 w - - 0 1"), (0, 0));
+
+        // Nothing lines up in the start position
+        assert_eq!(white_and_black("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), (0, 0));
+    }
+}
@@ -0,0 +1,521 @@
+//! Neural network evaluation, in the style of HalfKP: one hidden layer fed by features that pair the
+//! king square of one side with the square of another piece
+//!
+//! Each side has its own view of the board, with the board mirrored vertically for Black, and its
+//! own half of the hidden layer, the accumulator. The accumulator is the sum of the feature weights
+//! of all pieces on the board, so after a move it only needs the weights of the pieces that moved
+//! added and removed. A king move changes every feature of its own side, and refreshes that half
+//! from scratch.
+//!
+//! There is no network shipped with the engine. The classical evaluation is used unless one is
+//! loaded with the EvalFile option.
+//!
+//! # File format
+//!
+//! All numbers are little-endian.
+//!
+//! | Field           | Type  | Count                                      |
+//! |-----------------|-------|--------------------------------------------|
+//! | Magic           | bytes | 4, `CENN`                                  |
+//! | Format version  | u32   | 1, currently 1                             |
+//! | Hidden size `H` | u32   | 1                                          |
+//! | Feature weights | i16   | `INPUTS * H`, feature by feature           |
+//! | Feature biases  | i16   | `H`                                        |
+//! | Output weights  | i16   | `2 * H`, the side to move's half first     |
+//! | Output bias     | i32   | 1                                          |
+
+use std::fs;
+use std::path::Path;
+
+use chess::{Board, Color, Piece, Square};
+use crate::geometry;
+use crate::score::BoardScore;
+
+const MAGIC: &[u8; 4] = b"CENN";
+const FORMAT_VERSION: u32 = 1;
+
+/// Pawns to queens of both colors. Kings are not features, only the king square of the side whose
+/// view it is.
+const PIECE_KINDS: usize = 10;
+
+/// Number of features: every king square for every kind of piece on every square
+pub const INPUTS: usize = 64 * PIECE_KINDS * 64;
+
+/// Largest hidden layer the loader accepts, to keep a corrupt file from allocating gigabytes
+const MAX_HIDDEN_SIZE: usize = 2048;
+
+/// Hidden layer activations are clipped to 0..=QA, and the output weights are in units of 1/QB
+const QA: i32 = 255;
+const QB: i32 = 64;
+
+/// Centipawns per unit of network output
+const EVAL_SCALE: i32 = 400;
+
+/// Number of accumulator values handled together. Loops over chunks of this size compile to SIMD
+/// instructions where available, and the rest of a hidden layer that is not a multiple of it is
+/// handled one value at a time.
+const CHUNK_SIZE: usize = 16;
+
+pub struct Network
+{
+    pub(super) hidden_size: usize,
+    /// `INPUTS` rows of `hidden_size` weights each
+    pub(super) feature_weights: Vec<i16>,
+    pub(super) feature_biases: Vec<i16>,
+    /// The weights for the side to move, followed by those for the other side
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network
+{
+    /// Load a network from a file in the format described in the module documentation
+    pub fn load(path: &Path) -> Result<Network, String>
+    {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String>
+    {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != MAGIC {
+            return Err("Not a network file".to_string());
+        }
+        let version = reader.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(format!("Unsupported network format version {version}"));
+        }
+        let hidden_size = reader.u32()? as usize;
+        if !(1..=MAX_HIDDEN_SIZE).contains(&hidden_size) {
+            return Err(format!("Hidden layer size {hidden_size} is out of range 1 to {MAX_HIDDEN_SIZE}"));
+        }
+
+        let network = Network {
+            hidden_size,
+            feature_weights: reader.i16s(INPUTS * hidden_size)?,
+            feature_biases: reader.i16s(hidden_size)?,
+            output_weights: reader.i16s(2 * hidden_size)?,
+            output_bias: reader.i32()?,
+        };
+
+        if !reader.bytes.is_empty() {
+            return Err(format!("{} bytes of trailing data after the network", reader.bytes.len()));
+        }
+        Ok(network)
+    }
+
+    /// Evaluate the position from the point of view of the side to move, with the accumulator of
+    /// that position
+    pub fn evaluate(&self, accumulator: &Accumulator, side_to_move: Color) -> BoardScore
+    {
+        let (own_weights, other_weights) = self.output_weights.split_at(self.hidden_size);
+        let own = &accumulator.values[side_to_move.to_index()];
+        let other = &accumulator.values[(!side_to_move).to_index()];
+
+        let output = clipped_dot(own, own_weights) + clipped_dot(other, other_weights) + self.output_bias as i64;
+        let centipawns = output * EVAL_SCALE as i64 / (QA * QB) as i64;
+        // Stay clear of the mate scores
+        BoardScore::evaluation(centipawns.clamp(-10_000, 10_000) as i16)
+    }
+
+    /// Evaluate a position from scratch, without an accumulator to start from
+    pub fn evaluate_board(&self, board: &Board) -> BoardScore
+    {
+        self.evaluate(&Accumulator::new(self, board), board.side_to_move())
+    }
+
+    fn feature_row(&self, feature: usize) -> &[i16]
+    {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+}
+
+/// The hidden layer before activation, for each side's view of the board
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Accumulator
+{
+    /// Indexed by the color whose view it is
+    pub(super) values: [Vec<i16>; 2],
+}
+
+impl Accumulator
+{
+    /// Compute the accumulator of a position from scratch
+    pub fn new(network: &Network, board: &Board) -> Accumulator
+    {
+        let values = [Color::White, Color::Black].map(|view| refresh(network, board, view));
+        Accumulator { values }
+    }
+
+    /// The accumulator after the move from `before` to `after`, computed from this one, which must be
+    /// the accumulator of `before`
+    ///
+    /// The pieces that changed are found by comparing the boards, which covers captures, en passant,
+    /// promotions and castling alike. So `after` can be any position, but the fewer pieces differ, the
+    /// cheaper the update.
+    pub fn after_move(&self, network: &Network, before: &Board, after: &Board) -> Accumulator
+    {
+        let changed = changed_squares(before, after);
+
+        let mut result = self.clone();
+        for view in [Color::White, Color::Black]
+        {
+            if before.king_square(view) != after.king_square(view)
+            {
+                result.values[view.to_index()] = refresh(network, after, view);
+                continue;
+            }
+
+            let king = after.king_square(view);
+            let values = &mut result.values[view.to_index()];
+            for square in changed
+            {
+                if let Some(feature) = feature_index(before, view, king, square) {
+                    subtract_row(values, network.feature_row(feature));
+                }
+                if let Some(feature) = feature_index(after, view, king, square) {
+                    add_row(values, network.feature_row(feature));
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The accumulators of the positions along the line that the search is in, one per ply, so that each
+/// one is computed from the one before it instead of from scratch
+#[derive(Default)]
+pub struct AccumulatorStack
+{
+    /// The position last evaluated at each ply, with its accumulator
+    entries: Vec<Option<(Board, Accumulator)>>,
+}
+
+impl AccumulatorStack
+{
+    /// Evaluate `board`, the position at `ply` of the line being searched, from the point of view of
+    /// the side to move
+    ///
+    /// The accumulator is updated from the one at the ply before, which is the parent unless the
+    /// parent wasn't evaluated, or else from the previous position at this ply. Any position gives
+    /// the right result, see `Accumulator::after_move`, only at a different cost.
+    pub fn evaluate(&mut self, network: &Network, board: &Board, ply: usize) -> BoardScore
+    {
+        if self.entries.len() <= ply {
+            self.entries.resize(ply + 1, None);
+        }
+
+        let closest = ply.checked_sub(1)
+            .and_then(|parent| self.entries[parent].as_ref())
+            .or(self.entries[ply].as_ref());
+        let accumulator = match closest
+        {
+            Some((before, accumulator)) if before == board => accumulator.clone(),
+            Some((before, accumulator)) => accumulator.after_move(network, before, board),
+            None => Accumulator::new(network, board),
+        };
+        #[cfg(reference_checks)]
+        super::reference::verify_accumulator(network, board, &accumulator.values);
+        let score = network.evaluate(&accumulator, board.side_to_move());
+        self.entries[ply] = Some((*board, accumulator));
+        score
+    }
+
+    /// Forget every accumulator, which must be done when the network changes
+    pub fn clear(&mut self)
+    {
+        self.entries.clear();
+    }
+}
+
+/// Squares where the piece differs between the boards, including squares that are empty on one
+fn changed_squares(before: &Board, after: &Board) -> chess::BitBoard
+{
+    let mut changed = chess::EMPTY;
+    for piece in chess::ALL_PIECES {
+        changed |= before.pieces(piece) ^ after.pieces(piece);
+    }
+    for color in chess::ALL_COLORS {
+        changed |= before.color_combined(color) ^ after.color_combined(color);
+    }
+    changed
+}
+
+/// The feature of the piece on `square`, as seen from the side of `view` with its king on `king`, or
+/// `None` if there is no piece there or it is a king
+fn feature_index(board: &Board, view: Color, king: Square, square: Square) -> Option<usize>
+{
+    let piece = board.piece_on(square)?;
+    if piece == Piece::King {
+        return None;
+    }
+    let color = board.color_on(square)?;
+
+    let kind = piece.to_index() + if color == view { 0 } else { PIECE_KINDS / 2 };
+    let king = geometry::relative_square(view, king).to_index();
+    let square = geometry::relative_square(view, square).to_index();
+    Some((king * PIECE_KINDS + kind) * 64 + square)
+}
+
+/// Half of the accumulator, computed from scratch
+fn refresh(network: &Network, board: &Board, view: Color) -> Vec<i16>
+{
+    let mut values = network.feature_biases.clone();
+    let king = board.king_square(view);
+    for square in *board.combined()
+    {
+        if let Some(feature) = feature_index(board, view, king, square) {
+            add_row(&mut values, network.feature_row(feature));
+        }
+    }
+    values
+}
+
+fn add_row(values: &mut [i16], row: &[i16])
+{
+    let mut values_chunks = values.chunks_exact_mut(CHUNK_SIZE);
+    let mut row_chunks = row.chunks_exact(CHUNK_SIZE);
+    for (values, row) in (&mut values_chunks).zip(&mut row_chunks)
+    {
+        for i in 0..CHUNK_SIZE {
+            values[i] = values[i].wrapping_add(row[i]);
+        }
+    }
+    for (value, weight) in values_chunks.into_remainder().iter_mut().zip(row_chunks.remainder()) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+fn subtract_row(values: &mut [i16], row: &[i16])
+{
+    let mut values_chunks = values.chunks_exact_mut(CHUNK_SIZE);
+    let mut row_chunks = row.chunks_exact(CHUNK_SIZE);
+    for (values, row) in (&mut values_chunks).zip(&mut row_chunks)
+    {
+        for i in 0..CHUNK_SIZE {
+            values[i] = values[i].wrapping_sub(row[i]);
+        }
+    }
+    for (value, weight) in values_chunks.into_remainder().iter_mut().zip(row_chunks.remainder()) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+/// Dot product of the clipped activations with the output weights
+fn clipped_dot(values: &[i16], weights: &[i16]) -> i64
+{
+    values.iter()
+        .zip(weights)
+        .map(|(&value, &weight)| ((value as i32).clamp(0, QA) * weight as i32) as i64)
+        .sum()
+}
+
+/// Reads the fields of a network file in order
+struct Reader<'a>
+{
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a>
+{
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String>
+    {
+        if self.bytes.len() < count {
+            return Err("Network file is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String>
+    {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String>
+    {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String>
+    {
+        let bytes = self.take(2 * count)?;
+        Ok(bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+    use chess::MoveGen;
+
+    use super::*;
+    use crate::bench::BENCH_POSITIONS;
+
+    /// Hidden layer size of the test network, not a multiple of `CHUNK_SIZE` so that the remainder
+    /// loops get tested too
+    const TEST_HIDDEN_SIZE: usize = 40;
+
+    /// A network file with small pseudo-random weights, the same every time
+    fn test_network_file() -> Vec<u8>
+    {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |range: i64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (2 * range as u64 + 1)) as i64 - range
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bytes.extend((TEST_HIDDEN_SIZE as u32).to_le_bytes());
+        for _ in 0..(INPUTS + 1) * TEST_HIDDEN_SIZE {
+            bytes.extend((next(16) as i16).to_le_bytes());
+        }
+        for _ in 0..2 * TEST_HIDDEN_SIZE {
+            bytes.extend((next(64) as i16).to_le_bytes());
+        }
+        bytes.extend((next(1000) as i32).to_le_bytes());
+        bytes
+    }
+
+    /// Evaluation straight from the definition of the network, feature by feature in 32 bits
+    fn reference_evaluate(network: &Network, board: &Board) -> BoardScore
+    {
+        let mut output = network.output_bias as i64;
+        for (half, view) in [board.side_to_move(), !board.side_to_move()].into_iter().enumerate()
+        {
+            for neuron in 0..network.hidden_size
+            {
+                let mut sum = network.feature_biases[neuron] as i32;
+                for square in chess::ALL_SQUARES {
+                    if let Some(feature) = feature_index(board, view, board.king_square(view), square) {
+                        sum += network.feature_weights[feature * network.hidden_size + neuron] as i32;
+                    }
+                }
+                let weight = network.output_weights[half * network.hidden_size + neuron] as i64;
+                output += sum.clamp(0, QA) as i64 * weight;
+            }
+        }
+        let centipawns = output * EVAL_SCALE as i64 / (QA * QB) as i64;
+        BoardScore::evaluation(centipawns.clamp(-10_000, 10_000) as i16)
+    }
+
+    #[test]
+    fn test_load_network()
+    {
+        let bytes = test_network_file();
+        let network = Network::from_bytes(&bytes).unwrap();
+        assert_eq!(network.hidden_size, TEST_HIDDEN_SIZE);
+
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(Network::from_bytes(b"").is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(Network::from_bytes(&wrong_magic).is_err());
+
+        let mut huge_hidden_size = bytes.clone();
+        huge_hidden_size[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Network::from_bytes(&huge_hidden_size).is_err());
+    }
+
+    #[test]
+    fn test_evaluation_matches_reference()
+    {
+        let network = Network::from_bytes(&test_network_file()).unwrap();
+        for fen in BENCH_POSITIONS
+        {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!(network.evaluate_board(&board), reference_evaluate(&network, &board), "{fen}");
+            // The same network always gives the same evaluation
+            assert_eq!(network.evaluate_board(&board), network.evaluate_board(&board));
+        }
+    }
+
+    #[test]
+    fn test_incremental_update_matches_refresh()
+    {
+        let network = Network::from_bytes(&test_network_file()).unwrap();
+        // Castling, en passant, promotions and king moves, among others
+        let positions = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        for fen in positions
+        {
+            let board = Board::from_str(fen).unwrap();
+            let accumulator = Accumulator::new(&network, &board);
+            for chess_move in MoveGen::new_legal(&board)
+            {
+                let after = board.make_move_new(chess_move);
+                let updated = accumulator.after_move(&network, &board, &after);
+                assert_eq!(updated, Accumulator::new(&network, &after), "{fen} {chess_move}");
+
+                // And one move further, from the updated accumulator
+                for reply in MoveGen::new_legal(&after)
+                {
+                    let after_reply = after.make_move_new(reply);
+                    let updated = updated.after_move(&network, &after, &after_reply);
+                    assert_eq!(updated, Accumulator::new(&network, &after_reply), "{fen} {chess_move} {reply}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_accumulator_stack_matches_refresh()
+    {
+        let network = Network::from_bytes(&test_network_file()).unwrap();
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+
+        for fen in BENCH_POSITIONS
+        {
+            // Random lines from the position, backing up some plies now and then as the search does
+            let mut stack = AccumulatorStack::default();
+            let mut line = vec![Board::from_str(fen).unwrap()];
+            for _ in 0..300
+            {
+                state = crate::timeman::xorshift(state);
+                let board = *line.last().unwrap();
+                let moves = MoveGen::new_legal(&board).collect::<Vec<_>>();
+                if moves.is_empty() || state % 5 == 0 {
+                    line.truncate(1 + (state >> 8) as usize % line.len());
+                } else {
+                    line.push(board.make_move_new(moves[(state >> 8) as usize % moves.len()]));
+                }
+
+                let ply = line.len() - 1;
+                let board = line[ply];
+                let score = stack.evaluate(&network, &board, ply);
+                assert_eq!(stack.entries[ply].as_ref().map(|(_, accumulator)| accumulator), Some(&Accumulator::new(&network, &board)), "{board}");
+                assert_eq!(score, network.evaluate_board(&board), "{board}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(reference_checks), ignore = "the reference checks are only in debug builds")]
+    fn test_search_passes_reference_checks()
+    {
+        // The search checks every accumulator that it updates against the reference implementation
+        let network = std::sync::Arc::new(Network::from_bytes(&test_network_file()).unwrap());
+        for fen in &BENCH_POSITIONS[..5]
+        {
+            let stop_conditions = crate::stopconditions::StopConditions::new();
+            *stop_conditions.depth.get_mut() = 3;
+            let mut searcher = crate::search::Searcher::new(&stop_conditions);
+            searcher.set_network(Some(network.clone()));
+            let result = searcher.search(Board::from_str(fen).unwrap(), 0);
+            assert!(result.best_move.is_some(), "{fen}");
+        }
+    }
+}
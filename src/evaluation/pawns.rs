@@ -0,0 +1,176 @@
+//! Pawn structure terms that look ahead to the endgame: pawn majorities and candidate passed pawns
+//!
+//! A side with more pawns than the opponent on a wing can eventually create a passed pawn there, by
+//! advancing the majority and trading it off against the minority. A candidate passed pawn is the pawn
+//! that would become passed: nothing blocks it on its own file, and the enemy pawns that guard its way
+//! on the adjacent files can all be traded off against its own neighbours.
+//!
+//! Neither term counts pawns that are passed already. A wing where the opponent has no pawns at all
+//! holds passed pawns rather than a majority.
+//!
+//! The terms only depend on the pawns, so they could be cached by a hash of the pawns, see "Pawn hash
+//! table" in TODO.md. Both matter more the fewer pieces are left, and are tapered by `phase_value`.
+
+use chess::{BitBoard, Board, Color, File, Piece};
+use crate::geometry;
+use super::{phase_value, taper};
+
+/// Bonus for each wing where a side has more pawns than the opponent, who has pawns there too, as
+/// (middlegame, endgame)
+const MAJORITY_BONUS: (i16, i16) = (5, 20);
+
+/// Bonus for a candidate passed pawn by its rank as seen from its own side, as (middlegame, endgame).
+/// A pawn on the seventh rank is either passed or blocked, so it is never a candidate.
+const CANDIDATE_BONUS: [(i16, i16); 8] = [(0, 0), (2, 5), (3, 8), (5, 12), (8, 20), (12, 32), (0, 0), (0, 0)];
+
+/// The queenside and the kingside, files a-d and e-h
+const WINGS: [[File; 4]; 2] = [[File::A, File::B, File::C, File::D], [File::E, File::F, File::G, File::H]];
+
+/// The bonus in centipawns for the pawn majorities and candidate passed pawns of `color`
+pub fn structure_bonus(board: &Board, color: Color) -> i16
+{
+    let phase = phase_value(board);
+    let mut bonus = majorities(board, color) as i16 * taper(MAJORITY_BONUS, phase);
+    for pawn in candidate_passers(board, color)
+    {
+        let rank = geometry::relative_square(color, pawn).get_rank().to_index();
+        bonus += taper(CANDIDATE_BONUS[rank], phase);
+    }
+    bonus
+}
+
+/// The number of wings where `color` has more pawns than the opponent, who has at least one
+fn majorities(board: &Board, color: Color) -> u32
+{
+    let own_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+
+    let mut majorities = 0;
+    for wing in WINGS
+    {
+        let wing_mask = wing.into_iter().fold(chess::EMPTY, |mask, file| mask | chess::get_file(file));
+        let own = (own_pawns & wing_mask).popcnt();
+        let enemy = (enemy_pawns & wing_mask).popcnt();
+        if own > enemy && enemy > 0 {
+            majorities += 1;
+        }
+    }
+    majorities
+}
+
+/// The candidate passed pawns of `color`
+///
+/// A pawn is a candidate if it is not passed, no enemy pawn stands in front of it on its own file, and
+/// the enemy pawns in front of it on the adjacent files (the sentries) are no more than its own pawns
+/// on the adjacent files that are level with it or behind it (the helpers).
+fn candidate_passers(board: &Board, color: Color) -> BitBoard
+{
+    let own_pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+
+    let mut candidates = chess::EMPTY;
+    for pawn in own_pawns
+    {
+        let front = geometry::front_span(color, pawn);
+        let passed_mask = geometry::passed_pawn_mask(color, pawn);
+        let is_opposed = enemy_pawns & front != chess::EMPTY;
+        let sentries = enemy_pawns & passed_mask & !front;
+        if is_opposed || sentries == chess::EMPTY {
+            continue;
+        }
+
+        let helpers = own_pawns & chess::get_adjacent_files(pawn.get_file()) & !passed_mask;
+        if helpers.popcnt() >= sentries.popcnt() {
+            candidates |= BitBoard::from_square(pawn);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use chess::Square;
+
+    use super::*;
+
+    fn white_and_black(fen: &str, term: impl Fn(&Board, Color) -> i16) -> (i16, i16)
+    {
+        let board = Board::from_str(fen).unwrap();
+        (term(&board, Color::White), term(&board, Color::Black))
+    }
+
+    #[test]
+    fn test_queenside_majorities()
+    {
+        let structures = [
+            // Three against two on the queenside after an exchange in the centre
+            "4k3/pp3ppp/8/8/8/8/PPP2PPP/4K3 w - - 0 1",
+            "6k1/pp3ppp/8/8/8/2P5/PP3PPP/6K1 b - - 0 1",
+            // The majority on its way, with the c-pawn as the candidate
+            "6k1/5ppp/p7/1p6/1PP5/P7/5PPP/6K1 w - - 0 1",
+            // With pieces on the board
+            "r4rk1/pp3ppp/2n1bn2/8/8/2N1BN2/PPP2PPP/R4RK1 w - - 0 1",
+        ];
+
+        for fen in structures
+        {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!((majorities(&board, Color::White), majorities(&board, Color::Black)), (1, 0), "{fen}");
+            assert_eq!(candidate_passers(&board, Color::Black), chess::EMPTY, "{fen}");
+            assert_eq!(candidate_passers(&board, Color::White).popcnt(), 1, "{fen}");
+
+            let (white, black) = white_and_black(fen, structure_bonus);
+            assert!(white > 0 && black == 0, "{fen}: {white} against {black}");
+        }
+
+        // The majority counts for more with fewer pieces on the board
+        let (endgame, _) = white_and_black(structures[0], structure_bonus);
+        let (middlegame, _) = white_and_black(structures[3], structure_bonus);
+        assert!(endgame > middlegame, "{endgame} in the endgame, {middlegame} in the middlegame");
+    }
+
+    #[test]
+    fn test_protected_candidate()
+    {
+        // The c-pawn, protected by the b-pawn, can only be stopped by the d-pawn, which the b-pawn
+        // can be traded for
+        let board = Board::from_str("6k1/3p4/8/2P5/1P6/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(candidate_passers(&board, Color::White), BitBoard::from_square(Square::C5));
+        assert!(structure_bonus(&board, Color::White) > 0);
+
+        // With a second sentry, the b-pawn can't trade off both of them
+        let board = Board::from_str("6k1/1p1p4/8/2P5/1P6/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(candidate_passers(&board, Color::White), chess::EMPTY);
+
+        // Blocked on its own file, and already passed, are not candidates either
+        let board = Board::from_str("6k1/2pp4/8/2P5/1P6/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(candidate_passers(&board, Color::White), chess::EMPTY);
+        let board = Board::from_str("6k1/8/8/2P5/1P6/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(candidate_passers(&board, Color::White), chess::EMPTY);
+        assert_eq!(structure_bonus(&board, Color::White), 0);
+    }
+
+    #[test]
+    fn test_symmetric_structures()
+    {
+        let structures = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "4k3/pp3ppp/8/8/8/8/PP3PPP/4K3 w - - 0 1",
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/3P4/2N1PN2/PP2BPPP/R1BQ1RK1 w - - 0 1",
+        ];
+
+        for fen in structures
+        {
+            let (white, black) = white_and_black(fen, structure_bonus);
+            assert_eq!((white, black), (0, 0), "{fen}");
+        }
+
+        // Majorities on opposite wings cancel out
+        let (white, black) = white_and_black("4k3/ppp3pp/8/8/8/8/PP3PPP/4K3 w - - 0 1", structure_bonus);
+        assert!(white > 0);
+        assert_eq!(white, black);
+    }
+}
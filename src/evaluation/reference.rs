@@ -0,0 +1,149 @@
+//! Slow reference implementations of the evaluation terms and of what the evaluation keeps cached or
+//! updates incrementally
+//!
+//! These are written to be obviously correct rather than fast, and double as documentation of what
+//! each of them computes. In debug builds, which build.rs gives the `reference_checks` cfg, the fast
+//! evaluation is checked against them as the engine runs: the material balance and the material key
+//! at every classical evaluation, and the accumulator at every evaluation with a network. Release
+//! builds keep debug assertions on, but not these checks.
+//!
+//! The pawn structure terms have no cache to check yet, see "Pawn hash table" in TODO.md. The key of
+//! a cached pawn entry belongs here once they do.
+
+#![cfg_attr(not(reference_checks), allow(dead_code))]
+
+use chess::{Board, Color, Piece};
+use crate::params::EngineParams;
+use super::scaling::MaterialKey;
+
+/// Material balance in centipawns, from the point of view of the side to move
+///
+/// Looks at every square of the board in turn and adds or subtracts the value of the piece on it.
+pub fn material(board: &Board, params: &EngineParams) -> i16
+{
+    let mut result = 0;
+
+    for square in chess::ALL_SQUARES
+    {
+        if let (Some(piece), Some(color)) = (board.piece_on(square), board.color_on(square))
+        {
+            let value = params.piece_value(piece);
+
+            if color == board.side_to_move() {
+                result += value;
+            } else {
+                result -= value;
+            }
+        }
+    }
+
+    result
+}
+
+/// The material key of `board` with `strong` as the stronger side, see `MaterialKey`
+///
+/// Counts the pieces square by square. Four bits for each count, pawns first and queens last, for the
+/// stronger side and then for the weaker.
+pub fn material_key(board: &Board, strong: Color) -> u64
+{
+    let mut counts = [[0u64; 5]; 2];
+    for square in chess::ALL_SQUARES
+    {
+        let (Some(piece), Some(color)) = (board.piece_on(square), board.color_on(square)) else {
+            continue;
+        };
+        let index = match piece
+        {
+            Piece::Pawn => 0,
+            Piece::Knight => 1,
+            Piece::Bishop => 2,
+            Piece::Rook => 3,
+            Piece::Queen => 4,
+            Piece::King => continue,
+        };
+        let side = if color == strong { 0 } else { 1 };
+        counts[side][index] = (counts[side][index] + 1).min(15);
+    }
+
+    let mut key = 0;
+    for (i, count) in counts.iter().flatten().enumerate() {
+        key |= count << (4 * i);
+    }
+    key
+}
+
+/// Recompute the material balance and the material key from scratch, and panic if either differs
+/// from what the evaluation computed
+pub fn verify_evaluation(board: &Board, params: &EngineParams, material_balance: i16, strong: Color)
+{
+    let expected = material(board, params);
+    assert!(
+        material_balance == expected,
+        "Material mismatch in position {board}: evaluated {material_balance}, reference implementation gives {expected}",
+    );
+
+    let key = MaterialKey::new(board, strong).0;
+    let expected = material_key(board, strong);
+    assert!(
+        key == expected,
+        "Material key mismatch in position {board}: computed {key:#x}, reference implementation gives {expected:#x}",
+    );
+}
+
+/// The accumulator of `view` for `board`, see `nnue::Accumulator`
+///
+/// For each neuron of the hidden layer, adds the weight of every feature on the board to the bias in
+/// 32 bits, and wraps the sum to 16 bits as the accumulator does. A feature is a piece other than a
+/// king on a square, as seen from the side of `view` with the board mirrored vertically for Black,
+/// paired with the king square of `view`.
+#[cfg(feature = "nnue")]
+pub fn accumulator(network: &super::nnue::Network, board: &Board, view: Color) -> Vec<i16>
+{
+    // Pawns to queens of `view`, then of the other side
+    const PIECE_KINDS: usize = 10;
+
+    let mirror = |square: chess::Square| match view
+    {
+        Color::White => square.to_index(),
+        Color::Black => square.to_index() ^ 56,
+    };
+    let king = mirror(board.king_square(view));
+
+    let mut features = Vec::new();
+    for square in chess::ALL_SQUARES
+    {
+        if let (Some(piece), Some(color)) = (board.piece_on(square), board.color_on(square))
+        {
+            if piece != Piece::King {
+                let kind = piece.to_index() + if color == view { 0 } else { PIECE_KINDS / 2 };
+                features.push((king * PIECE_KINDS + kind) * 64 + mirror(square));
+            }
+        }
+    }
+
+    (0..network.hidden_size)
+        .map(|neuron| {
+            let mut sum = network.feature_biases[neuron] as i32;
+            for &feature in &features {
+                sum += network.feature_weights[feature * network.hidden_size + neuron] as i32;
+            }
+            sum as i16
+        })
+        .collect()
+}
+
+/// Recompute both halves of the accumulator of `board` from scratch, and panic if they differ from
+/// `values`, the halves that the search updated incrementally
+#[cfg(feature = "nnue")]
+pub fn verify_accumulator(network: &super::nnue::Network, board: &Board, values: &[Vec<i16>; 2])
+{
+    for view in [Color::White, Color::Black]
+    {
+        let expected = accumulator(network, board, view);
+        assert!(
+            values[view.to_index()] == expected,
+            "Accumulator mismatch in position {board} for the view of {view:?}: updated {:?}, reference implementation gives {expected:?}",
+            values[view.to_index()],
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Scaling of the evaluation in endgames that are harder to win than the material suggests
+//!
+//! Some material configurations rarely win however large the advantage looks: two knights can't
+//! force mate, and a rook against a minor piece is usually a draw without pawns. Their evaluation is
+//! scaled towards a draw, so that the search doesn't trade down into them while ahead.
+//!
+//! The scale factors are looked up by the material of both sides in `SCALE_TABLE`. Any configuration
+//! that is not in the table keeps its full evaluation.
+
+use chess::{Board, Color, Piece};
+
+/// Scale factors in percent, by the material of the stronger and the weaker side. The kings are
+/// written out for readability, but don't count.
+const SCALE_TABLE: &[(MaterialKey, i16)] = &[
+    // No pawns: a single minor piece can't mate at all, and two knights can't force it
+    (MaterialKey::from_letters("KN", "K"), 0),
+    (MaterialKey::from_letters("KB", "K"), 0),
+    (MaterialKey::from_letters("KNN", "K"), 5),
+    // No pawns: an extra piece that is usually not enough, or takes a long defence to convert
+    (MaterialKey::from_letters("KR", "KN"), 20),
+    (MaterialKey::from_letters("KR", "KB"), 15),
+    (MaterialKey::from_letters("KRN", "KR"), 15),
+    (MaterialKey::from_letters("KRB", "KR"), 25),
+    (MaterialKey::from_letters("KQ", "KR"), 60),
+    // A minor piece against a pawn can't win, and is itself lucky to draw
+    (MaterialKey::from_letters("KN", "KP"), 10),
+    (MaterialKey::from_letters("KB", "KP"), 10),
+    // A single pawn up with the same pieces is often not enough
+    (MaterialKey::from_letters("KRP", "KR"), 50),
+    (MaterialKey::from_letters("KBP", "KB"), 40),
+    (MaterialKey::from_letters("KNP", "KN"), 60),
+    (MaterialKey::from_letters("KBP", "KN"), 60),
+    (MaterialKey::from_letters("KNP", "KB"), 50),
+];
+
+/// No configuration in `SCALE_TABLE` has more pieces than this, kings included, so positions with
+/// more pieces don't need to be looked up
+const MAX_TABLE_PIECES: u32 = 5;
+
+/// Pieces in the order of their counts in a `MaterialKey`
+const KEY_PIECES: [Piece; 5] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// The material of both sides: the number of pawns, knights, bishops, rooks and queens of the stronger
+/// side, then the same for the weaker side, four bits each
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaterialKey(pub(super) u64);
+
+impl MaterialKey
+{
+    /// The material on `board`, with `strong` as the stronger side
+    pub fn new(board: &Board, strong: Color) -> Self
+    {
+        let mut key = 0;
+        for (side_idx, color) in [strong, !strong].into_iter().enumerate()
+        {
+            for (piece_idx, piece) in KEY_PIECES.into_iter().enumerate()
+            {
+                let count = (board.pieces(piece) & board.color_combined(color)).popcnt().min(15) as u64;
+                key |= count << (4 * (KEY_PIECES.len() * side_idx + piece_idx));
+            }
+        }
+        MaterialKey(key)
+    }
+
+    /// The material given as piece letters for each side, like "KRP" and "KR"
+    const fn from_letters(strong: &str, weak: &str) -> Self
+    {
+        MaterialKey(letter_counts(strong.as_bytes(), 0) | letter_counts(weak.as_bytes(), KEY_PIECES.len()))
+    }
+}
+
+/// The counts of the pieces in `letters`, shifted into place for the side starting at `first_index`
+const fn letter_counts(letters: &[u8], first_index: usize) -> u64
+{
+    let mut counts = 0;
+    let mut i = 0;
+    while i < letters.len()
+    {
+        let piece_idx = match letters[i]
+        {
+            b'P' => Some(0),
+            b'N' => Some(1),
+            b'B' => Some(2),
+            b'R' => Some(3),
+            b'Q' => Some(4),
+            b'K' => None,
+            _ => panic!("unknown piece letter"),
+        };
+        if let Some(piece_idx) = piece_idx {
+            counts += 1u64 << (4 * (first_index + piece_idx));
+        }
+        i += 1;
+    }
+    counts
+}
+
+/// Percentage of the evaluation to keep, when `strong` is the side that the evaluation favours
+pub fn scale_percent(board: &Board, strong: Color) -> i16
+{
+    if board.combined().popcnt() > MAX_TABLE_PIECES {
+        return 100;
+    }
+
+    let key = MaterialKey::new(board, strong);
+    SCALE_TABLE.iter()
+        .find(|(table_key, _)| *table_key == key)
+        .map_or(100, |&(_, percent)| percent)
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn white_scale(fen: &str) -> i16
+    {
+        scale_percent(&Board::from_str(fen).unwrap(), Color::White)
+    }
+
+    #[test]
+    fn test_scaled_configurations()
+    {
+        // Two knights
+        assert_eq!(white_scale("8/8/4k3/8/8/2NN4/8/4K3 w - - 0 1"), 5);
+        // A single minor piece
+        assert_eq!(white_scale("8/8/4k3/8/8/2B5/8/4K3 b - - 0 1"), 0);
+        // Rook against bishop, and queen against rook
+        assert_eq!(white_scale("8/8/4k3/4b3/8/8/8/R3K3 w - - 0 1"), 15);
+        assert_eq!(white_scale("8/8/4k3/4r3/8/8/8/Q3K3 w - - 0 1"), 60);
+        // Rook and pawn against rook
+        assert_eq!(white_scale("8/8/4k3/4r3/8/8/P7/R3K3 w - - 0 1"), 50);
+
+        // The table is read from the point of view of the stronger side
+        assert_eq!(scale_percent(&Board::from_str("8/8/4k3/4r3/8/8/8/Q3K3 w - - 0 1").unwrap(), Color::Black), 100);
+        // Winnable material is not scaled
+        assert_eq!(white_scale("8/8/4k3/8/8/8/8/R3K3 w - - 0 1"), 100);
+        assert_eq!(white_scale("8/8/4k3/8/8/2BN4/8/4K3 w - - 0 1"), 100);
+        assert_eq!(white_scale("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), 100);
+    }
+
+    #[test]
+    fn test_extra_pawn_restores_scale()
+    {
+        let with_and_without_pawn = [
+            ("8/8/4k3/8/8/2NN4/8/4K3 w - - 0 1", "8/8/4k3/8/8/2NN4/P7/4K3 w - - 0 1"),
+            ("8/8/4k3/4b3/8/8/8/R3K3 w - - 0 1", "8/8/4k3/4b3/8/8/P7/R3K3 w - - 0 1"),
+            ("8/8/4k3/4n3/8/8/8/R3K3 w - - 0 1", "8/8/4k3/4n3/8/8/P7/R3K3 w - - 0 1"),
+            ("8/8/4k3/4r3/8/8/8/Q3K3 w - - 0 1", "8/8/4k3/4r3/8/8/P7/Q3K3 w - - 0 1"),
+        ];
+
+        for (without_pawn, with_pawn) in with_and_without_pawn
+        {
+            assert!(white_scale(without_pawn) < 100, "{without_pawn}");
+            assert!(white_scale(with_pawn) >= 90, "{with_pawn}");
+        }
+    }
+
+    #[test]
+    fn test_scale_table()
+    {
+        for (i, &(key, percent)) in SCALE_TABLE.iter().enumerate()
+        {
+            assert!((0..=100).contains(&percent));
+
+            // Kings, plus the number of pieces in the key
+            let pieces = 2 + (0..2 * KEY_PIECES.len()).map(|j| (key.0 >> (4 * j)) & 15).sum::<u64>();
+            assert!(pieces <= MAX_TABLE_PIECES as u64, "entry {i} has {pieces} pieces");
+
+            assert!(SCALE_TABLE[..i].iter().all(|&(earlier, _)| earlier != key), "entry {i} is a duplicate");
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Threats: enemy pieces the side to move can win, judged from how many times they are attacked and
+//! defended rather than by searching
+//!
+//! Quiescence search finds the same captures and more, so where it is used the two count the same
+//! material. To keep the overlap small, only the single most valuable hanging piece counts, and only
+//! for part of its value.
+
+use chess::{BitBoard, Board, Color, Piece, Square};
+use crate::values;
+use super::attacks::AttackMaps;
+
+/// Share of the value of the hanging piece that is credited, in percent. The opponent is to move
+/// next and may well be able to save it.
+const HANGING_PIECE_SHARE_PERCENT: i16 = 50;
+
+/// Bonus in centipawns for the side to move, for the most valuable enemy piece that it attacks and
+/// that is insufficiently defended
+pub fn hanging_piece_bonus(board: &Board, attacks: &AttackMaps) -> i16
+{
+    let up = board.side_to_move();
+    let targets = board.color_combined(!up) & attacks.attacked(up);
+
+    // From the most valuable piece down, so the first hanging piece is the one that counts
+    for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn]
+    {
+        for square in board.pieces(piece) & targets
+        {
+            if is_hanging(piece, square, up, attacks) {
+                return values::piece_value(piece) * HANGING_PIECE_SHARE_PERCENT / 100;
+            }
+        }
+    }
+
+    0
+}
+
+/// Whether `attacker` can expect to win the `piece` on `square`, which it attacks: the piece is not
+/// defended, or it is attacked by something less valuable, or it has more attackers than defenders
+fn is_hanging(piece: Piece, square: Square, attacker: Color, attacks: &AttackMaps) -> bool
+{
+    let on_square = |squares: BitBoard| squares & BitBoard::from_square(square) != chess::EMPTY;
+
+    let is_defended = on_square(attacks.attacked(!attacker));
+    let is_attacked_by_lesser = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook].into_iter()
+        .filter(|&lesser| values::exchange_value(lesser) < values::exchange_value(piece))
+        .any(|lesser| on_square(attacks.attacked_by(attacker, lesser)));
+    let is_outnumbered = on_square(attacks.attacked_twice(attacker)) && !on_square(attacks.attacked_twice(!attacker));
+
+    !is_defended || is_attacked_by_lesser || is_outnumbered
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn bonus_for(fen: &str) -> i16
+    {
+        let board = Board::from_str(fen).unwrap();
+        hanging_piece_bonus(&board, &AttackMaps::new(&board))
+    }
+
+    #[test]
+    fn test_hanging_queen()
+    {
+        let queen_bonus = values::piece_value(Piece::Queen) * HANGING_PIECE_SHARE_PERCENT / 100;
+
+        // The queen on d5 is attacked by the rook on d1 and not defended
+        assert_eq!(bonus_for("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1"), queen_bonus);
+        // Defended by the king, but the rook is worth less
+        assert_eq!(bonus_for("8/8/3k4/3q4/8/8/8/3RK3 w - - 0 1"), queen_bonus);
+        // Only the side to move gets the bonus
+        assert_eq!(bonus_for("4k3/8/8/3q4/8/8/8/3RK3 b - - 0 1"), 0);
+        // Nothing attacked
+        assert_eq!(bonus_for("4k3/8/8/3q4/8/8/8/4K2R w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn test_defended_pieces()
+    {
+        // The knight on d5 is attacked by the rook and defended by a pawn
+        assert_eq!(bonus_for("4k3/8/4p3/3n4/8/8/8/3RK3 w - - 0 1"), 0);
+        // Attacked by a pawn, it hangs after all
+        assert_eq!(bonus_for("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1"), values::piece_value(Piece::Knight) / 2);
+        // The pawn on d5 is attacked twice and defended only once
+        assert_eq!(bonus_for("4k3/8/4p3/3p4/8/2N5/8/3RK3 w - - 0 1"), values::piece_value(Piece::Pawn) / 2);
+    }
+
+    #[test]
+    fn test_only_most_valuable_counts()
+    {
+        // Both the rook on a5 and the knight on h5 hang, but only the rook counts
+        assert_eq!(bonus_for("4k3/8/8/r6n/8/8/8/R3K2R w - - 0 1"), values::piece_value(Piece::Rook) / 2);
+    }
+}
@@ -0,0 +1,143 @@
+//! Penalties for pieces that are trapped, and therefore worth far less than their nominal value
+//!
+//! The patterns are narrow on purpose. A piece only counts as trapped if it stands on one of a few
+//! specific squares and also has (almost) no safe squares to go to, where squares attacked by enemy
+//! pawns are not safe.
+
+use chess::{BitBoard, Board, Color, Piece, Square};
+use crate::geometry;
+
+/// A bishop on a7 or h7 (as seen from its own side), shut in by an enemy pawn on b6 or g6
+const TRAPPED_BISHOP_PENALTY: i16 = 120;
+
+/// A knight in one of the enemy's corners with no safe square to retreat to
+const CORNERED_KNIGHT_PENALTY: i16 = 100;
+
+/// A rook in the corner, boxed in by its own king that can no longer castle on that side
+const BOXED_ROOK_PENALTY: i16 = 60;
+
+/// Total penalty in centipawns for the trapped pieces of `color`
+pub fn penalty(board: &Board, color: Color) -> i16
+{
+    let own = *board.color_combined(color);
+    let occupied = *board.combined();
+    let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(!color);
+    let unsafe_squares = pawn_attacks(enemy_pawns, !color);
+    let safe_mobility = |moves: BitBoard| (moves & !own & !unsafe_squares).popcnt();
+    let relative = |square| geometry::relative_square(color, square);
+
+    let mut penalty = 0;
+
+    for bishop in board.pieces(Piece::Bishop) & own
+    {
+        let blocking_square = if bishop == relative(Square::A7) {
+            relative(Square::B6)
+        } else if bishop == relative(Square::H7) {
+            relative(Square::G6)
+        } else {
+            continue;
+        };
+
+        let is_blocked = enemy_pawns & BitBoard::from_square(blocking_square) != chess::EMPTY;
+        if is_blocked && safe_mobility(chess::get_bishop_moves(bishop, occupied)) <= 1 {
+            penalty += TRAPPED_BISHOP_PENALTY;
+        }
+    }
+
+    for knight in board.pieces(Piece::Knight) & own
+    {
+        if knight != relative(Square::A8) && knight != relative(Square::H8) {
+            continue;
+        }
+
+        let retreats = chess::get_knight_moves(knight) & !occupied & !unsafe_squares;
+        if retreats == chess::EMPTY {
+            penalty += CORNERED_KNIGHT_PENALTY;
+        }
+    }
+
+    let king = board.king_square(color);
+    if king.get_rank() == relative(Square::A1).get_rank()
+    {
+        let castle_rights = board.castle_rights(color);
+        let king_file = king.get_file().to_index();
+
+        for rook in board.pieces(Piece::Rook) & own & geometry::rank_mask(king)
+        {
+            let rook_file = rook.get_file().to_index();
+            let is_boxed_in = if rook_file > king_file {
+                (5..=6).contains(&king_file) && !castle_rights.has_kingside()
+            } else {
+                (1..=3).contains(&king_file) && !castle_rights.has_queenside()
+            };
+
+            if is_boxed_in && safe_mobility(chess::get_rook_moves(rook, occupied)) <= 1 {
+                penalty += BOXED_ROOK_PENALTY;
+            }
+        }
+    }
+
+    penalty
+}
+
+/// All squares attacked by `pawns` of `color`
+fn pawn_attacks(pawns: BitBoard, color: Color) -> BitBoard
+{
+    pawns.fold(chess::EMPTY, |attacks, square| attacks | chess::get_pawn_attacks(square, color, !chess::EMPTY))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn penalty_for(fen: &str, color: Color) -> i16
+    {
+        penalty(&Board::from_str(fen).unwrap(), color)
+    }
+
+    #[test]
+    fn test_trapped_bishop()
+    {
+        assert_eq!(penalty_for("4k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1", Color::White), TRAPPED_BISHOP_PENALTY);
+        assert_eq!(penalty_for("4k3/5p1B/6p1/8/8/8/8/4K3 w - - 0 1", Color::White), TRAPPED_BISHOP_PENALTY);
+        assert_eq!(penalty_for("4k3/8/8/8/8/1P6/b1P5/4K3 b - - 0 1", Color::Black), TRAPPED_BISHOP_PENALTY);
+
+        // The pawn on b6 is not defended, so the bishop can take it and escape
+        assert_eq!(penalty_for("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1", Color::White), 0);
+        // No pawn on b6 to block the diagonal
+        assert_eq!(penalty_for("4k3/B1p5/8/8/8/8/8/4K3 w - - 0 1", Color::White), 0);
+        // A bishop on a7 in its own camp is not in the enemy's pawn chain
+        assert_eq!(penalty_for("4k3/b1p5/1p6/8/8/8/8/4K3 b - - 0 1", Color::Black), 0);
+    }
+
+    #[test]
+    fn test_cornered_knight()
+    {
+        // f7 is occupied, and g6 is covered by the pawns on f7 and h7
+        assert_eq!(penalty_for("6kN/5p1p/8/8/8/8/8/4K3 w - - 0 1", Color::White), CORNERED_KNIGHT_PENALTY);
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/P1P5/n3K3 b - - 0 1", Color::Black), CORNERED_KNIGHT_PENALTY);
+
+        // The knight can retreat to f7
+        assert_eq!(penalty_for("6kN/7p/8/8/8/8/8/4K3 w - - 0 1", Color::White), 0);
+        // A knight in its own corner is a different matter
+        assert_eq!(penalty_for("6kn/5p1p/8/8/8/8/8/4K3 b - - 0 1", Color::Black), 0);
+    }
+
+    #[test]
+    fn test_boxed_rook()
+    {
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1", Color::White), BOXED_ROOK_PENALTY);
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/PPP5/RK6 w - - 0 1", Color::White), BOXED_ROOK_PENALTY);
+        assert_eq!(penalty_for("5k1r/5ppp/8/8/8/8/8/4K3 b - - 0 1", Color::Black), BOXED_ROOK_PENALTY);
+
+        // The open h-file lets the rook out
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/5PP1/5K1R w - - 0 1", Color::White), 0);
+        // The king has not moved yet and can still castle
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1", Color::White), 0);
+        // The rook is on the other side of the king
+        assert_eq!(penalty_for("4k3/8/8/8/8/8/PPP2PPP/R4K2 w - - 0 1", Color::White), 0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Parsing the FENs that GUIs, scripts and files give, for the UCI client, the evaluation overrides
+//! and the hosts that embed the engine
+
+use std::str::FromStr;
+
+/// Parse a FEN, checking its layout before the chess crate gets to see it
+///
+/// The chess crate can be trusted with FENs that look right, but FENs from buggy or hostile GUIs
+/// must be turned away with an error rather than risk a panic. What GUIs and scripts commonly get
+/// slightly wrong is put right instead: the clocks may be left out, the fields may be separated by
+/// any whitespace, and an en passant square without a pawn that just moved past it is ignored.
+pub fn parse_fen(fen: &str) -> Result<chess::Board, String>
+{
+    let mut fields = fen.split_ascii_whitespace().collect::<Vec<_>>();
+    if !(4..=6).contains(&fields.len()) {
+        return Err(format!("Expected 4 to 6 fields in FEN, got {}", fields.len()));
+    }
+    // EPD and many scripts leave out the halfmove clock and the fullmove number
+    fields.extend(&["0", "1"][fields.len() - 4..]);
+
+    let ranks = fields[0].split('/').collect::<Vec<_>>();
+    if ranks.len() != 8 {
+        return Err(format!("Expected 8 ranks in FEN, got {}", ranks.len()));
+    }
+    for rank in &ranks
+    {
+        let mut files = 0;
+        for c in rank.chars()
+        {
+            match c
+            {
+                '1'..='8' => files += c as usize - '0' as usize,
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r' | 'q' | 'k' => files += 1,
+                _ => return Err(format!("Invalid character '{c}' in FEN")),
+            }
+        }
+        if files != 8 {
+            return Err(format!("Rank \"{rank}\" in FEN has {files} files, expected 8"));
+        }
+    }
+
+    // The chess crate looks up the kings while setting up the board
+    for (king, side) in [('K', "white"), ('k', "black")]
+    {
+        match fields[0].matches(king).count()
+        {
+            0 => return Err(format!("No {side} king in FEN")),
+            1 => { },
+            _ => return Err(format!("More than one {side} king in FEN")),
+        }
+    }
+    if ranks[0].contains(['P', 'p']) || ranks[7].contains(['P', 'p']) {
+        return Err("Pawn on the first or last rank in FEN".to_owned());
+    }
+
+    if !matches!(fields[1], "w" | "b") {
+        return Err(format!("Invalid side to move \"{}\" in FEN", fields[1]));
+    }
+    if fields[2] != "-" && !fields[2].chars().all(|c| "KQkq".contains(c)) {
+        return Err(format!("Invalid castling rights \"{}\" in FEN", fields[2]));
+    }
+    // A pawn of the side to move just passed the square, so it's behind the pawns of the other side
+    let en_passant_rank = if fields[1] == "w" { b'6' } else { b'3' };
+    if fields[3] != "-" && !matches!(fields[3].as_bytes(), &[b'a'..=b'h', rank] if rank == en_passant_rank) {
+        return Err(format!("Invalid en passant square \"{}\" in FEN with {} to move", fields[3], if fields[1] == "w" { "White" } else { "Black" }));
+    }
+    for (clock, name) in [(fields[4], "halfmove clock"), (fields[5], "fullmove number")]
+    {
+        if u32::from_str(clock).is_err() {
+            return Err(format!("Invalid {name} \"{clock}\" in FEN"));
+        }
+    }
+
+    // The chess crate only says that the position is invalid, so find out why for the common cases
+    let piece_at = |square: &str| {
+        let (file, rank) = (square.as_bytes()[0] - b'a', square.as_bytes()[1] - b'1');
+        let mut file_so_far = 0;
+        for c in ranks[7 - rank as usize].chars()
+        {
+            match c
+            {
+                '1'..='8' => file_so_far += c as u8 - b'0',
+                _ if file_so_far == file => return Some(c),
+                _ => file_so_far += 1,
+            }
+            if file_so_far > file {
+                return None;
+            }
+        }
+        None
+    };
+    if fields[3] != "-"
+    {
+        // The pawn that moved two squares must be in front of the square, and the square it came from
+        // empty. Otherwise the square says nothing about the last move, and the chess crate would allow
+        // capturing a pawn that isn't there.
+        let file = fields[3].as_bytes()[0] as char;
+        let (pawn_rank, from_rank, pawn) = if fields[1] == "w" { ('5', '7', 'p') } else { ('4', '2', 'P') };
+        let square = |rank| format!("{file}{rank}");
+        if piece_at(&square(pawn_rank)) != Some(pawn) || piece_at(fields[3]).is_some() || piece_at(&square(from_rank)).is_some() {
+            fields[3] = "-";
+        }
+    }
+    if fields[2] != "-"
+    {
+        for (right, king, rook) in [('K', "e1", "h1"), ('Q', "e1", "a1"), ('k', "e8", "h8"), ('q', "e8", "a8")]
+        {
+            let (king_piece, rook_piece) = if right.is_ascii_uppercase() { ('K', 'R') } else { ('k', 'r') };
+            if fields[2].contains(right) && (piece_at(king) != Some(king_piece) || piece_at(rook) != Some(rook_piece)) {
+                return Err(format!("Castling right '{right}' in FEN without the king on {king} and the rook on {rook}"));
+            }
+        }
+    }
+
+    chess::Board::from_str(&fields.join(" ")).map_err(|e| {
+        // With the other side to move, the side that can't move would be in check
+        let other_side = if fields[1] == "w" { "b" } else { "w" };
+        let turned = format!("{} {other_side} {} -", fields[0], fields[2]);
+        match chess::Board::from_str(&turned)
+        {
+            Ok(board) if *board.checkers() != chess::EMPTY => {
+                let side = if other_side == "w" { "White" } else { "Black" };
+                format!("{side} is in check but not to move in FEN")
+            }
+            _ => e.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_fen()
+    {
+        assert!(parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_ok());
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4K3 b - -").is_ok());
+
+        for fen in [
+            "",
+            "4k3/8/8/8/8/8/8/4K3",
+            "4k3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k4/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k2/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K9 w - - 0 1",
+            "4x3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w KX - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - e5 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d3 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 -1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1 extra",
+            "8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            // Well formed, but White could capture the king
+            "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            assert!(parse_fen(fen).is_err(), "{fen}");
+        }
+
+        // The common mistakes get specific reasons
+        for (fen, reason) in [
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", "No black king in FEN"),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", "More than one white king in FEN"),
+            ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", "Pawn on the first or last rank in FEN"),
+            ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", "Castling right 'K' in FEN without the king on e1 and the rook on h1"),
+            ("4k2r/8/8/8/8/8/8/4K3 w q - 0 1", "Castling right 'q' in FEN without the king on e8 and the rook on a8"),
+            ("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", "Black is in check but not to move in FEN"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d3 0 1", "Invalid en passant square \"d3\" in FEN with White to move"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0x 1", "Invalid halfmove clock \"0x\" in FEN"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 -1", "Invalid fullmove number \"-1\" in FEN"),
+        ] {
+            assert_eq!(parse_fen(fen).err().as_deref(), Some(reason), "{fen}");
+        }
+        assert!(parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").is_ok());
+    }
+
+    #[test]
+    fn test_parse_fen_recovers()
+    {
+        let start = chess::Board::default();
+        let after_e4 = parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let black_can_take = parse_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        for (fen, expected) in [
+            // No clocks, or only the halfmove clock
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", start),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0", start),
+            // Stray whitespace between and around the fields
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR  w  KQkq  -  0  1", start),
+            ("\trnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w\tKQkq - 0 1 ", start),
+            // A move number of 0, as some GUIs send
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", start),
+            // The en passant square of a pawn that can't be captured, with and without the clocks
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", after_e4),
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3", after_e4),
+            // An en passant square that no pawn has passed
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e3", parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap()),
+            ("4k3/8/8/8/3pP3/8/8/4K3 b - e3", black_can_take),
+        ] {
+            let parsed = parse_fen(fen);
+            assert_eq!(parsed.as_ref().map(chess::Board::get_hash), Ok(expected.get_hash()), "{fen}");
+            assert_eq!(parsed.unwrap().side_to_move(), expected.side_to_move(), "{fen}");
+        }
+
+        // A white pawn next to the square could otherwise take a black pawn that never was on d5
+        let no_pawn_passed = parse_fen("4k3/8/8/4P3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert_eq!(no_pawn_passed.en_passant(), None);
+        assert_eq!(chess::MoveGen::new_legal(&no_pawn_passed).len(), chess::MoveGen::new_legal(&parse_fen("4k3/8/8/4P3/8/8/8/4K3 w - - 0 1").unwrap()).len());
+        assert!(black_can_take.en_passant().is_some());
+    }
+}
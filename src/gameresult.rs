@@ -0,0 +1,363 @@
+//! The result of a finished game, and how it came about
+//!
+//! The search only knows the two ways a game ends on the board, as the scores of a position without
+//! legal moves: `BoardScore::MATED` for checkmate and `BoardScore::EVEN` for stalemate. `game_result`
+//! agrees with those, and also knows the draws that take the material or the game history into account.
+
+use chess::{BitBoard, Board, BoardStatus, Color, Piece};
+use crate::rootmoves::canonical_root;
+
+/// Plies without captures or pawn moves after which the game is drawn by the fifty-move rule
+const FIFTY_MOVE_PLIES: u32 = 100;
+
+/// The light squares of the board: b1, a2, and so on
+const LIGHT_SQUARES: BitBoard = BitBoard(0x55aa_55aa_55aa_55aa);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum GameResult
+{
+    WhiteWins,
+    BlackWins,
+    Draw(DrawReason),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DrawReason
+{
+    Stalemate,
+    /// Neither side has the material to checkmate
+    InsufficientMaterial,
+    FiftyMoves,
+    Threefold,
+    /// Declared a draw by whoever runs the game, rather than by the rules
+    Adjudication,
+}
+
+impl GameResult
+{
+    /// The result as written in PGN: "1-0", "0-1" or "1/2-1/2"
+    pub fn score(self) -> &'static str
+    {
+        match self
+        {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+}
+
+/// The result and its reason, like "1/2-1/2 {Stalemate}"
+impl std::fmt::Display for GameResult
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let reason = match self
+        {
+            GameResult::WhiteWins => "White mates",
+            GameResult::BlackWins => "Black mates",
+            GameResult::Draw(DrawReason::Stalemate) => "Stalemate",
+            GameResult::Draw(DrawReason::InsufficientMaterial) => "Insufficient material",
+            GameResult::Draw(DrawReason::FiftyMoves) => "Fifty-move rule",
+            GameResult::Draw(DrawReason::Threefold) => "Threefold repetition",
+            GameResult::Draw(DrawReason::Adjudication) => "Adjudication",
+        };
+        write!(f, "{} {{{reason}}}", self.score())
+    }
+}
+
+/// The result of the game if it is over in `board`, or `None` if it goes on
+///
+/// `halfmove_clock` is the number of plies since the last capture or pawn move, and `history` holds the
+/// hashes of the positions of the game up to and including `board`, as hashed by `canonical_root`.
+/// The fifty-move rule and threefold repetition are treated as if the draw was claimed right away.
+/// Checkmate takes precedence over both, even when it comes with the hundredth ply.
+///
+/// ```
+/// use std::str::FromStr;
+/// use engine::chess::Board;
+/// use engine::{canonical_root, game_result, DrawReason, GameResult};
+///
+/// let stalemate = Board::from_str("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+/// let history = [canonical_root(&stalemate).get_hash()];
+/// assert_eq!(game_result(&stalemate, 0, &history), Some(GameResult::Draw(DrawReason::Stalemate)));
+///
+/// let start = Board::default();
+/// assert_eq!(game_result(&start, 0, &[canonical_root(&start).get_hash()]), None);
+/// ```
+pub fn game_result(board: &Board, halfmove_clock: u32, history: &[u64]) -> Option<GameResult>
+{
+    match board.status()
+    {
+        BoardStatus::Checkmate => {
+            return Some(match board.side_to_move()
+            {
+                Color::White => GameResult::BlackWins,
+                Color::Black => GameResult::WhiteWins,
+            });
+        }
+        BoardStatus::Stalemate => return Some(GameResult::Draw(DrawReason::Stalemate)),
+        BoardStatus::Ongoing => { },
+    }
+
+    if has_insufficient_material(board) {
+        return Some(GameResult::Draw(DrawReason::InsufficientMaterial));
+    }
+    if halfmove_clock >= FIFTY_MOVE_PLIES {
+        return Some(GameResult::Draw(DrawReason::FiftyMoves));
+    }
+
+    let hash = canonical_root(board).get_hash();
+    if history.iter().filter(|&&h| h == hash).count() >= 3 {
+        return Some(GameResult::Draw(DrawReason::Threefold));
+    }
+
+    None
+}
+
+/// Whether no sequence of moves can lead to checkmate: there are no pawns, rooks or queens, and the
+/// pieces left are a single knight or bishop, or bishops that all stand on squares of one color
+fn has_insufficient_material(board: &Board) -> bool
+{
+    let mating_material = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    if mating_material != chess::EMPTY {
+        return false;
+    }
+
+    let knights = board.pieces(Piece::Knight).popcnt();
+    let bishops = *board.pieces(Piece::Bishop);
+    if knights + bishops.popcnt() <= 1 {
+        return true;
+    }
+    knights == 0 && (bishops & LIGHT_SQUARES == chess::EMPTY || bishops & !LIGHT_SQUARES == chess::EMPTY)
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+    use std::sync::atomic::Ordering;
+
+    use chess::ChessMove;
+    use crate::boardutil::PositionExt;
+    use crate::search::Searcher;
+    use crate::stopconditions::StopConditions;
+
+    use super::*;
+
+    /// Finished games in PGN, each ending with the reason as `GameResult` displays it, like
+    /// "{Stalemate} 1/2-1/2"
+    const FINISHED_GAMES: &str = include_str!("../tests/fixtures/finished_games.pgn");
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// A game of `FINISHED_GAMES`, with its moves in UCI notation
+    struct FinishedGame
+    {
+        event: String,
+        fen: String,
+        moves: String,
+        /// The result tag and the reason, as in "1/2-1/2 {Stalemate}"
+        expected: String,
+    }
+
+    /// Read the games of `FINISHED_GAMES`. Only the tags and the movetext that the fixture uses are
+    /// understood: no variations, and a single comment, before the result.
+    fn finished_games() -> Vec<FinishedGame>
+    {
+        let mut games = Vec::new();
+        let mut tags = std::collections::HashMap::new();
+        let mut movetext = String::new();
+
+        for line in FINISHED_GAMES.lines()
+        {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(tag) = line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']'))
+            {
+                let (name, value) = tag.split_once(' ').unwrap();
+                tags.insert(name.to_string(), value.trim_matches('"').to_string());
+                continue;
+            }
+            movetext.push_str(line);
+            movetext.push(' ');
+
+            let result = tags.get("Result").expect("the tags come before the moves");
+            if !movetext.trim_end().ends_with(result.as_str()) {
+                continue;
+            }
+
+            let (moves, rest) = movetext.split_once('{').expect("a comment gives the reason");
+            let (reason, _) = rest.split_once('}').unwrap();
+            let fen = tags.get("FEN").map_or(STARTPOS, String::as_str);
+            let mut board = Board::from_str(fen).unwrap();
+            let mut uci_moves = Vec::new();
+            for word in moves.split_ascii_whitespace()
+            {
+                // Move numbers, like "12." or "12..."
+                if word.trim_end_matches('.').bytes().all(|b| b.is_ascii_digit()) {
+                    continue;
+                }
+                let chess_move = crate::san::from_san(&board, word).unwrap_or_else(|e| panic!("{word} in {board}: {e}"));
+                board = board.make_move_new(chess_move);
+                uci_moves.push(chess_move.to_string());
+            }
+
+            games.push(FinishedGame {
+                event: tags["Event"].clone(),
+                fen: fen.to_string(),
+                moves: uci_moves.join(" "),
+                expected: format!("{result} {{{reason}}}"),
+            });
+            tags.clear();
+            movetext.clear();
+        }
+        games
+    }
+
+    /// Play `moves` from `fen`, keeping track of the halfmove clock and the history as the UCI client
+    /// does, and return the result of the game after each move
+    fn play(fen: &str, moves: &str) -> (Board, Vec<Option<GameResult>>)
+    {
+        let mut board = Board::from_str(fen).unwrap();
+        let mut halfmove_clock = fen.split_ascii_whitespace().nth(4).map_or(0, |n| n.parse().unwrap());
+        let mut history = vec![canonical_root(&board).get_hash()];
+        let mut results = Vec::new();
+
+        for word in moves.split_ascii_whitespace()
+        {
+            let chess_move = ChessMove::from_str(word).unwrap();
+            assert!(board.legal(chess_move), "{word} in {board}");
+            halfmove_clock = if board.resets_halfmove_clock(chess_move) { 0 } else { halfmove_clock + 1 };
+            board = board.make_move_new(chess_move);
+            history.push(canonical_root(&board).get_hash());
+            results.push(game_result(&board, halfmove_clock, &history));
+        }
+        (board, results)
+    }
+
+    #[test]
+    fn test_finished_games()
+    {
+        let games = finished_games();
+        assert_eq!(games.len(), 9);
+        for game in games
+        {
+            let (_, results) = play(&game.fen, &game.moves);
+            let (last, before) = results.split_last().unwrap();
+            assert_eq!(last.map(|result| result.to_string()), Some(game.expected), "{}", game.event);
+            assert!(before.iter().all(Option::is_none), "{}: {before:?}", game.event);
+        }
+    }
+
+    #[test]
+    fn test_search_agrees_on_game_over()
+    {
+        for game in finished_games()
+        {
+            let (board, results) = play(&game.fen, &game.moves);
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(2, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            let result = searcher.search(board, 0);
+
+            // The search only ends the game on the board. Repetitions are up to whoever claims them,
+            // and the rest is left to the GUI.
+            let expected_info = match results.last().unwrap().unwrap()
+            {
+                GameResult::WhiteWins | GameResult::BlackWins => Some("info depth 0 score mate 0"),
+                GameResult::Draw(DrawReason::Stalemate) => Some("info depth 0 score cp 0"),
+                _ => None,
+            };
+            match expected_info
+            {
+                Some(info) => {
+                    assert_eq!(result.best_move, None, "{}", game.event);
+                    assert_eq!(result.final_info, info, "{}", game.event);
+                }
+                None => assert!(result.best_move.is_some(), "{}", game.event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_agrees_on_repetitions()
+    {
+        // Before every move of a game, the search sees a draw by repetition in that move exactly when
+        // the game ends with it in threefold repetition
+        for game in finished_games()
+        {
+            let mut board = Board::from_str(&game.fen).unwrap();
+            let mut history = vec![canonical_root(&board).get_hash()];
+            let moves: Vec<_> = game.moves.split_ascii_whitespace().map(|m| ChessMove::from_str(m).unwrap()).collect();
+            for (index, &chess_move) in moves.iter().enumerate()
+            {
+                let stop_conditions = StopConditions::new();
+                let mut searcher = Searcher::new(&stop_conditions);
+                searcher.set_game_history(history.clone());
+
+                let is_last = index + 1 == moves.len();
+                let ends_in_repetition = is_last && game.expected.ends_with("{Threefold repetition}");
+                assert_eq!(searcher.is_repetition_draw(&board, &[chess_move]), ends_in_repetition, "{} at {chess_move}", game.event);
+
+                board = board.make_move_new(chess_move);
+                history.push(canonical_root(&board).get_hash());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fifty_move_rule()
+    {
+        let (_, results) = play("8/8/4k3/8/8/4K3/8/R7 w - - 98 80", "a1a2 e6e5 a2a3");
+        assert_eq!(results, [None, Some(GameResult::Draw(DrawReason::FiftyMoves)), Some(GameResult::Draw(DrawReason::FiftyMoves))]);
+
+        // A pawn move starts the count again
+        let (_, results) = play("8/8/4k3/8/8/4K3/P7/R7 w - - 99 80", "a2a3");
+        assert_eq!(results, [None]);
+
+        // Checkmate with the hundredth ply wins all the same
+        let (_, results) = play("7k/8/6K1/8/8/8/8/R7 w - - 99 80", "a1a8");
+        assert_eq!(results, [Some(GameResult::WhiteWins)]);
+    }
+
+    #[test]
+    fn test_insufficient_material()
+    {
+        let dead = [
+            "8/8/4k3/8/8/4K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/2B1K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/2N1K3/8/8 b - - 0 1",
+            // Bishops on squares of the same color, whichever side they belong to
+            "8/8/4k3/2b5/8/2B1K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/2B1K3/3B4/8 w - - 0 1",
+        ];
+        for fen in dead {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!(game_result(&board, 0, &[]), Some(GameResult::Draw(DrawReason::InsufficientMaterial)), "{fen}");
+        }
+
+        let alive = [
+            // Two knights can't force mate, but the opponent can walk into it
+            "8/8/4k3/8/8/2NNK3/8/8 w - - 0 1",
+            // Bishops on squares of different colors
+            "8/8/4k3/3b4/8/2B1K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/2N1K3/8/b7 w - - 0 1",
+            "8/8/4k3/8/8/4K3/P7/8 w - - 0 1",
+        ];
+        for fen in alive {
+            let board = Board::from_str(fen).unwrap();
+            assert_eq!(game_result(&board, 0, &[]), None, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_display()
+    {
+        assert_eq!(GameResult::WhiteWins.to_string(), "1-0 {White mates}");
+        assert_eq!(GameResult::Draw(DrawReason::Threefold).to_string(), "1/2-1/2 {Threefold repetition}");
+    }
+}
@@ -0,0 +1,339 @@
+//! Precomputed geometry of the board: distances between squares, and masks of lines, pawn spans and
+//! king zones. All tables are computed at compile time.
+//!
+//! ```
+//! use engine::chess::{Color, Square};
+//! use engine::geometry;
+//!
+//! assert_eq!(geometry::distance(Square::A1, Square::H8), 7);
+//! assert_eq!(geometry::manhattan_distance(Square::A1, Square::H8), 14);
+//! assert_eq!(geometry::file_mask(Square::E4).popcnt(), 8);
+//! assert_eq!(geometry::rank_mask(Square::E4).popcnt(), 8);
+//! assert_eq!(geometry::diagonal_mask(Square::A1).popcnt(), 8);
+//! assert_eq!(geometry::anti_diagonal_mask(Square::A1).popcnt(), 1);
+//! assert_eq!(geometry::front_span(Color::White, Square::E4).popcnt(), 4);
+//! assert_eq!(geometry::passed_pawn_mask(Color::Black, Square::A7).popcnt(), 10);
+//! assert!(geometry::is_in_front(Color::White, Square::E4, Square::E7));
+//! assert_eq!(geometry::relative_square(Color::Black, Square::A7), Square::A2);
+//! assert_eq!(geometry::king_zone(Square::H1).popcnt(), 4);
+//! ```
+
+use chess::{BitBoard, Color, Rank, Square};
+
+/// Distance in king moves between two squares
+static CHEBYSHEV_DISTANCE: [[u8; 64]; 64] = distance_table(false);
+
+/// Distance in rook moves of one square each between two squares
+static MANHATTAN_DISTANCE: [[u8; 64]; 64] = distance_table(true);
+
+static FILE_MASKS: [u64; 64] = line_masks(Line::File);
+static RANK_MASKS: [u64; 64] = line_masks(Line::Rank);
+static DIAGONAL_MASKS: [u64; 64] = line_masks(Line::Diagonal);
+static ANTI_DIAGONAL_MASKS: [u64; 64] = line_masks(Line::AntiDiagonal);
+
+/// Squares in front of a square on the same file, from the point of view of each color
+static FRONT_SPANS: [[u64; 64]; 2] = [span_masks(1, false), span_masks(-1, false)];
+
+/// Squares in front of a square on the same and adjacent files, from the point of view of each color.
+/// A pawn is passed if there are no enemy pawns in this mask.
+static PASSED_PAWN_MASKS: [[u64; 64]; 2] = [span_masks(1, true), span_masks(-1, true)];
+
+/// A square and all squares adjacent to it
+static KING_ZONES: [u64; 64] = king_zone_masks();
+
+#[derive(Clone, Copy)]
+enum Line
+{
+    File,
+    Rank,
+    /// From a1 towards h8
+    Diagonal,
+    /// From h1 towards a8
+    AntiDiagonal,
+}
+
+const fn file_of(index: usize) -> i32
+{
+    (index % 8) as i32
+}
+
+const fn rank_of(index: usize) -> i32
+{
+    (index / 8) as i32
+}
+
+const fn distance_table(manhattan: bool) -> [[u8; 64]; 64]
+{
+    let mut table = [[0; 64]; 64];
+    let mut a = 0;
+    while a < 64
+    {
+        let mut b = 0;
+        while b < 64
+        {
+            let file_distance = (file_of(a) - file_of(b)).abs();
+            let rank_distance = (rank_of(a) - rank_of(b)).abs();
+            table[a][b] = if manhattan {
+                (file_distance + rank_distance) as u8
+            } else if file_distance > rank_distance {
+                file_distance as u8
+            } else {
+                rank_distance as u8
+            };
+            b += 1;
+        }
+        a += 1;
+    }
+    table
+}
+
+const fn is_on_line(line: Line, a: usize, b: usize) -> bool
+{
+    match line
+    {
+        Line::File => file_of(a) == file_of(b),
+        Line::Rank => rank_of(a) == rank_of(b),
+        Line::Diagonal => file_of(a) - rank_of(a) == file_of(b) - rank_of(b),
+        Line::AntiDiagonal => file_of(a) + rank_of(a) == file_of(b) + rank_of(b),
+    }
+}
+
+const fn line_masks(line: Line) -> [u64; 64]
+{
+    let mut masks = [0; 64];
+    let mut a = 0;
+    while a < 64
+    {
+        let mut b = 0;
+        while b < 64
+        {
+            if is_on_line(line, a, b) {
+                masks[a] |= 1 << b;
+            }
+            b += 1;
+        }
+        a += 1;
+    }
+    masks
+}
+
+/// Masks of the squares ahead of each square, where ahead is towards higher ranks if `forward` is 1,
+/// and towards lower ranks if it is -1
+const fn span_masks(forward: i32, include_adjacent_files: bool) -> [u64; 64]
+{
+    let mut masks = [0; 64];
+    let mut a = 0;
+    while a < 64
+    {
+        let mut b = 0;
+        while b < 64
+        {
+            let file_distance = (file_of(a) - file_of(b)).abs();
+            let is_ahead = (rank_of(b) - rank_of(a)) * forward > 0;
+            let max_file_distance = if include_adjacent_files { 1 } else { 0 };
+            if is_ahead && file_distance <= max_file_distance {
+                masks[a] |= 1 << b;
+            }
+            b += 1;
+        }
+        a += 1;
+    }
+    masks
+}
+
+const fn king_zone_masks() -> [u64; 64]
+{
+    let distances = distance_table(false);
+    let mut masks = [0; 64];
+    let mut a = 0;
+    while a < 64
+    {
+        let mut b = 0;
+        while b < 64
+        {
+            if distances[a][b] <= 1 {
+                masks[a] |= 1 << b;
+            }
+            b += 1;
+        }
+        a += 1;
+    }
+    masks
+}
+
+/// The number of king moves needed to go from one square to another
+#[inline]
+pub fn distance(a: Square, b: Square) -> u8
+{
+    CHEBYSHEV_DISTANCE[a.to_index()][b.to_index()]
+}
+
+/// The sum of the file distance and the rank distance between two squares
+#[inline]
+pub fn manhattan_distance(a: Square, b: Square) -> u8
+{
+    MANHATTAN_DISTANCE[a.to_index()][b.to_index()]
+}
+
+/// All squares on the same file as `square`, including itself
+#[inline]
+pub fn file_mask(square: Square) -> BitBoard
+{
+    BitBoard(FILE_MASKS[square.to_index()])
+}
+
+/// All squares on the same rank as `square`, including itself
+#[inline]
+pub fn rank_mask(square: Square) -> BitBoard
+{
+    BitBoard(RANK_MASKS[square.to_index()])
+}
+
+/// All squares on the same a1-h8 direction diagonal as `square`, including itself
+#[inline]
+pub fn diagonal_mask(square: Square) -> BitBoard
+{
+    BitBoard(DIAGONAL_MASKS[square.to_index()])
+}
+
+/// All squares on the same h1-a8 direction diagonal as `square`, including itself
+#[inline]
+pub fn anti_diagonal_mask(square: Square) -> BitBoard
+{
+    BitBoard(ANTI_DIAGONAL_MASKS[square.to_index()])
+}
+
+/// The squares in front of `square` on the same file, as seen by `color`
+#[inline]
+pub fn front_span(color: Color, square: Square) -> BitBoard
+{
+    BitBoard(FRONT_SPANS[color.to_index()][square.to_index()])
+}
+
+/// The squares in front of `square` on the same and adjacent files, as seen by `color`. A pawn of
+/// `color` on `square` is passed if no enemy pawns are in this mask.
+#[inline]
+pub fn passed_pawn_mask(color: Color, square: Square) -> BitBoard
+{
+    BitBoard(PASSED_PAWN_MASKS[color.to_index()][square.to_index()])
+}
+
+/// Whether `square` is in front of a pawn of `color` on `pawn_square`, on the same file
+#[inline]
+pub fn is_in_front(color: Color, pawn_square: Square, square: Square) -> bool
+{
+    FRONT_SPANS[color.to_index()][pawn_square.to_index()] & (1 << square.to_index()) != 0
+}
+
+/// The square as seen from the side of `color`: unchanged for White, and mirrored vertically for
+/// Black, so that a7 for White corresponds to a2 for Black
+#[inline]
+pub fn relative_square(color: Color, square: Square) -> Square
+{
+    match color
+    {
+        Color::White => square,
+        Color::Black => Square::make_square(Rank::from_index(7 - square.get_rank().to_index()), square.get_file()),
+    }
+}
+
+/// The king's square and all squares adjacent to it
+#[inline]
+pub fn king_zone(square: Square) -> BitBoard
+{
+    BitBoard(KING_ZONES[square.to_index()])
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use chess::ALL_SQUARES;
+
+    use super::*;
+
+    fn file(square: Square) -> i32
+    {
+        square.get_file().to_index() as i32
+    }
+
+    fn rank(square: Square) -> i32
+    {
+        square.get_rank().to_index() as i32
+    }
+
+    fn contains(mask: BitBoard, square: Square) -> bool
+    {
+        mask & BitBoard::from_square(square) != chess::EMPTY
+    }
+
+    #[test]
+    fn test_distances()
+    {
+        for a in ALL_SQUARES {
+            for b in ALL_SQUARES {
+                let file_distance = (file(a) - file(b)).abs();
+                let rank_distance = (rank(a) - rank(b)).abs();
+                assert_eq!(distance(a, b) as i32, file_distance.max(rank_distance), "{a} {b}");
+                assert_eq!(manhattan_distance(a, b) as i32, file_distance + rank_distance, "{a} {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_masks()
+    {
+        for a in ALL_SQUARES {
+            for b in ALL_SQUARES {
+                assert_eq!(contains(file_mask(a), b), file(a) == file(b), "{a} {b}");
+                assert_eq!(contains(rank_mask(a), b), rank(a) == rank(b), "{a} {b}");
+                assert_eq!(contains(diagonal_mask(a), b), file(a) - file(b) == rank(a) - rank(b), "{a} {b}");
+                assert_eq!(contains(anti_diagonal_mask(a), b), file(a) - file(b) == rank(b) - rank(a), "{a} {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_pawn_masks()
+    {
+        for color in [Color::White, Color::Black] {
+            let forward = if color == Color::White { 1 } else { -1 };
+            for a in ALL_SQUARES {
+                for b in ALL_SQUARES {
+                    let is_ahead = (rank(b) - rank(a)) * forward > 0;
+                    let file_distance = (file(a) - file(b)).abs();
+                    let in_front = is_ahead && file_distance == 0;
+                    assert_eq!(contains(front_span(color, a), b), in_front, "{color:?} {a} {b}");
+                    assert_eq!(is_in_front(color, a, b), in_front, "{color:?} {a} {b}");
+                    assert_eq!(contains(passed_pawn_mask(color, a), b), is_ahead && file_distance <= 1, "{color:?} {a} {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_king_zones()
+    {
+        for a in ALL_SQUARES {
+            for b in ALL_SQUARES {
+                let is_near = (file(a) - file(b)).abs() <= 1 && (rank(a) - rank(b)).abs() <= 1;
+                assert_eq!(contains(king_zone(a), b), is_near, "{a} {b}");
+            }
+        }
+        assert_eq!(king_zone(Square::from_str("a1").unwrap()).popcnt(), 4);
+        assert_eq!(king_zone(Square::from_str("e4").unwrap()).popcnt(), 9);
+    }
+
+    #[test]
+    fn test_relative_square()
+    {
+        for square in ALL_SQUARES {
+            assert_eq!(relative_square(Color::White, square), square);
+            let mirrored = relative_square(Color::Black, square);
+            assert_eq!(file(mirrored), file(square));
+            assert_eq!(rank(mirrored), 7 - rank(square));
+        }
+        assert_eq!(relative_square(Color::Black, Square::from_str("a7").unwrap()), Square::from_str("a2").unwrap());
+    }
+}
@@ -2,30 +2,21 @@ use std::alloc;
 use std::alloc::Layout;
 use std::mem;
 use std::ptr;
-use chess::{Board, ChessMove};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use chess::{Board, ChessMove, Color};
 
 use crate::score::{BoardScore, BoundedScore};
 use crate::search;
 
+mod mapping;
+mod packedmove;
+pub mod snapshot;
+
 /*
- * Optimal hash entry in 16 bytes (could be packed to 15)
- * 8 bytes hash
- * 2 bytes BoardScore
- * 1 byte Exact, UpperBound, LowerBound packed with
- *        is node in use, full, half, quiescent, etc
- * 1 byte depth
- * 3 bytes Option<ChessMove> (1 byte alignment)
- *      (could be packed to 2 bytes if necessary:
- *          3 bits source file
- *          3 bits source rank
- *          3 bits target file
- *          3 bits target rank
- *          3 bits promotion (-, N, B, R, Q)
- *          1 bit Some/None)
- * 1 byte generation counter
- * 16 bytes total, 8 byte alignment
+ * A slot of the map is 16 bytes: the entry packed into 8 bytes of data, see `HashEntry::pack`, and 8
+ * bytes of key, which is the hash of the position XORed with the data.
  */
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub struct HashEntry
 {
     entry_type: HashEntryInfo,
@@ -41,14 +32,14 @@ pub struct HashEntry
 ///
 /// Semantically equivalent to this struct, but stores all of it in a single byte:
 ///
-/// ```
+/// ```ignore
 /// struct HashEntryInfo
 /// {
 ///     entry_type: HashEntryKind,
 ///     score_type: BoardScoreType,
 /// }
 /// ```
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 struct HashEntryInfo(u8);
 
 impl HashEntryInfo
@@ -123,7 +114,7 @@ impl HashEntryInfo
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum HashEntryKind
 {
     Unused, Deficient, Full,
@@ -188,6 +179,57 @@ impl HashEntry
     {
         self.best_move
     }
+
+    /// The entry without its hash in 64 bits: the entry info in bits 0-7, the best move as packed by
+    /// `packedmove::encode_move` in bits 8-23, the score in bits 24-39, the depth in bits 40-47 and
+    /// the generation in bits 48-55. An unused entry packs to zero.
+    fn pack(&self) -> u64
+    {
+        (self.entry_type.0 as u64) |
+            (packedmove::encode_move(self.best_move) as u64) << 8 |
+            (self.score.raw() as u16 as u64) << 24 |
+            (self.depth as u64) << 40 |
+            (self.generation as u64) << 48
+    }
+
+    /// The entry stored under `hash` as packed by `pack`
+    fn unpack(hash: u64, data: u64) -> Self
+    {
+        HashEntry {
+            entry_type: HashEntryInfo(data as u8),
+            hash,
+            // Nothing but moves written by encode_move is ever unpacked, see `Slot`
+            best_move: packedmove::decode_move((data >> 8) as u16).flatten(),
+            score: BoardScore::from_raw((data >> 24) as u16 as i16),
+            depth: (data >> 40) as u8,
+            generation: (data >> 48) as u8,
+        }
+    }
+}
+
+/// A slot of the map, which the threads of the search share
+///
+/// The slot holds an entry packed by `HashEntry::pack` in `data`, and its hash XORed with the data in
+/// `key`. Two threads writing the same slot at once may leave the key of one entry with the data of
+/// the other. The key then unpacks to a hash that neither entry was stored under, so the torn entry
+/// is never found. The data itself is always whole, so the fields make sense even then.
+struct Slot
+{
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// What the hash map holds about a position, as reported by `HashMap::probe`
+#[derive(Clone, Debug)]
+pub struct ProbeResult
+{
+    pub depth: search::Depth,
+    pub score: BoundedScore,
+    pub best_move: Option<ChessMove>,
+    /// Number of generations since the entry was written
+    pub age: u8,
+    /// Which of the `NUM_SLOTS_PER_HASH` candidate slots for the hash holds the entry
+    pub slot: usize,
 }
 
 /// A special purpose hash map for storing chess positions
@@ -201,90 +243,353 @@ impl HashEntry
 /// are filled, some form of purging is necessary. This purge primarily happens using the generation
 /// number: entries from older generations are purged in favor of newer ones. It also uses the depth
 /// number: entries of low depth are easier to recompute if necessary, so they are also candidates for
-/// purging.
+/// purging. In order of priority, the purge takes:
+///
+/// 1. Any entry from more than one generation ago
+/// 2. Any deficient entry from last generation
+/// 3. The full entry of lowest depth from last generation
+/// 4. The deficient entry of lowest depth from this generation
+/// 5. The full entry of lowest depth from this generation
+///
+/// Among entries from more than one generation ago, deficient and shallow entries still go first. The
+/// choice is a single pass over the `NUM_SLOTS_PER_HASH` candidate slots without allocating, so an
+/// insert into a saturated map costs little more than one into a map with free slots, which
+/// `bench --inserts` measures. That matters, since a long search keeps the map saturated.
+///
+/// ## Table pressure
+///
+/// Once `PRESSURE_HASHFULL` per mille of the map is filled in this generation, the shallow entries
+/// of depth `PRESSURE_MAX_DEPTH` or less, of which a search produces millions, would mostly evict
+/// deeper entries that took longer to compute. `insert` then only stores a shallow entry if it takes
+/// the place of an entry of even lower value, going by the purging priority, or if it holds an exact
+/// mate score. The skipped stores are counted in `skipped_stores`. Quiescence search stores no
+/// entries at all.
+///
+/// ## Generation wraparound
+///
+/// The generation number is a `u8` compared with wrapping arithmetic, so an entry that is 256
+/// generations old would look like it is from the current generation. To prevent this, every call to
+/// `new_generation` sweeps a chunk of the map, capping the age of the entries in it at `MAX_AGE`. The
+/// whole map is swept every `MAX_AGE` generations, so no entry ever gets older than `2 * MAX_AGE`.
 ///
-/// Hash collisions are not handled gracefully: should two positions have the same Zobrist
-/// hash, the wrong entry may be returned.
+/// ## Threads
+///
+/// The threads of a search share the map, and look up and insert entries without locking, see
+/// `Slot`. When two threads insert at once, one of the entries may get lost, or two entries for the
+/// same position may end up in different slots, which is harmless since a lookup finds one of them.
+/// Clearing the map and starting a new generation need it to themselves, between searches.
+///
+/// ## Collision detection
+///
+/// Should two positions have the same Zobrist hash, the entry of one is returned for the other. With
+/// collision detection on, see `set_collision_detection`, every slot also keeps a 32-bit check key of
+/// its position, see `check_key`, which is independent of the Zobrist hash. `get` compares it, and
+/// treats an entry whose check key differs as missing, counting it in `detected_collisions`. Entries
+/// inserted without their position, like those of snapshots, have no check key and are not checked.
+/// Since the check key is written after the slot, a lookup racing with an insert by another thread may
+/// count a collision that isn't one.
+///
+/// Two entries for the same position, which concurrent inserts may leave behind, are counted in
+/// `duplicate_entries` when an insert comes across them, and the second one is removed.
+///
+/// ## Mapped storage
+///
+/// A snapshot is an image of the slots, see `snapshot`. Loaded into an empty map of the same capacity,
+/// with the `mmap` feature, the snapshot file is mapped copy-on-write and the map uses it as its slots,
+/// in place of its own allocation, see `use_mapped_storage`. The entries are then read from the file as
+/// the search comes across them, and only the pages that the search writes to are copied into memory.
+/// Nothing is ever written back to the file, so dropping the map only unmaps it.
 pub struct HashMap
 {
-    pointer: ptr::NonNull<HashEntry>,
-    layout: Layout,
-    phantom_data: std::marker::PhantomData<[HashEntry]>,
+    pointer: ptr::NonNull<Slot>,
+    storage: Storage,
+    phantom_data: std::marker::PhantomData<[Slot]>,
 
-    count: usize,
+    count: AtomicUsize,
     capacity: usize,
+    /// Shallow entries not stored under table pressure in this generation, see "Table pressure"
+    skipped_stores: AtomicU64,
+    /// Check keys by slot, if collision detection is on, see "Collision detection"
+    check_keys: Option<Box<[AtomicU32]>>,
+    /// Lookups in this generation that found the entry of another position, see "Collision detection"
+    detected_collisions: AtomicU64,
+    /// Second entries for the same position found in this generation, see "Collision detection"
+    duplicate_entries: AtomicU64,
 
     generation: u8,
+    sweep_position: usize,
+}
+
+// SAFETY: The slots are only ever accessed through atomics, and so are the other fields that change
+// behind a shared reference. A mapping is only unmapped when the map is dropped.
+unsafe impl Send for HashMap {}
+unsafe impl Sync for HashMap {}
+
+/// Where the slots of a `HashMap` are
+enum Storage
+{
+    /// Memory of the map's own, allocated with this layout
+    Allocated(Layout),
+    /// A snapshot mapped into memory, see "Mapped storage"
+    Mapped(mapping::MappedFile),
 }
 
 const NUM_SLOTS_PER_HASH: usize = 4;
 
+/// Entries this many generations old or older are equally old for the purposes of purging
+const MAX_AGE: u8 = 32;
+
+/// Filled per mille of the map in this generation, from which shallow entries are only stored if they
+/// replace an entry of even lower value, see "Table pressure"
+const PRESSURE_HASHFULL: usize = 850;
+/// Entries of this depth or less are the shallow ones that table pressure keeps out
+const PRESSURE_MAX_DEPTH: u8 = 1;
+
 impl HashMap
 {
     /// Create a new hash map of a specific size
+    #[cfg(test)]
     pub fn new(megabytes: usize) -> Self
+    {
+        Self::try_new(megabytes).unwrap_or_else(|| panic!("could not allocate {megabytes} MB for the hash table"))
+    }
+
+    /// Create a new hash map of a specific size, or if there isn't enough memory for it, of the largest
+    /// size that fits, halving down to 1 MB. See `megabytes` for the size it got.
+    ///
+    /// Without even 1 MB, nothing else can be allocated either, so this gives up the way the standard
+    /// library does when it runs out of memory.
+    pub fn new_or_smaller(megabytes: usize) -> Self
+    {
+        let mut size = megabytes;
+        loop
+        {
+            if let Some(hashmap) = Self::try_new(size) {
+                return hashmap;
+            }
+            if size == 1 {
+                alloc::handle_alloc_error(alloc::Layout::new::<[u8; 1024 * 1024]>());
+            }
+            size = (size / 2).max(1);
+        }
+    }
+
+    /// Create a new hash map of a specific size, or `None` if there isn't enough memory for it
+    pub fn try_new(megabytes: usize) -> Option<Self>
     {
         assert!(megabytes > 0);
         // TODO: Maybe allocate megabyte-aligned memory using megapage mapping, for better performance?
-        let nbr_bytes = megabytes.checked_mul(1024*1024).expect("overflow");
-        let nbr_entries = nbr_bytes / mem::size_of::<HashEntry>();
-        let layout = alloc::Layout::array::<HashEntry>(nbr_entries)
-            .expect("layout error");
+        let nbr_bytes = megabytes.checked_mul(1024*1024)?;
+        let hashmap = Self::try_with_capacity_entries(nbr_bytes / mem::size_of::<Slot>())?;
 
-        assert!(layout.size() == nbr_bytes, "HashMap had unexpected size, was {} bytes, requested {}", layout.size(), nbr_bytes);
+        debug_assert!(hashmap.megabytes() * 1024 * 1024 == nbr_bytes, "HashMap had unexpected size, was {} MB, requested {} bytes", hashmap.megabytes(), nbr_bytes);
+        Some(hashmap)
+    }
+
+    /// Create a new hash map with room for a specific number of entries, so that tests can exercise
+    /// purging on a handful of entries
+    ///
+    /// Every hash needs `NUM_SLOTS_PER_HASH` distinct slots, so smaller capacities are rounded up to that.
+    #[cfg(test)]
+    pub fn with_capacity_entries(entries: usize) -> Self
+    {
+        Self::try_with_capacity_entries(entries).unwrap_or_else(|| panic!("could not allocate {entries} hash entries"))
+    }
+
+    fn try_with_capacity_entries(entries: usize) -> Option<Self>
+    {
+        let nbr_entries = entries.max(NUM_SLOTS_PER_HASH);
+        let layout = alloc::Layout::array::<Slot>(nbr_entries).ok()?;
 
         let allocation =
         // SAFETY: Allocating raw memory
         unsafe {
-            let pointer = alloc::alloc_zeroed(layout) as *mut HashEntry;
-            ptr::NonNull::new(pointer).expect("alloc returned null")
+            let pointer = alloc::alloc_zeroed(layout) as *mut Slot;
+            ptr::NonNull::new(pointer)?
         };
 
-        HashMap {
+        Some(HashMap {
             pointer: allocation,
-            layout,
+            storage: Storage::Allocated(layout),
             phantom_data: std::marker::PhantomData,
-            count: 0,
+            count: AtomicUsize::new(0),
             capacity: nbr_entries,
+            skipped_stores: AtomicU64::new(0),
+            check_keys: None,
+            detected_collisions: AtomicU64::new(0),
+            duplicate_entries: AtomicU64::new(0),
             generation: 0,
+            sweep_position: 0,
+        })
+    }
+
+    pub fn get(&self, position: &Board) -> Option<HashEntry>
+    {
+        let (idx, entry) = self.find(position.get_hash())?;
+        if let Some(check_keys) = &self.check_keys
+        {
+            let stored = check_keys[idx].load(Ordering::Relaxed);
+            if stored != 0 && stored != check_key(position)
+            {
+                self.detected_collisions.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
         }
+        Some(entry)
     }
 
-    pub fn get<'a>(&'a self, position: &Board) -> Option<&'a HashEntry>
+    /// Look up an entry by its hash alone, for tests that make up hashes
+    #[cfg(test)]
+    fn get_by_hash(&self, hash: u64) -> Option<HashEntry>
     {
-        let hash = position.get_hash();
-        let slot_idx = self.get_slot_idx_for_hash(hash);
-        let current_generation = self.generation;
+        self.find(hash).map(|(_, entry)| entry)
+    }
 
-        let mut slots = slot_idx
-            .map(|idx| self.get_slot(idx))
+    /// The slot and entry for a hash
+    fn find(&self, hash: u64) -> Option<(usize, HashEntry)>
+    {
+        // TODO: Upmark fetched entries to this generation. We don't actually know if the entry was
+        // useful at this point, so maybe this should happen in search.rs instead?
+        self.get_slot_idx_for_hash(hash)
             .into_iter()
-            .filter(|e| e.hash == hash && e.entry_type.is_used());
+            .map(|idx| (idx, self.read_slot(idx)))
+            .find(|(_, e)| e.hash == hash && e.entry_type.is_used())
+    }
 
-        let result = slots.next();
-        debug_assert!(slots.next().is_none(), "More than one entry for the same hash in table!");
-        if let Some(entry) = result
+    /// Have the processor load the slots of `hash` into its cache, ahead of a lookup of it that is
+    /// coming up. Only a hint, which does nothing on other architectures than x86-64.
+    pub fn prefetch(&self, hash: u64)
+    {
+        #[cfg(target_arch = "x86_64")]
+        for idx in self.get_slot_idx_for_hash(hash)
         {
-            if entry.generation != current_generation
+            // SAFETY: Prefetching never faults, and the slot is within the allocation anyway
+            unsafe
             {
-                // TODO: Upmark fetched entries to this generation. This will require that generation
-                // numbers are wrapped in Cell. We also don't actually know if the entry was useful
-                // at this point, so maybe this should happen in search.rs instead?
-                // entry.generation = current_generation;
-                // self.count += 1;
+                use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+                _mm_prefetch::<_MM_HINT_T0>(self.get_slot(idx) as *const Slot as *const i8);
             }
         }
-        result
+
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = hash;
     }
 
-    pub fn insert(&mut self, position: &Board, entry: HashEntry)
+    /// Look up a position for inspection, reporting where and how old the entry is along with its
+    /// contents
+    pub fn probe(&self, hash: u64) -> Option<ProbeResult>
     {
-        let hash = position.get_hash();
         let current_generation = self.generation;
-        let slot = self.get_mut_or_new_slot(hash);
-        *slot = entry;
-        slot.hash = hash;
-        slot.generation = current_generation;
+
+        self.get_slot_idx_for_hash(hash)
+            .into_iter()
+            .enumerate()
+            .map(|(slot, idx)| (slot, self.read_slot(idx)))
+            .find(|(_, e)| e.hash == hash && e.entry_type.is_used())
+            .map(|(slot, entry)| ProbeResult {
+                depth: entry.depth(),
+                score: entry.score(),
+                best_move: entry.best_move(),
+                age: current_generation.wrapping_sub(entry.generation),
+                slot,
+            })
+    }
+
+    /// Iterate over all entries in use, in table order and regardless of generation
+    pub fn iter_used(&self) -> impl Iterator<Item = HashEntry> + '_
+    {
+        (0..self.capacity)
+            .map(|idx| self.read_slot(idx))
+            .filter(|e| e.entry_type.is_used())
+    }
+
+    /// Store an entry of the search, unless it is a shallow entry kept out by table pressure, see
+    /// "Table pressure"
+    pub fn insert(&self, position: &Board, entry: HashEntry)
+    {
+        let hash = position.get_hash();
+        if self.is_kept_out_by_pressure(hash, &entry)
+        {
+            self.skipped_stores.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if let Some(idx) = self.insert_by_hash(hash, entry) {
+            self.set_check_key(idx, check_key(position));
+        }
+    }
+
+    /// Insert an entry under the hash it was created with, for entries read back from a snapshot
+    pub fn insert_entry(&self, entry: HashEntry)
+    {
+        if let Some(idx) = self.insert_by_hash(entry.hash, entry) {
+            self.set_check_key(idx, 0);
+        }
+    }
+
+    /// Write an entry under `hash`, and return its slot, or `None` if the entry wasn't written
+    fn insert_by_hash(&self, hash: u64, mut entry: HashEntry) -> Option<usize>
+    {
+        let idx = self.get_or_new_slot(hash);
+        entry.hash = hash;
+        entry.generation = self.generation;
+        self.write_slot(idx, &entry);
+        Some(idx)
+    }
+
+    /// Set the check key of a slot, if collision detection is on. Zero means that it is unknown.
+    fn set_check_key(&self, idx: usize, key: u32)
+    {
+        if let Some(check_keys) = &self.check_keys {
+            check_keys[idx].store(key, Ordering::Relaxed);
+        }
+    }
+
+    /// Turn collision detection on or off, see "Collision detection". Turning it on allocates the check
+    /// keys, which the entries already in the map don't have.
+    pub fn set_collision_detection(&mut self, enabled: bool)
+    {
+        if enabled != self.check_keys.is_some() {
+            self.check_keys = enabled.then(|| (0..self.capacity).map(|_| AtomicU32::new(0)).collect());
+        }
+    }
+
+    /// The number of lookups in this generation that found the entry of another position, or `None`
+    /// if collision detection is off
+    pub fn detected_collisions(&self) -> Option<u64>
+    {
+        self.check_keys.as_ref().map(|_| self.detected_collisions.load(Ordering::Relaxed))
+    }
+
+    /// The number of second entries for the same position that inserts have removed in this generation
+    pub fn duplicate_entries(&self) -> u64
+    {
+        self.duplicate_entries.load(Ordering::Relaxed)
+    }
+
+    /// Whether `entry` is a shallow entry that is not worth its slot in a map this full
+    fn is_kept_out_by_pressure(&self, hash: u64, entry: &HashEntry) -> bool
+    {
+        let is_shallow = entry.depth <= PRESSURE_MAX_DEPTH;
+        let is_exact_mate = matches!(entry.score(), BoundedScore::Exact(score) if score.is_mate_score());
+        if !is_shallow || is_exact_mate || 1000 * self.filled() < PRESSURE_HASHFULL * self.capacity {
+            return false;
+        }
+
+        // The slot that the entry would take, as in get_or_new_slot. Empty slots are left for deeper
+        // entries.
+        let slot_idx = self.get_slot_idx_for_hash(hash);
+        let entries = slot_idx.map(|idx| self.read_slot(idx));
+        let existing = entries.iter().find(|e| e.hash == hash && e.entry_type.is_used());
+        let target_priority = match existing
+        {
+            Some(existing) => self.purge_priority(existing),
+            None if entries.iter().any(|e| !e.entry_type.is_used()) => return true,
+            None => self.purge_victim(slot_idx).1,
+        };
+
+        // The new entry would be of this generation, and full
+        let entry_priority = purge_priority(0, HashEntryKind::Full, entry.depth);
+        target_priority <= entry_priority
     }
 
     /// The capacity of the hash map, in number of entries
@@ -293,30 +598,125 @@ impl HashMap
         self.capacity
     }
 
+    /// The number of shallow entries that `insert` has left out in this generation, see "Table
+    /// pressure"
+    pub fn skipped_stores(&self) -> u64
+    {
+        self.skipped_stores.load(Ordering::Relaxed)
+    }
+
     /// The number of entries that are filled in the hash map in this generation
     ///
     /// If it gets too high, nodes from this generation will start being purged.
     pub fn filled(&self) -> usize
     {
-        self.count
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// The size of the hash map, in megabytes
+    pub fn megabytes(&self) -> usize
+    {
+        self.capacity * mem::size_of::<Slot>() / (1024*1024)
+    }
+
+    /// Whether no slot of the map is in use, in any generation
+    pub fn is_empty(&self) -> bool
+    {
+        self.iter_used().next().is_none()
+    }
+
+    /// Use the slots that a mapped snapshot holds from `offset` on as the storage of the map, in place of
+    /// its own, see "Mapped storage". The map continues from `generation`, the generation the snapshot
+    /// was taken in, of which `filled` entries are in use.
+    ///
+    /// The caller has checked that the mapping holds `capacity` slots from `offset` on, all of them as
+    /// `write_slot` or `clear_slot` would have left them, so that every entry unpacks to what it was.
+    fn use_mapped_storage(&mut self, mapped: mapping::MappedFile, offset: usize, generation: u8, filled: usize)
+    {
+        debug_assert_eq!(mapped.bytes().len(), offset + self.capacity * mem::size_of::<Slot>());
+        // SAFETY: The offset is within the mapping, as the caller checked. The mapping is aligned to a
+        // page, so the slots are aligned as long as the offset is.
+        let slots = unsafe { mapped.as_mut_ptr().add(offset) as *mut Slot };
+        assert!(slots.is_aligned(), "snapshot slots at unaligned offset {offset}");
+
+        if let Storage::Allocated(layout) = self.storage
+        {
+            // SAFETY: Deallocating as in drop(), and the pointer is replaced right after
+            unsafe {
+                alloc::dealloc(self.pointer.as_ptr() as *mut _, layout);
+            }
+        }
+        self.pointer = ptr::NonNull::new(slots).expect("mappings are never at address zero");
+        self.storage = Storage::Mapped(mapped);
+
+        // The entries of the snapshot were stored without their positions
+        if let Some(check_keys) = &mut self.check_keys {
+            check_keys.iter_mut().for_each(|key| *key.get_mut() = 0);
+        }
+        *self.count.get_mut() = filled;
+        *self.skipped_stores.get_mut() = 0;
+        *self.detected_collisions.get_mut() = 0;
+        *self.duplicate_entries.get_mut() = 0;
+        self.generation = generation;
+        self.sweep_position = 0;
     }
 
     pub fn new_generation(&mut self)
     {
         self.generation = self.generation.wrapping_add(1);
-        self.count = 0;
+        *self.count.get_mut() = 0;
+        *self.skipped_stores.get_mut() = 0;
+        *self.detected_collisions.get_mut() = 0;
+        *self.duplicate_entries.get_mut() = 0;
+        self.sweep_ages();
     }
 
+    /// Cap the age of the entries in the next chunk of the map at MAX_AGE, see "Generation wraparound"
+    fn sweep_ages(&mut self)
+    {
+        let current_generation = self.generation;
+        let chunk_size = self.capacity.div_ceil(MAX_AGE as usize);
+        let sweep_end = (self.sweep_position + chunk_size).min(self.capacity);
 
+        for idx in self.sweep_position..sweep_end
+        {
+            let mut entry = self.read_slot(idx);
+            if entry.entry_type.is_used() && current_generation.wrapping_sub(entry.generation) > MAX_AGE {
+                entry.generation = current_generation.wrapping_sub(MAX_AGE);
+                self.write_slot(idx, &entry);
+            }
+        }
 
-    /// Get a mutable reference to the entry for a specific hash, or insert a new one if necessary
+        self.sweep_position = if sweep_end >= self.capacity { 0 } else { sweep_end };
+    }
+
+    /// Remove all entries from the hash map
+    pub fn clear(&mut self)
+    {
+        // SAFETY: Writing zeroes to all the slots, which is a valid Slot bit pattern (unused entry), as
+        // in new(). Nothing else accesses the slots meanwhile, since this has &mut self. With mapped
+        // storage, this copies every page, as any other write does.
+        unsafe
+        {
+            ptr::write_bytes(self.pointer.as_ptr(), 0, self.capacity);
+        }
+        if let Some(check_keys) = &mut self.check_keys {
+            check_keys.iter_mut().for_each(|key| *key.get_mut() = 0);
+        }
+        *self.count.get_mut() = 0;
+        *self.skipped_stores.get_mut() = 0;
+        *self.detected_collisions.get_mut() = 0;
+        *self.duplicate_entries.get_mut() = 0;
+        self.generation = 0;
+    }
+
+    /// Get the slot of the entry for a specific hash, or a slot for a new one
     ///
     /// This method will use purging to create a new slot for this hash if the table is full.
-    fn get_mut_or_new_slot(&mut self, hash: u64) -> &mut HashEntry
+    fn get_or_new_slot(&self, hash: u64) -> usize
     {
         let slot_idx = self.get_slot_idx_for_hash(hash);
 
-        let slot_to_use =
         // Find existing slot with this same hash
         if let Some(slot) = self.get_existing_slot(hash, slot_idx)
         {
@@ -333,114 +733,78 @@ impl HashMap
         else
         {
             self.get_purgeable_slot(slot_idx)
-        };
-
-        self.get_slot_mut(slot_to_use)
+        }
     }
 
-    fn get_existing_slot(&mut self, hash: u64, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> Option<usize>
+    fn get_existing_slot(&self, hash: u64, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> Option<usize>
     {
-        let mut existing_slot = self.get_multi_slot_mut(slot_idx)
-            .into_iter()
-            .zip(slot_idx)
-            .filter(|(e, _)| e.hash == hash);
-
-        if let Some((_, i)) = existing_slot.next()
+        let mut existing = slot_idx.into_iter().filter(|&idx| {
+            let entry = self.read_slot(idx);
+            entry.hash == hash && entry.entry_type.is_used()
+        });
+        let first = existing.next()?;
+
+        // Another thread may have stored the same position in another slot at the same time, see
+        // "Threads". Only the first one is ever found, so the others only take up room.
+        for duplicate in existing
         {
-            debug_assert!(existing_slot.next().is_none(), "More than one entry for the same hash in table!");
-            return Some(i);
+            self.duplicate_entries.fetch_add(1, Ordering::Relaxed);
+            self.clear_slot(duplicate);
         }
-
-        return None;
+        Some(first)
     }
 
-    fn get_empty_slot(&mut self, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> Option<usize>
+    fn get_empty_slot(&self, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> Option<usize>
     {
-        let mut empty_slots = self.get_multi_slot_mut(slot_idx)
-            .into_iter()
-            .zip(slot_idx)
-            .filter(|(e, _)| !e.entry_type.is_used());
-
-        let result =
-        if let Some((_, i)) = empty_slots.next()
-        {
-            // self.count += 1;
-            Some(i)
-        }
-        else
-        {
-            None
-        };
-        drop(empty_slots);
-
+        let result = slot_idx.into_iter().find(|&idx| !self.read_slot(idx).entry_type.is_used());
         if result.is_some() {
-            self.count += 1;
+            self.count.fetch_add(1, Ordering::Relaxed);
         }
-
-        return result;
+        result
     }
 
-    fn get_purgeable_slot(&mut self, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> usize
+    /// The slot to overwrite when all candidate slots for a hash are in use, see "Purging strategy"
+    fn get_purgeable_slot(&self, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> usize
     {
-        // The full purging priority could go something like this:
-        // 1. Purge any entry from more than one generation ago
-        // 2. Purge any deficient entry from last generation
-        // 3. Purge the full entry of lowest depth from last generation
-        // 4. Purge the deficient entry of lowest depth from this generation
-        // 5. Purge the full entry of lowest depth from this generation
-
-        let current_generation = self.generation;
+        let (victim, (age, _, _)) = self.purge_victim(slot_idx);
 
-        // Entries older than 2 generations, i.e. not from this nor the previous one
-        let mut old_entries = self.get_multi_slot_mut(slot_idx)
-            .into_iter()
-            .zip(slot_idx)
-            // Use wrapping arithmetic: if we are in generation 2 and an entry is from generation 255,
-            // then that entry is 3 generations old. 2u8.wrapping_sub(255u8) == 3u8
-            .filter(|(e, _)| current_generation.wrapping_sub(e.generation) >= 2)
-            .map(|(_, i)| i);
-
-        // Purge the first one that comes up: it's unnecessary to sort them
-        let first_entry = old_entries.next();
-        drop(old_entries);
-
-        if let Some(idx) = first_entry
-        {
-            self.count += 1;
-            return idx;
+        // Only an entry of this generation was already counted as filled
+        if age > 0 {
+            self.count.fetch_add(1, Ordering::Relaxed);
         }
+        victim
+    }
 
-        // TODO: Handle deficient entries
-
-        // Entry of lowest depth from last generation
-        let mut entries = self.get_multi_slot_mut(slot_idx)
-            .into_iter()
-            .zip(slot_idx)
-            .filter(|(e, _)| current_generation.wrapping_sub(e.generation) >= 1)
-            .map(|(e, i)| (e.depth, i))
-            .collect::<Vec<_>>(); // TODO: Collect onto stack instead of allocating
-
-        if entries.len() > 0 {
-            entries.sort_unstable_by_key(|(d, _)| *d);
-            self.count += 1;
-            return entries[0].1;
+    /// The slot that purging picks among `slot_idx`, all of which are in use, and its priority
+    fn purge_victim(&self, slot_idx: [usize; NUM_SLOTS_PER_HASH]) -> (usize, (u8, bool, u8))
+    {
+        let mut victim = slot_idx[0];
+        let mut victim_priority = self.purge_priority(&self.read_slot(victim));
+        for idx in slot_idx.into_iter().skip(1)
+        {
+            // On a tie, the earlier slot goes
+            let priority = self.purge_priority(&self.read_slot(idx));
+            if priority > victim_priority {
+                victim = idx;
+                victim_priority = priority;
+            }
         }
+        (victim, victim_priority)
+    }
 
-        // Entry of lowest depth from this generation. This might be an entry that is actually useful
-        // to us, so this will hurt search performance.
-        let mut entries = self.get_multi_slot_mut(slot_idx)
-            .into_iter()
-            .zip(slot_idx)
-            .map(|(e, i)| (e.depth, i))
-            .collect::<Vec<_>>(); // TODO: Collect onto stack instead of allocating
-
-        entries.sort_unstable_by_key(|(d, _)| *d);
-        return entries[0].1;
+    /// How eagerly a used entry is purged in the current generation
+    fn purge_priority(&self, entry: &HashEntry) -> (u8, bool, u8)
+    {
+        // Use wrapping arithmetic: if we are in generation 2 and an entry is from generation 255,
+        // then that entry is 3 generations old. 2u8.wrapping_sub(255u8) == 3u8
+        let age = self.generation.wrapping_sub(entry.generation);
+        purge_priority(age, entry.entry_type.entry_kind(), entry.depth)
     }
 
     /// Get the slots where this hash can be stored
     fn get_slot_idx_for_hash(&self, hash: u64) -> [usize; NUM_SLOTS_PER_HASH]
     {
+        debug_assert!(self.capacity >= NUM_SLOTS_PER_HASH);
         let mut hash = hash;
         let mut result = [0; NUM_SLOTS_PER_HASH];
 
@@ -458,7 +822,8 @@ impl HashMap
         //
         // If we go through all 64 rotations of the hash without finding 4 unique slots, we increment
         // the hash by 0x1000100010005. This number has been picked because is affects one bit in every
-        // short of the hash, and is prime.
+        // short of the hash, and is prime. The loop ends because the capacity is at least
+        // NUM_SLOTS_PER_HASH, so that there are enough distinct slots to find.
         let mut i = 0;
         loop {
             for _ in 0..u64::BITS {
@@ -485,8 +850,8 @@ impl HashMap
         }
     }
 
-    /// Get the entry at a particular location
-    fn get_slot(&self, idx: usize) -> &HashEntry
+    /// Get the slot at a particular location
+    fn get_slot(&self, idx: usize) -> &Slot
     {
         debug_assert!(idx < self.capacity);
         // SAFETY:
@@ -494,76 +859,369 @@ impl HashMap
         //   whenever slot is valid for our capacity
         // - as_ref() is only sound if the pointer is aligned and points to an initialized object. This
         //   is upheld since the allocation is aligned to begin with, offset() preserves alignment,
-        //   the allocation was zeroing to begin with, and HashEntry is valid when zero-initialized.
-        // - The resulting lifetime matches that of &self
+        //   the allocation was zeroing to begin with, and Slot is valid when zero-initialized. Mapped
+        //   storage is aligned and initialized too, see `use_mapped_storage`.
+        // - The resulting lifetime matches that of &self, and the slot is only accessed through its
+        //   atomics
         unsafe
         {
             self.pointer.offset(idx as isize).as_ref()
         }
     }
 
-    /// Get a mutable reference to the entry at a particular location
-    fn get_slot_mut(&self, idx: usize) -> &mut HashEntry
+    /// The entry in the slot at a particular location, with the hash it was stored under
+    fn read_slot(&self, idx: usize) -> HashEntry
     {
-        debug_assert!(idx < self.capacity);
-        // SAFETY:
-        // - offset() must only be used to produce pointers within the same allocation. This is upheld
-        //   whenever slot is valid for our capacity
-        // - as_ref() is only sound if the pointer is aligned and points to an initialized object. This
-        //   is upheld since the allocation is aligned to begin with, offset() preserves alignment,
-        //   the allocation was zeroing to begin with, and HashEntry is valid when zero-initialized.
-        // - The resulting lifetime matches that of &self
-        unsafe
-        {
-            self.pointer.offset(idx as isize).as_mut()
-        }
+        let slot = self.get_slot(idx);
+        let data = slot.data.load(Ordering::Relaxed);
+        let key = slot.key.load(Ordering::Relaxed);
+        HashEntry::unpack(key ^ data, data)
     }
 
-    /// Get the entries mutably at particular slots
-    ///
-    /// This method exists to be able to take mutable borrows to several slots simultaneously.
-    ///
-    /// Safety: It is the caller's responsibility to ensure that all four indexes provided refer to
-    /// different slots. Otherwise you will get duplicate mut-references to the same slot.
-    fn get_multi_slot_mut(&mut self, idx: [usize; NUM_SLOTS_PER_HASH]) -> [&mut HashEntry; NUM_SLOTS_PER_HASH]
-    {
-        debug_assert!(
-            idx[0] < self.capacity &&
-            idx[1] < self.capacity &&
-            idx[2] < self.capacity &&
-            idx[3] < self.capacity
-        );
-        debug_assert!(
-            idx[0] != idx[1] &&
-            idx[0] != idx[2] &&
-            idx[0] != idx[3] &&
-            idx[1] != idx[2] &&
-            idx[1] != idx[3] &&
-            idx[2] != idx[3]
-        );
-        // SAFETY:
-        // - offset() must only be used to produce pointers within the same allocation. This is upheld
-        //   whenever idx is valid for our capacity
-        // - as_ref() is only sound if the pointer is aligned and points to an initialized object. This
-        //   is upheld since the allocation is aligned to begin with, offset() preserves alignment,
-        //   the allocation was zeroing to begin with, and HashEntry is valid when zero-initialized.
-        // - The resulting lifetime matches that of &mut self, and all four entries are ensured to be
-        //   distinct so that no two slots have simultaneous mut-references.
-        unsafe
-        {
-            idx.map(|idx| self.pointer.offset(idx as isize).as_mut())
-        }
+    /// Make the slot at a particular location unused
+    fn clear_slot(&self, idx: usize)
+    {
+        let slot = self.get_slot(idx);
+        slot.key.store(0, Ordering::Relaxed);
+        slot.data.store(0, Ordering::Relaxed);
+    }
+
+    /// Write an entry, with its hash and generation as they are, to the slot at a particular location
+    fn write_slot(&self, idx: usize, entry: &HashEntry)
+    {
+        let slot = self.get_slot(idx);
+        let data = entry.pack();
+        slot.key.store(entry.hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// A 32-bit key of the position for telling apart positions with the same Zobrist hash, see "Collision
+/// detection". It mixes the bitboards of the position rather than Zobrist keys, so that it is
+/// independent of the Zobrist hash. Never zero, which stands for an unknown key.
+fn check_key(position: &Board) -> u32
+{
+    let mut key = 0u64;
+    let mut mix = |value: u64| key = (key ^ value).wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(29);
+    for color in chess::ALL_COLORS {
+        mix(position.color_combined(color).0);
+    }
+    for piece in chess::ALL_PIECES {
+        mix(position.pieces(piece).0);
     }
+    mix(position.side_to_move().to_index() as u64
+        | (position.castle_rights(Color::White).to_index() as u64) << 1
+        | (position.castle_rights(Color::Black).to_index() as u64) << 3
+        | (position.en_passant().map_or(64, |square| square.to_index()) as u64) << 5);
+    ((key >> 32) as u32).max(1)
+}
+
+/// How eagerly an entry of `age` generations is purged, the entry with the greatest priority going
+/// first: older entries first, then deficient entries before full ones, then shallower entries first
+fn purge_priority(age: u8, entry_kind: HashEntryKind, depth: u8) -> (u8, bool, u8)
+{
+    (age.min(2), entry_kind == HashEntryKind::Deficient, u8::MAX - depth)
 }
 
 impl Drop for HashMap
 {
     fn drop(&mut self)
     {
-        // SAFETY: Deallocating using the same layout object that was used to allocate. The pointer
-        // is not dereferenced again.
-        unsafe {
-            alloc::dealloc(self.pointer.as_ptr() as *mut _, self.layout);
+        match self.storage
+        {
+            // SAFETY: Deallocating using the same layout object that was used to allocate. The pointer
+            // is not dereferenced again.
+            Storage::Allocated(layout) => unsafe {
+                alloc::dealloc(self.pointer.as_ptr() as *mut _, layout);
+            },
+            // Unmapped when the mapping is dropped, right after this. The copied pages are thrown away
+            // with it, and the file stays as it was.
+            Storage::Mapped(_) => { },
         }
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_clear()
+    {
+        let mut hashmap = HashMap::new(1);
+        let position = Board::default();
+        let other_position = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        hashmap.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 5));
+        hashmap.insert(&other_position, HashEntry::with_contents(other_position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 3));
+        assert!(hashmap.get(&position).is_some());
+        assert_eq!(hashmap.filled(), 2);
+        hashmap.new_generation();
+
+        hashmap.clear();
+        assert!(hashmap.get(&position).is_none());
+        assert!(hashmap.get(&other_position).is_none());
+        assert_eq!(hashmap.filled(), 0);
+        // The next game starts counting generations from scratch
+        assert_eq!(hashmap.generation, 0);
+    }
+
+    #[test]
+    fn test_iter_used()
+    {
+        let mut hashmap = HashMap::new(1);
+        assert_eq!(hashmap.iter_used().count(), 0);
+
+        let position = Board::default();
+        let other_position = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        hashmap.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 5));
+        hashmap.new_generation();
+        hashmap.insert(&other_position, HashEntry::with_contents(other_position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 3));
+
+        // Entries from older generations are included too
+        let mut depths = hashmap.iter_used().map(|e| e.depth()).collect::<Vec<_>>();
+        depths.sort();
+        assert_eq!(depths, [3, 5]);
+    }
+
+    #[test]
+    fn test_collision_detection()
+    {
+        let position = Board::default();
+        let other_position = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_ne!(check_key(&position), check_key(&other_position));
+
+        // Force a collision: move the entry of one position under the hash of another, keeping the
+        // check key of the slot
+        let force_collision = |hashmap: &HashMap| {
+            hashmap.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 5));
+            let (idx, mut entry) = hashmap.find(position.get_hash()).unwrap();
+            entry.hash = other_position.get_hash();
+            hashmap.write_slot(idx, &entry);
+        };
+
+        // Without detection, the other position gets the entry
+        let mut hashmap = HashMap::new(1);
+        force_collision(&hashmap);
+        assert!(hashmap.get(&other_position).is_some());
+        assert_eq!(hashmap.detected_collisions(), None);
+
+        // With detection, the entry is not returned, and counted
+        hashmap.clear();
+        hashmap.set_collision_detection(true);
+        force_collision(&hashmap);
+        assert!(hashmap.get(&other_position).is_none());
+        assert_eq!(hashmap.detected_collisions(), Some(1));
+
+        // Entries without a check key, like those of snapshots, are returned as they are
+        hashmap.insert_entry(HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 5));
+        assert!(hashmap.get(&position).is_some());
+        assert_eq!(hashmap.detected_collisions(), Some(1));
+
+        hashmap.new_generation();
+        assert_eq!(hashmap.detected_collisions(), Some(0));
+    }
+
+    #[test]
+    fn test_duplicate_entries()
+    {
+        // Two entries for the same position, as two threads inserting at once may leave behind
+        let position = Board::default();
+        let hashmap = HashMap::new(1);
+        let slot_idx = hashmap.get_slot_idx_for_hash(position.get_hash());
+        for (idx, depth) in slot_idx.into_iter().zip([3, 4]) {
+            hashmap.write_slot(idx, &HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), depth));
+        }
+        assert_eq!(hashmap.get(&position).map(|e| e.depth()), Some(3));
+
+        // The next insert goes to the first, and removes the second
+        hashmap.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 6));
+        assert_eq!(hashmap.duplicate_entries(), 1);
+        assert_eq!(hashmap.iter_used().count(), 1);
+        assert_eq!(hashmap.get(&position).map(|e| e.depth()), Some(6));
+    }
+
+    #[test]
+    fn test_tiny_capacities()
+    {
+        for (entries, capacity) in [(1, 4), (2, 4), (4, 4), (5, 5)]
+        {
+            let mut hashmap = HashMap::with_capacity_entries(entries);
+            assert_eq!(hashmap.capacity(), capacity);
+
+            // Many more hashes than slots, so every insert after the first few purges an entry
+            let hashes = (0..64u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).chain([0, u64::MAX]);
+            for (i, hash) in hashes.enumerate()
+            {
+                let entry = HashEntry::with_contents(hash, None, BoundedScore::Exact(BoardScore::EVEN), (i % 8) as u8);
+                hashmap.insert_by_hash(hash, entry);
+                assert_eq!(hashmap.get_by_hash(hash).map(|e| e.depth()), Some((i % 8) as u8), "capacity {capacity}, hash {hash:x}");
+                assert!(hashmap.iter_used().count() <= capacity);
+
+                if i % 16 == 15 {
+                    hashmap.new_generation();
+                }
+            }
+
+            hashmap.clear();
+            assert_eq!(hashmap.iter_used().count(), 0);
+            assert_eq!(hashmap.filled(), 0);
+        }
+    }
+
+    #[test]
+    fn test_purge_priority()
+    {
+        use HashEntryKind::{Deficient as D, Full as F};
+
+        // Age in generations, kind and depth of the entries in the four candidate slots, and which
+        // slot gets purged
+        let cases = [
+            // Shallowest of this generation, or the first of equals
+            ([(0, F, 5), (0, F, 3), (0, F, 7), (0, F, 4)], 1),
+            ([(0, F, 4), (0, F, 4), (0, F, 4), (0, F, 4)], 0),
+            // 1. Anything from more than one generation ago, however deep
+            ([(0, F, 1), (0, F, 1), (2, F, 30), (0, F, 1)], 2),
+            ([(1, D, 1), (1, D, 1), (1, D, 1), (9, F, 30)], 3),
+            // ... and among those, the deficient and shallow entries, whatever their exact age
+            ([(30, F, 8), (2, F, 3), (0, D, 0), (5, D, 9)], 3),
+            ([(30, F, 8), (2, F, 3), (0, D, 0), (5, F, 9)], 1),
+            // 2. A deficient entry from last generation before a shallower full one
+            ([(1, F, 1), (0, D, 0), (1, D, 9), (0, F, 0)], 2),
+            // 3. The shallowest full entry from last generation before anything of this one
+            ([(1, F, 6), (0, D, 0), (1, F, 2), (1, F, 4)], 2),
+            ([(0, D, 0), (0, D, 0), (0, F, 0), (1, F, 20)], 3),
+            // 4. The shallowest deficient entry of this generation before a shallower full one
+            ([(0, D, 5), (0, F, 0), (0, F, 1), (0, D, 2)], 3),
+            // 5. The shallowest full entry of this generation
+            ([(0, F, 9), (0, F, 2), (0, F, 0), (0, F, 1)], 2),
+        ];
+
+        const GENERATION: u8 = 3;
+        for (slots, expected) in cases
+        {
+            // Every hash has all four slots of the smallest map as candidates
+            let mut hashmap = HashMap::with_capacity_entries(NUM_SLOTS_PER_HASH);
+            hashmap.generation = GENERATION;
+            let slot_idx = hashmap.get_slot_idx_for_hash(0x1234);
+            for ((age, kind, depth), idx) in slots.into_iter().zip(slot_idx)
+            {
+                let mut entry = HashEntry::with_contents(idx as u64, None, BoundedScore::Exact(BoardScore::EVEN), depth);
+                entry.entry_type.set_entry_kind(kind);
+                entry.generation = GENERATION.wrapping_sub(age);
+                hashmap.write_slot(idx, &entry);
+            }
+
+            let victim = hashmap.get_purgeable_slot(slot_idx);
+            assert_eq!(victim, slot_idx[expected], "{slots:?}");
+
+            // Purging an entry of an earlier generation fills one more slot for this generation
+            let (age, _, _) = slots[expected];
+            assert_eq!(hashmap.filled(), (age > 0) as usize, "{slots:?}");
+        }
+    }
+
+    #[test]
+    fn test_table_pressure()
+    {
+        let position_entry = |fen: &str, score, depth| {
+            let position = Board::from_str(fen).unwrap();
+            (position, HashEntry::with_contents(position.get_hash(), None, score, depth))
+        };
+        let even = BoundedScore::Exact(BoardScore::EVEN);
+
+        // The smallest map, filled with entries of this generation: every slot is a candidate for
+        // every hash
+        let fill = |depth| {
+            let hashmap = HashMap::with_capacity_entries(NUM_SLOTS_PER_HASH);
+            for hash in 1..=NUM_SLOTS_PER_HASH as u64 {
+                hashmap.insert_by_hash(hash, HashEntry::with_contents(hash, None, even, depth));
+            }
+            assert_eq!(hashmap.filled(), NUM_SLOTS_PER_HASH);
+            hashmap
+        };
+
+        // A shallow entry doesn't evict deeper ones
+        let hashmap = fill(5);
+        let (position, entry) = position_entry("4k3/8/8/8/8/8/8/4K3 w - - 0 1", even, PRESSURE_MAX_DEPTH);
+        hashmap.insert(&position, entry);
+        assert!(hashmap.get(&position).is_none());
+        assert_eq!(hashmap.skipped_stores(), 1);
+
+        // ... but a deeper one does, and so does an exact mate score
+        let (position, entry) = position_entry("4k3/8/8/8/8/8/8/3K4 w - - 0 1", even, PRESSURE_MAX_DEPTH + 1);
+        hashmap.insert(&position, entry);
+        assert!(hashmap.get(&position).is_some());
+        let (position, entry) = position_entry("4k3/4Q3/4K3/8/8/8/8/8 b - - 0 1", BoundedScore::Exact(BoardScore::MATED), 0);
+        hashmap.insert(&position, entry);
+        assert!(hashmap.get(&position).is_some());
+        assert_eq!(hashmap.skipped_stores(), 1);
+
+        // A shallow entry replaces an even shallower one
+        let hashmap = fill(0);
+        let (position, entry) = position_entry("4k3/8/8/8/8/8/8/4K3 w - - 0 1", even, 1);
+        hashmap.insert(&position, entry);
+        assert!(hashmap.get(&position).is_some());
+        assert_eq!(hashmap.skipped_stores(), 0);
+
+        // Without pressure, shallow entries are stored as usual, also over entries of earlier
+        // generations
+        let mut hashmap = fill(5);
+        hashmap.new_generation();
+        let (position, entry) = position_entry("4k3/8/8/8/8/8/8/4K3 w - - 0 1", even, 0);
+        hashmap.insert(&position, entry);
+        assert!(hashmap.get(&position).is_some());
+        assert_eq!(hashmap.skipped_stores(), 0);
+    }
+
+    #[test]
+    fn test_generation_wraparound()
+    {
+        const ENTRIES_PER_GENERATION: usize = 2048;
+
+        let mut hashmap = HashMap::new(1);
+        let mut rng_state = 0x2545f4914f6cdd1du64;
+        let mut next_hash = || {
+            rng_state = crate::timeman::xorshift(rng_state);
+            rng_state
+        };
+
+        // Fraction of the entries inserted in each generation that are still there at its end
+        let mut retention = Vec::new();
+        for _ in 0..1000
+        {
+            hashmap.new_generation();
+
+            let hashes = (0..ENTRIES_PER_GENERATION).map(|_| next_hash()).collect::<Vec<_>>();
+            for (i, &hash) in hashes.iter().enumerate() {
+                let entry = HashEntry::with_contents(hash, None, BoundedScore::Exact(BoardScore::EVEN), (i % 8) as u8);
+                hashmap.insert_by_hash(hash, entry);
+            }
+
+            let retained = hashes.iter().filter(|&&hash| hashmap.get_by_hash(hash).is_some()).count();
+            retention.push(retained as f64 / ENTRIES_PER_GENERATION as f64);
+        }
+
+        // No entry ever looks younger than it is
+        let current_generation = hashmap.generation;
+        for idx in 0..hashmap.capacity
+        {
+            let entry = hashmap.read_slot(idx);
+            if entry.entry_type.is_used() {
+                assert!(current_generation.wrapping_sub(entry.generation) <= 2 * MAX_AGE);
+            }
+        }
+
+        // Replacement doesn't get worse after the generation counter has wrapped around
+        let average = |r: &[f64]| r.iter().sum::<f64>() / r.len() as f64;
+        let before_wraparound = average(&retention[50..250]);
+        let after_wraparound = average(&retention[300..1000]);
+        assert!(
+            after_wraparound >= before_wraparound - 0.001,
+            "retention before wraparound {before_wraparound}, after {after_wraparound}",
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Copy-on-write memory mapping of files, so that a hash table can use a snapshot as its storage without
+//! reading it, see "Mapped storage" of `HashMap`
+//!
+//! Mapping is behind the `mmap` feature, and only implemented on 64-bit little-endian Unix, where a
+//! snapshot has the layout of the slots in memory, by calling `mmap` and `munmap` of the C library
+//! directly. Elsewhere, and whenever mapping fails, `map` returns `None` and the caller reads the file
+//! as usual.
+
+pub use platform::{map, MappedFile};
+
+#[cfg(all(feature = "mmap", unix, target_pointer_width = "64", target_endian = "little"))]
+mod platform
+{
+    use std::ffi::{c_int, c_void};
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::ptr;
+
+    // The same values on Linux and the BSDs, including macOS
+    const PROT_READ: c_int = 1;
+    const PROT_WRITE: c_int = 2;
+    const MAP_PRIVATE: c_int = 2;
+
+    extern "C"
+    {
+        fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    /// The contents of a file, mapped into memory until dropped
+    ///
+    /// Writes to the mapping copy the pages they touch, and never reach the file, so there is nothing
+    /// to flush before unmapping.
+    pub struct MappedFile
+    {
+        pointer: *mut c_void,
+        len: usize,
+    }
+
+    /// Map the whole of `file`, or `None` if it is empty or can't be mapped
+    pub fn map(file: &File) -> Option<MappedFile>
+    {
+        let len = usize::try_from(file.metadata().ok()?.len()).ok()?;
+        if len == 0 {
+            return None;
+        }
+
+        // SAFETY: A new private mapping, which doesn't alias any memory of the program. The file stays
+        // mapped after the descriptor is closed.
+        let pointer = unsafe { mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE, file.as_raw_fd(), 0) };
+        // MAP_FAILED is -1
+        if pointer as isize == -1 {
+            return None;
+        }
+        Some(MappedFile { pointer, len })
+    }
+
+    impl MappedFile
+    {
+        pub fn bytes(&self) -> &[u8]
+        {
+            // SAFETY: The mapping is `len` bytes long, readable, and stays until `self` is dropped. Another
+            // process truncating the file meanwhile would make reading past the end fault, as with any
+            // mapped file.
+            unsafe { std::slice::from_raw_parts(self.pointer as *const u8, self.len) }
+        }
+
+        /// The start of the mapping, which is aligned to a page, for writing to it
+        pub fn as_mut_ptr(&self) -> *mut u8
+        {
+            self.pointer as *mut u8
+        }
+    }
+
+    impl Drop for MappedFile
+    {
+        fn drop(&mut self)
+        {
+            // SAFETY: Unmapping exactly what `map` mapped. The slices of `bytes` borrow `self`, so none
+            // is left, and whoever writes through `as_mut_ptr` keeps `self` for as long as it does.
+            unsafe {
+                munmap(self.pointer, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(feature = "mmap", unix, target_pointer_width = "64", target_endian = "little")))]
+mod platform
+{
+    use std::fs::File;
+
+    /// Mapping isn't supported by this build, so there are never any mapped files
+    pub enum MappedFile {}
+
+    pub fn map(_file: &File) -> Option<MappedFile>
+    {
+        None
+    }
+
+    impl MappedFile
+    {
+        pub fn bytes(&self) -> &[u8]
+        {
+            match *self {}
+        }
+
+        pub fn as_mut_ptr(&self) -> *mut u8
+        {
+            match *self {}
+        }
+    }
+}
+
+/// Whether this build maps files at all, which tells the tests what `map` should do
+#[cfg(test)]
+pub fn is_supported() -> bool
+{
+    cfg!(all(feature = "mmap", unix, target_pointer_width = "64", target_endian = "little"))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::fs::{self, File};
+
+    use super::*;
+
+    #[test]
+    fn test_map()
+    {
+        let path = std::env::temp_dir().join(format!("mapping-test-{}.bin", std::process::id()));
+        fs::write(&path, b"mapped contents").unwrap();
+        let mapped = map(&File::open(&path).unwrap());
+        assert_eq!(mapped.as_ref().map(MappedFile::bytes), is_supported().then_some(&b"mapped contents"[..]));
+
+        // Writes change the mapping, but not the file
+        if let Some(mapped) = &mapped
+        {
+            // SAFETY: The first byte of the mapping, which nothing else refers to
+            unsafe {
+                *mapped.as_mut_ptr() = b'M';
+            }
+            assert_eq!(mapped.bytes(), b"Mapped contents");
+        }
+        drop(mapped);
+        assert_eq!(fs::read(&path).unwrap(), b"mapped contents");
+
+        fs::write(&path, b"").unwrap();
+        let empty = map(&File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(empty.is_none());
+    }
+}
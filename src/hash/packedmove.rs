@@ -0,0 +1,186 @@
+//! Moves packed into 16 bits, for hash table snapshots, book files, and eventually the hash entries
+//! themselves
+//!
+//! The chess crate represents castling as a king move of two squares, and en passant as a pawn capture
+//! onto the empty square behind the captured pawn, so neither needs bits of its own. The encoding
+//! doesn't know the position, but it refuses bits that no piece could ever move by, so that damaged
+//! data can't pass for a move.
+
+use chess::{ChessMove, Piece, Rank, Square};
+
+/// The encoding of no move
+pub const NO_MOVE: u16 = u16::MAX;
+
+/// A move in 16 bits: the source square in bits 0-5, the target square in bits 6-11, and the
+/// promotion in bits 12-15 (0 for none, then knight, bishop, rook and queen). No move is `NO_MOVE`.
+pub fn encode_move(chess_move: Option<ChessMove>) -> u16
+{
+    let Some(chess_move) = chess_move else {
+        return NO_MOVE;
+    };
+
+    let promotion = match chess_move.get_promotion()
+    {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    (chess_move.get_source().to_index() as u16) | ((chess_move.get_dest().to_index() as u16) << 6) | (promotion << 12)
+}
+
+/// The move written by `encode_move`, or `None` if no move encodes as `bits`
+pub fn decode_move(bits: u16) -> Option<Option<ChessMove>>
+{
+    if bits == NO_MOVE {
+        return Some(None);
+    }
+
+    let promotion = match bits >> 12
+    {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None,
+    };
+    let source = chess::ALL_SQUARES[(bits & 63) as usize];
+    let dest = chess::ALL_SQUARES[((bits >> 6) & 63) as usize];
+    if !is_possible_move(source, dest, promotion.is_some()) {
+        return None;
+    }
+    Some(Some(ChessMove::new(source, dest, promotion)))
+}
+
+/// Whether some piece could ever move from `source` to `dest`: along a line like a queen, or jumping
+/// like a knight. A promotion must go from the seventh rank to the eighth, or from the second rank to
+/// the first, to the same or a neighbouring file.
+fn is_possible_move(source: Square, dest: Square, is_promotion: bool) -> bool
+{
+    let files = source.get_file().to_index().abs_diff(dest.get_file().to_index());
+    let ranks = source.get_rank().to_index().abs_diff(dest.get_rank().to_index());
+
+    if is_promotion
+    {
+        let promotes = matches!(
+            (source.get_rank(), dest.get_rank()),
+            (Rank::Seventh, Rank::Eighth) | (Rank::Second, Rank::First)
+        );
+        return promotes && files <= 1;
+    }
+
+    let is_line = (files == 0 || ranks == 0 || files == ranks) && source != dest;
+    let is_jump = (files, ranks) == (1, 2) || (files, ranks) == (2, 1);
+    is_line || is_jump
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use chess::{Board, MoveGen};
+
+    use super::*;
+
+    /// Check that every move in the tree below `position` decodes to itself, counting the castling
+    /// moves, en passant captures and promotions among them in `special_moves`
+    fn check_tree(position: &Board, depth: u32, special_moves: &mut [usize; 3])
+    {
+        for chess_move in MoveGen::new_legal(position)
+        {
+            let bits = encode_move(Some(chess_move));
+            assert_ne!(bits, NO_MOVE);
+            assert_eq!(decode_move(bits), Some(Some(chess_move)), "{chess_move} in {position}");
+
+            let moved = position.piece_on(chess_move.get_source());
+            let files = chess_move.get_source().get_file().to_index().abs_diff(chess_move.get_dest().get_file().to_index());
+            if moved == Some(Piece::King) && files == 2 {
+                special_moves[0] += 1;
+            }
+            if moved == Some(Piece::Pawn) && files == 1 && position.piece_on(chess_move.get_dest()).is_none() {
+                special_moves[1] += 1;
+            }
+            if chess_move.get_promotion().is_some() {
+                special_moves[2] += 1;
+            }
+
+            if depth > 1 {
+                check_tree(&position.make_move_new(chess_move), depth - 1, special_moves);
+            }
+        }
+    }
+
+    #[test]
+    fn test_legal_moves_round_trip()
+    {
+        // Castling rights on both wings, en passant, and promotions with and without captures, for
+        // both sides
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        let mut special_moves = [0; 3];
+        for fen in positions {
+            check_tree(&Board::from_str(fen).unwrap(), 3, &mut special_moves);
+        }
+
+        // The sweep is only worth something if it went through every kind of special move
+        assert!(special_moves.iter().all(|&count| count > 0), "castling, en passant, promotions: {special_moves:?}");
+    }
+
+    #[test]
+    fn test_no_move()
+    {
+        assert_eq!(encode_move(None), NO_MOVE);
+        assert_eq!(decode_move(NO_MOVE), Some(None));
+    }
+
+    #[test]
+    fn test_every_bit_pattern()
+    {
+        let mut decoded = 0;
+        for bits in 0..=u16::MAX
+        {
+            // Whatever decodes, encodes back to the same bits, so no two bit patterns mean the same
+            if let Some(chess_move) = decode_move(bits) {
+                assert_eq!(encode_move(chess_move), bits, "{chess_move:?}");
+                decoded += 1;
+            }
+        }
+
+        // 1456 queen moves and 336 knight moves between distinct squares, 4 promotions of 44 pawn
+        // moves onto the last ranks, and no move
+        assert_eq!(decoded, 1456 + 336 + 4 * 44 + 1);
+    }
+
+    #[test]
+    fn test_garbage_decodes_to_nothing()
+    {
+        let bits = |source: Square, dest: Square, promotion: u16| {
+            source.to_index() as u16 | (dest.to_index() as u16) << 6 | promotion << 12
+        };
+
+        // Promotions to a king or beyond
+        for promotion in 5..16 {
+            assert_eq!(decode_move(bits(Square::E7, Square::E8, promotion)), None);
+        }
+        // Moves that go nowhere, or that no piece could make
+        assert_eq!(decode_move(bits(Square::E2, Square::E2, 0)), None);
+        assert_eq!(decode_move(bits(Square::A1, Square::C4, 0)), None);
+        assert_eq!(decode_move(bits(Square::B1, Square::H2, 0)), None);
+        // Promotions away from the last ranks, or onto a file that no pawn can reach
+        assert_eq!(decode_move(bits(Square::E2, Square::E4, 4)), None);
+        assert_eq!(decode_move(bits(Square::E6, Square::E7, 4)), None);
+        assert_eq!(decode_move(bits(Square::A7, Square::C8, 1)), None);
+        assert_eq!(decode_move(bits(Square::E7, Square::E8, 0)), Some(Some(ChessMove::new(Square::E7, Square::E8, None))));
+    }
+}
@@ -0,0 +1,534 @@
+//! Snapshots of the hash table in a file, so that what a long analysis found out survives a restart
+//!
+//! A snapshot is an image of the slots of the table. It starts with a header of `HEADER_SIZE` bytes:
+//! - 8 bytes `MAGIC`
+//! - 8 bytes capacity, the number of slots
+//! - 1 byte generation of the table
+//! - 7 bytes zero, so that the slots after the header are aligned
+//!
+//! followed by every slot in table order, as the table holds it, see `Slot`: 8 bytes key and 8 bytes
+//! data, both little endian. Unused slots are all zero.
+//!
+//! Loading a snapshot into an empty table of the same capacity can use the file as the storage of the
+//! table, see "Mapped storage" of `HashMap`. Otherwise, its entries are inserted into the table, and
+//! belong to the current generation.
+//!
+//! A snapshot is written to a temporary file next to its path, and renamed to the path once it is
+//! complete, so that a crash while writing leaves the previous snapshot as it was.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use crate::clock::Instant;
+use crate::score::BoardScore;
+use super::mapping::{self, MappedFile};
+use super::packedmove::decode_move;
+use super::{HashEntry, HashMap, Slot, NUM_SLOTS_PER_HASH};
+
+const MAGIC: &[u8; 8] = b"ENGHASH2";
+
+const HEADER_SIZE: usize = 24;
+const SLOT_SIZE: usize = mem::size_of::<Slot>();
+
+/// Number of slots that `SnapshotWriter::write_chunk` goes through between looks at the clock
+const DEADLINE_CHECK_SLOTS: usize = 4096;
+
+/// What the header of a snapshot tells
+struct Header
+{
+    capacity: usize,
+    generation: u8,
+}
+
+/// A snapshot in the making, written a part of the table at a time
+pub struct SnapshotWriter
+{
+    output: BufWriter<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+    /// The capacity of the table, as the header gives it
+    capacity: usize,
+    /// The first slot of the table that is not written yet
+    next_slot: usize,
+    /// Number of entries written so far
+    written: usize,
+}
+
+impl SnapshotWriter
+{
+    /// Start writing a snapshot of `hashmap` to `path`
+    pub fn create(path: &Path, hashmap: &HashMap) -> io::Result<Self>
+    {
+        let temp_path = path_with_suffix(path, ".tmp");
+        let mut writer = SnapshotWriter {
+            output: BufWriter::new(File::create(&temp_path)?),
+            path: path.to_owned(),
+            temp_path,
+            capacity: hashmap.capacity(),
+            next_slot: 0,
+            written: 0,
+        };
+        if let Err(e) = writer.output.write_all(&encode_header(hashmap))
+        {
+            writer.abandon();
+            return Err(e);
+        }
+        Ok(writer)
+    }
+
+    /// Write the next slots of the table, until the whole table is written or the deadline has passed,
+    /// and return whether the whole table is written
+    ///
+    /// The entries may change between calls. Each slot is written as it is when it comes up, so the
+    /// snapshot misses entries that are stored in slots that have been written already.
+    pub fn write_chunk(&mut self, hashmap: &HashMap, deadline: Option<Instant>) -> io::Result<bool>
+    {
+        debug_assert_eq!(hashmap.capacity(), self.capacity, "the table was resized while writing a snapshot of it");
+        while self.next_slot < self.capacity
+        {
+            let end = (self.next_slot + DEADLINE_CHECK_SLOTS).min(self.capacity);
+            for idx in self.next_slot..end
+            {
+                let slot = hashmap.get_slot(idx);
+                let data = slot.data.load(Ordering::Relaxed);
+                let key = slot.key.load(Ordering::Relaxed);
+                self.output.write_all(&encode_slot(key, data))?;
+                self.written += (data != 0) as usize;
+            }
+            self.next_slot = end;
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+        }
+
+        // Have the disk catch up with every chunk, rather than with the whole table when finishing
+        self.output.flush()?;
+        self.output.get_ref().sync_data()?;
+        Ok(self.next_slot >= self.capacity)
+    }
+
+    /// Replace the file at the path with the snapshot, and return the number of entries in it
+    pub fn finish(self) -> io::Result<usize>
+    {
+        let file = self.output.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        Ok(self.written)
+    }
+
+    /// Give up on the snapshot, leaving the file at the path as it was
+    pub fn abandon(self)
+    {
+        drop(self.output);
+        // Nothing is lost if the temporary file can't be removed, it is overwritten by the next snapshot
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+/// Write a snapshot of the whole table to `path` at once, and return the number of entries in it
+pub fn save(hashmap: &HashMap, path: &Path) -> io::Result<usize>
+{
+    let mut writer = SnapshotWriter::create(path, hashmap)?;
+    match writer.write_chunk(hashmap, None)
+    {
+        Ok(_) => writer.finish(),
+        Err(e) => {
+            writer.abandon();
+            Err(e)
+        }
+    }
+}
+
+/// Load the entries of the snapshot at `path` into the table, and return how many there were
+///
+/// With the `mmap` feature, a snapshot of a table of the same capacity, loaded into an empty table,
+/// becomes the storage of the table, see "Mapped storage" of `HashMap`. The table then continues from
+/// the generation of the snapshot. Otherwise, and where mapping fails, the entries are read through a
+/// buffer and inserted, since those of a table of another capacity belong in other slots anyway.
+///
+/// A damaged snapshot gives an `InvalidData` error. When the entries are read through a buffer, those
+/// before the damage may have been inserted by then, but none are unless the header is valid. Mapped
+/// storage is only used once every slot has been checked.
+pub fn load(hashmap: &mut HashMap, path: &Path) -> io::Result<usize>
+{
+    let file = File::open(path)?;
+    if hashmap.is_empty()
+    {
+        if let Some(mapped) = mapping::map(&file)
+        {
+            if decode_header(mapped.bytes())?.capacity == hashmap.capacity() {
+                return load_mapped(hashmap, mapped);
+            }
+        }
+    }
+    load_from_reader(hashmap, BufReader::new(file))
+}
+
+fn invalid_data(message: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// `load` by using the mapped snapshot as the storage of the table, which is empty and of the capacity
+/// of the snapshot
+fn load_mapped(hashmap: &mut HashMap, mapped: MappedFile) -> io::Result<usize>
+{
+    let header = decode_header(mapped.bytes())?;
+    let slots = &mapped.bytes()[HEADER_SIZE..];
+    if slots.len() != header.capacity * SLOT_SIZE {
+        return Err(invalid_data("snapshot size doesn't match its capacity"));
+    }
+
+    let (mut loaded, mut filled) = (0, 0);
+    for slot in slots.chunks_exact(SLOT_SIZE)
+    {
+        if let Some(entry) = decode_slot(slot.try_into().unwrap())?
+        {
+            loaded += 1;
+            filled += (entry.generation == header.generation) as usize;
+        }
+    }
+
+    hashmap.use_mapped_storage(mapped, HEADER_SIZE, header.generation, filled);
+    Ok(loaded)
+}
+
+/// `load` from a snapshot that is read as it goes, inserting its entries
+fn load_from_reader(hashmap: &mut HashMap, mut input: impl BufRead) -> io::Result<usize>
+{
+    let mut header = [0; HEADER_SIZE];
+    match input.read_exact(&mut header)
+    {
+        Ok(()) => { },
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(invalid_data("not a hash snapshot")),
+        Err(e) => return Err(e),
+    }
+    let header = decode_header(&header)?;
+
+    let (mut slots, mut loaded) = (0, 0);
+    while !input.fill_buf()?.is_empty()
+    {
+        let mut slot = [0; SLOT_SIZE];
+        match input.read_exact(&mut slot)
+        {
+            Ok(()) => { },
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(invalid_data("snapshot ends in the middle of a slot")),
+            Err(e) => return Err(e),
+        }
+        slots += 1;
+
+        if let Some(entry) = decode_slot(&slot)?
+        {
+            hashmap.insert_entry(entry);
+            loaded += 1;
+        }
+    }
+
+    if slots != header.capacity {
+        return Err(invalid_data("snapshot size doesn't match its capacity"));
+    }
+    Ok(loaded)
+}
+
+/// `path` with `suffix` added to the end of the file name, like "analysis.hash.tmp" for
+/// "analysis.hash"
+pub fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf
+{
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn encode_header(hashmap: &HashMap) -> [u8; HEADER_SIZE]
+{
+    let mut header = [0; HEADER_SIZE];
+    header[0..8].copy_from_slice(MAGIC);
+    header[8..16].copy_from_slice(&(hashmap.capacity() as u64).to_le_bytes());
+    header[16] = hashmap.generation;
+    header
+}
+
+/// The header at the start of `snapshot`, which may go on with the slots
+fn decode_header(snapshot: &[u8]) -> io::Result<Header>
+{
+    let header = snapshot.get(..HEADER_SIZE)
+        .filter(|header| header.starts_with(MAGIC))
+        .ok_or_else(|| invalid_data("not a hash snapshot"))?;
+
+    let capacity = usize::try_from(u64::from_le_bytes(header[8..16].try_into().unwrap()))
+        .ok()
+        .filter(|&capacity| capacity >= NUM_SLOTS_PER_HASH && capacity.checked_mul(SLOT_SIZE).is_some());
+    let Some(capacity) = capacity else {
+        return Err(invalid_data("invalid capacity in snapshot header"));
+    };
+    if header[17..].iter().any(|&byte| byte != 0) {
+        return Err(invalid_data("invalid snapshot header"));
+    }
+    Ok(Header { capacity, generation: header[16] })
+}
+
+fn encode_slot(key: u64, data: u64) -> [u8; SLOT_SIZE]
+{
+    let mut slot = [0; SLOT_SIZE];
+    slot[0..8].copy_from_slice(&key.to_le_bytes());
+    slot[8..16].copy_from_slice(&data.to_le_bytes());
+    slot
+}
+
+/// The entry in a slot, `None` if the slot is unused, or an error if the slot is not one that the
+/// table could have left behind, see `HashEntry::pack`
+fn decode_slot(slot: &[u8; SLOT_SIZE]) -> io::Result<Option<HashEntry>>
+{
+    let key = u64::from_le_bytes(slot[0..8].try_into().unwrap());
+    let data = u64::from_le_bytes(slot[8..16].try_into().unwrap());
+    if key == 0 && data == 0 {
+        return Ok(None);
+    }
+
+    // Entry kind in bits 0-1, which is neither unused nor beyond full, score type in bits 2-3, and
+    // nothing in the bits of the entry info and above the generation that `pack` leaves zero
+    let info = data as u8;
+    let is_valid_info = matches!(info & 0b11, 1 | 2) && info & 0b1100 != 0b1100 && info >> 4 == 0;
+    let is_valid_move = decode_move((data >> 8) as u16).is_some();
+    let entry = HashEntry::unpack(key ^ data, data);
+    if !is_valid_info || !is_valid_move || data >> 56 != 0 || entry.score == BoardScore::NO_SCORE {
+        return Err(invalid_data("invalid entry in snapshot"));
+    }
+    Ok(Some(entry))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove};
+
+    use crate::hash::Storage;
+    use crate::score::BoundedScore;
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("snapshot-test-{name}-{}.hash", std::process::id()))
+    }
+
+    /// A table with entries of every bound, with and without best moves and promotions
+    fn fixture() -> (HashMap, Vec<(u64, BoundedScore, u8, Option<ChessMove>)>)
+    {
+        let promotion_position = Board::from_str("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        let contents = vec![
+            (Board::default().get_hash(), BoundedScore::Exact(BoardScore::evaluation(25)), 12, Some(ChessMove::from_str("e2e4").unwrap())),
+            (promotion_position.get_hash(), BoundedScore::LowerBound(BoardScore::MATE.increment_mate_plies()), 255, Some(ChessMove::from_str("e7e8q").unwrap())),
+            (0x0123_4567_89ab_cdef, BoundedScore::UpperBound(BoardScore::evaluation(-310)), 3, None),
+            (0xfedc_ba98_7654_3210, BoundedScore::Exact(BoardScore::EVEN), 0, Some(ChessMove::from_str("h7h8n").unwrap())),
+        ];
+
+        let hashmap = HashMap::new(1);
+        for &(hash, score, depth, best_move) in &contents {
+            hashmap.insert_entry(HashEntry::with_contents(hash, best_move, score, depth));
+        }
+        (hashmap, contents)
+    }
+
+    fn assert_contents(hashmap: &HashMap, contents: &[(u64, BoundedScore, u8, Option<ChessMove>)])
+    {
+        for &(hash, score, depth, best_move) in contents
+        {
+            let probe = hashmap.probe(hash).unwrap();
+            assert_eq!((probe.score, probe.depth, probe.best_move), (score, depth, best_move), "{hash:016x}");
+            assert_eq!(probe.age, 0);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load()
+    {
+        let (hashmap, contents) = fixture();
+        let path = temp_path("roundtrip");
+        assert_eq!(save(&hashmap, &path).unwrap(), contents.len());
+        assert!(!path_with_suffix(&path, ".tmp").exists());
+        assert_eq!(fs::metadata(&path).unwrap().len() as usize, HEADER_SIZE + hashmap.capacity() * SLOT_SIZE);
+
+        // Into an empty table of the same capacity, mapped where the build supports it
+        let mut loaded = HashMap::new(1);
+        assert_eq!(load(&mut loaded, &path).unwrap(), contents.len());
+        assert_eq!(matches!(loaded.storage, Storage::Mapped(_)), mapping::is_supported());
+        assert_contents(&loaded, &contents);
+        assert_eq!(loaded.filled(), contents.len());
+
+        // Into a table of another capacity, and into one with entries already, by inserting
+        let mut larger = HashMap::new(2);
+        assert_eq!(load(&mut larger, &path).unwrap(), contents.len());
+        assert!(matches!(larger.storage, Storage::Allocated(_)));
+        assert_contents(&larger, &contents);
+
+        let mut used = HashMap::new(1);
+        let position = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        used.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 7));
+        assert_eq!(load(&mut used, &path).unwrap(), contents.len());
+        assert!(matches!(used.storage, Storage::Allocated(_)));
+        assert_contents(&used, &contents);
+        assert!(used.get(&position).is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mapped_storage()
+    {
+        let (mut hashmap, contents) = fixture();
+        hashmap.new_generation();
+        hashmap.new_generation();
+        let path = temp_path("mapped");
+        save(&hashmap, &path).unwrap();
+        let snapshot = fs::read(&path).unwrap();
+
+        let mut loaded = HashMap::new(1);
+        loaded.set_collision_detection(true);
+        load(&mut loaded, &path).unwrap();
+        if !mapping::is_supported() {
+            fs::remove_file(&path).unwrap();
+            return;
+        }
+
+        // The mapped table continues from the generation of the snapshot, and its entries are of the
+        // generation they were stored in
+        assert_eq!(loaded.generation, 2);
+        assert_eq!(loaded.filled(), 0);
+        assert_eq!(loaded.probe(contents[0].0).unwrap().age, 2);
+        assert_eq!(loaded.detected_collisions(), Some(0));
+
+        // Writes copy the pages of the mapping, and leave the file as it was, before and after the
+        // table is cleared and dropped
+        let position = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        loaded.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 7));
+        assert!(loaded.get(&position).is_some());
+        assert_eq!(fs::read(&path).unwrap(), snapshot);
+        loaded.clear();
+        assert!(loaded.is_empty());
+        loaded.insert(&position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 7));
+        drop(loaded);
+        assert_eq!(fs::read(&path).unwrap(), snapshot);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_in_chunks()
+    {
+        let (hashmap, contents) = fixture();
+        let path = temp_path("chunks");
+
+        // A deadline that has passed already writes one part of the table per call
+        let mut writer = SnapshotWriter::create(&path, &hashmap).unwrap();
+        let mut calls = 1;
+        while !writer.write_chunk(&hashmap, Some(Instant::now())).unwrap() {
+            calls += 1;
+        }
+        assert_eq!(calls, hashmap.capacity().div_ceil(DEADLINE_CHECK_SLOTS));
+        assert!(!path.exists(), "the snapshot only appears when finished");
+        assert_eq!(writer.finish().unwrap(), contents.len());
+
+        let mut loaded = HashMap::new(1);
+        let count = load(&mut loaded, &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(count.unwrap(), contents.len());
+
+        // An abandoned snapshot leaves the previous one as it was
+        let path = temp_path("abandon");
+        fs::write(&path, b"previous").unwrap();
+        let mut writer = SnapshotWriter::create(&path, &hashmap).unwrap();
+        writer.write_chunk(&hashmap, Some(Instant::now())).unwrap();
+        writer.abandon();
+        let previous = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(previous, b"previous");
+        assert!(!path_with_suffix(&path, ".tmp").exists());
+    }
+
+    /// Load `contents` both by mapping, where the build supports it, and by reading, into an empty
+    /// table of the capacity of the fixture, and return the errors and the tables
+    fn load_both_ways(contents: &[u8]) -> Vec<(io::Result<usize>, HashMap)>
+    {
+        let mut results = Vec::new();
+
+        let path = temp_path(&format!("damaged-{}", contents.len()));
+        fs::write(&path, contents).unwrap();
+        if let Some(mapped) = mapping::map(&File::open(&path).unwrap())
+        {
+            let mut hashmap = HashMap::new(1);
+            results.push((load_mapped(&mut hashmap, mapped), hashmap));
+        }
+        fs::remove_file(&path).unwrap();
+
+        let mut hashmap = HashMap::new(1);
+        results.push((load_from_reader(&mut hashmap, contents), hashmap));
+        results
+    }
+
+    #[test]
+    fn test_damaged_snapshots()
+    {
+        let (hashmap, _) = fixture();
+        let path = temp_path("damaged");
+        save(&hashmap, &path).unwrap();
+        let snapshot = fs::read(&path).unwrap();
+        let first_used = HEADER_SIZE + snapshot[HEADER_SIZE..].chunks_exact(SLOT_SIZE).position(|slot| slot.iter().any(|&b| b != 0)).unwrap() * SLOT_SIZE;
+
+        // A score type that doesn't exist, and a promotion to a king
+        let mut bad_bound = snapshot.clone();
+        bad_bound[first_used + 8] |= 0b1100;
+        let mut bad_move = snapshot.clone();
+        bad_move[first_used + 10] = 5 << 4;
+        let damaged = [
+            snapshot[..snapshot.len() - 1].to_vec(),
+            snapshot[..snapshot.len() - SLOT_SIZE].to_vec(),
+            [snapshot.clone(), vec![0; SLOT_SIZE]].concat(),
+            bad_bound,
+            bad_move,
+        ];
+
+        for contents in damaged
+        {
+            fs::write(&path, &contents).unwrap();
+            let error = load(&mut HashMap::new(1), &path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{error}");
+
+            // Both ways of loading reject it, whichever one `load` took, and mapped storage is only
+            // used once every slot is checked
+            for (result, hashmap) in load_both_ways(&contents)
+            {
+                let error = result.unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{error}");
+                assert!(matches!(hashmap.storage, Storage::Allocated(_)));
+            }
+        }
+
+        // A damaged header is rejected before any entry is inserted
+        let mut bad_magic = snapshot.clone();
+        bad_magic[0] = b'X';
+        let mut bad_capacity = snapshot.clone();
+        bad_capacity[8..16].copy_from_slice(&0u64.to_le_bytes());
+        let mut bad_padding = snapshot.clone();
+        bad_padding[HEADER_SIZE - 1] = 1;
+        for contents in [b"ENGHASH".to_vec(), bad_magic, bad_capacity, bad_padding]
+        {
+            fs::write(&path, &contents).unwrap();
+            assert_eq!(load(&mut HashMap::new(1), &path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+            for (result, hashmap) in load_both_ways(&contents)
+            {
+                assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                assert!(hashmap.is_empty());
+            }
+        }
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(load(&mut HashMap::new(1), &path).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(save(&hashmap, Path::new("/nonexistent/dir/file.hash")).is_err());
+    }
+}
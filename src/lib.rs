@@ -0,0 +1,153 @@
+//! A UCI chess engine, and the parts of it that are useful without the search: perft, static exchange
+//! evaluation, notation, evaluation, game results and board geometry
+//!
+//! The public API is what this file exports, and `prelude` has its functions and types for a glob
+//! import. The search is exported for plugging in an `Evaluator` of one's own, with
+//! `Searcher::with_evaluator`, or `SearchInterface::with_evaluator` for searching on a thread of its
+//! own. The hash table and the UCI client stay internal to the crate. The engine binary reaches them
+//! through `binary`, which is not part of the API.
+//!
+//! The UCI client searches on a thread of its own, and needs the `std-threads` feature, which is on by
+//! default. Without it, as for WebAssembly, the search runs on the thread that starts it: `wasm` is
+//! the interface for JavaScript, and `clock` lets the host supply the time.
+//!
+//! Positions and moves are those of the `chess` crate, which is exported as `engine::chess`, so that
+//! the versions match.
+//!
+//! ```
+//! use engine::prelude::*;
+//!
+//! let position = Board::default();
+//! assert_eq!(perft(&position, 2), 400);
+//! assert_eq!(evaluate(&position), BoardScore::EVEN);
+//! ```
+
+#![feature(str_split_whitespace_remainder)]
+// Without the UCI client, the options of the search that only it sets go unused
+#![cfg_attr(not(feature = "std-threads"), allow(dead_code))]
+
+#[cfg(feature = "std-threads")]
+mod bench;
+mod boardutil;
+pub mod clock;
+#[cfg(feature = "std-threads")]
+mod book;
+#[cfg(feature = "std-threads")]
+mod commandlog;
+#[cfg(feature = "std-threads")]
+mod epd;
+mod evaluation;
+mod fen;
+mod gameresult;
+pub mod geometry;
+mod hash;
+#[cfg(feature = "std-threads")]
+mod matesolver;
+mod moveorder;
+mod output;
+mod overrides;
+mod params;
+mod perft;
+mod rootmoves;
+pub mod san;
+mod score;
+mod search;
+#[cfg(feature = "std-threads")]
+mod searchinterface;
+mod see;
+mod stopconditions;
+mod timeman;
+#[cfg(feature = "std-threads")]
+mod uci;
+mod values;
+mod version;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+mod zobrist;
+
+use chess::Board;
+
+pub use chess;
+pub use evaluation::EvalBreakdown;
+pub use evaluation::Evaluator;
+pub use evaluation::SharedEvaluator;
+pub use gameresult::game_result;
+pub use gameresult::DrawReason;
+pub use gameresult::GameResult;
+pub use perft::perft;
+pub use rootmoves::canonical_root;
+pub use score::BoardScore;
+pub use search::Searcher;
+#[cfg(feature = "std-threads")]
+pub use searchinterface::SearchInterface;
+pub use see::move_gain as see;
+pub use stopconditions::StopConditions;
+pub use version::build_info;
+pub use version::version;
+
+/// The functions and types that the library exports, for `use engine::prelude::*`
+pub mod prelude
+{
+    pub use chess::{Board, ChessMove, Color, Piece, Square};
+    pub use crate::gameresult::{game_result, DrawReason, GameResult};
+    pub use crate::geometry;
+    pub use crate::perft::perft;
+    pub use crate::rootmoves::canonical_root;
+    pub use crate::san::{from_san, to_san};
+    pub use crate::see::move_gain as see;
+    pub use crate::{evaluate, trace, BoardScore, EvalBreakdown, Evaluator, SharedEvaluator};
+    pub use crate::{Searcher, StopConditions};
+    #[cfg(feature = "std-threads")]
+    pub use crate::SearchInterface;
+}
+
+/// The static evaluation of `board` from the point of view of the side to move, as the search sees
+/// it with the default options
+///
+/// ```
+/// use std::str::FromStr;
+/// use engine::chess::Board;
+/// use engine::BoardScore;
+///
+/// // White is a knight up, and Black is to move
+/// let board = Board::from_str("4k3/pppp4/8/8/8/8/PPPP4/3NK3 b - - 0 1").unwrap();
+/// assert!(engine::evaluate(&board) < BoardScore::EVEN);
+/// ```
+pub fn evaluate(board: &Board) -> BoardScore
+{
+    evaluation::evaluate(board, evaluation::EvalTerms::default(), &params::EngineParams::default())
+}
+
+/// The static evaluation like `evaluate`, broken down into the terms that it adds up
+///
+/// ```
+/// use engine::chess::Board;
+///
+/// let trace = engine::trace(&Board::default());
+/// assert_eq!(trace.terms[0], ("material", 0));
+/// assert_eq!(trace.score, engine::evaluate(&Board::default()));
+/// ```
+pub fn trace(board: &Board) -> EvalBreakdown
+{
+    evaluation::breakdown(board, evaluation::EvalTerms::default(), &params::EngineParams::default())
+}
+
+/// What the engine binary runs, which is not part of the API
+#[doc(hidden)]
+#[cfg(feature = "std-threads")]
+pub mod binary
+{
+    /// Run the bench with the arguments of the command line, see `UciClient::command_line_bench`
+    pub fn bench(arguments: &str) -> bool
+    {
+        crate::uci::UciClient::new().command_line_bench(arguments)
+    }
+
+    /// Talk UCI over standard input and output until told to quit
+    pub fn uci()
+    {
+        crate::commandlog::install_panic_hook();
+        let mut uci = crate::uci::UciClient::new();
+        uci.main_loop();
+    }
+}
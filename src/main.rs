@@ -1,16 +1,25 @@
-#![feature(str_split_whitespace_remainder)]
-
-mod evaluation;
-mod hash;
-mod moveorder;
-mod score;
-mod search;
-mod searchinterface;
-mod uci;
-
 fn main()
 {
-    println!("Hello, world!");
-    let mut uci = uci::UciClient::new();
-    uci.main_loop();
+    if std::env::args().nth(1).as_deref() == Some("--version")
+    {
+        println!("{}", engine::version());
+        println!("{}", engine::build_info());
+        return;
+    }
+
+    // The bench and its regression gate, with a nonzero exit code when they fail
+    if std::env::args().nth(1).as_deref() == Some("bench")
+    {
+        let arguments = std::env::args().skip(2).collect::<Vec<_>>().join(" ");
+        let succeeded = engine::binary::bench(&arguments);
+        std::process::exit(if succeeded { 0 } else { 1 });
+    }
+
+    // Only greet people at a terminal. GUIs, and strict tournament wrappers most of all, expect
+    // nothing before the uci command.
+    if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        println!("Hello, world!");
+        println!("info string {}, build {}", engine::version(), engine::build_info());
+    }
+    engine::binary::uci();
 }
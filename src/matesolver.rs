@@ -0,0 +1,150 @@
+//! Exhaustive proof of mates in a given number of moves, for verifying composed problems
+//!
+//! The search finds mates, but it reduces late moves, so a mate it doesn't find may still exist. A
+//! composer's claim of mate in N needs a proof either way, so this tries every move of both sides up to
+//! the number of moves given. The attacker's moves stop at the first one that mates, the defender's at
+//! the first one that escapes, and positions already decided are remembered. That is quick enough for
+//! the mates in two to four moves of problems, though not for long ones.
+//!
+//! As problem convention has it, there are no draws by repetition or by the fifty-move rule.
+
+use std::collections::HashMap;
+
+use chess::{Board, BoardStatus, ChessMove, MoveGen};
+
+/// Most moves that `verify_mate` is asked to prove mates in, since the work grows exponentially
+pub const MAX_MATE_MOVES: u32 = 5;
+
+/// What a first move of the side to move achieves, as found by `verify_mate`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyCandidate
+{
+    pub chess_move: ChessMove,
+    /// The fewest moves, counting this one, in which it forces mate, or `None` if it doesn't within
+    /// the moves given
+    pub mate_moves: Option<u32>,
+}
+
+/// Find out for every legal move in `position` whether it forces mate within `moves` moves, counting
+/// itself, and how soon
+pub fn verify_mate(position: &Board, moves: u32) -> Vec<KeyCandidate>
+{
+    let mut solver = Solver::default();
+    MoveGen::new_legal(position)
+        .map(|chess_move| {
+            let after = position.make_move_new(chess_move);
+            let mate_moves = (1..=moves).find(|&n| solver.is_mated_within(&after, n - 1));
+            KeyCandidate { chess_move, mate_moves }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Solver
+{
+    /// Whether the side to move forces mate within some number of moves, by hash and number of moves.
+    /// Two positions of one problem with the same hash are unlikely enough to ignore.
+    can_mate: HashMap<(u64, u32), bool>,
+}
+
+impl Solver
+{
+    /// Whether the side to move can force mate in `moves` moves or fewer
+    fn can_mate(&mut self, position: &Board, moves: u32) -> bool
+    {
+        if moves == 0 {
+            return false;
+        }
+        if let Some(&known) = self.can_mate.get(&(position.get_hash(), moves)) {
+            return known;
+        }
+
+        let result = MoveGen::new_legal(position).any(|chess_move| {
+            let after = position.make_move_new(chess_move);
+            // The mating move gives check, so the replies to the last moves only matter after checks
+            if moves == 1 && *after.checkers() == chess::EMPTY {
+                return false;
+            }
+            self.is_mated_within(&after, moves - 1)
+        });
+        self.can_mate.insert((position.get_hash(), moves), result);
+        result
+    }
+
+    /// Whether the side to move is checkmated, or will be within `moves` moves of the opponent whatever
+    /// it plays
+    fn is_mated_within(&mut self, position: &Board, moves: u32) -> bool
+    {
+        match position.status()
+        {
+            BoardStatus::Checkmate => true,
+            BoardStatus::Stalemate => false,
+            BoardStatus::Ongoing => {
+                moves > 0 && MoveGen::new_legal(position).all(|reply| self.can_mate(&position.make_move_new(reply), moves))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    /// The moves that mate within `moves`, with the number of moves they take, in coordinate notation
+    fn keys(fen: &str, moves: u32) -> Vec<(String, u32)>
+    {
+        let position = Board::from_str(fen).unwrap();
+        let candidates = verify_mate(&position, moves);
+        assert_eq!(candidates.len(), MoveGen::new_legal(&position).len());
+        candidates.into_iter()
+            .filter_map(|c| c.mate_moves.map(|n| (c.chess_move.to_string(), n)))
+            .collect()
+    }
+
+    /// Morphy's mate in two: 1. Ra6 bxa6 2. b7#, and the bishop can't get away from a7 either
+    const MORPHY: &str = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+    /// Philidor's legacy: 1. Nh6+ Kh8 2. Qg8+ Rxg8 3. Nf7#
+    const PHILIDOR: &str = "5rk1/5Npp/8/8/2Q5/8/6PP/6K1 w - - 0 1";
+
+    #[test]
+    fn test_unique_keys()
+    {
+        assert_eq!(keys(MORPHY, 2), [("a1a6".to_owned(), 2)]);
+        assert_eq!(keys(PHILIDOR, 3), [("f7h6".to_owned(), 3)]);
+    }
+
+    #[test]
+    fn test_no_mate_in_fewer_moves()
+    {
+        assert!(keys(MORPHY, 1).is_empty());
+        assert!(keys(PHILIDOR, 2).is_empty());
+    }
+
+    #[test]
+    fn test_shorter_mate()
+    {
+        // Claimed as a mate in three, the key mates in two
+        assert_eq!(keys(MORPHY, 3), [("a1a6".to_owned(), 2)]);
+    }
+
+    #[test]
+    fn test_dual_solutions()
+    {
+        // A queen mates in one on the back rank, and in two from many squares
+        const QUEEN: &str = "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1";
+        assert_eq!(keys(QUEEN, 1), [("b1b8".to_owned(), 1)]);
+        let keys = keys(QUEEN, 2);
+        assert!(keys.contains(&("b1b8".to_owned(), 1)) && keys.contains(&("b1h1".to_owned(), 2)), "{keys:?}");
+        assert!(keys.iter().filter(|&(_, n)| *n == 2).count() > 1, "{keys:?}");
+    }
+
+    #[test]
+    fn test_no_legal_moves()
+    {
+        // Checkmated already
+        assert!(keys("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", 2).is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+//! The flush policy for output to the GUI
+//!
+//! GUIs wait for some lines before they do anything else: `uciok`, `readyok`, `bestmove`, and the
+//! info lines of a running search. Those are printed with `send`, which flushes them right away.
+//! Standard output happens to flush at every newline as it is, but that is a detail of the standard
+//! library which any buffering in between would undo, so it is not relied upon.
+//!
+//! Bulk output, like the differences of a bench comparison, is collected in a `Batch` and printed with
+//! a single flush at the end.
+//!
+//! Errors are printed as "ERROR: " lines, which people at a terminal notice. Strict UCI mode, for
+//! tournament wrappers that log anything outside the protocol as a violation, sends them as info
+//! strings instead, see `set_strict`.
+//!
+//! Writing fails once the GUI has closed the pipe, typically because it crashed. Where `println!`
+//! would panic, the output here is dropped instead, and `is_disconnected` tells the engine to shut
+//! down.
+//!
+//! The helper threads of a search print nothing, see `silence_this_thread`. Hosts without standard
+//! output, like a web page, take the lines of the search from a sink instead, see `set_sink`.
+//!
+//! For diagnosing problems inside a GUI, the whole conversation can be recorded in a file, see
+//! `set_traffic_log`. Everything sent through here is recorded, and the UCI client records the lines it
+//! reads with `log_input`.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::{Display, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether writing to standard output has failed
+static DISCONNECTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether only output that the UCI protocol defines may be sent
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// The file that the conversation with the GUI is recorded in, if any
+static TRAFFIC_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+thread_local! {
+    /// Whether output from this thread is dropped
+    static SILENT: Cell<bool> = const { Cell::new(false) };
+    /// Where output from this thread goes instead of standard output, if anywhere
+    static SINK: RefCell<Option<Box<dyn FnMut(&str)>>> = const { RefCell::new(None) };
+}
+
+/// Print a line that the GUI may be waiting for, and flush it
+pub fn send(line: impl Display)
+{
+    write(format_args!("{line}\n"));
+}
+
+/// Print an error about a command or an option, and flush it
+pub fn error(message: impl Display)
+{
+    if is_strict() {
+        send(format_args!("info string ERROR: {message}"));
+    } else {
+        send(format_args!("ERROR: {message}"));
+    }
+}
+
+/// Turn strict UCI mode on or off. In strict mode, errors are sent as info strings, and the UCI client
+/// refuses its extension commands.
+pub fn set_strict(strict: bool)
+{
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool
+{
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Record the conversation with the GUI in the file at `path` from now on, or stop recording with
+/// `None`. The file is appended to, so that it can collect several sessions. If it can't be opened,
+/// nothing is recorded.
+pub fn set_traffic_log(path: Option<&Path>) -> io::Result<()>
+{
+    let mut traffic_log = lock_traffic_log();
+    *traffic_log = None;
+    if let Some(path) = path {
+        *traffic_log = Some(OpenOptions::new().create(true).append(true).open(path)?);
+    }
+    Ok(())
+}
+
+/// Record a line read from the GUI
+pub fn log_input(line: &str)
+{
+    log_traffic('<', line);
+}
+
+/// Record each line of `text` with the time and the direction, `<` for input and `>` for output. Every
+/// line is written on its own, so that the log is complete up to a crash.
+fn log_traffic(direction: char, text: &str)
+{
+    let mut traffic_log = lock_traffic_log();
+    let Some(file) = traffic_log.as_mut() else {
+        return;
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    for line in text.lines() {
+        let entry = format!("{}.{:03} {direction} {line}\n", timestamp.as_secs(), timestamp.subsec_millis());
+        if file.write_all(entry.as_bytes()).and_then(|()| file.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// The log stays usable even if a thread panicked while writing to it, since a line is written whole
+fn lock_traffic_log() -> MutexGuard<'static, Option<File>>
+{
+    TRAFFIC_LOG.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether the GUI has gone away, so that there is nobody left to search for
+pub fn is_disconnected() -> bool
+{
+    DISCONNECTED.load(Ordering::Relaxed)
+}
+
+/// Drop all output from the current thread from now on. The helper threads of a search use this, since
+/// only the main search thread reports to the GUI.
+pub fn silence_this_thread()
+{
+    SILENT.set(true);
+}
+
+/// Hand the output from the current thread to `sink` a line at a time from now on, without the
+/// newlines, or print it again with `None`. The sink must not send output itself.
+pub fn set_sink(sink: Option<Box<dyn FnMut(&str)>>)
+{
+    SINK.set(sink);
+}
+
+fn write(text: impl Display)
+{
+    if SILENT.get() {
+        return;
+    }
+    let text = text.to_string();
+    log_traffic('>', &text);
+    let is_sunk = SINK.with_borrow_mut(|sink| {
+        let Some(sink) = sink else {
+            return false;
+        };
+        text.lines().for_each(|line| sink(line));
+        true
+    });
+    if is_sunk {
+        return;
+    }
+    let mut stdout = io::stdout().lock();
+    if write!(stdout, "{text}").and_then(|()| stdout.flush()).is_err() {
+        DISCONNECTED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Lines of bulk output, printed together when the batch is sent
+#[derive(Default)]
+pub struct Batch
+{
+    text: String,
+}
+
+impl Batch
+{
+    pub fn new() -> Self
+    {
+        Batch::default()
+    }
+
+    pub fn line(&mut self, line: impl Display)
+    {
+        writeln!(self.text, "{line}").expect("writing to a String can't fail");
+    }
+
+    pub fn send(self)
+    {
+        write(self.text);
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_batch_collects_lines()
+    {
+        let mut batch = Batch::new();
+        batch.line("info string one");
+        batch.line(format_args!("info string {}", 2));
+        assert_eq!(batch.text, "info string one\ninfo string 2\n");
+    }
+
+    #[test]
+    fn test_sink()
+    {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let sunk = Rc::clone(&lines);
+        set_sink(Some(Box::new(move |line| sunk.borrow_mut().push(line.to_owned()))));
+        send("info depth 1");
+        let mut batch = Batch::new();
+        batch.line("info string one");
+        batch.line("info string two");
+        batch.send();
+        set_sink(None);
+        send("info string printed");
+
+        assert_eq!(*lines.borrow(), ["info depth 1", "info string one", "info string two"]);
+    }
+
+    #[test]
+    fn test_traffic_log()
+    {
+        let path = std::env::temp_dir().join(format!("traffic-log-test-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        set_traffic_log(Some(&path)).unwrap();
+        log_input("isready");
+        send("readyok");
+        let mut batch = Batch::new();
+        batch.line("info string one");
+        batch.line("info string two");
+        batch.send();
+        set_traffic_log(None).unwrap();
+        log_input("quit");
+
+        // Other tests may send output meanwhile, which gets recorded too
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let entries = log.lines()
+            .map(|line| line.split_once(' ').unwrap())
+            .inspect(|(timestamp, _)| assert!(timestamp.parse::<f64>().is_ok(), "{log}"))
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+        for entry in ["< isready", "> readyok", "> info string one", "> info string two"] {
+            assert!(entries.contains(&entry), "no \"{entry}\" in {log}");
+        }
+        assert!(!entries.contains(&"< quit"), "{log}");
+    }
+}
@@ -0,0 +1,229 @@
+//! Evaluation overrides: scores that an analyst pins to positions the engine misjudges, in a file that
+//! persists from session to session
+//!
+//! Wherever the search reaches a position with an override, it takes the override as the exact score
+//! of the position, instead of searching or evaluating it, and without looking it up in the hash table.
+//! Positions are keyed by the hash of `canonical_root`, so that an override applies however the en
+//! passant square of the position was given.
+//!
+//! An overrides file is text, with one override per line: the score in centipawns, from the point of
+//! view of the side to move, followed by the FEN of the position. Empty lines and lines starting with
+//! `#` are skipped.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use chess::Board;
+use crate::fen::parse_fen;
+use crate::hash::snapshot;
+use crate::rootmoves::canonical_root;
+use crate::score::BoardScore;
+
+/// Overrides are evaluations, and must stay well clear of the mate scores
+pub const MAX_CENTIPAWNS: i16 = 10_000;
+
+/// A score pinned to a position
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Override
+{
+    pub fen: String,
+    pub centipawns: i16,
+}
+
+/// The overrides by the hash of the canonical position
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct EvalOverrides
+{
+    scores: HashMap<u64, Override>,
+}
+
+impl EvalOverrides
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.scores.is_empty()
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.scores.len()
+    }
+
+    /// The overridden score of `position`, if it has one
+    ///
+    /// This is called at every node of the search, so it only canonicalizes the position when it has
+    /// an en passant square and isn't found as it is.
+    pub fn get(&self, position: &Board) -> Option<BoardScore>
+    {
+        let found = self.scores.get(&position.get_hash()).or_else(|| {
+            position.en_passant().and_then(|_| self.scores.get(&canonical_root(position).get_hash()))
+        });
+        found.map(|o| BoardScore::evaluation(o.centipawns))
+    }
+
+    /// Pin `centipawns` to `position`, replacing any override it had. Returns whether it had one.
+    pub fn set(&mut self, position: &Board, centipawns: i16) -> bool
+    {
+        debug_assert!(centipawns.abs() <= MAX_CENTIPAWNS);
+        let position = canonical_root(position);
+        let fen = position.to_string();
+        self.scores.insert(position.get_hash(), Override { fen, centipawns }).is_some()
+    }
+
+    /// Remove the override of `position`. Returns whether it had one.
+    pub fn clear(&mut self, position: &Board) -> bool
+    {
+        self.scores.remove(&canonical_root(position).get_hash()).is_some()
+    }
+
+    /// The overrides, sorted by FEN
+    pub fn list(&self) -> Vec<&Override>
+    {
+        let mut overrides = self.scores.values().collect::<Vec<_>>();
+        overrides.sort_by(|a, b| a.fen.cmp(&b.fen));
+        overrides
+    }
+
+    /// Read the overrides file at `path`. A file that doesn't exist yet has no overrides.
+    ///
+    /// A line that isn't an override gives an `InvalidData` error.
+    pub fn read(path: &Path) -> io::Result<EvalOverrides>
+    {
+        let text = match fs::read_to_string(path)
+        {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(EvalOverrides::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut overrides = EvalOverrides::default();
+        for (number, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_data = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {message}", number + 1));
+
+            let (centipawns_str, fen) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let centipawns = parse_centipawns(centipawns_str).map_err(invalid_data)?;
+            let position = parse_fen(fen).map_err(invalid_data)?;
+            overrides.set(&position, centipawns);
+        }
+        Ok(overrides)
+    }
+
+    /// Write the overrides to `path`, replacing the previous file only once it is complete
+    pub fn write(&self, path: &Path) -> io::Result<()>
+    {
+        let temp_path = snapshot::path_with_suffix(path, ".tmp");
+        let result = (|| {
+            let mut output = BufWriter::new(File::create(&temp_path)?);
+            for o in self.list() {
+                writeln!(output, "{} {}", o.centipawns, o.fen)?;
+            }
+            output.into_inner().map_err(|e| e.into_error())?.sync_data()
+        })();
+
+        match result
+        {
+            Ok(()) => fs::rename(&temp_path, path),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Parse the score of an override, which must be within `MAX_CENTIPAWNS`
+pub fn parse_centipawns(word: &str) -> Result<i16, String>
+{
+    match i16::from_str(word)
+    {
+        Ok(centipawns) if centipawns.abs() <= MAX_CENTIPAWNS => Ok(centipawns),
+        Ok(_) => Err(format!("Score {word} is out of range, expected -{MAX_CENTIPAWNS} to {MAX_CENTIPAWNS} centipawns")),
+        Err(e) => Err(format!("Invalid score \"{word}\": {e}")),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use chess::ChessMove;
+
+    use super::*;
+
+    /// Black is about to play f7f5 next to a white pawn that is pinned, so that en passant isn't legal
+    const BEFORE_EN_PASSANT: &str = "4r2k/5p2/8/4P3/8/8/8/4K3 b - - 0 1";
+
+    fn after_f5() -> Board
+    {
+        Board::from_str(BEFORE_EN_PASSANT).unwrap().make_move_new(ChessMove::from_str("f7f5").unwrap())
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf
+    {
+        std::env::temp_dir().join(format!("overrides-test-{name}-{}.txt", std::process::id()))
+    }
+
+    #[test]
+    fn test_set_and_clear()
+    {
+        let start = Board::default();
+        let after_e4 = start.make_move_new(ChessMove::from_str("e2e4").unwrap());
+        let mut overrides = EvalOverrides::default();
+        assert!(!overrides.set(&after_e4, -50));
+        assert!(overrides.set(&after_e4, -70));
+        assert_eq!(overrides.len(), 1);
+        assert_eq!(overrides.get(&after_e4), Some(BoardScore::evaluation(-70)));
+        assert_eq!(overrides.get(&start), None);
+
+        assert!(overrides.clear(&after_e4));
+        assert!(!overrides.clear(&after_e4));
+        assert!(overrides.is_empty());
+        assert_eq!(overrides.get(&after_e4), None);
+    }
+
+    #[test]
+    fn test_en_passant_square()
+    {
+        // An override given without the en passant square applies to the position reached with it
+        let with_square = after_f5();
+        let without_square = Board::from_str("4r2k/8/8/4Pp2/8/8/8/4K3 w - - 0 2").unwrap();
+        let mut overrides = EvalOverrides::default();
+        overrides.set(&without_square, 120);
+        assert_eq!(overrides.get(&with_square), Some(BoardScore::evaluation(120)));
+    }
+
+    #[test]
+    fn test_overrides_file()
+    {
+        let path = temp_path("file");
+        let _ = fs::remove_file(&path);
+        assert_eq!(EvalOverrides::read(&path).unwrap(), EvalOverrides::default());
+
+        let mut overrides = EvalOverrides::default();
+        overrides.set(&Board::default(), 25);
+        overrides.set(&after_f5(), -300);
+        overrides.write(&path).unwrap();
+        let read = EvalOverrides::read(&path);
+
+        fs::write(&path, "# comment\n\n15 8/8/8/8/8/8/8/K6k w - - 0 1\n").unwrap();
+        let commented = EvalOverrides::read(&path);
+        fs::write(&path, "15 8/8/8/8/8/8/8/K6k w - - 0 1\n20000 8/8/8/8/8/8/8/K6k w - - 0 1\n").unwrap();
+        let out_of_range = EvalOverrides::read(&path);
+        fs::write(&path, "15 not a fen\n").unwrap();
+        let not_a_fen = EvalOverrides::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), overrides);
+        assert_eq!(commented.unwrap().len(), 1);
+        let error = out_of_range.unwrap_err();
+        assert!(error.kind() == io::ErrorKind::InvalidData && error.to_string().starts_with("line 2:"), "{error}");
+        assert_eq!(not_a_fen.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
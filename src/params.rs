@@ -0,0 +1,131 @@
+//! Parameters of the evaluation and the search that tuners such as SPSA and CLOP adjust
+//!
+//! The values live in an `EngineParams`, which the evaluation and the search read instead of constants.
+//! `TUNING_PARAMS` lists them by name with their limits. Builds with the `tune` feature declare them as
+//! UCI options, so that a tuner can set them with `setoption` between games, and a new value takes
+//! effect from the next search.
+
+use chess::Piece;
+
+use crate::values;
+
+/// The tunable parameters, in centipawns
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EngineParams
+{
+    /// Material values of the pieces in the evaluation, see `values::piece_value`
+    pub pawn_value: i16,
+    pub knight_value: i16,
+    pub bishop_value: i16,
+    pub rook_value: i16,
+    pub queen_value: i16,
+    /// Margin per ply of depth by which the static evaluation must exceed beta for reverse futility
+    /// pruning
+    pub futility_margin: i16,
+    /// Margin per ply of depth by which a quiet move must hang material to be pruned, in the values the
+    /// exchange evaluation counts in
+    pub see_pruning_margin: i16,
+}
+
+impl Default for EngineParams
+{
+    fn default() -> Self
+    {
+        EngineParams {
+            pawn_value: values::piece_value(Piece::Pawn),
+            knight_value: values::piece_value(Piece::Knight),
+            bishop_value: values::piece_value(Piece::Bishop),
+            rook_value: values::piece_value(Piece::Rook),
+            queen_value: values::piece_value(Piece::Queen),
+            futility_margin: 120,
+            see_pruning_margin: values::exchange_value(Piece::Pawn),
+        }
+    }
+}
+
+impl EngineParams
+{
+    /// Material value of a piece in the evaluation. The king is never traded, so it counts for nothing.
+    pub fn piece_value(&self, piece: Piece) -> i16
+    {
+        match piece
+        {
+            Piece::Pawn => self.pawn_value,
+            Piece::Knight => self.knight_value,
+            Piece::Bishop => self.bishop_value,
+            Piece::Rook => self.rook_value,
+            Piece::Queen => self.queen_value,
+            Piece::King => 0,
+        }
+    }
+}
+
+/// A parameter as a tuner sees it: a name and the range it may be set in
+pub struct TuningParam
+{
+    pub name: &'static str,
+    pub min: i16,
+    pub max: i16,
+    /// The value in a set of parameters
+    pub field: fn(&mut EngineParams) -> &mut i16,
+}
+
+/// Every tunable parameter. The ranges leave room for tuning without letting the pieces trade places.
+pub const TUNING_PARAMS: &[TuningParam] = &[
+    TuningParam { name: "PawnValue", min: 50, max: 200, field: |params| &mut params.pawn_value },
+    TuningParam { name: "KnightValue", min: 200, max: 450, field: |params| &mut params.knight_value },
+    TuningParam { name: "BishopValue", min: 200, max: 450, field: |params| &mut params.bishop_value },
+    TuningParam { name: "RookValue", min: 350, max: 700, field: |params| &mut params.rook_value },
+    TuningParam { name: "QueenValue", min: 700, max: 1300, field: |params| &mut params.queen_value },
+    TuningParam { name: "FutilityMargin", min: 0, max: 400, field: |params| &mut params.futility_margin },
+    TuningParam { name: "SeePruningMargin", min: 0, max: 400, field: |params| &mut params.see_pruning_margin },
+];
+
+impl TuningParam
+{
+    /// The value of the parameter in `EngineParams::default`
+    pub fn default_value(&self) -> i16
+    {
+        *(self.field)(&mut EngineParams::default())
+    }
+}
+
+/// The parameter called `name`. Names are not case sensitive, like the names of options.
+pub fn find(name: &str) -> Option<&'static TuningParam>
+{
+    TUNING_PARAMS.iter().find(|param| param.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_defaults()
+    {
+        let params = EngineParams::default();
+        for piece in chess::ALL_PIECES {
+            assert_eq!(params.piece_value(piece), values::piece_value(piece), "{piece:?}");
+        }
+        for param in TUNING_PARAMS {
+            assert!((param.min..=param.max).contains(&param.default_value()), "{}", param.name);
+        }
+    }
+
+    #[test]
+    fn test_fields()
+    {
+        // Every parameter sets a value of its own
+        let mut params = EngineParams::default();
+        for (i, param) in TUNING_PARAMS.iter().enumerate() {
+            *(param.field)(&mut params) = 1000 + i as i16;
+        }
+        for (i, param) in TUNING_PARAMS.iter().enumerate() {
+            assert_eq!(*(param.field)(&mut params), 1000 + i as i16, "{}", param.name);
+        }
+
+        assert_eq!(find("pawnvalue").map(|param| param.name), Some("PawnValue"));
+        assert!(find("Hash").is_none());
+    }
+}
@@ -0,0 +1,102 @@
+//! Perft: counting the leaf nodes of the tree of legal moves to a fixed depth, which known counts check
+//! the move generation and the position handling against
+
+use chess::{Board, ChessMove, MoveGen};
+
+/// Deepest perft that the `perft` command runs. Depth 7 from the start position is already billions
+/// of nodes.
+pub const MAX_PERFT_DEPTH: u32 = 10;
+
+/// Number of move sequences of `depth` plies from `position`
+///
+/// The last ply is counted rather than played, since every legal move that `MoveGen` gives is a leaf.
+///
+/// ```
+/// use engine::chess::Board;
+///
+/// assert_eq!(engine::perft(&Board::default(), 3), 8902);
+/// ```
+pub fn perft(position: &Board, depth: u32) -> u64
+{
+    match depth
+    {
+        0 => 1,
+        1 => MoveGen::new_legal(position).len() as u64,
+        _ => MoveGen::new_legal(position).map(|m| perft(&position.make_move_new(m), depth - 1)).sum(),
+    }
+}
+
+/// The perft of `depth` split up by the legal moves of `position`, sorted by the moves in coordinate
+/// notation so that the lists of two engines can be compared line by line. `depth` must be at least 1.
+pub fn divide(position: &Board, depth: u32) -> Vec<(ChessMove, u64)>
+{
+    debug_assert!(depth >= 1);
+    let mut counts = MoveGen::new_legal(position)
+        .map(|m| (m, perft(&position.make_move_new(m), depth - 1)))
+        .collect::<Vec<_>>();
+    counts.sort_by_cached_key(|(m, _)| m.to_string());
+    counts
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn perft_fen(fen: &str, depth: u32) -> u64
+    {
+        perft(&Board::from_str(fen).unwrap(), depth)
+    }
+
+    #[test]
+    fn test_start_position()
+    {
+        let counts = [1, 20, 400, 8_902, 197_281, 4_865_609, 119_060_324];
+        for (depth, count) in counts.into_iter().enumerate() {
+            assert_eq!(perft(&Board::default(), depth as u32), count, "depth {depth}");
+        }
+    }
+
+    #[test]
+    fn test_divide()
+    {
+        let counts = divide(&Board::default(), 3);
+        assert_eq!(counts.len(), 20);
+        assert_eq!(counts.iter().map(|&(_, count)| count).sum::<u64>(), perft(&Board::default(), 3));
+        assert_eq!(counts[0], (ChessMove::from_str("a2a3").unwrap(), 380));
+        assert!(counts.contains(&(ChessMove::from_str("e2e4").unwrap(), 600)));
+        assert!(counts.contains(&(ChessMove::from_str("g1f3").unwrap(), 440)));
+
+        // Depth 1 counts every move once
+        let kiwipete = Board::from_str(KIWIPETE).unwrap();
+        let counts = divide(&kiwipete, 1);
+        assert!(counts.iter().all(|&(_, count)| count == 1));
+        assert_eq!(counts.len(), 48);
+    }
+
+    #[test]
+    fn test_tricky_positions()
+    {
+        // Castling through and out of check, promotions, and pins, from the Chess Programming Wiki
+        assert_eq!(perft_fen(KIWIPETE, 1), 48);
+        assert_eq!(perft_fen(KIWIPETE, 2), 2_039);
+        assert_eq!(perft_fen(KIWIPETE, 3), 97_862);
+        // An en passant capture that would leave the king in check along the rank
+        assert_eq!(perft_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5), 674_624);
+        assert_eq!(perft_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 3), 9_467);
+        assert_eq!(perft_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3), 62_379);
+    }
+
+    #[test]
+    fn test_en_passant_pins()
+    {
+        // En passant captures that are illegal for the pin they would open, and one that gives check
+        assert_eq!(perft_fen("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6), 1_134_888);
+        assert_eq!(perft_fen("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6), 1_015_133);
+        assert_eq!(perft_fen("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6), 1_440_467);
+    }
+}
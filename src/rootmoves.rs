@@ -0,0 +1,342 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use chess::{Board, ChessMove, MoveGen};
+use crate::boardutil::PositionExt;
+use crate::score::{BoardScore, BoundedScore};
+
+/// A legal move in the root position, along with what the last search found out about it
+#[derive(Clone, Debug)]
+pub struct RootMove
+{
+    pub chess_move: ChessMove,
+    /// Score from the last iteration that searched this move
+    pub score: BoundedScore,
+    /// Principal variation, starting with `chess_move`. Only meaningful for exact scores.
+    pub pv: Vec<ChessMove>,
+    /// Number of nodes spent on this move in the last iteration
+    pub nodes: u64,
+    /// Whether the move is a capture or gives check
+    pub is_forcing: bool,
+    /// Whether the move is a pawn move or a capture, which resets the fifty-move counter
+    pub resets_halfmove_clock: bool,
+}
+
+impl RootMove
+{
+    pub fn new(position: &Board, chess_move: ChessMove) -> Self
+    {
+        RootMove {
+            chess_move,
+            score: BoundedScore::UpperBound(BoardScore::NO_SCORE),
+            pv: vec![chess_move],
+            nodes: 0,
+            is_forcing: position.is_capture(chess_move) || position.gives_check(chess_move),
+            resets_halfmove_clock: position.resets_halfmove_clock(chess_move),
+        }
+    }
+
+    /// Compare how much we would like to play two root moves. `Ordering::Greater` means that `self`
+    /// is preferred over `other`.
+    ///
+    /// Moves are ranked by score first. For equal scores, a lower bound is better than an exact score,
+    /// which is better than an upper bound. Ties between equal exact scores are broken by:
+    ///
+    /// 1. The length of the PV, if the score is a mate score. Shorter is better when we are mating,
+    ///    longer is better when we are getting mated.
+    /// 2. If `prefer_progress` is set, moves that reset the fifty-move counter are preferred, so that
+    ///    a winning side doesn't shuffle its pieces around between equally good moves.
+    /// 3. The number of nodes in the subtree. Fewer nodes suggests the line is simpler.
+    /// 4. Captures and checks are preferred over quiet moves.
+    ///
+    /// These preferences never override a difference in score, not even of a single centipawn.
+    pub fn preference(&self, other: &RootMove, prefer_progress: bool) -> Ordering
+    {
+        score_key(self.score).cmp(&score_key(other.score))
+            .then_with(|| self.mate_length_preference(other))
+            .then_with(|| if prefer_progress {
+                self.resets_halfmove_clock.cmp(&other.resets_halfmove_clock)
+            } else {
+                Ordering::Equal
+            })
+            .then_with(|| other.nodes.cmp(&self.nodes))
+            .then_with(|| self.is_forcing.cmp(&other.is_forcing))
+    }
+
+    fn mate_length_preference(&self, other: &RootMove) -> Ordering
+    {
+        if !self.score.is_exact() || !other.score.is_exact() {
+            return Ordering::Equal;
+        }
+
+        let score = self.score.unwrap();
+        if !score.is_mate_score() {
+            Ordering::Equal
+        } else if score > BoardScore::EVEN {
+            other.pv.len().cmp(&self.pv.len())
+        } else {
+            self.pv.len().cmp(&other.pv.len())
+        }
+    }
+}
+
+/// Sort root moves so that the most preferred move comes first. Moves that are equally preferred keep
+/// their relative order. See `RootMove::preference` for `prefer_progress`.
+pub fn sort_root_moves(root_moves: &mut [RootMove], prefer_progress: bool)
+{
+    root_moves.sort_by(|a, b| b.preference(a, prefer_progress));
+}
+
+/// The index of the root move to play for variety, among the moves with exact scores at most `margin`
+/// centipawns worse than the best move, picked by `seed`
+///
+/// The root moves must be sorted with the best move first. A mate score is never given up for variety.
+pub fn varied_choice(root_moves: &[RootMove], margin: i16, seed: u64) -> usize
+{
+    let Some(best) = root_moves.first().filter(|best| best.score.is_exact() && !best.score.unwrap().is_mate_score()) else {
+        return 0;
+    };
+
+    let threshold = best.score.unwrap().add_centipawns(-margin);
+    let candidates = root_moves.iter()
+        .enumerate()
+        .filter(|(_, r)| r.score.is_exact() && r.score.unwrap() >= threshold)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    candidates[(seed % candidates.len() as u64) as usize]
+}
+
+/// How settled the search is on its best move, after some iteration of the iterative deepening
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct BestMoveStability
+{
+    /// The best move after the iteration, `None` before the first iteration
+    pub best_move: Option<ChessMove>,
+    /// Number of consecutive iterations, up to and including this one, that ended with this best move
+    pub stable_iterations: u32,
+    /// Percentage of the nodes of the iteration that were spent on the best move
+    pub node_share_percent: u64,
+}
+
+impl BestMoveStability
+{
+    /// Take in the root moves after a completed iteration, sorted with the best move first
+    pub fn update(&mut self, root_moves: &[RootMove])
+    {
+        let Some(best) = root_moves.first() else {
+            return;
+        };
+
+        if self.best_move == Some(best.chess_move) {
+            self.stable_iterations += 1;
+        } else {
+            self.best_move = Some(best.chess_move);
+            self.stable_iterations = 1;
+        }
+
+        let total_nodes = root_moves.iter().map(|r| r.nodes).sum::<u64>();
+        self.node_share_percent = if total_nodes != 0 { 100 * best.nodes / total_nodes } else { 0 };
+    }
+}
+
+/// The position with the en passant square cleared, unless an en passant capture is actually legal
+///
+/// The chess crate keeps the en passant square whenever an enemy pawn stands next to the pawn that
+/// just moved, even if that pawn is pinned. A FEN of the same position may leave the square out, and
+/// would then hash differently. Searching the canonical position instead makes the hash of the root
+/// the same however the position was given.
+pub fn canonical_root(position: &Board) -> Board
+{
+    if position.en_passant().is_none() {
+        return *position;
+    }
+
+    let is_en_passant = |m: &ChessMove| position.is_capture(*m) && position.piece_on(m.get_dest()).is_none();
+    if MoveGen::new_legal(position).any(|m| is_en_passant(&m)) {
+        return *position;
+    }
+
+    let fen = position.to_string();
+    let mut fields = fen.split_ascii_whitespace().collect::<Vec<_>>();
+    fields[3] = "-";
+    Board::from_str(&fields.join(" ")).expect("clearing the en passant square keeps the position valid")
+}
+
+fn score_key(score: BoundedScore) -> (BoardScore, u8)
+{
+    match score
+    {
+        BoundedScore::UpperBound(s) => (s, 0),
+        BoundedScore::Exact(s) => (s, 1),
+        BoundedScore::LowerBound(s) => (s, 2),
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn root_move(chess_move: &str, score: BoundedScore, pv_length: usize, nodes: u64, is_forcing: bool) -> RootMove
+    {
+        let chess_move = ChessMove::from_str(chess_move).unwrap();
+        RootMove {
+            chess_move,
+            score,
+            pv: vec![chess_move; pv_length],
+            nodes,
+            is_forcing,
+            resets_halfmove_clock: false,
+        }
+    }
+
+    fn sorted_moves(mut root_moves: Vec<RootMove>) -> Vec<String>
+    {
+        sort_root_moves(&mut root_moves, false);
+        root_moves.iter().map(|r| r.chess_move.to_string()).collect()
+    }
+
+    #[test]
+    fn test_higher_score_first()
+    {
+        use BoundedScore::*;
+
+        let root_moves = vec![
+            root_move("a2a3", UpperBound(BoardScore::evaluation(50)), 1, 10, false),
+            root_move("b2b3", Exact(BoardScore::evaluation(50)), 1, 1000, false),
+            root_move("c2c3", Exact(BoardScore::evaluation(80)), 1, 1000, false),
+            root_move("d2d3", LowerBound(BoardScore::evaluation(50)), 1, 1000, false),
+        ];
+        assert_eq!(sorted_moves(root_moves), ["c2c3", "d2d3", "b2b3", "a2a3"]);
+    }
+
+    #[test]
+    fn test_shorter_mate_first()
+    {
+        use BoundedScore::*;
+
+        let mate = BoardScore::MATE.increment_mate_plies().increment_mate_plies();
+        let root_moves = vec![
+            root_move("a2a3", Exact(mate), 5, 10, true),
+            root_move("b2b3", Exact(mate), 3, 1000, false),
+        ];
+        assert_eq!(sorted_moves(root_moves), ["b2b3", "a2a3"]);
+
+        // When getting mated, hold out as long as possible
+        let root_moves = vec![
+            root_move("a2a3", Exact(-mate), 3, 10, true),
+            root_move("b2b3", Exact(-mate), 5, 1000, false),
+        ];
+        assert_eq!(sorted_moves(root_moves), ["b2b3", "a2a3"]);
+    }
+
+    #[test]
+    fn test_fewer_nodes_then_forcing_first()
+    {
+        use BoundedScore::*;
+
+        let score = Exact(BoardScore::evaluation(20));
+        let root_moves = vec![
+            root_move("a2a3", score, 3, 500, false),
+            root_move("b2b3", score, 7, 200, false),
+            root_move("c2c3", score, 7, 500, true),
+            root_move("d2d3", score, 3, 500, false),
+        ];
+        assert_eq!(sorted_moves(root_moves), ["b2b3", "c2c3", "a2a3", "d2d3"]);
+    }
+
+    #[test]
+    fn test_best_move_stability()
+    {
+        use BoundedScore::*;
+
+        let score = Exact(BoardScore::EVEN);
+        let mut stability = BestMoveStability::default();
+        stability.update(&[root_move("a2a3", score, 1, 300, false), root_move("b2b3", score, 1, 100, false)]);
+        assert_eq!(stability.stable_iterations, 1);
+        assert_eq!(stability.node_share_percent, 75);
+
+        stability.update(&[root_move("a2a3", score, 1, 900, false), root_move("b2b3", score, 1, 100, false)]);
+        assert_eq!(stability.stable_iterations, 2);
+        assert_eq!(stability.node_share_percent, 90);
+
+        // The best move changes
+        stability.update(&[root_move("b2b3", score, 1, 500, false), root_move("a2a3", score, 1, 500, false)]);
+        assert_eq!(stability.best_move, Some(ChessMove::from_str("b2b3").unwrap()));
+        assert_eq!(stability.stable_iterations, 1);
+        assert_eq!(stability.node_share_percent, 50);
+    }
+
+    #[test]
+    fn test_progress_preference()
+    {
+        use BoundedScore::*;
+
+        let score = Exact(BoardScore::evaluation(300));
+        let mut root_moves = vec![
+            root_move("g1f3", score, 3, 100, true),
+            root_move("a2a3", score, 3, 500, false),
+            root_move("b1c3", Exact(BoardScore::evaluation(301)), 3, 500, false),
+        ];
+        root_moves[1].resets_halfmove_clock = true;
+
+        // Never at the expense of score
+        sort_root_moves(&mut root_moves, true);
+        let order = root_moves.iter().map(|r| r.chess_move.to_string()).collect::<Vec<_>>();
+        assert_eq!(order, ["b1c3", "a2a3", "g1f3"]);
+
+        sort_root_moves(&mut root_moves, false);
+        let order = root_moves.iter().map(|r| r.chess_move.to_string()).collect::<Vec<_>>();
+        assert_eq!(order, ["b1c3", "g1f3", "a2a3"]);
+    }
+
+    #[test]
+    fn test_varied_choice()
+    {
+        use BoundedScore::*;
+
+        let root_moves = vec![
+            root_move("e2e4", Exact(BoardScore::evaluation(40)), 1, 1000, false),
+            root_move("d2d4", Exact(BoardScore::evaluation(35)), 1, 1000, false),
+            root_move("g1f3", UpperBound(BoardScore::evaluation(30)), 1, 1000, false),
+            root_move("c2c4", Exact(BoardScore::evaluation(20)), 1, 1000, false),
+            root_move("a2a4", Exact(BoardScore::evaluation(-30)), 1, 1000, false),
+        ];
+        let choices = |margin| (0..100).map(|seed| root_moves[varied_choice(&root_moves, margin, seed)].chess_move.to_string())
+            .collect::<std::collections::BTreeSet<_>>();
+
+        // Only exact scores within the margin, and every one of them for some seed
+        assert_eq!(choices(0), ["e2e4".to_owned()].into());
+        assert_eq!(choices(5), ["d2d4".to_owned(), "e2e4".to_owned()].into());
+        assert_eq!(choices(20), ["c2c4".to_owned(), "d2d4".to_owned(), "e2e4".to_owned()].into());
+
+        // Not when mating
+        let mating = vec![
+            root_move("e2e4", Exact(BoardScore::MATE), 2, 1000, false),
+            root_move("d2d4", Exact(BoardScore::evaluation(900)), 1, 1000, false),
+        ];
+        assert!((0..10).all(|seed| varied_choice(&mating, 50, seed) == 0));
+    }
+
+    #[test]
+    fn test_canonical_root()
+    {
+        // After e2e4 the pawn on f4 is next to it, but can't capture en passant without exposing its
+        // king to the rook
+        let pinned = Board::from_str("8/8/8/8/R4p1k/8/4P3/4K3 w - - 0 1").unwrap()
+            .make_move_new(ChessMove::from_str("e2e4").unwrap());
+        let without_square = Board::from_str("8/8/8/8/R3Pp1k/8/8/4K3 b - - 0 1").unwrap();
+        let with_square = Board::from_str("8/8/8/8/R3Pp1k/8/8/4K3 b - e3 0 1").unwrap();
+
+        assert_eq!(canonical_root(&pinned).get_hash(), without_square.get_hash());
+        assert_eq!(canonical_root(&with_square).get_hash(), without_square.get_hash());
+        assert_eq!(canonical_root(&without_square).get_hash(), without_square.get_hash());
+
+        // A legal en passant capture is part of the position
+        let capturable = Board::from_str("4k3/8/8/8/4Pp2/8/8/4K3 b - e3 0 1").unwrap();
+        assert!(capturable.en_passant().is_some());
+        assert_eq!(canonical_root(&capturable).get_hash(), capturable.get_hash());
+    }
+}
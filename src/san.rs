@@ -0,0 +1,243 @@
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
+use crate::boardutil::PositionExt;
+
+/// Notation used for the moves in the `pv` field of info lines
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum PvNotation
+{
+    /// Coordinate notation, as required by the UCI protocol, e.g. `e2e4 e7e5 g1f3`
+    Uci,
+    /// Standard Algebraic Notation with move numbers, e.g. `1. e4 e5 2. Nf3`
+    San,
+}
+
+/// Format a move in Standard Algebraic Notation
+///
+/// The move must be legal in `position`.
+///
+/// ```
+/// use std::str::FromStr;
+/// use engine::chess::{Board, ChessMove};
+///
+/// let position = Board::default();
+/// assert_eq!(engine::san::to_san(&position, ChessMove::from_str("g1f3").unwrap()), "Nf3");
+/// ```
+pub fn to_san(position: &Board, chess_move: ChessMove) -> String
+{
+    debug_assert!(position.legal(chess_move));
+
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
+    let piece = position.piece_on(source).expect("move from empty square");
+    let is_capture = position.is_capture(chess_move);
+
+    let mut result = String::new();
+
+    if position.is_castling(chess_move)
+    {
+        if dest.get_file().to_index() > source.get_file().to_index() {
+            result.push_str("O-O");
+        } else {
+            result.push_str("O-O-O");
+        }
+    }
+    else if piece == Piece::Pawn
+    {
+        if is_capture {
+            result.push(file_char(source));
+            result.push('x');
+        }
+        result.push_str(&dest.to_string());
+        if let Some(promotion) = chess_move.get_promotion() {
+            result.push('=');
+            result.push_str(&promotion.to_string(Color::White));
+        }
+    }
+    else
+    {
+        result.push_str(&piece.to_string(Color::White));
+
+        // Disambiguate if another piece of the same kind can move to the same square. Prefer the
+        // file, then the rank, and only if neither is sufficient use the full square.
+        let other_sources = MoveGen::new_legal(position)
+            .filter(|m| m.get_dest() == dest && m.get_source() != source)
+            .filter(|m| position.piece_on(m.get_source()) == Some(piece))
+            .map(|m| m.get_source())
+            .collect::<Vec<_>>();
+
+        if !other_sources.is_empty()
+        {
+            let shares_file = other_sources.iter().any(|s| s.get_file() == source.get_file());
+            let shares_rank = other_sources.iter().any(|s| s.get_rank() == source.get_rank());
+            if !shares_file {
+                result.push(file_char(source));
+            } else if !shares_rank {
+                result.push(rank_char(source));
+            } else {
+                result.push_str(&source.to_string());
+            }
+        }
+
+        if is_capture {
+            result.push('x');
+        }
+        result.push_str(&dest.to_string());
+    }
+
+    let new_position = position.make_move_new(chess_move);
+    if new_position.status() == BoardStatus::Checkmate {
+        result.push('#');
+    } else if *new_position.checkers() != chess::EMPTY {
+        result.push('+');
+    }
+
+    result
+}
+
+/// Parse a move in Standard Algebraic Notation, as `to_san` writes it
+///
+/// Check and mate signs and annotations like "!?" may be left out or added, and castling may be
+/// written with zeros, as in "0-0".
+///
+/// ```
+/// use std::str::FromStr;
+/// use engine::chess::{Board, ChessMove};
+///
+/// let position = Board::default();
+/// assert_eq!(engine::san::from_san(&position, "e4"), Ok(ChessMove::from_str("e2e4").unwrap()));
+/// assert!(engine::san::from_san(&position, "e5").is_err());
+/// ```
+pub fn from_san(position: &Board, san: &str) -> Result<ChessMove, String>
+{
+    let normalize = |san: &str| san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+    let wanted = normalize(san.trim());
+    MoveGen::new_legal(position)
+        .find(|&m| normalize(&to_san(position, m)) == wanted)
+        .ok_or_else(|| format!("No legal move {san}"))
+}
+
+/// Format a sequence of moves, starting at `position`, in the requested notation
+///
+/// The moves are separated by single spaces. In SAN, move numbers are inserted before every White
+/// move, and before the first move if it is a Black move ("1... e5"). Move numbers count from the
+/// start of the sequence.
+pub fn format_pv(position: &Board, moves: &[ChessMove], notation: PvNotation) -> String
+{
+    let mut words = Vec::with_capacity(moves.len());
+
+    match notation
+    {
+        PvNotation::Uci => {
+            for chess_move in moves {
+                words.push(chess_move.to_string());
+            }
+        }
+
+        PvNotation::San => {
+            let mut position = *position;
+            let mut move_number = 1;
+            for (i, &chess_move) in moves.iter().enumerate()
+            {
+                match position.side_to_move()
+                {
+                    Color::White => words.push(format!("{move_number}.")),
+                    Color::Black if i == 0 => words.push(format!("{move_number}...")),
+                    Color::Black => { },
+                }
+                words.push(to_san(&position, chess_move));
+
+                if position.side_to_move() == Color::Black {
+                    move_number += 1;
+                }
+                position = position.make_move_new(chess_move);
+            }
+        }
+    }
+
+    words.join(" ")
+}
+
+fn file_char(square: Square) -> char
+{
+    (b'a' + square.get_file().to_index() as u8) as char
+}
+
+fn rank_char(square: Square) -> char
+{
+    (b'1' + square.get_rank().to_index() as u8) as char
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn parse_moves(moves: &str) -> Vec<ChessMove>
+    {
+        moves.split_ascii_whitespace()
+            .map(|m| ChessMove::from_str(m).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_format_pv_castling_and_promotion()
+    {
+        let position = Board::from_str("4k3/1P6/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let pv = parse_moves("e1c1 e8e7 b7b8q");
+
+        assert_eq!(format_pv(&position, &pv, PvNotation::Uci), "e1c1 e8e7 b7b8q");
+        assert_eq!(format_pv(&position, &pv, PvNotation::San), "1. O-O-O Ke7 2. b8=Q");
+    }
+
+    #[test]
+    fn test_format_pv_black_to_move()
+    {
+        let position = Board::from_str("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let pv = parse_moves("e7e5 g1f3 b8c6 f1b5");
+
+        assert_eq!(format_pv(&position, &pv, PvNotation::Uci), "e7e5 g1f3 b8c6 f1b5");
+        assert_eq!(format_pv(&position, &pv, PvNotation::San), "1... e5 2. Nf3 Nc6 3. Bb5");
+    }
+
+    #[test]
+    fn test_to_san_check_mate_and_disambiguation()
+    {
+        // Back rank mate
+        let position = Board::from_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(to_san(&position, ChessMove::from_str("a1a8").unwrap()), "Ra8#");
+
+        // Two knights can reach d2
+        let position = Board::from_str("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap();
+        assert_eq!(to_san(&position, ChessMove::from_str("b1d2").unwrap()), "Nbd2");
+
+        // Pawn capture with promotion and check
+        let position = Board::from_str("3rk3/2P5/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(to_san(&position, ChessMove::from_str("c7d8q").unwrap()), "cxd8=Q+");
+    }
+
+    #[test]
+    fn test_from_san()
+    {
+        // Every legal move reads back from its SAN, with or without the check sign
+        for fen in crate::bench::BENCH_POSITIONS
+        {
+            let position = Board::from_str(fen).unwrap();
+            for chess_move in MoveGen::new_legal(&position)
+            {
+                let san = to_san(&position, chess_move);
+                assert_eq!(from_san(&position, &san), Ok(chess_move), "{fen} {san}");
+                assert_eq!(from_san(&position, san.trim_end_matches(['+', '#'])), Ok(chess_move), "{fen} {san}");
+            }
+        }
+
+        let position = Board::from_str("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(from_san(&position, "0-0-0"), Ok(ChessMove::from_str("e1c1").unwrap()));
+        assert_eq!(from_san(&position, "Rd1!?"), Ok(ChessMove::from_str("a1d1").unwrap()));
+        // The king is in the way of the other rook, and there is nothing to capture
+        assert_eq!(from_san(&position, "Rf1"), Ok(ChessMove::from_str("h1f1").unwrap()));
+        assert!(from_san(&position, "Rxa8").is_err());
+    }
+}
@@ -1,3 +1,17 @@
+use chess::Color;
+
+/// The evaluation in centipawns at which the side to move is as likely to win as not, in the model of
+/// `BoardScore::wdl`
+const WDL_EVEN_WIN_CENTIPAWNS: f64 = 200.0;
+
+/// How many centipawns the odds of a win take to change by a factor of e, in the model of
+/// `BoardScore::wdl`. Smaller values make the model more certain.
+const WDL_SCALE_CENTIPAWNS: f64 = 60.0;
+
+/// A score from the point of view of the side to move, as negamax needs it, and as UCI reports it
+///
+/// Outputs meant for other readers than GUIs, such as JSON files, rather report scores from White's
+/// point of view. `to_white_relative` and `from_white_relative` convert between the two.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct BoardScore
 {
@@ -23,6 +37,10 @@ pub struct BoardScore
     // Mated in 2 = score mate -1
     // Mate in 3  = score mate 2
     // Mated in 4 = score mate -2
+    //
+    // Scores turned around to White's point of view with Black to move use the other numbers, which
+    // are reported the same way, rounding to whole moves: mated in 1 = score mate -1, and mate in 2 =
+    // score mate 1.
 
     inner: i16,
 }
@@ -87,10 +105,94 @@ impl BoardScore
         }
     }
 
+    /// The next lower score, saturating at WORST_SCORE. Values of NO_SCORE are unaffected.
+    ///
+    /// Searching with `alpha.next_lower()` as the lower bound instead of `alpha` gives exact scores,
+    /// rather than upper bounds, to moves that are exactly as good as `alpha`.
+    pub fn next_lower(self) -> Self
+    {
+        if self > Self::WORST_SCORE {
+            Self { inner: self.inner - 1 }
+        } else {
+            self
+        }
+    }
+
+    /// The next higher score, saturating at BEST_SCORE. Values of NO_SCORE are unaffected.
+    pub fn next_higher(self) -> Self
+    {
+        if self != Self::NO_SCORE && self < Self::BEST_SCORE {
+            Self { inner: self.inner + 1 }
+        } else {
+            self
+        }
+    }
+
+    /// Add a number of centipawns to an evaluation. Must not be used on mate scores.
+    pub fn add_centipawns(self, centipawns: i16) -> Self
+    {
+        debug_assert!(!self.is_mate_score());
+        Self { inner: self.inner.saturating_add(centipawns) }
+    }
+
     pub fn evaluation(evaluation: i16) -> BoardScore
     {
         BoardScore { inner: evaluation }
     }
+
+    /// The score from White's point of view, for a score from the point of view of `side_to_move`
+    pub fn to_white_relative(self, side_to_move: Color) -> Self
+    {
+        match side_to_move
+        {
+            Color::White => self,
+            Color::Black => self.neg(),
+        }
+    }
+
+    /// The score from the point of view of `side_to_move`, for a score from White's point of view
+    pub fn from_white_relative(self, side_to_move: Color) -> Self
+    {
+        // Turning the board around twice gets back where it started
+        self.to_white_relative(side_to_move)
+    }
+
+    /// The score as stored, for writing it to a file
+    pub const fn raw(self) -> i16
+    {
+        self.inner
+    }
+
+    /// A score read back from a file, as given by `raw`
+    pub const fn from_raw(raw: i16) -> Self
+    {
+        BoardScore { inner: raw }
+    }
+
+    /// Moves to mate as in a UCI `score mate` line: positive when giving mate, negative when getting
+    /// mated, and `None` for evaluations
+    pub fn mate_moves(self) -> Option<i32>
+    {
+        match self.describe()
+        {
+            BoardScoreDescription::Mate(mate) => Some(mate),
+            BoardScoreDescription::Cp(_) => None,
+        }
+    }
+
+    /// The score as the value of an EPD `ce` opcode: centipawns, or for mate scores, 32767 minus the
+    /// number of plies to mate, negated when getting mated
+    pub fn epd_centipawns(self) -> i32
+    {
+        debug_assert!(self != Self::NO_SCORE);
+        if self >= Self::MATE_RANGE_BOTTOM && self <= Self::MATE {
+            self.inner as i32 + 1
+        } else if self <= Self::MATED_RANGE_TOP && self >= Self::MATED {
+            self.inner as i32 - 1
+        } else {
+            self.inner as i32
+        }
+    }
 }
 
 impl std::ops::Neg for BoardScore
@@ -106,40 +208,90 @@ impl std::ops::Neg for BoardScore
 impl std::fmt::Display for BoardScore
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.into() {
-            BoardScoreDescription::Cp(cp) => write!(f, "cp {cp}")?,
-            BoardScoreDescription::Mate(mate) => write!(f, "mate {mate}")?,
-        }
-        Ok(())
+        write!(f, "{}", self.describe())
+    }
+}
+
+/// Expected shares of wins, draws and losses in per mille, which add up to 1000, as UCI `wdl` reports
+/// them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Wdl
+{
+    pub win: u16,
+    pub draw: u16,
+    pub loss: u16,
+}
+
+impl std::fmt::Display for Wdl
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "wdl {} {} {}", self.win, self.draw, self.loss)
     }
 }
 
-#[derive(Clone, Copy)]
-enum BoardScoreDescription
+/// A score as UCI reports it, see `BoardScore::describe`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoardScoreDescription
 {
     Cp(i32),
+    /// Moves to mate: positive when giving mate, negative when getting mated
     Mate(i32),
 }
 
-impl Into<BoardScoreDescription> for &BoardScore
+impl std::fmt::Display for BoardScoreDescription
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoardScoreDescription::Cp(cp) => write!(f, "cp {cp}"),
+            BoardScoreDescription::Mate(mate) => write!(f, "mate {mate}"),
+        }
+    }
+}
+
+impl BoardScore
 {
-    fn into(self) -> BoardScoreDescription {
-        if self >= &BoardScore::MATE_RANGE_BOTTOM {
-            // Positive values for mate
-            // Mate in 1 = score mate 1
-            // Mate in 3 = score mate 2
-            // Mate in 5 = score mate 3
-            BoardScoreDescription::Mate((BoardScore::MATE.inner as i32 - self.inner as i32 + 1) / 2)
-        } else if self <= &BoardScore::MATED_RANGE_TOP {
-            // Negative values if mated
-            // Mated in 2 = score mate -1
-            // Mated in 4 = score mate -2
-            // Mated in 6 = score mate -3
-            BoardScoreDescription::Mate((BoardScore::MATED.inner as i32 - self.inner as i32) / 2)
+    /// The score in centipawns, or in moves to mate, as UCI reports it and as every other output
+    /// formats it
+    ///
+    /// Mate scores count plies, and a mate in `n` plies is `(n + 1) / 2` moves of the side that gives
+    /// mate, both for giving mate and for getting mated:
+    /// - Mate in 1 ply = score mate 1, mate in 3 plies = score mate 2
+    /// - Mated in 2 plies = score mate -1, mated in 4 plies = score mate -2
+    /// - Mated in 0 plies, being checkmated already = score mate 0
+    ///
+    /// Scores turned around to White's point of view with Black to move use the other numbers of plies,
+    /// which round up the same way: mated in 1 ply = score mate -1, and mate in 2 plies = score mate 1.
+    pub fn describe(self) -> BoardScoreDescription
+    {
+        let moves = |plies: i32| (plies + 1) / 2;
+        if self >= BoardScore::MATE_RANGE_BOTTOM {
+            BoardScoreDescription::Mate(moves(BoardScore::MATE.inner as i32 - self.inner as i32))
+        } else if self <= BoardScore::MATED_RANGE_TOP {
+            BoardScoreDescription::Mate(-moves(self.inner as i32 - BoardScore::MATED.inner as i32))
         } else {
             BoardScoreDescription::Cp(self.inner as i32)
         }
     }
+
+    /// The expected shares of wins, draws and losses for the side that the score is for
+    ///
+    /// A win and a loss each follow a logistic curve of the evaluation, which makes an even position
+    /// mostly a draw and a few pawns up mostly a win. The model only knows the evaluation, not the
+    /// material left or the phase of the game. Mates are certain wins and losses.
+    pub fn wdl(self) -> Wdl
+    {
+        debug_assert!(self != Self::NO_SCORE);
+        if self.is_mate_score() {
+            return if self > Self::EVEN { Wdl { win: 1000, draw: 0, loss: 0 } } else { Wdl { win: 0, draw: 0, loss: 1000 } };
+        }
+
+        let share = |centipawns: f64| 1000.0 / (1.0 + ((WDL_EVEN_WIN_CENTIPAWNS - centipawns) / WDL_SCALE_CENTIPAWNS).exp());
+        let centipawns = self.inner as f64;
+        let win = share(centipawns).round() as u16;
+        // The two curves never add up to more than 1000 before rounding, but may after it
+        let loss = (share(-centipawns).round() as u16).min(1000 - win);
+        Wdl { win, draw: 1000 - win - loss, loss }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -176,6 +328,25 @@ impl BoundedScore
         }
     }
 
+    /// The score from White's point of view, see `BoardScore::to_white_relative`. A bound for Black is
+    /// the opposite bound for White.
+    pub fn to_white_relative(self, side_to_move: Color) -> BoundedScore
+    {
+        match side_to_move
+        {
+            Color::White => self,
+            Color::Black => self.neg(),
+        }
+    }
+
+    /// The score from the point of view of `side_to_move`, see `BoardScore::from_white_relative`
+    // Nothing reads White-relative scores back in yet
+    #[allow(dead_code)]
+    pub fn from_white_relative(self, side_to_move: Color) -> BoundedScore
+    {
+        self.to_white_relative(side_to_move)
+    }
+
     pub fn increment_mate_plies(self) -> BoundedScore
     {
         use BoundedScore::*;
@@ -226,12 +397,20 @@ impl std::ops::Neg for BoundedScore
     }
 }
 
+/// Bounds are ordered when they say which score is better: `LowerBound(a) > UpperBound(b)` if
+/// `a >= b`, and so on. Bounds that overlap are unordered. NO_SCORE is not a score, and must not be
+/// compared with anything.
 impl std::cmp::PartialOrd for BoundedScore
 {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering>
     {
         use BoundedScore::*;
 
+        debug_assert!(
+            self.unwrap() != BoardScore::NO_SCORE && other.unwrap() != BoardScore::NO_SCORE,
+            "Comparing {self:?} with {other:?}",
+        );
+
         match (self, other)
         {
             (LowerBound(a), LowerBound(b)) |
@@ -307,4 +486,110 @@ mod test
         assert!(!(LowerBound(mid) < Exact(high)));
     }
 
+    #[test]
+    fn test_describe_mate_distances()
+    {
+        // Mate in n plies from the side to move, as the search produces it
+        let mate_in = |plies| (0..plies).fold(BoardScore::MATE, |score, _| score.increment_mate_plies());
+        let mated_in = |plies| (0..plies).fold(BoardScore::MATED, |score, _| score.increment_mate_plies());
+
+        for plies in 1..=20
+        {
+            let moves = (plies + 1) / 2;
+            assert_eq!(mate_in(plies).describe(), BoardScoreDescription::Mate(moves), "mate in {plies} plies");
+            assert_eq!(mated_in(plies).describe(), BoardScoreDescription::Mate(-moves), "mated in {plies} plies");
+            assert_eq!(mate_in(plies).to_string(), format!("mate {moves}"));
+            assert_eq!(mated_in(plies).to_string(), format!("mate -{moves}"));
+        }
+
+        // The distances that the side to move can have, spelled out
+        let expected = [
+            (mated_in(0), "mate 0"),
+            (mate_in(1), "mate 1"),
+            (mated_in(2), "mate -1"),
+            (mate_in(3), "mate 2"),
+            (mated_in(4), "mate -2"),
+            (mate_in(5), "mate 3"),
+            (mated_in(6), "mate -3"),
+            (mate_in(19), "mate 10"),
+            (mated_in(20), "mate -10"),
+        ];
+        for (score, uci) in expected {
+            assert_eq!(score.to_string(), uci, "{score:?}");
+            assert_eq!(score.mate_moves(), uci.strip_prefix("mate ").unwrap().parse().ok());
+        }
+
+        // The longest mates that can be told apart, and evaluations next to them
+        assert_eq!(mate_in(255).to_string(), "mate 128");
+        assert_eq!(mated_in(255).to_string(), "mate -128");
+        assert_eq!(mate_in(255).next_lower().describe(), BoardScoreDescription::Cp(BoardScore::MATE_RANGE_BOTTOM.inner as i32 - 1));
+        assert_eq!(BoardScore::evaluation(-25).to_string(), "cp -25");
+        assert_eq!(BoardScore::evaluation(-25).mate_moves(), None);
+    }
+
+    #[test]
+    fn test_wdl()
+    {
+        // Even is mostly a draw, and the same for either side
+        let even = BoardScore::EVEN.wdl();
+        assert!(even.draw > 800 && even.win == even.loss, "{even}");
+
+        // Three pawns up is clearly winning, and a little better only a little more so
+        let winning = BoardScore::evaluation(300).wdl();
+        assert!(winning.win > 700 && winning.loss < 10, "{winning}");
+        assert!(BoardScore::evaluation(50).wdl().win > even.win);
+        assert_eq!(BoardScore::evaluation(-300).wdl(), Wdl { win: winning.loss, draw: winning.draw, loss: winning.win });
+
+        // Mates and evaluations as far as they go are certain, and everything adds up
+        let mate = BoardScore::MATE.increment_mate_plies();
+        assert_eq!(mate.wdl(), Wdl { win: 1000, draw: 0, loss: 0 });
+        assert_eq!((-mate).wdl(), Wdl { win: 0, draw: 0, loss: 1000 });
+        assert_eq!(BoardScore::MATED.wdl().to_string(), "wdl 0 0 1000");
+        for centipawns in (-9999..=9999).step_by(37)
+        {
+            let wdl = BoardScore::evaluation(centipawns).wdl();
+            assert_eq!(wdl.win + wdl.draw + wdl.loss, 1000, "{centipawns}: {wdl}");
+        }
+        assert_eq!(BoardScore::evaluation(9999).wdl(), Wdl { win: 1000, draw: 0, loss: 0 });
+    }
+
+    #[test]
+    fn test_white_relative()
+    {
+        use std::str::FromStr;
+        use BoundedScore::*;
+
+        use crate::evaluation::{evaluate, EvalTerms};
+        use crate::params::EngineParams;
+
+        // Black to move and winning: good for the side to move, bad for White
+        let position = chess::Board::from_str("4k3/8/8/3q4/8/8/8/4K3 b - - 0 1").unwrap();
+        let side_to_move = position.side_to_move();
+        let score = evaluate(&position, EvalTerms::default(), &EngineParams::default());
+        assert!(score > BoardScore::EVEN);
+        assert!(score.to_white_relative(side_to_move) < BoardScore::EVEN);
+
+        // Mate for Black is getting mated for White, and bounds turn around with the sign
+        let mate = BoardScore::MATE.increment_mate_plies();
+        assert_eq!(mate.to_white_relative(Color::Black).to_string(), "mate -1");
+        assert_eq!((-mate.increment_mate_plies()).to_white_relative(Color::Black).to_string(), "mate 1");
+        assert_eq!(LowerBound(score).to_white_relative(Color::Black), UpperBound(-score));
+        assert_eq!(UpperBound(score).to_white_relative(Color::Black), LowerBound(-score));
+
+        // White's scores stay as they are
+        assert_eq!(score.to_white_relative(Color::White), score);
+        assert_eq!(LowerBound(score).to_white_relative(Color::White), LowerBound(score));
+
+        // Round trips, also for the placeholder that isn't a score
+        for score in [score, -score, BoardScore::EVEN, mate, -mate, BoardScore::NO_SCORE]
+        {
+            for side_to_move in [Color::White, Color::Black]
+            {
+                assert_eq!(score.to_white_relative(side_to_move).from_white_relative(side_to_move), score);
+                let bound = LowerBound(score);
+                assert_eq!(bound.to_white_relative(side_to_move).from_white_relative(side_to_move), bound);
+            }
+        }
+    }
+
 }
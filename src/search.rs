@@ -1,331 +1,3021 @@
-use std::fmt::Write;
-use std::sync::atomic::Ordering;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "std-threads")]
+use std::thread;
 use std::time;
 
-use chess::{Board, MoveGen};
-use crate::evaluation;
-use crate::hash::{HashEntry, HashMap};
+use chess::{Board, ChessMove, Color, MoveGen, Piece};
+use crate::boardutil::PositionExt;
+use crate::clock::Instant;
+use crate::evaluation::{self, EvalTerms, Evaluator, SharedEvaluator};
+#[cfg(feature = "nnue")]
+use crate::evaluation::nnue::{AccumulatorStack, Network};
+use crate::hash::{self, HashEntry, HashMap, ProbeResult};
 use crate::moveorder::MoveGenerator;
+use crate::output;
+use crate::overrides::EvalOverrides;
+use crate::params::EngineParams;
+use crate::rootmoves::{canonical_root, sort_root_moves, varied_choice, BestMoveStability, RootMove};
+use crate::san::{self, PvNotation};
 use crate::score::{BoardScore, BoundedScore};
-use crate::searchinterface::StopConditions;
+use crate::see;
+use crate::stopconditions::StopConditions;
+use crate::timeman;
+use crate::zobrist::zobrist_after;
+
+pub mod autosave;
+mod export;
+mod lmr;
+mod pruning;
+pub use export::ExportStats;
+pub use lmr::LmrStats;
+use autosave::Autosave;
+use lmr::LmrTable;
+use pruning::{NodeKind, PruningContext, PruningStats};
 
 pub type Depth = u8;
 
-pub struct Searcher<'a>
+/// Maximum number of plies the quiescence search may go beyond the horizon
+const MAX_QUIESCENCE_PLIES: u32 = 16;
+
+/// In debug mode, root moves whose subtrees spend more than this percentage of their nodes in
+/// quiescence search are reported, if the subtree has at least `QUIESCENCE_REPORT_MIN_NODES` nodes.
+const QUIESCENCE_REPORT_PERCENT: u64 = 95;
+const QUIESCENCE_REPORT_MIN_NODES: u64 = 100_000;
+
+/// Size of the hash table in megabytes, until the Hash option sets it. Browsers are stingy with memory,
+/// so it is kept small on WASM.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_HASH_MEGABYTES: usize = 128;
+#[cfg(target_arch = "wasm32")]
+pub const DEFAULT_HASH_MEGABYTES: usize = 16;
+
+/// Largest hash table the Hash option allows, in megabytes
+pub const MAX_HASH_MEGABYTES: usize = 65536;
+
+/// Most threads the Threads option allows
+pub const MAX_THREADS: usize = 256;
+
+/// Plies added to the depth of a node where the side to move is in check
+const CHECK_EXTENSION: Depth = 1;
+
+/// Nodes are only extended for check up to this many times the depth of the iteration, so that long
+/// sequences of checks can't extend the search without end
+const CHECK_EXTENSION_MAX_PLY_FACTOR: usize = 2;
+
+/// Reverse futility pruning is done at this depth and below, with the margin
+/// `EngineParams::futility_margin`
+const FUTILITY_MAX_DEPTH: Depth = 3;
+
+/// Quiet moves that hang material are pruned at this depth and below, with the margin
+/// `EngineParams::see_pruning_margin`
+const SEE_PRUNING_MAX_DEPTH: Depth = 2;
+
+/// Null move pruning is done at this depth and above, and the null move is searched this many plies
+/// shallower than a real move would be
+const NULL_MOVE_MIN_DEPTH: Depth = 3;
+const NULL_MOVE_REDUCTION: Depth = 2;
+
+/// When the best root move scores above this many centipawns, ties between root moves are broken in
+/// favour of moves that reset the fifty-move counter
+const PROGRESS_MIN_SCORE: i16 = 200;
+
+/// When the best root move scores above this many centipawns, a move that would let the opponent claim
+/// a draw by threefold repetition is only played if every alternative is worse than a draw
+const REPETITION_AVOIDANCE_MIN_SCORE: i16 = 200;
+
+/// Largest margin, in centipawns, by which a move played for variety in the opening may be worse than
+/// the best move
+pub const MAX_OPENING_VARIETY: i16 = 50;
+
+/// Scores from an unfinished iteration are only reported once the search has run this long, so that
+/// fast searches aren't cluttered with them
+const IN_PROGRESS_INFO_MIN_TIME: u128 = 1000;
+
+/// Once the search has run this many milliseconds, each root move is announced with `info currmove`
+/// as its search starts, so that long searches show progress between iterations. Before that, the
+/// iterations are too short for it to tell anything.
+const CURRMOVE_MIN_TIME: u128 = 2000;
+
+/// When the search starts at least this many milliseconds after the go command arrived, because of
+/// hash maintenance queued before it, the delay is reported
+const START_DELAY_REPORT_MIN_TIME: u128 = 5;
+
+/// The search takes care of its periodic work every this many nodes, see `periodic_step`
+const PERIODIC_STEP_NODES: u64 = 4096;
+
+/// In debug mode, the line being searched is reported this often, so that a search that seems stuck
+/// can be looked into
+const CURRLINE_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// While each of this many first moves at a node is searched, the hash entries of the child after the
+/// next move are prefetched, so that they are in the cache by the time it is searched. The `prefetch`
+/// bench measures what it gains.
+pub const SPECULATIVE_PREFETCH_MOVES: usize = 3;
+
+thread_local! {
+    /// What the search calls at every periodic step on this thread, see `set_yield_hook`
+    static YIELD_HOOK: RefCell<Option<Box<dyn FnMut(u64)>>> = const { RefCell::new(None) };
+}
+
+/// Have searches on the current thread call `hook` with the number of nodes searched so far every
+/// `PERIODIC_STEP_NODES` nodes, or stop calling it with `None`
+///
+/// A host that runs the search on its only thread, as a web page does, can't send stop while the search
+/// runs. The hook is its chance to look for input and set `StopConditions::stop_now`. It must not set
+/// another hook meanwhile.
+pub fn set_yield_hook(hook: Option<Box<dyn FnMut(u64)>>)
+{
+    YIELD_HOOK.set(hook);
+}
+
+/// Statistics of one iteration of the iterative deepening. Nodes and time are counted from the
+/// start of the search.
+///
+/// The iterations of a search, in order, are the history of its score, for plotting how the score
+/// settled or looking for instability.
+#[derive(Clone, Debug)]
+pub struct IterationStats
+{
+    pub depth: Depth,
+    pub score: BoundedScore,
+    pub nodes: u64,
+    pub time: u64,
+    pub stability: BestMoveStability,
+}
+
+/// The score history of a search in one line, as in `d1:+23 d2:+31 d3:#2`, for debug output
+fn score_history(iterations: &[IterationStats]) -> String
+{
+    iterations.iter()
+        .map(|iteration| {
+            let score = iteration.score.unwrap();
+            match score.mate_moves()
+            {
+                Some(moves) => format!("d{}:#{moves}", iteration.depth),
+                None => format!("d{}:{:+}", iteration.depth, score.raw()),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The reply to ponder on after the first move of `pv`, which is the second move of the PV if it is
+/// legal. A PV cut short by the end of the game or by the hash table has none.
+fn ponder_move(position: &Board, pv: &[ChessMove]) -> Option<ChessMove>
+{
+    let (&best_move, &reply) = (pv.first()?, pv.get(1)?);
+    Some(reply).filter(|&reply| position.make_move_new(best_move).legal(reply))
+}
+
+/// The score of the root, as far as it is known at some point during the search
+///
+/// Pairs the exact score of the last completed iteration with a bound from the iteration in progress.
+/// The bound only tells something new if it is a lower bound above the committed score or an upper
+/// bound below it. Otherwise the committed score is the better description of the position.
+#[derive(Clone, Copy, Debug)]
+pub struct RootScore
+{
+    committed: BoardScore,
+    in_progress: Option<BoundedScore>,
+}
+
+impl RootScore
+{
+    pub fn new(committed: BoardScore) -> Self
+    {
+        RootScore {
+            committed,
+            in_progress: None,
+        }
+    }
+
+    pub fn set_in_progress(&mut self, bound: BoundedScore)
+    {
+        debug_assert!(!bound.is_exact(), "Exact scores are committed when the iteration completes");
+        self.in_progress = Some(bound);
+    }
+
+    /// The score to report in an info line
+    pub fn display_for_info(&self) -> BoundedScore
+    {
+        match self.in_progress
+        {
+            Some(BoundedScore::LowerBound(s)) if s > self.committed => BoundedScore::LowerBound(s),
+            Some(BoundedScore::UpperBound(s)) if s < self.committed => BoundedScore::UpperBound(s),
+            _ => BoundedScore::Exact(self.committed),
+        }
+    }
+}
+
+pub struct SearchResult
+{
+    /// The move to play, or `None` if there are no legal moves
+    pub best_move: Option<ChessMove>,
+    /// The reply that the PV of the best move expects, for the GUI to ponder on
+    pub ponder_move: Option<ChessMove>,
+    /// Why the search stopped, `None` if there was nothing to search
+    pub stop_reason: Option<StopReason>,
+    /// Every completed iteration, the last of which gave the best move
+    pub iterations: Vec<IterationStats>,
+    /// The info line printed right before `bestmove`
+    pub final_info: String,
+    pub lmr_stats: LmrStats,
+}
+
+/// Why a search stopped, which debug mode reports
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason
+{
+    /// The depth limit, or the greatest depth there is
+    Depth,
+    /// The soft time limit, which is checked between iterations
+    SoftTime,
+    /// The hard time limit, from movetime or maxtime
+    Time,
+    /// Both minimums of `StopConditions::min_searchtime`
+    Minimums,
+    Nodes,
+    Mate,
+    /// The stop command, or the main thread for a helper
+    Stop,
+    /// Board validation found a problem, see `set_validate_boards`
+    Validation,
+    /// The GUI has gone away
+    Disconnected,
+}
+
+impl fmt::Display for StopReason
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let reason = match self
+        {
+            StopReason::Depth => "depth",
+            StopReason::SoftTime => "soft time limit",
+            StopReason::Time => "time limit",
+            StopReason::Minimums => "depth and time minimums",
+            StopReason::Nodes => "nodes",
+            StopReason::Mate => "mate",
+            StopReason::Stop => "stop",
+            StopReason::Validation => "board validation",
+            StopReason::Disconnected => "disconnected",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// The result of a completed iteration that the search would play, see `Searcher::confirmed`
+#[derive(Clone, Debug)]
+struct ConfirmedMove
+{
+    depth: Depth,
+    /// The best root move, with its score and its PV as the iteration found them
+    root_move: RootMove,
+}
+
+/// Lookups of the hash table by the search, which debug mode reports for every iteration
+#[derive(Clone, Copy, Default, Debug)]
+struct HashStats
 {
-    // TODO: Use a better, custom hashmap
-    hashmap: HashMap,
+    probes: u64,
+    /// Probes that found an entry for the position
+    hits: u64,
+    /// Hits whose score was returned without searching the node
+    cutoffs: u64,
+}
+
+/// The search of one thread
+///
+/// The searcher is generic over the evaluator that can be plugged in, so that one whose type is known
+/// at compile time is called directly, and `Searcher<'a>` plugs in an evaluator chosen at run time,
+/// called through `dyn`. The `dispatch` bench compares the two with the classical evaluation.
+pub struct Searcher<'a, E = SharedEvaluator>
+{
+    /// Shared with the helper threads while a search runs, and only changed in place between searches,
+    /// see `table`
+    hashmap: Arc<HashMap>,
     stop_conditions: &'a StopConditions,
     nodes: u64,
-    starttime: time::Instant,
+    qnodes: u64,
+    /// Deepest ply that the search has reached so far, counting the quiescence search, which info lines
+    /// report as the selective depth
+    seldepth: usize,
+    /// Number of threads that search, counting this one, see `set_threads`
+    threads: usize,
+    /// Whether this searcher is a helper thread of another, which prints nothing and only contributes
+    /// to the shared hash table
+    is_helper: bool,
+    /// Stops the helper threads once this searcher, the main thread, has its result
+    helper_stop: Arc<StopConditions>,
+    /// Nodes searched by the helper threads of the running search, which they add to as they go
+    helper_nodes: Arc<AtomicU64>,
+    /// The part of `nodes` that a helper thread has added to `helper_nodes` so far
+    published_nodes: u64,
+    /// When the search started, for the time reported in info lines
+    starttime: Instant,
+    /// When the time limits started counting, which is at ponderhit for a ponder search
+    limits_starttime: Instant,
+    /// When the go command for the next search arrived, if it is known
+    go_time: Option<Instant>,
+    /// Whether the search is a ponder search that has not yet been converted by ponderhit
+    is_pondering: bool,
+    pv_notation: PvNotation,
+    /// Number of plies since the last capture or pawn move, in the position being searched
+    halfmove_clock: u32,
+    /// Static evaluation of the nodes along the current line, by ply. `None` when in check.
+    static_evals: Vec<Option<BoardScore>>,
+    /// Depth that the nodes along the current line search to, including extensions, by ply
+    searched_depths: Vec<Depth>,
+    /// Hash cutoffs at nodes that were extended for being in check
+    extended_hash_cutoffs: u64,
+    /// Nodes cut off by null move pruning, which `pruning_stats` also counts
+    null_move_cutoffs: u64,
+    /// Ply of the node that the innermost null move along the current line was made from
+    null_move_ply: Option<usize>,
+    hash_stats: HashStats,
+    /// Why the search is stopping, once it is
+    stop_reason: Option<StopReason>,
+    pruning_stats: PruningStats,
+    lmr_table: LmrTable,
+    lmr_stats: LmrStats,
+    /// Whether to tune the base reduction of `lmr_table` between iterations, see `LmrTable::adapt`
+    adaptive_lmr: bool,
+    /// How many first moves at a node prefetch the hash entries of the next, see
+    /// `SPECULATIVE_PREFETCH_MOVES`
+    prefetch_moves: usize,
+    /// How many centipawns worse than the best move the move played may be, for variety in the opening.
+    /// Zero means the best move is always played.
+    opening_variety: i16,
+    /// Picks the move among those within `opening_variety`, see `rootmoves::varied_choice`
+    variety_seed: u64,
+    /// Optional terms of the static evaluation
+    eval_terms: EvalTerms,
+    /// The values of the evaluation and the search that tuning adjusts, see `set_params`
+    params: EngineParams,
+    /// Network to evaluate with instead of the classical evaluation, if one has been loaded
+    #[cfg(feature = "nnue")]
+    network: Option<Arc<Network>>,
+    /// The accumulators of the network along the current line
+    #[cfg(feature = "nnue")]
+    accumulators: AccumulatorStack,
+    /// Evaluation to use instead of the network and the classical evaluation, if one was plugged in
+    /// with `with_evaluator`. The classical evaluation is called directly rather than through the
+    /// `Evaluator` trait, so that searches without a plugged in evaluator pay nothing for dispatch.
+    evaluator: Option<E>,
+    /// Scores pinned to positions by the analyst, `None` if there are none, so that the search only
+    /// pays for a lookup when there are. See `set_overrides`.
+    overrides: Option<Arc<EvalOverrides>>,
+    /// Score of the root, `None` until the first iteration has completed
+    root_score: Option<RootScore>,
+    /// The best root move with its PV as of the last completed iteration, which the info line of the
+    /// iteration and the final bestmove are both taken from. `None` until the first iteration has
+    /// completed.
+    confirmed: Option<ConfirmedMove>,
+    /// Whether the search has a move to play, so that it may honor the stop conditions
+    has_minimum_result: bool,
+    /// Hashes of the positions of the game up to and including the root, see `canonical_root`
+    game_history: Vec<u64>,
+    /// Root moves that the next search leaves out, see `set_excluded_moves`
+    excluded_moves: Vec<ChessMove>,
+    /// Root moves that the next search is restricted to, or empty for all, see `set_search_moves`
+    search_moves: Vec<ChessMove>,
+    /// How long the best move has lasted, as of the last completed iteration
+    stability: BestMoveStability,
+    /// The completed iterations of the search in progress, which time management between iterations
+    /// reads the score history from
+    iterations: Vec<IterationStats>,
+    /// Final score of the previous search in this game, and the side to move it is for
+    previous_score: Option<(Color, BoardScore)>,
+    /// Whether the GUI has asked for extra information with `debug on`
+    debug: bool,
+    /// Whether to check the boards and hash moves of the search, see `set_validate_boards`
+    validate_boards: bool,
+    /// Whether info lines report scores from White's point of view, see `set_report_white_relative`
+    report_white_relative: bool,
+    /// Whether info lines report the expected wins, draws and losses with the score, see `set_show_wdl`
+    show_wdl: bool,
+    /// Whether board validation has found a problem, which stops the search
+    validation_failed: bool,
+    /// Moves from the root to the node being searched
+    line: Vec<ChessMove>,
+    /// Time into the search when the line is next reported in debug mode, see `CURRLINE_INTERVAL`
+    next_currline: time::Duration,
+    /// Snapshots of the hash table during the search, if enabled, see `set_autosave`
+    autosave: Option<Autosave>,
 }
 
 impl<'a> Searcher<'a>
 {
     pub fn new(stop_conditions: &'a StopConditions) -> Self
+    {
+        Self::with_default_hash(stop_conditions, None)
+    }
+}
+
+impl<'a, E: Evaluator + Clone + Send> Searcher<'a, E>
+{
+    /// Create a searcher that evaluates positions with `evaluator` instead of the classical evaluation
+    pub fn with_evaluator(stop_conditions: &'a StopConditions, evaluator: E) -> Self
+    {
+        Self::with_default_hash(stop_conditions, Some(evaluator))
+    }
+
+    fn with_default_hash(stop_conditions: &'a StopConditions, evaluator: Option<E>) -> Self
+    {
+        let hashmap = HashMap::new_or_smaller(DEFAULT_HASH_MEGABYTES);
+        if hashmap.megabytes() != DEFAULT_HASH_MEGABYTES {
+            output::send(format_args!("info string Could not allocate {DEFAULT_HASH_MEGABYTES} MB for the hash table, using {} MB", hashmap.megabytes()));
+        }
+        let mut searcher = Searcher { evaluator, ..Self::with_hashmap(stop_conditions, Arc::new(hashmap)) };
+        searcher.update_collision_detection();
+        searcher
+    }
+
+    fn with_hashmap(stop_conditions: &'a StopConditions, hashmap: Arc<HashMap>) -> Self
     {
         Searcher {
-            hashmap: HashMap::new(128),
+            hashmap,
             stop_conditions,
             nodes: 0,
-            starttime: time::Instant::now(),
+            qnodes: 0,
+            seldepth: 0,
+            threads: 1,
+            is_helper: false,
+            helper_stop: Arc::new(StopConditions::new()),
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            published_nodes: 0,
+            starttime: Instant::now(),
+            limits_starttime: Instant::now(),
+            go_time: None,
+            is_pondering: false,
+            pv_notation: PvNotation::Uci,
+            halfmove_clock: 0,
+            static_evals: Vec::new(),
+            searched_depths: Vec::new(),
+            extended_hash_cutoffs: 0,
+            null_move_cutoffs: 0,
+            null_move_ply: None,
+            hash_stats: HashStats::default(),
+            stop_reason: None,
+            pruning_stats: PruningStats::default(),
+            lmr_table: LmrTable::default(),
+            lmr_stats: LmrStats::default(),
+            adaptive_lmr: false,
+            prefetch_moves: SPECULATIVE_PREFETCH_MOVES,
+            opening_variety: 0,
+            variety_seed: 0,
+            eval_terms: EvalTerms::default(),
+            params: EngineParams::default(),
+            #[cfg(feature = "nnue")]
+            network: None,
+            #[cfg(feature = "nnue")]
+            accumulators: AccumulatorStack::default(),
+            evaluator: None,
+            overrides: None,
+            root_score: None,
+            confirmed: None,
+            has_minimum_result: false,
+            game_history: Vec::new(),
+            excluded_moves: Vec::new(),
+            search_moves: Vec::new(),
+            stability: BestMoveStability::default(),
+            iterations: Vec::new(),
+            previous_score: None,
+            debug: false,
+            validate_boards: false,
+            report_white_relative: false,
+            show_wdl: false,
+            validation_failed: false,
+            line: Vec::new(),
+            next_currline: CURRLINE_INTERVAL,
+            autosave: None,
+        }
+    }
+
+    /// A helper thread for the next search, which searches the same position with the same settings
+    /// into the same hash table, and stops when `stop_conditions` tell it to
+    #[cfg(feature = "std-threads")]
+    fn helper<'b>(&self, stop_conditions: &'b StopConditions) -> Searcher<'b, E>
+    {
+        Searcher {
+            is_helper: true,
+            helper_nodes: Arc::clone(&self.helper_nodes),
+            lmr_table: self.lmr_table.clone(),
+            adaptive_lmr: self.adaptive_lmr,
+            prefetch_moves: self.prefetch_moves,
+            opening_variety: self.opening_variety,
+            eval_terms: self.eval_terms,
+            params: self.params,
+            #[cfg(feature = "nnue")]
+            network: self.network.clone(),
+            evaluator: self.evaluator.clone(),
+            overrides: self.overrides.clone(),
+            game_history: self.game_history.clone(),
+            excluded_moves: self.excluded_moves.clone(),
+            search_moves: self.search_moves.clone(),
+            validate_boards: self.validate_boards,
+            ..Searcher::with_hashmap(stop_conditions, Arc::clone(&self.hashmap))
+        }
+    }
+
+    /// The hash table, for the changes that need it to itself. Helper threads only share it while a
+    /// search runs, so between searches there is no other owner.
+    fn table(&mut self) -> &mut HashMap
+    {
+        Arc::get_mut(&mut self.hashmap).expect("the hash table is only shared during a search")
+    }
+
+    pub fn clear_hash(&mut self)
+    {
+        self.table().clear();
+        output::send(format_args!("info string hash cleared ({} MB)", self.hashmap.megabytes()));
+    }
+
+    /// Replace the hash table with an empty one of another size
+    ///
+    /// The new table is written to right away, so that the operating system commits the memory now,
+    /// rather than page by page during the next search. If there isn't enough memory, the table goes
+    /// back to its old size, or failing that, the default size or less.
+    pub fn resize_hash(&mut self, megabytes: usize)
+    {
+        let starttime = Instant::now();
+        let old_megabytes = self.hashmap.megabytes();
+        // Free the old table first, so that the old and new ones don't need to fit in memory together
+        self.hashmap = Arc::new(HashMap::new_or_smaller(1));
+        self.hashmap = Arc::new(
+            [megabytes, old_megabytes].into_iter()
+                .find_map(HashMap::try_new)
+                .unwrap_or_else(|| HashMap::new_or_smaller(DEFAULT_HASH_MEGABYTES)),
+        );
+        self.update_collision_detection();
+        self.table().clear();
+
+        let actual_megabytes = self.hashmap.megabytes();
+        if actual_megabytes != megabytes {
+            output::send(format_args!("info string Could not allocate {megabytes} MB for the hash table, using {actual_megabytes} MB"));
+        }
+        output::send(format_args!("info string hash resized to {actual_megabytes} MB in {} ms", starttime.elapsed().as_millis()));
+    }
+
+    /// Start a new game announced with ucinewgame, forgetting everything learned in the previous one
+    pub fn new_game(&mut self)
+    {
+        self.clear_hash();
+        self.forget_game();
+    }
+
+    pub fn implicit_new_game(&mut self)
+    {
+        // Skip a generation, so that entries from the previous game are old enough to be purged
+        // right away when the next search starts
+        self.table().new_generation();
+        self.forget_game();
+    }
+
+    /// Forget what the searches of the previous game learned, other than the hash table
+    fn forget_game(&mut self)
+    {
+        self.previous_score = None;
+        // Adaptive reductions are tuned to the positions of a game
+        self.lmr_table = LmrTable::default();
+    }
+
+    pub fn set_pv_notation(&mut self, pv_notation: PvNotation)
+    {
+        self.pv_notation = pv_notation;
+    }
+
+    /// Turn the extra info strings of UCI debug mode on or off
+    pub fn set_debug(&mut self, debug: bool)
+    {
+        self.debug = debug;
+    }
+
+    /// Search with this many threads. The others are helpers which search the same position into the
+    /// shared hash table, from which this thread, the main one, picks up their results. Only the main
+    /// thread reports, and it decides when the search stops.
+    pub fn set_threads(&mut self, threads: usize)
+    {
+        self.threads = threads.max(1);
+    }
+
+    /// Prefetch the hash entries of the next move while searching each of the first `moves` moves at
+    /// a node, or not at all with 0, for measuring what the prefetch gains
+    pub fn set_prefetch_moves(&mut self, moves: usize)
+    {
+        self.prefetch_moves = moves;
+    }
+
+    /// Turn adaptive late move reductions on or off. Turning them off goes back to the default table.
+    pub fn set_adaptive_lmr(&mut self, adaptive_lmr: bool)
+    {
+        self.adaptive_lmr = adaptive_lmr;
+        if !adaptive_lmr {
+            self.lmr_table = LmrTable::default();
+        }
+    }
+
+    /// Play a move up to `margin` centipawns worse than the best one in the next search, picked by
+    /// `seed`. The caller decides whether the position is still in the opening.
+    pub fn set_opening_variety(&mut self, margin: i16, seed: u64)
+    {
+        self.opening_variety = margin.clamp(0, MAX_OPENING_VARIETY);
+        self.variety_seed = seed;
+    }
+
+    /// Turn the hanging piece term of the evaluation on or off
+    pub fn set_hanging_piece_eval(&mut self, hanging_piece: bool)
+    {
+        self.eval_terms.hanging_piece = hanging_piece;
+    }
+
+    /// Evaluate and prune with tuned values, see `params`
+    pub fn set_params(&mut self, params: EngineParams)
+    {
+        self.params = params;
+    }
+
+    /// Check every board of the search for consistency, and every hash move for legality before it is
+    /// played, as the debug assertions do but in any build. The first problem is reported with the
+    /// position, the moves leading to it from the root and its hash entry, and then the search stops
+    /// and plays the best move it has.
+    ///
+    /// Hash collisions are detected as well, see `HashMap::set_collision_detection`, which debug builds
+    /// always do.
+    pub fn set_validate_boards(&mut self, validate_boards: bool)
+    {
+        self.validate_boards = validate_boards;
+        self.update_collision_detection();
+    }
+
+    fn update_collision_detection(&mut self)
+    {
+        let enabled = self.validate_boards || cfg!(debug_assertions);
+        self.table().set_collision_detection(enabled);
+    }
+
+    /// Report the scores of info lines from White's point of view, for users who prefer that to the
+    /// point of view of the side to move that UCI prescribes
+    pub fn set_report_white_relative(&mut self, report_white_relative: bool)
+    {
+        self.report_white_relative = report_white_relative;
+    }
+
+    /// Report the expected shares of wins, draws and losses after the score of info lines, as the
+    /// UCI_ShowWDL option asks for, see `BoardScore::wdl`
+    pub fn set_show_wdl(&mut self, show_wdl: bool)
+    {
+        self.show_wdl = show_wdl;
+    }
+
+    /// Write a snapshot of the hash table to `path` every `interval` while searching, see `autosave`.
+    /// Without a path or with a zero interval, nothing is saved.
+    pub fn set_autosave(&mut self, path: Option<PathBuf>, interval: time::Duration)
+    {
+        self.autosave = path.filter(|_| !interval.is_zero()).map(|path| Autosave::new(path, interval));
+    }
+
+    /// Evaluate with a network, or with the classical evaluation if `None`
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<Network>>)
+    {
+        self.network = network;
+        self.accumulators.clear();
+    }
+
+    /// Take the scores of `overrides` for their positions wherever the search reaches them, see
+    /// `overrides`
+    ///
+    /// The hash table is cleared, since scores found with the old overrides may depend on them.
+    pub fn set_overrides(&mut self, overrides: Arc<EvalOverrides>)
+    {
+        self.overrides = (!overrides.is_empty()).then_some(overrides);
+        self.table().clear();
+    }
+
+    /// Set when the go command for the next search arrived. Its time limits count from then, since
+    /// that is when the clock of the GUI started.
+    pub fn set_go_time(&mut self, go_time: Instant)
+    {
+        self.go_time = Some(go_time);
+    }
+
+    /// Set the positions of the game leading up to the next search, for avoiding repetitions
+    pub fn set_game_history(&mut self, game_history: Vec<u64>)
+    {
+        self.game_history = game_history;
+    }
+
+    /// Leave these moves out at the root of the next search, to find the best move among the others.
+    /// They may still be played anywhere below the root. At least one legal move must be left.
+    ///
+    /// The search doesn't store the root in the hash table, since its result is not the result for
+    /// the position, and ends with an `excluded-best` info string comparing its best move to the best
+    /// of all moves.
+    pub fn set_excluded_moves(&mut self, excluded_moves: Vec<ChessMove>)
+    {
+        self.excluded_moves = excluded_moves;
+    }
+
+    /// Only search these moves at the root of the next search, as `go searchmoves` asks. Any moves may
+    /// still be played below the root. They must all be legal, and an empty list means all moves.
+    ///
+    /// As with excluded moves, the root isn't stored in the hash table, since the best of these moves
+    /// need not be the best move of the position.
+    pub fn set_search_moves(&mut self, search_moves: Vec<ChessMove>)
+    {
+        self.search_moves = search_moves;
+    }
+
+    /// Whether the next search leaves out some of the root moves, see `set_excluded_moves` and
+    /// `set_search_moves`
+    fn is_root_restricted(&self) -> bool
+    {
+        !self.excluded_moves.is_empty() || !self.search_moves.is_empty()
+    }
+
+    /// Look up what the hash table holds about a position
+    pub fn probe(&self, position: &Board) -> Option<ProbeResult>
+    {
+        self.hashmap.probe(position.get_hash())
+    }
+
+    /// Write a snapshot of the hash table to a file, see `hash::snapshot`. Returns the number of entries
+    /// written.
+    pub fn save_hash(&self, path: &Path) -> io::Result<usize>
+    {
+        hash::snapshot::save(&self.hashmap, path)
+    }
+
+    /// Load a snapshot written by `save_hash` into the hash table, see `hash::snapshot::load`. Returns
+    /// the number of entries loaded.
+    pub fn load_hash(&mut self, path: &Path) -> io::Result<usize>
+    {
+        hash::snapshot::load(self.table(), path)
+    }
+
+    /// Write the hash entries of at least `min_depth` for positions within `max_plies` of `position`
+    /// to an EPD file, see `export::export_tree`
+    pub fn export_tree(&self, position: &Board, path: &Path, min_depth: Depth, max_plies: usize) -> io::Result<ExportStats>
+    {
+        let mut output = BufWriter::new(File::create(path)?);
+        let stats = export::export_tree(&self.hashmap, position, min_depth, max_plies, &mut output)?;
+        output.flush()?;
+        Ok(stats)
+    }
+
+    /// Search the position and print the best move
+    ///
+    /// `halfmove_clock` is the number of plies since the last capture or pawn move, which the board
+    /// itself does not keep track of.
+    ///
+    /// Without the `std-threads` feature, there are no helper threads, and the search runs on the
+    /// thread of the caller until it is done, yielding to the host every now and then, see
+    /// `set_yield_hook`.
+    pub fn search(&mut self, position: Board, halfmove_clock: u32) -> SearchResult
+    {
+        self.table().new_generation();
+
+        #[cfg(feature = "std-threads")]
+        let result = self.search_with_helpers(position, halfmove_clock);
+        #[cfg(not(feature = "std-threads"))]
+        let result = self.search_position(position, halfmove_clock);
+        result
+    }
+
+    /// The search of this thread, with helper threads searching alongside it, see `set_threads`
+    #[cfg(feature = "std-threads")]
+    fn search_with_helpers(&mut self, position: Board, halfmove_clock: u32) -> SearchResult
+    {
+        let helper_stop = Arc::clone(&self.helper_stop);
+        helper_stop.stop_now.store(false, Ordering::Release);
+        self.helper_nodes.store(0, Ordering::Relaxed);
+        let helpers = (1..self.threads).map(|_| self.helper(&helper_stop)).collect::<Vec<_>>();
+
+        thread::scope(|scope| {
+            for (index, mut helper) in helpers.into_iter().enumerate()
+            {
+                let spawned = thread::Builder::new()
+                    .name(format!("helper thread {}", index + 1))
+                    .spawn_scoped(scope, move || {
+                        output::silence_this_thread();
+                        helper.search_position(position, halfmove_clock);
+                        helper.publish_nodes();
+                    });
+                // Without this helper, the search is only slower
+                if let Err(error) = spawned {
+                    output::send(format_args!("info string could not start helper thread {}: {error}", index + 1));
+                }
+            }
+            self.search_position(position, halfmove_clock)
+        })
+    }
+
+    /// The search of one thread, see `search`
+    fn search_position(&mut self, position: Board, halfmove_clock: u32) -> SearchResult
+    {
+        // Hash the root the same way however the position was given
+        let position = canonical_root(&position);
+        self.iterations.clear();
+        self.halfmove_clock = halfmove_clock;
+        self.pruning_stats = PruningStats::default();
+        self.lmr_stats = LmrStats::default();
+        self.extended_hash_cutoffs = 0;
+        self.null_move_cutoffs = 0;
+        self.null_move_ply = None;
+        self.hash_stats = HashStats::default();
+        self.stop_reason = None;
+        self.stability = BestMoveStability::default();
+        self.validation_failed = false;
+        self.line.clear();
+        self.next_currline = CURRLINE_INTERVAL;
+        self.root_score = None;
+        self.confirmed = None;
+        self.nodes = 0;
+        self.qnodes = 0;
+        self.seldepth = 0;
+        self.published_nodes = 0;
+        self.starttime = Instant::now();
+        self.limits_starttime = self.starttime;
+        self.is_pondering = self.stop_conditions.ponder.load(Ordering::Acquire);
+
+        // Hash maintenance queued before the go command runs first, and the time it takes after go
+        // arrived is lost from the time limits. The reported time only counts the search itself.
+        if let Some(go_time) = self.go_time.take()
+        {
+            self.limits_starttime = go_time;
+            let delay = self.starttime.duration_since(go_time).as_millis();
+            if delay >= START_DELAY_REPORT_MIN_TIME {
+                output::send(format_args!("info string search started {delay} ms after go"));
+            }
+        }
+        if let Some(autosave) = &mut self.autosave {
+            autosave.start_search(position.to_string());
+        }
+        if self.debug {
+            self.report_time_allocation();
+        }
+
+        let mut root_moves = MoveGen::new_legal(&position)
+            .filter(|m| !self.excluded_moves.contains(m))
+            .filter(|m| self.search_moves.is_empty() || self.search_moves.contains(m))
+            .map(|m| RootMove::new(&position, m))
+            .collect::<Vec<_>>();
+        debug_assert!(!root_moves.is_empty() || !self.is_root_restricted(), "Every legal move was excluded");
+
+        if root_moves.is_empty()
+        {
+            // Checkmate or stalemate, so there is nothing to search
+            let score = if *position.checkers() != chess::EMPTY { BoardScore::MATED } else { BoardScore::EVEN };
+            let final_info = format!("info depth 0 score {}", self.score_for_info(&position, BoundedScore::Exact(score)));
+            if self.wait_to_answer()
+            {
+                output::send(&final_info);
+                output::send("bestmove 0000");
+            }
+            self.excluded_moves.clear();
+            self.search_moves.clear();
+            return SearchResult {
+                best_move: None,
+                ponder_move: None,
+                stop_reason: None,
+                iterations: Vec::new(),
+                final_info,
+                lmr_stats: self.lmr_stats,
+            };
+        }
+
+        // Search the best move from a previous search first
+        let root_entry = self.hashmap.get(&position);
+        if self.debug
+        {
+            match root_entry
+            {
+                Some(entry) => {
+                    let best_move = entry.best_move().map_or("none".to_string(), |m| m.to_string());
+                    output::send(format_args!("info string root hash entry move {best_move} score {} depth {}", entry.score(), entry.depth()));
+                }
+                None => output::send("info string root hash entry none"),
+            }
+        }
+        let hash_move_index = root_entry
+            .and_then(|e| e.best_move())
+            .and_then(|hash_move| root_moves.iter().position(|r| r.chess_move == hash_move));
+        if let Some(index) = hash_move_index {
+            root_moves[..=index].rotate_right(1);
+        }
+
+        // Resume from the depth of a previous search of this position. The iterations below it would
+        // only repeat what the hash table already knows. Mate scores are stored at the maximum depth
+        // regardless of how deep the search went, so they don't tell where to resume. Nor does a
+        // search of all moves tell where a search of only some of them can resume.
+        let depth_limit = self.stop_conditions.depth.load(Ordering::Relaxed);
+        let first_depth = root_entry
+            .filter(|_| hash_move_index.is_some() && !self.is_root_restricted())
+            .map(|e| e.depth())
+            .filter(|&d| d < Depth::MAX)
+            .unwrap_or(1)
+            .min(depth_limit)
+            .max(1);
+
+        // The stop conditions are ignored until there is a move worth playing: either the hash move
+        // from a previous search, or the result of depth 1
+        self.has_minimum_result = first_depth > 1;
+
+        for depth in first_depth..=Depth::MAX
+        {
+            // The limits that can stop the search in the middle of an iteration, see `limits_exceeded`
+            if self.should_stop_search() {
+                break;
+            }
+
+            // The limits that only apply between iterations. The depth limit comes first, so a search
+            // that has reached its depth never waits for the clock. With a minimum search time, the
+            // depth is a minimum instead, which `limits_exceeded` checks.
+            let is_depth_minimum = self.stop_conditions.min_searchtime.load(Ordering::Relaxed) != 0;
+            if self.has_minimum_result && !is_depth_minimum && depth > self.stop_conditions.depth.load(Ordering::Relaxed) {
+                self.stop_reason = Some(StopReason::Depth);
+                break;
+            }
+
+            // If we have used up the time planned for this move, there is no point in starting another
+            // iteration that will not be able to finish. Less time is planned if the best move has
+            // stayed the same for a while, and more if the score has dropped since the last move.
+            let soft_movetime = match self.stop_conditions.soft_movetime.load(Ordering::Relaxed)
+            {
+                0 => 0,
+                soft_movetime => self.scaled_soft_limit(soft_movetime, position.side_to_move()),
+            };
+            if let Some(elapsed) = self.elapsed_for_limits() {
+                if self.has_minimum_result && soft_movetime != 0 && elapsed >= soft_movetime as u128 {
+                    self.stop_reason = Some(StopReason::SoftTime);
+                    break;
+                }
+            }
+
+            let hash_stats = self.hash_stats;
+            if !self.root_search(depth, &position, &mut root_moves) {
+                break;
+            }
+            if self.debug {
+                self.report_hash_stats(depth, hash_stats);
+            }
+            self.avoid_repetition(depth, &position, &mut root_moves);
+
+            let score = root_moves[0].score;
+            self.root_score = Some(RootScore::new(score.unwrap()));
+            self.has_minimum_result = true;
+            let confirmed = self.confirmed.insert(ConfirmedMove { depth, root_move: root_moves[0].clone() });
+            let pv = confirmed.root_move.pv.clone();
+            output::send(self.info_line(&position, depth, Some(score), &pv));
+
+            let time = self.starttime.elapsed().as_millis() as u64;
+            self.stability.update(&root_moves);
+            if self.debug {
+                output::send(format_args!("info string stability {} {}", self.stability.stable_iterations, self.stability.node_share_percent));
+            }
+            self.iterations.push(IterationStats { depth, score, nodes: self.nodes, time, stability: self.stability });
+            if let Some(autosave) = &mut self.autosave
+            {
+                let pv = pv.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ");
+                autosave.set_progress(depth, score.to_white_relative(position.side_to_move()).to_string(), pv);
+            }
+
+            if self.adaptive_lmr {
+                self.lmr_table.adapt(&self.lmr_stats);
+            }
+        }
+
+        // Having searched every depth there is also stops the search
+        let stop_reason = *self.stop_reason.get_or_insert(StopReason::Depth);
+        if self.debug {
+            output::send(format_args!("info string search stopped by {stop_reason}"));
+        }
+
+        // The helpers have nothing more to contribute once the main thread has its result
+        if !self.is_helper {
+            self.helper_stop.stop_now.store(true, Ordering::Release);
+        }
+        if let Some(autosave) = &mut self.autosave {
+            autosave.end_search();
+        }
+
+        let answer = self.wait_to_answer();
+
+        let pruning_stats = self.pruning_stats;
+        debug_assert!(pruning_stats.at_root == 0 && pruning_stats.in_pv == 0 && pruning_stats.in_check == 0);
+        if self.debug
+        {
+            let mut batch = output::Batch::new();
+            batch.line(format_args!("info string quiescence nodes {} of {} total, {} nodes pruned", self.qnodes, self.nodes, pruning_stats.total));
+            batch.line(format_args!("info string hash cutoffs at nodes extended for check {}", self.extended_hash_cutoffs));
+            batch.line(format_args!("info string null move cutoffs {}", self.null_move_cutoffs));
+            batch.line(format_args!("info string hash stores skipped under table pressure {}", self.hashmap.skipped_stores()));
+            match self.hashmap.detected_collisions()
+            {
+                Some(collisions) => batch.line(format_args!("info string hash collisions detected {collisions}, duplicate entries {}", self.hashmap.duplicate_entries())),
+                None => batch.line(format_args!("info string hash duplicate entries {}", self.hashmap.duplicate_entries())),
+            }
+            batch.line(format_args!("info string lmr re-searched {} of {} reduced moves, by depth {} (lmr base {})",
+                self.lmr_stats.total_researched(), self.lmr_stats.total_reduced(), self.lmr_stats.summary(), self.lmr_table.base()));
+            batch.send();
+        }
+
+        if self.debug && !self.iterations.is_empty() {
+            output::send(format_args!("info string scorehistory {}", score_history(&self.iterations)));
+        }
+
+        // In the opening, play any move nearly as good as the best one, so that games don't all follow
+        // the same line. The root moves are still those of the last completed iteration.
+        if self.opening_variety > 0 && !self.is_helper
+        {
+            if let Some(confirmed) = &mut self.confirmed {
+                let index = varied_choice(&root_moves, self.opening_variety, self.variety_seed);
+                confirmed.root_move = root_moves[index].clone();
+            }
+        }
+
+        // Remember the score for the next search, unless some moves were left out of this one
+        if let Some(last) = self.iterations.last().filter(|_| !self.is_root_restricted()) {
+            self.previous_score = Some((position.side_to_move(), last.score.unwrap()));
+        }
+
+        // GUIs take the last info line before bestmove as the final assessment. Whatever stopped the
+        // search, repeat the result of the last completed iteration, which is what we are going to
+        // play, with the final node count and time. The move and the PV come from the same confirmed
+        // result, so a stopped iteration or a purge of the hash table can't make them disagree.
+        let (best_move, ponder_move, final_info) = match &self.confirmed
+        {
+            Some(confirmed) => {
+                let root_move = &confirmed.root_move;
+                let final_info = self.info_line(&position, confirmed.depth, Some(root_move.score), &root_move.pv);
+                (root_move.chess_move, ponder_move(&position, &root_move.pv), final_info)
+            }
+            // Not even the first iteration completed, so we have nothing but the move, which is the
+            // hash move if there is one
+            None => (root_moves[0].chess_move, None, self.info_line(&position, 0, None, &[root_moves[0].chess_move])),
+        };
+        let excluded_summary = match self.confirmed.clone()
+        {
+            Some(confirmed) if !self.excluded_moves.is_empty() => Some(self.excluded_best_summary(&position, confirmed.depth, &confirmed.root_move)),
+            _ => None,
+        };
+        if answer
+        {
+            output::send(&final_info);
+            if let Some(summary) = excluded_summary {
+                output::send(summary);
+            }
+            match ponder_move
+            {
+                Some(ponder_move) => output::send(format_args!("bestmove {best_move} ponder {ponder_move}")),
+                None => output::send(format_args!("bestmove {best_move}")),
+            }
+        }
+        self.excluded_moves.clear();
+        self.search_moves.clear();
+
+        SearchResult {
+            best_move: Some(best_move),
+            ponder_move,
+            stop_reason: Some(stop_reason),
+            iterations: self.iterations.clone(),
+            final_info,
+            lmr_stats: self.lmr_stats,
+        }
+    }
+
+    /// Wait until the best move may be sent, and return whether it should be sent at all, which it
+    /// shouldn't if a new go superseded this ponder search
+    fn wait_to_answer(&self) -> bool
+    {
+        // A ponder search must not send bestmove until ponderhit or stop, and an infinite search not
+        // until stop, even if they finished early
+        self.stop_conditions.wait_while_held(None, |stop_conditions| {
+            stop_conditions.ponder.load(Ordering::Acquire) || stop_conditions.infinite.load(Ordering::Acquire)
+        });
+        if self.stop_conditions.superseded.load(Ordering::Acquire)
+        {
+            output::send("info string ponder search superseded by go, no bestmove");
+            return false;
+        }
+
+        // Nor is a move that was found quickly sent before the floor of the MoveTimeFloor option
+        let min_movetime = time::Duration::from_millis(self.stop_conditions.min_movetime.load(Ordering::Relaxed).into());
+        if let Some(remaining) = min_movetime.checked_sub(self.limits_starttime.elapsed()) {
+            self.stop_conditions.wait_while_held(Some(remaining), |_| true);
+        }
+        true
+    }
+
+    /// The soft time limit, scaled by the stability of the best move and by how the score has changed
+    /// since the previous search, see `timeman::scaled_soft_limit`
+    fn scaled_soft_limit(&self, soft_movetime: u32, side_to_move: Color) -> u32
+    {
+        let current = self.iterations.last().map(|last| last.score.unwrap());
+        let previous = self.previous_score.map(|(side, score)| if side == side_to_move { score } else { -score });
+        let hard_movetime = self.stop_conditions.movetime.load(Ordering::Relaxed);
+        timeman::scaled_soft_limit(soft_movetime, hard_movetime, &self.stability, previous, current)
+    }
+
+    /// The `excluded-best` info string, comparing `best`, the best of the moves that were not excluded,
+    /// to the best of all moves
+    ///
+    /// The excluded moves are searched again to the depth of the last iteration, which is quick since
+    /// the hash table holds most of their trees already. If the search was stopped, they only get
+    /// what the hash table knows.
+    fn excluded_best_summary(&mut self, position: &Board, depth: Depth, best: &RootMove) -> String
+    {
+        let (mut overall_move, mut overall_score) = (best.chess_move, best.score);
+        for excluded in self.excluded_moves.clone()
+        {
+            let new_position = position.make_move_new(excluded);
+            self.note_line_move(0, excluded);
+            let score = self.search_child(depth, 0, &new_position, BoardScore::WORST_SCORE, BoardScore::BEST_SCORE);
+            if score.unwrap() != BoardScore::NO_SCORE && score > overall_score {
+                (overall_move, overall_score) = (excluded, score);
+            }
+        }
+
+        format!("info string excluded-best {} score {} best {overall_move} score {overall_score}", best.chess_move, best.score)
+    }
+
+    /// Format an info line with the search statistics so far, for a result of the given depth
+    fn info_line(&self, position: &Board, depth: Depth, score: Option<BoundedScore>, pv: &[ChessMove]) -> String
+    {
+        let nodes = self.total_nodes();
+        let time = self.starttime.elapsed().as_millis() as u64;
+        let nps = if time != 0 { (1000 * nodes) / time } else { 0 };
+        let hashfull = (1000 * self.hashmap.filled()) / self.hashmap.capacity();
+        let score = score.map_or(String::new(), |score| format!(" score {}", self.score_for_info(position, score)));
+        // GUIs expect at least one move after the pv keyword
+        let pv = match pv
+        {
+            [] => String::new(),
+            pv => format!(" pv {}", san::format_pv(position, pv, self.pv_notation)),
+        };
+        // Hash cutoffs near the root can leave the deepest ply reached below the nominal depth
+        let seldepth = self.seldepth.max(depth as usize);
+        format!("info depth {depth} seldepth {seldepth} multipv 1{score} nodes {nodes} nps {nps} hashfull {hashfull} time {time}{pv}")
+    }
+
+    /// Nodes searched so far, by this thread and its helpers
+    fn total_nodes(&self) -> u64
+    {
+        if self.is_helper { self.nodes } else { self.nodes + self.helper_nodes.load(Ordering::Relaxed) }
+    }
+
+    /// Add the nodes that a helper thread has searched since it last did so to `helper_nodes`
+    fn publish_nodes(&mut self)
+    {
+        if self.is_helper
+        {
+            self.helper_nodes.fetch_add(self.nodes - self.published_nodes, Ordering::Relaxed);
+            self.published_nodes = self.nodes;
+        }
+    }
+
+    /// The score of an info line, with the expected wins, draws and losses if they are shown, from the
+    /// same point of view as the score
+    fn score_for_info(&self, position: &Board, score: BoundedScore) -> String
+    {
+        let score = self.reported_score(position, score);
+        if self.show_wdl { format!("{score} {}", score.unwrap().wdl()) } else { score.to_string() }
+    }
+
+    /// A score for the side to move in `position` as info lines report it, see `set_report_white_relative`
+    fn reported_score(&self, position: &Board, score: BoundedScore) -> BoundedScore
+    {
+        if self.report_white_relative { score.to_white_relative(position.side_to_move()) } else { score }
+    }
+
+    /// Search all root moves to the given depth, and sort them with the preferred move first
+    ///
+    /// Returns false if the search was stopped before the iteration completed. The root moves are
+    /// then left as they were after the previous iteration, since the partial results can't be
+    /// compared fairly.
+    fn root_search(&mut self, depth: Depth, position: &Board, root_moves: &mut Vec<RootMove>) -> bool
+    {
+        debug_assert!(depth > 0);
+        self.nodes += 1;
+        self.note_searched_depth(0, depth);
+
+        let beta = BoardScore::BEST_SCORE;
+        let mut alpha = BoardScore::WORST_SCORE;
+        let mut searched = root_moves.clone();
+
+        let in_check = *position.checkers() != chess::EMPTY;
+        let static_eval = if in_check { None } else { Some(self.static_evaluation(position, 0)) };
+        self.update_improving(0, static_eval);
+
+        for (index, root_move) in searched.iter_mut().enumerate()
+        {
+            if self.starttime.elapsed().as_millis() >= CURRMOVE_MIN_TIME
+            {
+                let currmove = san::format_pv(position, &[root_move.chess_move], self.pv_notation);
+                output::send(format_args!("info depth {depth} currmove {currmove} currmovenumber {}", index + 1));
+            }
+
+            let new_position = position.make_move_new(root_move.chess_move);
+            let nodes_before = (self.nodes, self.qnodes);
+            self.note_line_move(0, root_move.chess_move);
+
+            // Search one below alpha, so that moves that are as good as the best move so far get exact
+            // scores. Otherwise the tie-breaking in `RootMove::preference` would never come into play.
+            // For variety in the opening, the moves within the margin need exact scores too.
+            let lower_bound = if self.opening_variety > 0 && !alpha.is_mate_score() { alpha.add_centipawns(-self.opening_variety) } else { alpha };
+            let search_score = self.search_child(depth, 0, &new_position, lower_bound.next_lower(), beta);
+
+            self.report_quiescence_share(root_move.chess_move, nodes_before);
+
+            if self.should_stop_search() {
+                return false;
+            }
+
+            root_move.score = search_score;
+            root_move.nodes = self.nodes - nodes_before.0;
+            root_move.pv.truncate(1);
+            root_move.pv.extend(self.trace_pv(&new_position));
+
+            if search_score.is_exact() && search_score.unwrap() > alpha {
+                alpha = search_score.unwrap();
+                self.report_in_progress(position, depth, BoundedScore::LowerBound(alpha), &root_move.pv);
+            }
+        }
+
+        // When winning, avoid shuffling pieces back and forth by preferring moves that make progress
+        let prefer_progress = self.halfmove_clock > 0 && alpha > BoardScore::evaluation(PROGRESS_MIN_SCORE);
+        sort_root_moves(&mut searched, prefer_progress);
+
+        // Store the root in the hash table too, so a later search of the same position starts with
+        // the best move. Not if some moves were left out, since then this is not the best move.
+        if !self.is_root_restricted()
+        {
+            let best = &searched[0];
+            let hash_entry = HashEntry::with_contents(position.get_hash(), Some(best.chess_move), best.score, depth);
+            self.hashmap.insert(position, hash_entry);
+        }
+
+        *root_moves = searched;
+        true
+    }
+
+    /// When winning, don't play into a position that lets the opponent claim a draw by threefold
+    /// repetition, as long as there is another move that is better than a draw
+    ///
+    /// The search itself knows nothing about the game history, so the score of a repeating line is
+    /// what it would be without the repetition. If the PV of the best move repeats, the other moves
+    /// are searched again on their own to find the best alternative.
+    fn avoid_repetition(&mut self, depth: Depth, position: &Board, root_moves: &mut Vec<RootMove>)
+    {
+        let best = &root_moves[0];
+        let is_winning = best.score.is_exact() && best.score.unwrap() > BoardScore::evaluation(REPETITION_AVOIDANCE_MIN_SCORE);
+        if !is_winning || root_moves.len() < 2 || !self.is_repetition_draw(position, &best.pv) {
+            return;
+        }
+
+        let mut alternatives = root_moves[1..].to_vec();
+        if !self.root_search(depth, position, &mut alternatives) {
+            return;
+        }
+
+        let repeating = root_moves[0].clone();
+        if alternatives[0].score > BoundedScore::Exact(BoardScore::EVEN)
+        {
+            // Play the alternative, keeping the repeating move as the runner-up
+            alternatives.insert(1, repeating);
+            *root_moves = alternatives;
+        }
+        else
+        {
+            // The search of the alternatives replaced the root hash entry, so put back the move we
+            // are going to play
+            let hash_entry = HashEntry::with_contents(position.get_hash(), Some(repeating.chess_move), repeating.score, depth);
+            self.hashmap.insert(position, hash_entry);
+        }
+    }
+
+    /// Whether playing `pv` from `position` reaches a position for the third time, counting the game
+    /// history and the earlier positions of the PV itself
+    pub fn is_repetition_draw(&self, position: &Board, pv: &[ChessMove]) -> bool
+    {
+        let mut seen = self.game_history.clone();
+        let mut position = *position;
+        for &chess_move in pv
+        {
+            // Nothing from before an irreversible move can repeat
+            if position.is_irreversible(chess_move) {
+                seen.clear();
+            }
+            position = position.make_move_new(chess_move);
+            let hash = canonical_root(&position).get_hash();
+            if seen.iter().filter(|&&h| h == hash).count() >= 2 {
+                return true;
+            }
+            seen.push(hash);
+        }
+        false
+    }
+
+    /// Calculate the score for a position with alpha-beta search
+    ///
+    /// If the score is higher than `beta`, it may not calculate the exact score, but instead provide
+    /// a `BoundedScore::LowerBound` of at least `beta`. Similarly, if the score is lower than `alpha`
+    /// it may instead calculate a `BoundedScore::UpperBound` of at most `alpha`.
+    ///
+    /// If the search gets stopped partway, it may also return `LowerBound` and `UpperBound` scores that
+    /// lie inside the range of `alpha` and `beta`.
+    fn alphabeta_search(&mut self, mut depth: Depth, ply: usize, position: &Board, node_kind: NodeKind, mut alpha: BoardScore, beta: BoardScore) -> BoundedScore
+    {
+        use BoundedScore::*;
+
+        debug_assert!(position.is_sane());
+        debug_assert!(ply > 0, "The root is searched by root_search");
+        debug_assert_eq!(node_kind, NodeKind::new(ply, alpha, beta));
+        debug_assert!(alpha != BoardScore::NO_SCORE);
+        debug_assert!(beta != BoardScore::NO_SCORE);
+        debug_assert!(alpha <= beta);
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.nodes % PERIODIC_STEP_NODES == 0 {
+            self.periodic_step();
+        }
+
+        if self.validate_boards && !self.validation_failed && !position.is_sane()
+        {
+            self.report_invalid_board(position, "the board is not sane");
+            return LowerBound(BoardScore::WORST_SCORE);
+        }
+
+        // An overridden position is neither searched nor looked up in the hash table
+        if let Some(score) = self.overrides.as_ref().and_then(|overrides| overrides.get(position)) {
+            return Exact(score);
+        }
+
+        // First, alpha and beta may be overdetermined, so no searching is necessary. This will happen
+        // if, say, a mate-in-five has been found on another branch, and we are now six plies deep on
+        // this branch. There is no way be can beat a mate-in-five at a depth of six, so we bail.
+        if alpha >= BoardScore::BEST_SCORE {
+            // BEST_SCORE can never be achieved on a real board, the best is MATE
+            return UpperBound(BoardScore::MATE);
+        }
+
+        if beta == BoardScore::WORST_SCORE {
+            // WORST_SCORE can never be achieved on a real board, the worst is MATED
+            return LowerBound(BoardScore::MATED);
+        }
+
+        // Check extension: the checks may be leading up to a mate, and there are few moves out of
+        // check, so the node is searched one ply deeper. The extended depth is the depth actually
+        // searched, so it is also the depth that hash entries are compared with and stored at.
+        let in_check = *position.checkers() != chess::EMPTY;
+        let root_depth = self.searched_depths[0] as usize;
+        if in_check && ply < CHECK_EXTENSION_MAX_PLY_FACTOR * root_depth {
+            depth = depth.saturating_add(CHECK_EXTENSION);
+        }
+        self.note_searched_depth(ply, depth);
+
+        // Once the search is stopping, the iteration is thrown away, see `root_search`, so every node
+        // returns at once, without even a hash lookup, and the best move gets sent without delay
+        if self.should_stop_search() {
+            return LowerBound(BoardScore::WORST_SCORE);
+        }
+
+        let mut previous_best_move = None;
+
+        // Second, look up in hash table to see if this node has been searched already...
+        self.hash_stats.probes += 1;
+        if let Some(hash_entry) = self.hashmap.get(position)
+        {
+            self.hash_stats.hits += 1;
+            debug_assert!(hash_entry.hash() == position.get_hash());
+            debug_assert!(hash_entry.score().unwrap() != BoardScore::NO_SCORE);
+            // ... and to sufficient depth.
+            if hash_entry.depth() >= depth
+            {
+                // If the previous score is compatible with our alpha-beta bounds, we can return it
+                let cutoff = match hash_entry.score()
+                {
+                    Exact(s) => Some(Exact(s)),
+                    LowerBound(s) if s >= beta => Some(LowerBound(s)),
+                    UpperBound(s) if s <= alpha => Some(UpperBound(s)),
+                    _ => None,
+                };
+                if let Some(score) = cutoff
+                {
+                    self.hash_stats.cutoffs += 1;
+                    if in_check {
+                        self.extended_hash_cutoffs += 1;
+                    }
+                    return score;
+                }
+            }
+            // Even if the score is not compatible, we can use the previous information in our current
+            // search.
+            previous_best_move = hash_entry.best_move();
+        }
+
+        // A hash collision could give us a move that isn't legal here, which the move generator would
+        // play without checking
+        if self.validate_boards && !self.validation_failed
+        {
+            if let Some(hash_move) = previous_best_move.filter(|&m| !position.legal(m))
+            {
+                self.report_invalid_board(position, &format!("the hash move {hash_move} is not legal"));
+                return LowerBound(BoardScore::WORST_SCORE);
+            }
+        }
+
+        // Do the recursive search of all moves
+        if depth > 0
+        {
+            let static_eval = if in_check { None } else { Some(self.static_evaluation(position, ply)) };
+            let improving = self.update_improving(ply, static_eval);
+            let pruning = PruningContext::new(node_kind, in_check, improving, alpha, beta);
+
+            // Reverse futility pruning: if the static evaluation is far enough above beta, assume that
+            // there is a move that keeps it there
+            if let Some(static_eval) = static_eval
+            {
+                if pruning.allows_pruning() && depth <= FUTILITY_MAX_DEPTH
+                {
+                    let margin = self.params.futility_margin * (depth - improving as Depth) as i16;
+                    if static_eval >= beta.add_centipawns(margin) {
+                        self.pruning_stats.record(&pruning);
+                        return LowerBound(beta);
+                    }
+                }
+            }
+
+            // Null move pruning: if passing and letting the opponent move twice still scores at least
+            // beta in a shallower search, a real move would very likely do so too. Not right after
+            // another null move, which would just search the same position shallower, and not with only
+            // pawns left, where zugzwang makes passing better than any move.
+            if let Some(static_eval) = static_eval
+            {
+                if pruning.allows_pruning() && depth >= NULL_MOVE_MIN_DEPTH && static_eval >= beta &&
+                    position.can_null_move() && position.has_pieces_besides_pawns() &&
+                    self.null_move_ply.map_or(true, |null_ply| null_ply + 1 != ply)
+                {
+                    let null_position = position.null_move().expect("not in check");
+                    let outer_null_move_ply = self.null_move_ply.replace(ply);
+                    self.line.truncate(ply);
+                    let null_score = self.search_child(depth - NULL_MOVE_REDUCTION, ply, &null_position, beta.next_lower(), beta);
+                    self.null_move_ply = outer_null_move_ply;
+
+                    if self.should_stop_search() {
+                        return LowerBound(BoardScore::WORST_SCORE);
+                    }
+                    // A mate found after passing is not a mate that a real move leads to, so only the
+                    // bound is trusted
+                    if null_score.unwrap() >= beta
+                    {
+                        self.pruning_stats.record(&pruning);
+                        self.null_move_cutoffs += 1;
+                        return LowerBound(beta);
+                    }
+                }
+            }
+
+            // The best move so far and its score, `None` until a move has a score to compare
+            let mut best: Option<(BoundedScore, ChessMove)> = None;
+            let mut any_moves = false;
+            let mut move_number = 0;
+            let mut move_gen = MoveGenerator::new(position, previous_best_move).peekable();
+
+            while let Some(next_move) = move_gen.next()
+            {
+                let is_first_move = !any_moves;
+                any_moves = true;
+                move_number += 1;
+
+                let new_position = position.make_move_new(next_move);
+                let is_quiet = !position.is_capture(next_move) && next_move.get_promotion().is_none();
+                let gives_check = *new_position.checkers() != chess::EMPTY;
+                let is_hash_move = Some(next_move) == previous_best_move;
+                self.note_line_move(ply, next_move);
+
+                if move_number <= self.prefetch_moves {
+                    if let Some(&upcoming) = move_gen.peek() {
+                        self.hashmap.prefetch(zobrist_after(position, upcoming));
+                    }
+                }
+
+                // Static exchange pruning: a quiet move that puts a piece where the opponent can win
+                // material is unlikely to be good enough at low depth. Checks are exempt, since they
+                // may be tactics that the exchange evaluation can't see, and so is the hash move.
+                if pruning.allows_pruning() && depth <= SEE_PRUNING_MAX_DEPTH && is_quiet && !gives_check && !is_hash_move
+                {
+                    let hanging = see::exchange_gain(&new_position, next_move.get_dest());
+                    if hanging > self.params.see_pruning_margin * depth as i16 {
+                        self.pruning_stats.record(&pruning);
+                        continue;
+                    }
+                }
+
+                let reduction = if pruning.allows_reductions() && is_quiet && !gives_check && !is_hash_move {
+                    self.lmr_table.reduction(depth, move_number)
+                } else {
+                    0
+                };
+
+                // println!("Trying move {next_move} {{");
+                let search_score = if reduction > 0 {
+                    // Late move reduction: a quiet move this late in the move order is not expected to
+                    // reach beta, so a shallower search should be enough to show it. Only if it does
+                    // reach beta after all is it searched again to full depth.
+                    let reduced_score = self.search_child(depth - reduction, ply, &new_position, alpha, beta);
+                    let fails_high = !reduced_score.is_upperbound() && reduced_score.unwrap() >= beta;
+                    self.lmr_stats.record(depth, fails_high);
+                    if fails_high {
+                        self.search_child(depth, ply, &new_position, alpha, beta)
+                    } else {
+                        reduced_score
+                    }
+                } else if is_first_move || node_kind == NodeKind::NonPv {
+                    self.search_child(depth, ply, &new_position, alpha, beta)
+                } else {
+                    // Principal variation search: expect the moves after the first to be no better
+                    // than alpha, and verify that with a cheaper null window search. Only moves that
+                    // turn out better get searched again with the full window.
+                    let scout_score = self.search_child(depth, ply, &new_position, alpha, alpha.next_higher());
+                    if scout_score.is_lowerbound() && scout_score.unwrap() > alpha && scout_score.unwrap() < beta {
+                        self.search_child(depth, ply, &new_position, alpha, beta)
+                    } else {
+                        scout_score
+                    }
+                };
+
+                // A search that was stopped partway may return a score that isn't even a bound of the
+                // window, which we call a deficient search
+                debug_assert!(
+                    (!(search_score.is_lowerbound() && search_score.unwrap() < beta) &&
+                        !(search_score.is_upperbound() && search_score.unwrap() > alpha)) ||
+                        self.should_stop_search(),
+                    "Deficient scores should only appear if stopping",
+                );
+
+                // Once stopping, the score of this move may be deficient, and the moves not searched
+                // yet have none at all, so the node has no result. It stores nothing in the hash table,
+                // where a bound made up from part of the moves would mislead later searches, and
+                // returns a noncommitting score. The iteration gets thrown away anyway, see
+                // `root_search`.
+                if self.should_stop_search() {
+                    return LowerBound(BoardScore::WORST_SCORE);
+                }
+                debug_assert!(search_score.unwrap() != BoardScore::NO_SCORE);
+
+                let is_new_best = match best
+                {
+                    None => true,
+                    Some((best_score, _)) => search_score > best_score,
+                };
+                if is_new_best {
+                    // println!("New best move {next_move} with score {search_score}");
+                    best = Some((search_score, next_move));
+                    if !search_score.is_upperbound() && search_score.unwrap() > alpha
+                    {
+                        // Found a move better than alpha, so update alpha.
+                        alpha = search_score.unwrap();
+                    }
+                }
+                // println!("}}");
+                // TODO: Fix comparison function so this can be expressed as `best_score > Exact(beta)`
+                if let Some((best_score, best_move)) = best
+                {
+                    if !best_score.is_upperbound() && best_score.unwrap() >= beta {
+                        // If we found a move better than beta, we don't need to consider any other moves.
+                        // This particular position is "too good" for us, and will therefore never be played
+                        // by a minmaxing opponent anyway, so further search can be pruned. This score is
+                        // now a LowerBound score: there could be even higher scores in the other moves.
+                        // println!("beta bailing: {best_score:?} > {beta:?}");
+                        best = Some((LowerBound(best_score.unwrap()), best_move));
+                        break;
+                    }
+                }
+            }
+
+            let best_move = best.map(|(_, best_move)| best_move);
+            let best_score = if !any_moves
+            {
+                // There were no legal moves!
+                // This means checkmate or stalemate
+                if *position.checkers() != chess::EMPTY {
+                    Exact(BoardScore::MATED)
+                } else {
+                    Exact(BoardScore::EVEN)
+                }
+            }
+            else
+            {
+                // Without a best move, every move was pruned on the assumption that none of them would
+                // reach alpha. A search that was stopped has returned above.
+                best.map_or(UpperBound(alpha), |(best_score, _)| best_score)
+            };
+            debug_assert!(!best_score.is_exact() || best_move.is_some() || !any_moves, "Exact score {best_score} without a best move");
+
+            // Finally store the new result in the hash table
+            let store_depth = if !any_moves {
+                // This evaluation is valid for any depth for purposes of hashtable lookup
+                Depth::MAX
+            } else if best_score.is_exact() && best_score.unwrap().is_mate_score() {
+                // This evaluation is valid for any depth for purposes of hashtable lookup.
+                Depth::MAX
+            } else {
+                depth
+            };
+            // Only an extension can make a node search deeper than its parent
+            debug_assert!(
+                !any_moves || store_depth == Depth::MAX ||
+                    store_depth <= self.searched_depths[ply - 1].saturating_add(CHECK_EXTENSION),
+                "Storing depth {store_depth} at ply {ply}, below a node of depth {}", self.searched_depths[ply - 1],
+            );
+            debug_assert!(best_score.unwrap() != BoardScore::NO_SCORE);
+            debug_assert!(self.stop_reason.is_none(), "Storing the result of a node that was stopped");
+            let hash_entry = HashEntry::with_contents(
+                position.get_hash(),
+                best_move,
+                best_score,
+                store_depth,
+            );
+
+            // println!("info string returning {best_score} at depth = {depth}");
+            self.hashmap.insert(position, hash_entry);
+
+            best_score
+        }
+
+        else
+        {
+            // Depth is zero, resolve captures with quiescence search before evaluating
+            self.quiescence_search(ply, position, alpha, beta, 0)
+        }
+    }
+
+    /// Search the position after a move from a node at `depth` and `ply`, with `alpha` and `beta` given
+    /// from the point of view of the node
+    fn search_child(&mut self, depth: Depth, ply: usize, new_position: &Board, alpha: BoardScore, beta: BoardScore) -> BoundedScore
+    {
+        let child_alpha = -beta.decrement_mate_plies();
+        let child_beta = -alpha.decrement_mate_plies();
+        let node_kind = NodeKind::new(ply + 1, child_alpha, child_beta);
+
+        -self.alphabeta_search(depth - 1, ply + 1, new_position, node_kind, child_alpha, child_beta)
+            .increment_mate_plies()
+    }
+
+    /// Remember the depth, including extensions, that the node at `ply` searches to
+    fn note_searched_depth(&mut self, ply: usize, depth: Depth)
+    {
+        if self.searched_depths.len() <= ply {
+            self.searched_depths.resize(ply + 1, 0);
+        }
+        self.searched_depths[ply] = depth;
+    }
+
+    /// Remember that `chess_move` is being searched from the node at `ply`, for reporting problems
+    /// found below it and the current line in debug mode
+    fn note_line_move(&mut self, ply: usize, chess_move: ChessMove)
+    {
+        self.line.truncate(ply);
+        self.line.push(chess_move);
+    }
+
+    /// Report a problem that board validation found in `position`, and stop the search
+    fn report_invalid_board(&mut self, position: &Board, problem: &str)
+    {
+        let line = self.line.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ");
+        let hash_entry = match self.hashmap.get(position)
+        {
+            Some(entry) => {
+                let best_move = entry.best_move().map_or("none".to_string(), |m| m.to_string());
+                format!("move {best_move} score {} depth {}", entry.score(), entry.depth())
+            }
+            None => "none".to_string(),
+        };
+
+        output::send(format_args!("info string ValidateBoards: {problem}, stopping the search"));
+        output::send(format_args!("info string ValidateBoards: fen {position}"));
+        output::send(format_args!("info string ValidateBoards: moves from the root {line}"));
+        output::send(format_args!("info string ValidateBoards: hash entry {hash_entry}"));
+        self.validation_failed = true;
+    }
+
+    /// Remember the static evaluation of the node at `ply`, and tell whether it is better than two
+    /// plies earlier. When that can't be told, the position is assumed to be improving, which is the
+    /// cautious choice for pruning.
+    fn update_improving(&mut self, ply: usize, static_eval: Option<BoardScore>) -> bool
+    {
+        if self.static_evals.len() <= ply {
+            self.static_evals.resize(ply + 1, None);
+        }
+        self.static_evals[ply] = static_eval;
+
+        let earlier_eval = ply.checked_sub(2).and_then(|earlier_ply| self.static_evals[earlier_ply]);
+        match (static_eval, earlier_eval)
+        {
+            (Some(eval), Some(earlier_eval)) => eval > earlier_eval,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Calculate the score for a position by only searching captures and queen promotions, or all moves
+    /// when in check
+    ///
+    /// Follows the same conventions for bounds as `alphabeta_search`. The side to move may "stand pat"
+    /// on the static evaluation, unless in check. After `MAX_QUIESCENCE_PLIES`, no further moves are
+    /// searched. `ply` counts from the root, and `qply` from the start of the quiescence search.
+    fn quiescence_search(&mut self, ply: usize, position: &Board, mut alpha: BoardScore, beta: BoardScore, qply: u32) -> BoundedScore
+    {
+        use BoundedScore::*;
+
+        debug_assert!(position.is_sane());
+        debug_assert!(alpha != BoardScore::NO_SCORE);
+        debug_assert!(beta != BoardScore::NO_SCORE);
+        debug_assert!(alpha <= beta);
+
+        self.nodes += 1;
+        self.qnodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.nodes % PERIODIC_STEP_NODES == 0 {
+            self.periodic_step();
+        }
+
+        if self.validate_boards && !self.validation_failed && !position.is_sane()
+        {
+            self.report_invalid_board(position, "the board is not sane");
+            return LowerBound(BoardScore::WORST_SCORE);
+        }
+        if let Some(score) = self.overrides.as_ref().and_then(|overrides| overrides.get(position)) {
+            return Exact(score);
+        }
+        let is_in_check = *position.checkers() != chess::EMPTY;
+        let mut move_gen = MoveGen::new_legal(position);
+        if move_gen.len() == 0
+        {
+            // This means checkmate or stalemate
+            // TODO: This evaluation is valid for any depth for purposes of hashtable lookup.
+            return if is_in_check { Exact(BoardScore::MATED) } else { Exact(BoardScore::EVEN) };
+        }
+
+        if qply >= MAX_QUIESCENCE_PLIES
+        {
+            if is_in_check {
+                // Without searching the evasions we can't tell whether this is mate. Rather than
+                // returning a possibly wrong exact score, fail low.
+                return UpperBound(alpha);
+            }
+            return Exact(self.static_evaluation(position, ply));
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = BoardScore::WORST_SCORE;
+
+        if !is_in_check
+        {
+            let stand_pat = self.static_evaluation(position, ply);
+            if stand_pat >= beta {
+                return LowerBound(stand_pat);
+            }
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
+            best_score = stand_pat;
+        }
+
+        // In check, every evasion is searched. Otherwise only captures, and then quiet promotions to a
+        // queen, which change the material as much as a capture does.
+        let masks = if is_in_check {
+            [!chess::EMPTY, chess::EMPTY]
+        } else {
+            let enemy_pieces = *position.color_combined(!position.side_to_move());
+            let promotion_squares = chess::get_rank(position.side_to_move().to_their_backrank()) & !*position.combined();
+            [enemy_pieces, promotion_squares]
+        };
+
+        for mask in masks
+        {
+            move_gen.set_iterator_mask(mask);
+            for next_move in &mut move_gen
+            {
+                // Pieces other than pawns also move to the last rank, and pawns underpromote there
+                if !is_in_check && position.piece_on(next_move.get_dest()).is_none() && next_move.get_promotion() != Some(Piece::Queen) {
+                    continue;
+                }
+
+                let new_position = position.make_move_new(next_move);
+                self.note_line_move(ply, next_move);
+                let search_score = -self.quiescence_search(
+                    ply + 1,
+                    &new_position,
+                    -beta.decrement_mate_plies(),
+                    -alpha.decrement_mate_plies(),
+                    qply + 1)
+                    .increment_mate_plies();
+
+                if !search_score.is_upperbound() && search_score.unwrap() >= beta {
+                    return LowerBound(search_score.unwrap());
+                }
+                if search_score.is_exact() && search_score.unwrap() > alpha {
+                    alpha = search_score.unwrap();
+                }
+                if search_score.unwrap() > best_score {
+                    best_score = search_score.unwrap();
+                }
+            }
+        }
+
+        if alpha > original_alpha {
+            Exact(alpha)
+        } else {
+            UpperBound(best_score)
+        }
+    }
+
+    /// The static evaluation of `position`, which is at `ply` of the line being searched
+    #[cfg_attr(not(feature = "nnue"), allow(unused_variables))]
+    fn static_evaluation(&mut self, position: &Board, ply: usize) -> BoardScore
+    {
+        if let Some(evaluator) = &self.evaluator {
+            return evaluator.evaluate(position);
+        }
+
+        #[cfg(feature = "nnue")]
+        if let Some(network) = &self.network {
+            return self.accumulators.evaluate(network, position, ply);
+        }
+
+        evaluation::evaluate(position, self.eval_terms, &self.params)
+    }
+
+    /// Report the time limits that the go command set for the search, in debug mode
+    fn report_time_allocation(&self)
+    {
+        let limit = |millis: u32| if millis == 0 { "none".to_string() } else { format!("{millis} ms") };
+        let soft_movetime = self.stop_conditions.soft_movetime.load(Ordering::Relaxed);
+        let movetime = self.stop_conditions.movetime.load(Ordering::Relaxed);
+        output::send(format_args!("info string time allocated soft limit {} hard limit {}", limit(soft_movetime), limit(movetime)));
+    }
+
+    /// Report the lookups of the hash table in the iteration of `depth`, which started with `before`,
+    /// in debug mode
+    fn report_hash_stats(&self, depth: Depth, before: HashStats)
+    {
+        let probes = self.hash_stats.probes - before.probes;
+        let hits = self.hash_stats.hits - before.hits;
+        let cutoffs = self.hash_stats.cutoffs - before.cutoffs;
+        let hit_percent = if probes == 0 { 0 } else { 100 * hits / probes };
+        output::send(format_args!("info string hash depth {depth} probes {probes} hits {hits} ({hit_percent}%) cutoffs {cutoffs}"));
+    }
+
+    /// Note a bound on the root score from the iteration in progress, and report it if it supersedes
+    /// the score of the last completed iteration
+    fn report_in_progress(&mut self, position: &Board, depth: Depth, bound: BoundedScore, pv: &[ChessMove])
+    {
+        let Some(root_score) = self.root_score.as_mut() else {
+            return;
+        };
+        root_score.set_in_progress(bound);
+
+        let score = root_score.display_for_info();
+        if !score.is_exact() && self.starttime.elapsed().as_millis() >= IN_PROGRESS_INFO_MIN_TIME {
+            output::send(self.info_line(position, depth, Some(score), pv));
+        }
+    }
+
+    fn report_quiescence_share(&self, root_move: ChessMove, (nodes_before, qnodes_before): (u64, u64))
+    {
+        if !self.debug {
+            return;
+        }
+
+        let nodes = self.nodes - nodes_before;
+        let qnodes = self.qnodes - qnodes_before;
+        if nodes >= QUIESCENCE_REPORT_MIN_NODES && 100 * qnodes > QUIESCENCE_REPORT_PERCENT * nodes
+        {
+            let percent = 100 * qnodes / nodes;
+            output::send(format_args!("info string quiescence search used {percent}% of {nodes} nodes under root move {root_move}"));
+        }
+    }
+
+    /// Take a step of the autosave, count the nodes of a helper thread towards the total, yield to the
+    /// host if it has asked for that, and in debug mode report the line being searched when it is due
+    fn periodic_step(&mut self)
+    {
+        self.publish_nodes();
+
+        let nodes = self.total_nodes();
+        YIELD_HOOK.with_borrow_mut(|hook| {
+            if let Some(hook) = hook {
+                hook(nodes);
+            }
+        });
+
+        if let Some(autosave) = &mut self.autosave {
+            autosave.step(&self.hashmap);
+        }
+
+        if self.debug && self.starttime.elapsed() >= self.next_currline
+        {
+            self.next_currline += CURRLINE_INTERVAL;
+            let line = self.line.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ");
+            output::send(format_args!("info currline {line}"));
+        }
+    }
+
+    fn should_stop_search(&mut self) -> bool
+    {
+        // Once stopping, every node asks again on its way back up, and the answer stays the same
+        if self.stop_reason.is_some() {
+            return true;
+        }
+
+        // After board validation has found a problem, the search can't be trusted to continue, so it
+        // stops even without a result of its own
+        if self.validation_failed {
+            return self.stopping(StopReason::Validation);
+        }
+
+        // Once the GUI has gone away, there is nobody left to play a move for
+        if output::is_disconnected() {
+            return self.stopping(StopReason::Disconnected);
+        }
+
+        // Stopping before there is a result would leave us without a move to play. Depth 1 is quick
+        // enough to always complete, even with the shortest time limits.
+        if !self.has_minimum_result {
+            return false;
+        }
+
+        let reason = match self.stop_conditions.stop_now.load(Ordering::Relaxed)
+        {
+            true => Some(StopReason::Stop),
+            false => self.limits_exceeded(),
+        };
+        if let Some(reason) = reason {
+            return self.stopping(reason);
+        }
+
+        false
+    }
+
+    /// Remember why the search is stopping, unless it already was for another reason, and return true
+    fn stopping(&mut self, reason: StopReason) -> bool
+    {
+        self.stop_reason.get_or_insert(reason);
+        true
+    }
+
+    /// Which of the limits from the go command that can stop the search in the middle of an iteration
+    /// has been reached, if any, see `limits_reached`
+    fn limits_exceeded(&mut self) -> Option<StopReason>
+    {
+        let elapsed = self.elapsed_for_limits();
+        let last = self.iterations.last();
+        let progress = SearchProgress {
+            elapsed,
+            nodes: self.total_nodes(),
+            completed_depth: last.map_or(0, |last| last.depth),
+            mate_moves: last.and_then(|last| last.score.unwrap().mate_moves()),
+        };
+        limits_reached(self.stop_conditions, &progress)
+    }
+
+    /// Milliseconds to hold against the time limits, or `None` while pondering, when the limits don't
+    /// apply
+    ///
+    /// The time spent pondering before ponderhit doesn't count against the limits, which instead start
+    /// counting when this first notices that the search is no longer pondering.
+    fn elapsed_for_limits(&mut self) -> Option<u128>
+    {
+        if self.is_pondering
+        {
+            if self.stop_conditions.ponder.load(Ordering::Acquire) {
+                return None;
+            }
+            self.is_pondering = false;
+            self.limits_starttime = Instant::now();
+        }
+
+        Some(self.limits_starttime.elapsed().as_millis())
+    }
+
+    fn trace_pv(&self, position: &Board) -> Vec<ChessMove>
+    {
+        let mut result = Vec::new();
+
+        let mut position = *position;
+        while let Some(hash_entry) = self.hashmap.get(&position)
+        {
+            if let Some(best_move) = hash_entry.best_move()
+            {
+                // A hash collision could give us a move that isn't legal here
+                if !position.legal(best_move) {
+                    break;
+                }
+                result.push(best_move);
+                if result.len() > Depth::MAX as usize {
+                    break;
+                }
+                position = position.make_move_new(best_move);
+            }
+            else
+            {
+                break
+            }
+        }
+
+        result
+    }
+}
+
+/// How far a search has got, for checking its limits against
+struct SearchProgress
+{
+    /// Milliseconds to hold against the time limits, or `None` while pondering, when they don't apply
+    elapsed: Option<u128>,
+    nodes: u64,
+    /// Depth of the last completed iteration, or 0 before the first
+    completed_depth: Depth,
+    /// Moves to mate that the last completed iteration found, see `BoardScore::mate_moves`
+    mate_moves: Option<i32>,
+}
+
+/// Which of the limits of `stop_conditions` that can stop a search in the middle of an iteration has
+/// been reached, if any
+///
+/// The search stops at whichever limit it reaches first. This checks, in order:
+/// - the hard time limit, from movetime or maxtime, which applies regardless of the others,
+/// - the node limit,
+/// - the mate limit, which is reached once the last completed iteration found a short enough mate,
+/// - the minimums of `StopConditions::min_searchtime`, which are reached once the search has completed
+///   the depth limit and has searched for the minimum time, in either order. Without a depth limit,
+///   the minimum time alone counts.
+///
+/// The stop command overrides all limits, and no limit applies until the first iteration is done.
+/// The depth limit without a minimum time, and the soft time limit, are checked between iterations, in
+/// `Searcher::search`.
+fn limits_reached(stop_conditions: &StopConditions, progress: &SearchProgress) -> Option<StopReason>
+{
+    let movetime = stop_conditions.movetime.load(Ordering::Relaxed);
+    if let Some(elapsed) = progress.elapsed {
+        if movetime != 0 && elapsed >= movetime as u128 {
+            return Some(StopReason::Time);
+        }
+    }
+
+    let nodes = stop_conditions.nodes.load(Ordering::Relaxed);
+    if nodes != 0 && progress.nodes >= nodes {
+        return Some(StopReason::Nodes);
+    }
+
+    let mate = stop_conditions.mate.load(Ordering::Relaxed);
+    if mate != 0 && matches!(progress.mate_moves, Some(moves) if moves > 0 && moves <= mate as i32) {
+        return Some(StopReason::Mate);
+    }
+
+    let min_searchtime = stop_conditions.min_searchtime.load(Ordering::Relaxed);
+    if min_searchtime != 0
+    {
+        let depth = stop_conditions.depth.load(Ordering::Relaxed);
+        let has_depth = depth == Depth::MAX || progress.completed_depth >= depth;
+        if has_depth && matches!(progress.elapsed, Some(elapsed) if elapsed >= min_searchtime as u128) {
+            return Some(StopReason::Minimums);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::bench;
+
+    #[test]
+    fn test_root_score_display_for_info()
+    {
+        use BoundedScore::*;
+
+        let committed = BoardScore::evaluation(50);
+        let above = BoardScore::evaluation(80);
+        let below = BoardScore::evaluation(20);
+
+        let shown = |in_progress: Option<BoundedScore>| {
+            let mut root_score = RootScore::new(committed);
+            if let Some(bound) = in_progress {
+                root_score.set_in_progress(bound);
+            }
+            root_score.display_for_info()
+        };
+
+        assert_eq!(shown(None), Exact(committed));
+
+        // Only bounds that say more than the committed score replace it
+        assert_eq!(shown(Some(LowerBound(above))), LowerBound(above));
+        assert_eq!(shown(Some(LowerBound(committed))), Exact(committed));
+        assert_eq!(shown(Some(LowerBound(below))), Exact(committed));
+        assert_eq!(shown(Some(UpperBound(above))), Exact(committed));
+        assert_eq!(shown(Some(UpperBound(committed))), Exact(committed));
+        assert_eq!(shown(Some(UpperBound(below))), UpperBound(below));
+    }
+
+    #[test]
+    fn test_quiescence_search_is_bounded()
+    {
+        const QUIESCENCE_TEST_NODE_BUDGET: u64 = 5_000_000;
+
+        // Two queens and two knights each, all hanging or attacking something
+        let position = Board::from_str("7k/6pp/2n2n2/3qq3/3QQ3/2N2N2/6PP/7K w - - 0 1").unwrap();
+
+        // The node limit stops the search if it runs over the budget
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(6, Ordering::Relaxed);
+        stop_conditions.nodes.store(QUIESCENCE_TEST_NODE_BUDGET, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+
+        let last = result.iterations.last().unwrap();
+        assert_eq!(last.depth, 6, "only completed depth {} within {QUIESCENCE_TEST_NODE_BUDGET} nodes", last.depth);
+        assert!(searcher.qnodes > 0);
+        assert!(searcher.qnodes <= searcher.nodes);
+    }
+
+    #[test]
+    fn test_quiescence_searches_quiet_promotions()
+    {
+        // Promoting with check wins, while standing pat leaves White a pawn down
+        let position = Board::from_str("7k/P7/8/1pp5/8/8/8/6K1 w - - 0 1").unwrap();
+        let stop_conditions = StopConditions::new();
+        let mut searcher = Searcher::new(&stop_conditions);
+        let score = searcher.quiescence_search(0, &position, BoardScore::WORST_SCORE, BoardScore::BEST_SCORE, 0);
+        assert!(score.unwrap() > BoardScore::EVEN, "{score:?}");
+    }
+
+    #[test]
+    fn test_seldepth()
+    {
+        let seldepth = |result: &SearchResult| {
+            let mut words = result.final_info.split_ascii_whitespace();
+            words.find(|&w| w == "seldepth");
+            words.next().unwrap().parse::<usize>().unwrap()
+        };
+
+        // The captures of the quiescence search go beyond the depth
+        let position = Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+        assert!(seldepth(&result) > 4, "{}", result.final_info);
+        assert_eq!(seldepth(&result), searcher.seldepth);
+
+        // Every search starts counting again
+        searcher.clear_hash();
+        stop_conditions.depth.store(1, Ordering::Relaxed);
+        let shallow = searcher.search(Board::default(), 0);
+        assert!(seldepth(&shallow) < seldepth(&result), "{}", shallow.final_info);
+    }
+
+    #[test]
+    fn test_prefers_shortest_mate()
+    {
+        // Qb8# mates at once, while many other queen moves lead to slower mates
+        let position = Board::from_str("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+
+        assert_eq!(result.best_move, Some(ChessMove::from_str("b1b8").unwrap()));
+        let score = result.iterations.last().unwrap().score;
+        assert_eq!(score, BoundedScore::Exact(BoardScore::MATE.increment_mate_plies()));
+    }
+
+    #[test]
+    fn test_info_line_without_pv()
+    {
+        let position = Board::default();
+        let stop_conditions = StopConditions::new();
+        let searcher = Searcher::new(&stop_conditions);
+
+        let line = searcher.info_line(&position, 1, None, &[]);
+        assert!(!line.contains(" pv") && !line.ends_with(' '), "{line}");
+        let line = searcher.info_line(&position, 1, None, &[ChessMove::from_str("e2e4").unwrap()]);
+        assert!(line.ends_with(" pv e2e4"), "{line}");
+    }
+
+    #[test]
+    fn test_no_legal_moves()
+    {
+        // Stalemate
+        let position = Board::from_str("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+
+        assert_eq!(result.best_move, None);
+        assert!(result.iterations.is_empty());
+    }
+
+    #[test]
+    fn test_winning_side_makes_progress()
+    {
+        // Every pawn move is as good as any king move by material, so without a preference for
+        // progress, White could shuffle the king around forever
+        let mut position = Board::from_str("k7/8/8/8/8/8/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        let mut halfmove_clock = 0;
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(2, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+
+        for _ in 0..40
+        {
+            let Some(best_move) = searcher.search(position, halfmove_clock).best_move else {
+                break;
+            };
+            if position.resets_halfmove_clock(best_move) {
+                halfmove_clock = 0;
+            } else {
+                halfmove_clock += 1;
+            }
+            assert!(halfmove_clock <= 10, "no progress for {halfmove_clock} plies, last move {best_move}");
+            position = position.make_move_new(best_move);
+        }
+    }
+
+    #[test]
+    fn test_no_pruning_at_root_or_in_check()
+    {
+        for fen in bench::BENCH_POSITIONS
+        {
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(3, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            searcher.search(Board::from_str(fen).unwrap(), 0);
+
+            let stats = searcher.pruning_stats;
+            assert_eq!(stats.at_root, 0, "{fen}");
+            assert_eq!(stats.in_pv, 0, "{fen}");
+            assert_eq!(stats.in_check, 0, "{fen}");
         }
     }
 
-    pub fn search(&mut self, position: Board)
+    #[test]
+    fn test_null_move_pruning()
     {
-        self.nodes = 0;
-        self.starttime = time::Instant::now();
-        self.hashmap.new_generation();
+        let searcher_at_depth = |fen: &str, depth| {
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(depth, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            let best_move = searcher.search(Board::from_str(fen).unwrap(), 0).best_move;
+            (best_move, searcher.null_move_cutoffs)
+        };
+
+        let total: u64 = bench::BENCH_POSITIONS.iter().map(|fen| searcher_at_depth(fen, 5).1).sum();
+        assert!(total > 0);
+
+        // With only kings and pawns, where zugzwang decides, neither side passes
+        let (best_move, cutoffs) = searcher_at_depth("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1", 7);
+        assert_eq!(cutoffs, 0);
+        assert!(best_move.is_some());
+    }
 
-        // TODO: Loop from the latest depth in the hash table instead of 1?
-        for depth in 1..=Depth::MAX
+    #[test]
+    fn test_lmr_stats_over_bench()
+    {
+        let mut total = LmrStats::default();
+        for fen in bench::BENCH_POSITIONS
         {
-            if self.should_stop_search() {
-                break;
-            }
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(5, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            let stats = searcher.search(Board::from_str(fen).unwrap(), 0).lmr_stats;
 
-            if depth > self.stop_conditions.depth.load(Ordering::Relaxed) {
-                break;
+            for bucket in 0..lmr::DEPTH_BUCKETS.len() {
+                assert!(stats.researched[bucket] <= stats.reduced[bucket], "{fen}");
+                total.reduced[bucket] += stats.reduced[bucket];
+                total.researched[bucket] += stats.researched[bucket];
             }
+        }
 
-            let score = self.alphabeta_search(depth, &position, BoardScore::WORST_SCORE, BoardScore::BEST_SCORE);
+        assert!(total.total_reduced() > 0);
+        assert_eq!(total.total_reduced(), total.reduced.iter().sum::<u64>());
+        assert_eq!(total.total_researched(), total.researched.iter().sum::<u64>());
+        // Depth 5 only gets to nodes of depth 4 below the root
+        assert_eq!(total.reduced[1..], [0, 0, 0]);
+    }
 
-            let nodes = self.nodes;
-            let time = self.starttime.elapsed().as_millis() as u64;
-            let nps = if time != 0 { (1000 * nodes) / time } else { 0 };
-            let hashfull = (1000 * self.hashmap.filled()) / self.hashmap.capacity();
-            let pv = self.trace_pv(&position);
-            println!("info depth {depth} multipv 1 score {score} nodes {nodes} nps {nps} hashfull {hashfull} time {time} pv{pv}");
-        }
-        let best_move = self.hashmap.get(&position)
-            .expect("Root node has been purged from hash map")
-            .best_move()
-            .expect("root node had no best move?");
-        println!("bestmove {best_move}");
+    /// The first move of the PV in an info line
+    fn first_pv_move(info_line: &str) -> Option<&str>
+    {
+        let mut words = info_line.split_ascii_whitespace();
+        words.find(|&w| w == "pv")?;
+        words.next()
     }
 
-    /// Calculate the score for a position with alpha-beta search
-    ///
-    /// If the score is higher than `beta`, it may not calculate the exact score, but instead provide
-    /// a `BoundedScore::LowerBound` of at least `beta`. Similarly, if the score is lower than `alpha`
-    /// it may instead calculate a `BoundedScore::UpperBound` of at most `alpha`.
-    ///
-    /// If the search gets stopped partway, it may also return `LowerBound` and `UpperBound` scores that
-    /// lie inside the range of `alpha` and `beta`.
-    fn alphabeta_search(&mut self, mut depth: Depth, position: &Board, mut alpha: BoardScore, beta: BoardScore) -> BoundedScore
+    #[test]
+    fn test_minimum_depth_before_stopping()
     {
-        use BoundedScore::*;
+        // The queen on d5 hangs to the rook
+        let position = Board::from_str("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let winning_move = ChessMove::from_str("d2d5").unwrap();
 
-        debug_assert!(position.is_sane());
-        debug_assert!(alpha != BoardScore::NO_SCORE);
-        debug_assert!(beta != BoardScore::NO_SCORE);
-        debug_assert!(alpha <= beta);
-        self.nodes += 1;
+        for (movetime, stop_now) in [(1, false), (10, false), (0, true)]
+        {
+            let stop_conditions = StopConditions::new();
+            stop_conditions.movetime.store(movetime, Ordering::Relaxed);
+            stop_conditions.stop_now.store(stop_now, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            let result = searcher.search(position, 0);
 
-        // First, alpha and beta may be overdetermined, so no searching is necessary. This will happen
-        // if, say, a mate-in-five has been found on another branch, and we are now six plies deep on
-        // this branch. There is no way be can beat a mate-in-five at a depth of six, so we bail.
-        if alpha >= BoardScore::BEST_SCORE {
-            // BEST_SCORE can never be achieved on a real board, the best is MATE
-            return UpperBound(BoardScore::MATE);
+            assert!(!result.iterations.is_empty(), "movetime {movetime}, stop {stop_now}");
+            assert_eq!(result.iterations[0].depth, 1);
+            assert_eq!(result.best_move, Some(winning_move), "movetime {movetime}, stop {stop_now}");
         }
+    }
 
-        if beta == BoardScore::WORST_SCORE {
-            // WORST_SCORE can never be achieved on a real board, the worst is MATED
-            return LowerBound(BoardScore::MATED);
-        }
+    #[test]
+    fn test_move_that_seems_to_hang_is_found()
+    {
+        // Nd5 forks the rooks, winning the exchange at least. The pawn on e6 seems to win the knight,
+        // but is pinned, so the exchange evaluation is wrong about it. Static exchange pruning must not hide the move.
+        let position = Board::from_str("4k3/2r5/4pr2/8/1N6/8/8/4R2K w - - 0 1").unwrap();
 
-        let mut previous_best_move = None;
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
 
-        // If we are stopping, then we are going to do a quick search of hash entries
-        let is_stopping = self.should_stop_search();
+        assert_eq!(result.best_move, Some(ChessMove::from_str("b4d5").unwrap()));
+    }
 
-        // Second, look up in hash table to see if this node has been searched already...
-        if let Some(hash_entry) = self.hashmap.get(position)
+    #[test]
+    fn test_winning_side_avoids_repetition()
+    {
+        // Rook up, so there are many winning moves that are about equally good
+        let position = Board::from_str("8/8/8/3k4/8/8/8/R3K3 w - - 0 1").unwrap();
+        let search = |game_history: Vec<u64>| {
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(4, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            searcher.set_game_history(game_history);
+            searcher.search(position, 0).best_move.unwrap()
+        };
+
+        // Pretend that the move the engine would like to play repeats a position for the third time
+        let preferred = search(Vec::new());
+        let repeated = position.make_move_new(preferred).get_hash();
+        let game_history = vec![repeated, position.get_hash(), repeated, position.get_hash()];
+
+        let chosen = search(game_history);
+        assert_ne!(chosen, preferred);
+        assert_ne!(position.make_move_new(chosen).get_hash(), repeated);
+    }
+
+    #[test]
+    fn test_repetition_when_alternatives_are_worse()
+    {
+        // Taking the queen is the only move that doesn't lose
+        let position = Board::from_str("7k/8/8/8/3q4/8/8/3R3K w - - 0 1").unwrap();
+        let capture = ChessMove::from_str("d1d4").unwrap();
+        let repeated = position.make_move_new(capture).get_hash();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.set_game_history(vec![repeated, position.get_hash(), repeated, position.get_hash()]);
+        assert_eq!(searcher.search(position, 0).best_move, Some(capture));
+    }
+
+    #[test]
+    fn test_stability_rises_in_forced_mate()
+    {
+        // Re8# is best from the first iteration on
+        let position = Board::from_str("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(5, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+
+        assert_eq!(result.iterations.len(), 5);
+        for (i, iteration) in result.iterations.iter().enumerate()
         {
-            debug_assert!(hash_entry.hash() == position.get_hash());
-            debug_assert!(hash_entry.score().unwrap() != BoardScore::NO_SCORE);
-            // ... and to sufficient depth.
-            if is_stopping || hash_entry.depth() >= depth
-            {
-                // If the previous score is compatible with our alpha-beta bounds, we can return it.
-                // If we are stopping the search here, we return these values even if they aren't useful
-                match hash_entry.score()
-                {
-                    Exact(s) => return Exact(s),
-                    LowerBound(s) if is_stopping || s >= beta => return LowerBound(s),
-                    UpperBound(s) if is_stopping || s <= alpha => return UpperBound(s),
-                    _ => { },
-                }
-            }
-            // Even if the score is not compatible, we can use the previous information in our current
-            // search.
-            previous_best_move = hash_entry.best_move();
+            assert_eq!(iteration.stability.best_move, Some(ChessMove::from_str("e1e8").unwrap()));
+            assert_eq!(iteration.stability.stable_iterations, i as u32 + 1);
         }
+    }
 
-        // Do the recursive search of all moves
-        if depth > 0 && !is_stopping
-        {
-            let mut best_score = UpperBound(BoardScore::NO_SCORE);
-            let mut best_move = None;
-            let mut any_moves = false;
-            let mut deficient_search = false;
-            let move_gen = MoveGenerator::new(position, previous_best_move);
+    #[test]
+    fn test_score_history()
+    {
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(6, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(Board::default(), 0);
 
-            for next_move in move_gen
-            {
-                any_moves = true;
+        // One entry for every completed iteration, in order
+        let depths = result.iterations.iter().map(|i| i.depth).collect::<Vec<_>>();
+        assert_eq!(depths, (1..=6).collect::<Vec<Depth>>());
+        assert!(result.iterations.windows(2).all(|w| w[0].nodes <= w[1].nodes && w[0].time <= w[1].time));
 
-                let new_position = position.make_move_new(next_move);
-                // println!("Trying move {next_move} {{");
-                let search_score = -self.alphabeta_search(
-                    depth - 1,
-                    &new_position,
-                    -beta.decrement_mate_plies(),
-                    -alpha.decrement_mate_plies())
-                    .increment_mate_plies();
+        // The last entry is the result that the search committed to
+        let last = result.iterations.last().unwrap();
+        assert!(result.final_info.starts_with("info depth 6 "), "{}", result.final_info);
+        assert!(result.final_info.contains(&format!(" score {} ", last.score)), "{}", result.final_info);
+        let history = score_history(&result.iterations);
+        assert_eq!(history.split(' ').count(), 6, "{history}");
+        assert!(history.starts_with("d1:") && history.contains(" d6:"), "{history}");
 
-                // Test that alphabeta has returned a reasonable result. This is not necessarily true
-                // if search was aborted partway, which we call a deficient search.
-                // TODO: It could be that the stored result of lower depth from the hash table is of
-                // better quality than the deficient result we're calculating here. This depends on
-                // a number of things. If we've successfully searched the best_move already, then it
-                // is likely that we've got a good result coming. If we got a deficient score from the
-                // best_move, and we actually had a hash entry for this position from before, that
-                // hash entry is likely going to be better to use. Right now we always proceed with
-                // the deficient search, but it could be reasonable to break the loop and just return
-                // the old hash result instead.
-                if
-                    search_score.unwrap() == BoardScore::NO_SCORE ||
-                    (search_score.is_lowerbound() && search_score.unwrap() < beta) ||
-                    (search_score.is_upperbound() && search_score.unwrap() > alpha)
-                {
-                    // Search was deficient!
-                    debug_assert!(self.should_stop_search(), "Deficient scores should only appear if stopping");
-                    deficient_search = true;
-                }
+        // Mates show as moves to mate
+        let position = Board::from_str("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1").unwrap();
+        stop_conditions.depth.store(2, Ordering::Relaxed);
+        let result = Searcher::new(&stop_conditions).search(position, 0);
+        assert_eq!(score_history(&result.iterations), "d1:#1 d2:#1");
+    }
 
-                if search_score > best_score {
-                    // println!("New best move {next_move} with score {search_score}");
-                    best_score = search_score;
-                    best_move = Some(next_move);
-                    if !search_score.is_upperbound() && search_score.unwrap() > alpha
-                    {
-                        // Found a move better than alpha, so update alpha.
-                        debug_assert!(!search_score.is_upperbound(), "UpperBound scores should not raise alpha");
-                        alpha = search_score.unwrap();
-                    }
-                }
-                // println!("}}");
-                // TODO: Fix comparison function so this can be expressed as `best_score > Exact(beta)`
-                if !best_score.is_upperbound() && best_score.unwrap() >= beta {
-                    // If we found a move better than beta, we don't need to consider any other moves.
-                    // This particular position is "too good" for us, and will therefore never be played
-                    // by a minmaxing opponent anyway, so further search can be pruned. This score is
-                    // now a LowerBound score: there could be even higher scores in the other moves.
-                    debug_assert!(!best_score.is_upperbound(), "UpperBound scores should not cause beta termination");
-                    // println!("beta bailing: {best_score:?} > {beta:?}");
-                    best_score = LowerBound(best_score.unwrap());
-                    break;
-                }
-            }
+    #[test]
+    fn test_stability_resets_when_best_move_flips()
+    {
+        // Qxa4 wins a pawn at depth 1, but the second iteration sees that it allows Re1#
+        let position = Board::from_str("4r1k1/5ppp/8/8/p7/8/5PPP/3Q2K1 w - - 0 1").unwrap();
+        let capture = ChessMove::from_str("d1a4").unwrap();
 
-            if !any_moves
-            {
-                // There were no legal moves!
-                // This means checkmate or stalemate
-                if *position.checkers() != chess::EMPTY
-                {
-                    best_score = Exact(BoardScore::MATED);
-                }
-                else
-                {
-                    best_score = Exact(BoardScore::EVEN);
-                }
-                // This evaluation is valid for any depth for purposes of hashtable lookup.
-                depth = Depth::MAX;
-            }
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
 
-            // Finally store the new result in the hash table
-            let store_depth = if deficient_search || self.should_stop_search() {
-                // Deficient search only counts as one depth level lower
-                depth - 1
-            } else if best_score.is_exact() && best_score.unwrap().is_mate_score() {
-                // This evaluation is valid for any depth for purposes of hashtable lookup.
-                Depth::MAX
-            } else {
-                depth
-            };
-            if best_score.unwrap() != BoardScore::NO_SCORE
-            {
-                let hash_entry = HashEntry::with_contents(
-                    position.get_hash(),
-                    best_move,
-                    best_score,
-                    store_depth,
-                );
+        let first = result.iterations[0].stability;
+        assert_eq!(first.best_move, Some(capture));
+        assert_eq!(first.stable_iterations, 1);
 
-                // println!("info string returning {best_score} at depth = {depth}");
-                self.hashmap.insert(position, hash_entry);
-            }
+        let second = result.iterations[1].stability;
+        assert_ne!(second.best_move, Some(capture));
+        assert_eq!(second.stable_iterations, 1);
+        assert!(result.iterations.iter().skip(1).all(|i| i.stability.best_move != Some(capture)));
+    }
 
-            best_score
+    /// Search with the given limits, zero meaning no limit, and return the result along with the
+    /// node count and time of its final info line
+    fn search_with_limits(fen: &str, depth: Depth, movetime: u32, nodes: u64, mate: u32) -> (SearchResult, u64, u64)
+    {
+        let stop_conditions = StopConditions::new();
+        if depth != 0 {
+            stop_conditions.depth.store(depth, Ordering::Relaxed);
         }
+        stop_conditions.movetime.store(movetime, Ordering::Relaxed);
+        stop_conditions.nodes.store(nodes, Ordering::Relaxed);
+        stop_conditions.mate.store(mate, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(Board::from_str(fen).unwrap(), 0);
 
-        else if depth == 0 && !is_stopping
-        {
-            // Depth is zero, use leaf evaluation
-            Exact(self.leaf_evaluation(position, alpha, beta))
-        }
+        let field = |key| {
+            let mut words = result.final_info.split_ascii_whitespace();
+            words.find(|&w| w == key).and_then(|_| words.next()).unwrap().parse::<u64>().unwrap()
+        };
+        let (final_nodes, final_time) = (field("nodes"), field("time"));
+        assert_eq!(final_nodes, searcher.nodes);
+        (result, final_nodes, final_time)
+    }
 
-        else
-        {
-            // We are stopping the search. Unfortunately there was no hash entry to fall back to.
-            // We mustn't do leaf evaluation here, since that risks producing a worse result than what
-            // we already have achieved from previous searches. Instead return a noncommitting score,
-            // and let upper levels work out what to do.
-            debug_assert!(is_stopping);
-            LowerBound(BoardScore::WORST_SCORE)
-        }
+    const LIMITS_POSITION: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const MATE_IN_ONE: &str = "6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1";
+    const NODE_LIMIT: u64 = 50_000;
+    const LONG_MOVETIME: u32 = 60_000;
+    const SHORT_MOVETIME: u32 = 50;
+
+    /// The search stopped on the node limit, going at most a little over it while unwinding
+    fn assert_stopped_on_nodes(nodes: u64)
+    {
+        assert!(nodes >= NODE_LIMIT && nodes < 2 * NODE_LIMIT, "searched {nodes} nodes");
     }
 
-    fn leaf_evaluation(&mut self, position: &Board, alpha: BoardScore, beta: BoardScore) -> BoardScore
+    #[test]
+    fn test_depth_and_nodes_limits()
     {
-        debug_assert!(position.is_sane());
-        debug_assert!(alpha != BoardScore::NO_SCORE);
-        debug_assert!(beta != BoardScore::NO_SCORE);
-        debug_assert!(alpha <= beta);
+        let (result, nodes, _) = search_with_limits(LIMITS_POSITION, 2, 0, 1_000_000_000, 0);
+        assert_eq!(result.iterations.last().unwrap().depth, 2);
+        assert!(nodes < 1_000_000_000);
+        assert_eq!(result.stop_reason, Some(StopReason::Depth));
 
-        self.nodes += 1;
+        let (result, nodes, _) = search_with_limits(LIMITS_POSITION, 50, 0, NODE_LIMIT, 0);
+        assert_stopped_on_nodes(nodes);
+        assert!(result.iterations.last().unwrap().depth < 50);
+        assert_eq!(result.stop_reason, Some(StopReason::Nodes));
+    }
 
-        // TODO: Quiescent search should go here
-        // TODO: This method should also store its scores in the hash map
-        let legal_moves = MoveGen::new_legal(position);
-        if legal_moves.len() > 0
-        {
-            self.static_evaluation(position)
-        }
-        else
+    #[test]
+    fn test_depth_and_movetime_limits()
+    {
+        let (result, _, time) = search_with_limits(LIMITS_POSITION, 2, LONG_MOVETIME, 0, 0);
+        assert_eq!(result.iterations.last().unwrap().depth, 2);
+        assert!(time < LONG_MOVETIME as u64);
+
+        let (result, _, time) = search_with_limits(LIMITS_POSITION, 50, SHORT_MOVETIME, 0, 0);
+        assert!(time >= SHORT_MOVETIME as u64 && time < LONG_MOVETIME as u64, "searched for {time} ms");
+        assert!(result.iterations.last().unwrap().depth < 50);
+        assert_eq!(result.stop_reason, Some(StopReason::Time));
+    }
+
+    #[test]
+    fn test_nodes_and_movetime_limits()
+    {
+        let (_, nodes, time) = search_with_limits(LIMITS_POSITION, 0, LONG_MOVETIME, NODE_LIMIT, 0);
+        assert_stopped_on_nodes(nodes);
+        assert!(time < LONG_MOVETIME as u64);
+
+        let (_, nodes, time) = search_with_limits(LIMITS_POSITION, 0, SHORT_MOVETIME, 1_000_000_000, 0);
+        assert!(time >= SHORT_MOVETIME as u64 && time < LONG_MOVETIME as u64, "searched for {time} ms");
+        assert!(nodes < 1_000_000_000);
+    }
+
+    #[test]
+    fn test_mate_and_depth_limits()
+    {
+        let (result, _, _) = search_with_limits(MATE_IN_ONE, 50, 0, 0, 1);
+        assert_eq!(result.iterations.len(), 1);
+        assert_eq!(result.best_move, Some(ChessMove::from_str("e1e8").unwrap()));
+        assert_eq!(result.stop_reason, Some(StopReason::Mate));
+
+        // No mate to find, so the depth limit stops the search
+        let (result, _, _) = search_with_limits(LIMITS_POSITION, 2, 0, 0, 1);
+        assert_eq!(result.iterations.last().unwrap().depth, 2);
+    }
+
+    #[test]
+    fn test_mate_and_nodes_limits()
+    {
+        let (result, nodes, _) = search_with_limits(MATE_IN_ONE, 0, 0, NODE_LIMIT, 1);
+        assert_eq!(result.iterations.len(), 1);
+        assert!(nodes < NODE_LIMIT);
+
+        let (_, nodes, _) = search_with_limits(LIMITS_POSITION, 0, 0, NODE_LIMIT, 1);
+        assert_stopped_on_nodes(nodes);
+    }
+
+    #[test]
+    fn test_mate_and_movetime_limits()
+    {
+        let (result, _, time) = search_with_limits(MATE_IN_ONE, 0, LONG_MOVETIME, 0, 1);
+        assert_eq!(result.iterations.len(), 1);
+        assert!(time < LONG_MOVETIME as u64);
+
+        let (_, _, time) = search_with_limits(LIMITS_POSITION, 0, SHORT_MOVETIME, 0, 1);
+        assert!(time >= SHORT_MOVETIME as u64 && time < LONG_MOVETIME as u64, "searched for {time} ms");
+    }
+
+    #[test]
+    fn test_depth_and_time_minimums()
+    {
+        // go depth 6 mintime 1000 maxtime 5000, against a clock that the test sets
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(6, Ordering::Relaxed);
+        stop_conditions.min_searchtime.store(1000, Ordering::Relaxed);
+        stop_conditions.movetime.store(5000, Ordering::Relaxed);
+        let reached = |completed_depth, elapsed| {
+            let progress = SearchProgress { elapsed: Some(elapsed), nodes: 0, completed_depth, mate_moves: None };
+            limits_reached(&stop_conditions, &progress).is_some()
+        };
+
+        // The depth, then the minimum time, then the maximum time
+        assert!(!reached(6, 500));
+        assert!(!reached(9, 999));
+        assert!(reached(9, 1000));
+        let progress = SearchProgress { elapsed: Some(1000), nodes: 0, completed_depth: 9, mate_moves: None };
+        assert_eq!(limits_reached(&stop_conditions, &progress), Some(StopReason::Minimums));
+        // The minimum time, then the depth, then the maximum time
+        assert!(!reached(5, 1500));
+        assert!(reached(6, 1500));
+        // The maximum time before the depth, or before the minimum time, which can't be longer
+        assert!(!reached(3, 4999));
+        assert!(reached(3, 5000));
+        stop_conditions.min_searchtime.store(8000, Ordering::Relaxed);
+        assert!(!reached(6, 4999));
+        assert!(reached(6, 5000));
+
+        // While pondering, not even the depth can stop the search
+        let pondering = SearchProgress { elapsed: None, nodes: 0, completed_depth: 9, mate_moves: None };
+        assert_eq!(limits_reached(&stop_conditions, &pondering), None);
+
+        // Without a depth, the minimum time is what stops the search
+        stop_conditions.depth.store(Depth::MAX, Ordering::Relaxed);
+        stop_conditions.min_searchtime.store(1000, Ordering::Relaxed);
+        assert!(!reached(1, 999));
+        assert!(reached(1, 1000));
+    }
+
+    #[test]
+    fn test_search_with_depth_and_time_minimums()
+    {
+        // The depth comes long before the minimum time, so the search goes deeper meanwhile
+        const MIN_SEARCHTIME: u32 = 300;
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(1, Ordering::Relaxed);
+        stop_conditions.min_searchtime.store(MIN_SEARCHTIME, Ordering::Relaxed);
+        stop_conditions.movetime.store(LONG_MOVETIME, Ordering::Relaxed);
+        let starttime = time::Instant::now();
+        let result = Searcher::new(&stop_conditions).search(Board::default(), 0);
+        let elapsed = starttime.elapsed().as_millis();
+        assert!(result.iterations.last().unwrap().depth > 1);
+        assert!(elapsed >= MIN_SEARCHTIME as u128 && elapsed < LONG_MOVETIME as u128, "searched for {elapsed} ms");
+
+        // The maximum time comes before the depth
+        stop_conditions.depth.store(50, Ordering::Relaxed);
+        stop_conditions.movetime.store(SHORT_MOVETIME, Ordering::Relaxed);
+        let result = Searcher::new(&stop_conditions).search(Board::from_str(LIMITS_POSITION).unwrap(), 0);
+        assert!(result.iterations.last().unwrap().depth < 50);
+    }
+
+    #[test]
+    fn test_check_extension_depth_in_hash()
+    {
+        // The rooks can check the king up the board and back, one check after another
+        let position = Board::from_str("7k/8/8/8/8/8/1R6/R5K1 w - - 0 1").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(5, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.search(position, 0);
+
+        // After a check, the reply is searched to the depth of the iteration instead of one less
+        let checked = position.make_move_new(ChessMove::from_str("b2b8").unwrap());
+        let entry = searcher.hashmap.get(&checked).expect("the check was searched");
+        assert!(entry.depth() >= 5, "stored at depth {}", entry.depth());
+
+        // Searching again less deep, the entries of the extended nodes are deep enough to use
+        stop_conditions.depth.store(3, Ordering::Relaxed);
+        searcher.search(position, 0);
+        assert!(searcher.extended_hash_cutoffs > 0);
+    }
+
+    #[test]
+    fn test_excluded_root_moves()
+    {
+        // Rxd5 wins the queen, and nothing else comes close
+        let position = Board::from_str("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+        let capture = ChessMove::from_str("d1d5").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        let result = searcher.search(position, 0);
+        assert_eq!(result.best_move, Some(capture));
+        let best_score = result.iterations.last().unwrap().score.unwrap();
+
+        searcher.set_excluded_moves(vec![capture]);
+        let result = searcher.search(position, 0);
+        assert_ne!(result.best_move, Some(capture));
+        let excluded_score = result.iterations.last().unwrap().score.unwrap();
+        assert!(excluded_score < best_score.add_centipawns(-500), "{excluded_score} without the capture, {best_score} with it");
+
+        // The root entry still has the best of all moves, and the capture is searched as usual below
+        // the root
+        assert_eq!(searcher.probe(&position).unwrap().best_move, Some(capture));
+        let result = searcher.search(position, 0);
+        assert_eq!(result.best_move, Some(capture));
+    }
+
+    #[test]
+    fn test_search_moves()
+    {
+        // Rxd5 wins the queen, but only king moves may be searched
+        let position = Board::from_str("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+        let capture = ChessMove::from_str("d1d5").unwrap();
+        let king_moves = ["e1e2", "e1f2"].map(|m| ChessMove::from_str(m).unwrap());
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.set_search_moves(king_moves.to_vec());
+        let result = searcher.search(position, 0);
+        let best_move = result.best_move.unwrap();
+        assert!(king_moves.contains(&best_move), "{best_move}");
+        assert!(result.final_info.contains(&format!(" pv {best_move}")), "{}", result.final_info);
+
+        // The restriction was for that search only, and didn't leave its best move in the hash table
+        assert!(searcher.probe(&position).is_none_or(|probe| probe.best_move != Some(best_move)));
+        let result = searcher.search(position, 0);
+        assert_eq!(result.best_move, Some(capture));
+    }
+
+    #[test]
+    fn test_opening_variety()
+    {
+        let search = |fen: &str, margin, seed| {
+            let stop_conditions = StopConditions::new();
+            stop_conditions.depth.store(4, Ordering::Relaxed);
+            let mut searcher = Searcher::new(&stop_conditions);
+            searcher.set_opening_variety(margin, seed);
+            let result = searcher.search(Board::from_str(fen).unwrap(), 0);
+            let best_move = result.best_move.unwrap().to_string();
+            assert_eq!(first_pv_move(&result.final_info), Some(best_move.as_str()), "{}", result.final_info);
+            best_move
+        };
+        let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        // The same move every time without a margin, and different moves with one
+        let best_move = search(startpos, 0, 1);
+        assert!((2..6).all(|seed| search(startpos, 0, seed) == best_move));
+        let varied = (0..8).map(|seed| search(startpos, MAX_OPENING_VARIETY, seed)).collect::<std::collections::HashSet<_>>();
+        assert!(varied.len() > 1, "{varied:?}");
+
+        // Never a move that is much worse
+        let queen_capture = "4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1";
+        assert!((0..8).all(|seed| search(queen_capture, MAX_OPENING_VARIETY, seed) == "d1d5"));
+    }
+
+    #[test]
+    fn test_new_game_forgets_the_previous_game()
+    {
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.resize_hash(1);
+        searcher.search(Board::default(), 0);
+        // As if adaptive reductions had moved the base during the game
+        searcher.lmr_table = LmrTable::new(lmr::DEFAULT_BASE + 50, lmr::DEFAULT_DIVISOR);
+        assert!(searcher.hashmap.filled() > 0);
+        assert!(searcher.previous_score.is_some());
+
+        searcher.new_game();
+        assert_eq!(searcher.hashmap.filled(), 0);
+        assert!(searcher.hashmap.iter_used().next().is_none());
+        assert_eq!(searcher.lmr_table.base(), lmr::DEFAULT_BASE);
+        assert_eq!(searcher.previous_score, None);
+    }
+
+    #[test]
+    fn test_interrupted_search_stores_best_moves()
+    {
+        let position = Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.resize_hash(1);
+
+        // Stop the search at every node count in turn, each time with an empty hash table
+        for nodes in (1..3000).step_by(7)
         {
-            // This means checkmate or stalemate
-            if *position.checkers() != chess::EMPTY
-            {
-                BoardScore::MATED
+            stop_conditions.nodes.store(nodes, Ordering::Relaxed);
+            searcher.clear_hash();
+            let result = searcher.search(position, 0);
+
+            // The results of the stopped iteration never replace that of the last completed one
+            if let Some(last) = result.iterations.last() {
+                assert!(result.final_info.contains(&format!(" score {} ", last.score)), "stopped after {nodes} nodes: {}", result.final_info);
             }
-            else
+
+            // Only positions without legal moves have exact scores without a best move
+            for entry in searcher.hashmap.iter_used()
             {
-                // TODO: This evaluation is valid for any depth for purposes of hashtable lookup.
-                BoardScore::EVEN
+                if entry.score().is_exact() && entry.best_move().is_none() {
+                    assert_eq!(entry.depth(), Depth::MAX, "stopped after {nodes} nodes: {}", entry.score());
+                }
             }
         }
     }
 
-    fn static_evaluation(&self, position: &Board) -> BoardScore
+    #[test]
+    fn test_hashfull_after_resize()
     {
-        evaluation::evaluate_piece_values(position)
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(6, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+
+        // The same search fills a bigger share of a smaller table, as the Hash option sets it
+        let mut hashfull = |megabytes| {
+            searcher.resize_hash(megabytes);
+            assert_eq!(searcher.hashmap.megabytes(), megabytes);
+            let result = searcher.search(Board::default(), 0);
+            let mut words = result.final_info.split_ascii_whitespace();
+            words.find(|&w| w == "hashfull");
+            words.next().unwrap().parse::<usize>().unwrap()
+        };
+        let small = hashfull(1);
+        let large = hashfull(4);
+        assert!(small <= 1000 && small > large && large > 0, "hashfull {small} with 1 MB, {large} with 4 MB");
     }
 
-    fn should_stop_search(&mut self) -> bool
+    #[test]
+    fn test_helper_threads()
     {
-        if self.stop_conditions.stop_now.load(Ordering::Relaxed) {
-            return true;
-        }
+        let position = Board::from_str(LIMITS_POSITION).unwrap();
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(6, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.set_threads(4);
 
-        let movetime = self.stop_conditions.movetime.load(Ordering::Relaxed);
-        if movetime != 0 {
-            let elapsed = self.starttime.elapsed().as_millis();
-            if elapsed >= movetime as u128 {
-                return true;
-            }
-        }
+        let nodes = |result: &SearchResult| {
+            let mut words = result.final_info.split_ascii_whitespace();
+            words.find(|&w| w == "nodes");
+            words.next().unwrap().parse::<u64>().unwrap()
+        };
 
-        false
+        // The main thread answers with a legal move, and reports the nodes of the helpers with its own
+        let result = searcher.search(position, 0);
+        assert!(position.legal(result.best_move.unwrap()));
+        assert!(nodes(&result) > searcher.nodes, "{} nodes of the main thread: {}", searcher.nodes, result.final_info);
+
+        // The node limit counts the nodes of all threads
+        stop_conditions.depth.store(Depth::MAX, Ordering::Relaxed);
+        stop_conditions.nodes.store(NODE_LIMIT, Ordering::Relaxed);
+        let result = searcher.search(position, 0);
+        assert!(nodes(&result) >= NODE_LIMIT && searcher.nodes < NODE_LIMIT, "{} nodes of the main thread: {}", searcher.nodes, result.final_info);
+
+        // Once the helpers are done, the hash table is the searcher's own again
+        searcher.new_game();
+        searcher.resize_hash(2);
     }
 
-    fn trace_pv(&self, position: &Board) -> String
+    #[test]
+    fn test_report_white_relative()
     {
-        let mut result = String::new();
-        let mut nbr = 0u32;
+        // Black to move and a queen up
+        let position = Board::from_str("4k3/8/8/3q4/8/8/8/4K3 b - - 0 1").unwrap();
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
 
-        let mut position = *position;
-        while let Some(hash_entry) = self.hashmap.get(&position)
+        // The iterations keep the point of view of the side to move, whatever the info lines show
+        let result = searcher.search(position, 0);
+        let score = result.iterations.last().unwrap().score;
+        assert!(score.unwrap() > BoardScore::evaluation(500), "{score}");
+        assert!(result.final_info.contains(&format!(" score {score} ")), "{}", result.final_info);
+
+        searcher.set_report_white_relative(true);
+        let result = searcher.search(position, 0);
+        let score = result.iterations.last().unwrap().score;
+        assert!(score.unwrap() > BoardScore::evaluation(500), "{score}");
+        assert!(result.final_info.contains(&format!(" score {} ", -score)), "{}", result.final_info);
+    }
+
+    #[test]
+    fn test_show_wdl()
+    {
+        // Black to move and a queen up
+        let position = Board::from_str("4k3/8/8/3q4/8/8/8/4K3 b - - 0 1").unwrap();
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(4, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+
+        let result = searcher.search(position, 0);
+        assert!(!result.final_info.contains(" wdl "), "{}", result.final_info);
+
+        // The side to move is winning, and from White's point of view losing, as the score says
+        searcher.set_show_wdl(true);
+        let result = searcher.search(position, 0);
+        let score = result.iterations.last().unwrap().score;
+        assert!(result.final_info.contains(&format!(" score {score} wdl 1000 0 0 ")), "{}", result.final_info);
+        searcher.set_report_white_relative(true);
+        let result = searcher.search(position, 0);
+        assert!(result.final_info.contains(" wdl 0 0 1000 "), "{}", result.final_info);
+    }
+
+    #[test]
+    fn test_ponder_move()
+    {
+        let position = Board::default();
+        let pv = |moves: &str| moves.split_ascii_whitespace().map(|m| ChessMove::from_str(m).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ponder_move(&position, &pv("e2e4 e7e5 g1f3")), Some(ChessMove::from_str("e7e5").unwrap()));
+        assert_eq!(ponder_move(&position, &pv("e2e4")), None);
+        // A reply that isn't legal, as a hash move of another position could be
+        assert_eq!(ponder_move(&position, &pv("e2e4 e2e4")), None);
+    }
+
+    #[test]
+    fn test_final_info_matches_bestmove()
+    {
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1",
+        ];
+
+        // Stop after the depth limit, and in the middle of an iteration on time
+        for (depth, movetime) in [(3, 0), (255, 1), (255, 30)]
         {
-            if let Some(best_move) = hash_entry.best_move()
+            for fen in positions
             {
-                write!(result, " {}", best_move).expect("string write always succeeds");
-                nbr += 1;
-                if nbr > Depth::MAX as u32 {
-                    break;
+                let stop_conditions = StopConditions::new();
+                stop_conditions.depth.store(depth, Ordering::Relaxed);
+                stop_conditions.movetime.store(movetime, Ordering::Relaxed);
+                let mut searcher = Searcher::new(&stop_conditions);
+                let result = searcher.search(Board::from_str(fen).unwrap(), 0);
+
+                let best_move = result.best_move.unwrap().to_string();
+                assert_eq!(first_pv_move(&result.final_info), Some(best_move.as_str()), "{fen}: {}", result.final_info);
+                let ponder_move = result.ponder_move.map(|m| m.to_string());
+                let second_pv_move = result.final_info.split_once(&format!(" pv {best_move} "))
+                    .and_then(|(_, rest)| rest.split_ascii_whitespace().next());
+                if !result.iterations.is_empty() {
+                    assert_eq!(ponder_move.as_deref(), second_pv_move, "{fen}: {}", result.final_info);
+                }
+
+                if let Some(last) = result.iterations.last() {
+                    assert!(result.final_info.starts_with(&format!("info depth {} ", last.depth)), "{}", result.final_info);
+                    assert!(result.final_info.contains(&format!(" score {} ", last.score)), "{}", result.final_info);
                 }
-                position = position.make_move_new(best_move);
-            }
-            else
-            {
-                break
             }
         }
+    }
 
-        result
+    #[test]
+    fn test_stopped_search_plays_the_printed_pv()
+    {
+        let position = Board::from_str("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let stop_conditions = StopConditions::new();
+        let mut searcher = Searcher::new(&stop_conditions);
+        // A small table that the searches keep overwriting, so that the root entry and the PVs of the
+        // earlier iterations get replaced while later ones run
+        searcher.resize_hash(1);
+
+        // Stop in the middle of iterations, at node counts that fall on every root move in turn
+        for nodes in (500..40_000).step_by(1_237)
+        {
+            stop_conditions.nodes.store(nodes, Ordering::Relaxed);
+            let result = searcher.search(position, 0);
+
+            let best_move = result.best_move.unwrap();
+            assert_eq!(first_pv_move(&result.final_info), Some(best_move.to_string().as_str()), "stopped after {nodes} nodes: {}", result.final_info);
+
+            // A search that resumes from the depth of the hash entry may complete no iteration at all,
+            // and then plays the hash move
+            let Some(confirmed) = &searcher.confirmed else {
+                assert!(result.iterations.is_empty() && result.ponder_move.is_none(), "stopped after {nodes} nodes");
+                continue;
+            };
+            assert_eq!(confirmed.root_move.chess_move, best_move, "stopped after {nodes} nodes");
+            assert_eq!(result.ponder_move, ponder_move(&position, &confirmed.root_move.pv), "stopped after {nodes} nodes: {}", result.final_info);
+            assert_eq!(confirmed.root_move.score, result.iterations.last().unwrap().score, "stopped after {nodes} nodes");
+        }
+    }
+
+    #[test]
+    fn test_validate_boards_stops_on_illegal_hash_move()
+    {
+        let position = Board::default();
+        let e2e4 = ChessMove::from_str("e2e4").unwrap();
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(3, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.set_validate_boards(true);
+
+        // A sound search has nothing to report
+        let result = searcher.search(position, 0);
+        assert!(!searcher.validation_failed);
+        assert_eq!(result.iterations.len(), 3);
+
+        // Give the position after 1. e4 a hash move that Black can't play, as a collision could
+        searcher.clear_hash();
+        let child = position.make_move_new(e2e4);
+        let bad_entry = HashEntry::with_contents(child.get_hash(), Some(e2e4), BoundedScore::LowerBound(BoardScore::MATED), 0);
+        searcher.hashmap.insert(&child, bad_entry);
+
+        // The search stops before completing an iteration, and still plays a legal move
+        let result = searcher.search(position, 0);
+        assert!(searcher.validation_failed);
+        assert_eq!(searcher.line, [e2e4]);
+        assert!(result.iterations.is_empty());
+        assert!(result.best_move.is_some_and(|m| position.legal(m)));
+    }
+
+    #[test]
+    fn test_eval_overrides()
+    {
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(3, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+
+        // Pretend that Black is lost after 1. a3
+        let a3 = ChessMove::from_str("a2a3").unwrap();
+        let mut overrides = EvalOverrides::default();
+        overrides.set(&Board::default().make_move_new(a3), -500);
+        searcher.set_overrides(Arc::new(overrides));
+        let result = searcher.search(Board::default(), 0);
+        assert_eq!(result.best_move, Some(a3));
+        assert_eq!(result.iterations.last().unwrap().score.unwrap(), BoardScore::evaluation(500));
+        assert!(result.final_info.ends_with(" pv a2a3"), "{}", result.final_info);
+
+        // Without the override, and without hash entries found with it, 1. a3 is nothing special
+        searcher.set_overrides(Arc::new(EvalOverrides::default()));
+        let result = searcher.search(Board::default(), 0);
+        assert_ne!(result.best_move, Some(a3));
+        assert!(result.iterations.last().unwrap().score.unwrap() < BoardScore::evaluation(100), "{}", result.final_info);
     }
 }
@@ -0,0 +1,287 @@
+//! Snapshots of the hash table at regular intervals during long analysis, so that a crash or a power
+//! cut doesn't lose hours of work
+//!
+//! While a search runs, a snapshot is started every interval. The search writes it a chunk at a time,
+//! in `Autosave::step`, so that it never pauses for more than about `MAX_PAUSE`. Once the snapshot is
+//! complete, a small JSON file next to it, the sidecar, tells what the search had found out by then:
+//! the root position, the depth reached, the score and the PV. The sidecar is how a restarted engine
+//! knows that the snapshot is of the position it is given.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::clock::Instant;
+use crate::hash::HashMap;
+use crate::hash::snapshot::{self, SnapshotWriter};
+use crate::output;
+use super::Depth;
+
+/// The search pauses for at most about this long to write a chunk of a snapshot
+const MAX_PAUSE: Duration = Duration::from_millis(2);
+
+/// What a search has found out, as of its last completed iteration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnalysisState
+{
+    /// FEN of the root position
+    pub fen: String,
+    /// Depth of the last completed iteration, or 0 if none has completed
+    pub depth: Depth,
+    /// Score of the last completed iteration from White's point of view, formatted as in an info
+    /// line by `BoardScore::describe`, like "cp 25", or empty if none has completed. Unlike the info lines, it doesn't depend on
+    /// the side to move, since the readers of the sidecar are not only GUIs.
+    pub score: String,
+    /// PV of the last completed iteration in UCI notation, separated by spaces
+    pub pv: String,
+}
+
+pub struct Autosave
+{
+    path: PathBuf,
+    interval: Duration,
+    progress: AnalysisState,
+    /// When the next snapshot is due, if none is in progress
+    next_start: Instant,
+    in_progress: Option<SnapshotInProgress>,
+}
+
+struct SnapshotInProgress
+{
+    writer: SnapshotWriter,
+    started: Instant,
+    longest_pause: Duration,
+}
+
+impl Autosave
+{
+    pub fn new(path: PathBuf, interval: Duration) -> Self
+    {
+        Autosave {
+            path,
+            interval,
+            progress: AnalysisState::default(),
+            next_start: Instant::now() + interval,
+            in_progress: None,
+        }
+    }
+
+    /// Start counting the interval for a search of the position with this FEN
+    pub fn start_search(&mut self, fen: String)
+    {
+        self.progress = AnalysisState { fen, ..AnalysisState::default() };
+        self.next_start = Instant::now() + self.interval;
+    }
+
+    /// Record the result of a completed iteration, for the sidecar of the next snapshot
+    pub fn set_progress(&mut self, depth: Depth, score: String, pv: String)
+    {
+        self.progress.depth = depth;
+        self.progress.score = score;
+        self.progress.pv = pv;
+    }
+
+    /// Start a snapshot if one is due, and write the next chunk of the snapshot in progress
+    ///
+    /// The search calls this regularly, often enough that a chunk of `MAX_PAUSE` is a small part of
+    /// the time in between.
+    pub fn step(&mut self, hashmap: &HashMap)
+    {
+        let step_start = Instant::now();
+        if self.in_progress.is_none()
+        {
+            if step_start < self.next_start {
+                return;
+            }
+            self.next_start = step_start + self.interval;
+
+            match SnapshotWriter::create(&self.path, hashmap)
+            {
+                Ok(writer) => {
+                    self.in_progress = Some(SnapshotInProgress { writer, started: step_start, longest_pause: Duration::ZERO });
+                }
+                Err(e) => {
+                    output::error(format_args!("Autosave to \"{}\" failed: {e}", self.path.display()));
+                    return;
+                }
+            }
+        }
+
+        let Some(in_progress) = &mut self.in_progress else {
+            return;
+        };
+        let result = in_progress.writer.write_chunk(hashmap, Some(step_start + MAX_PAUSE));
+        match result
+        {
+            Ok(false) => in_progress.longest_pause = in_progress.longest_pause.max(step_start.elapsed()),
+            Ok(true) => {
+                let in_progress = self.in_progress.take().unwrap();
+                let (started, longest_pause) = (in_progress.started, in_progress.longest_pause);
+                let result = in_progress.writer.finish()
+                    .and_then(|entries| write_sidecar(&self.path, &self.progress).map(|()| entries));
+                let longest_pause = longest_pause.max(step_start.elapsed());
+                match result
+                {
+                    Ok(entries) => output::send(format_args!(
+                        "info string autosaved {entries} entries at depth {} to \"{}\" in {} ms, longest pause {} ms",
+                        self.progress.depth, self.path.display(), started.elapsed().as_millis(), longest_pause.as_millis(),
+                    )),
+                    Err(e) => output::error(format_args!("Autosave to \"{}\" failed: {e}", self.path.display())),
+                }
+            }
+            Err(e) => {
+                self.in_progress.take().unwrap().writer.abandon();
+                output::error(format_args!("Autosave to \"{}\" failed: {e}", self.path.display()));
+            }
+        }
+    }
+
+    /// Give up on a snapshot that is not complete when the search ends, keeping the previous one
+    pub fn end_search(&mut self)
+    {
+        if let Some(in_progress) = self.in_progress.take() {
+            in_progress.writer.abandon();
+        }
+    }
+}
+
+/// The path of the sidecar of the snapshot at `path`
+pub fn sidecar_path(path: &Path) -> PathBuf
+{
+    snapshot::path_with_suffix(path, ".json")
+}
+
+/// Read the sidecar of the snapshot at `path`
+pub fn read_sidecar(path: &Path) -> Result<AnalysisState, String>
+{
+    let sidecar_path = sidecar_path(path);
+    fs::read_to_string(&sidecar_path)
+        .map_err(|e| format!("Could not read \"{}\": {e}", sidecar_path.display()))
+        .and_then(|text| from_json(&text))
+}
+
+/// Write the sidecar of the snapshot at `path`, replacing the previous one only once it is complete
+fn write_sidecar(path: &Path, state: &AnalysisState) -> io::Result<()>
+{
+    let sidecar_path = sidecar_path(path);
+    let temp_path = snapshot::path_with_suffix(&sidecar_path, ".tmp");
+    fs::write(&temp_path, to_json(state))?;
+    fs::rename(&temp_path, &sidecar_path)
+}
+
+/// Format the state as a JSON object
+///
+/// None of the strings can contain quotes or backslashes, so they need no escaping.
+pub fn to_json(state: &AnalysisState) -> String
+{
+    format!(
+        "{{\n  \"fen\": \"{}\",\n  \"depth\": {},\n  \"score\": \"{}\",\n  \"pv\": \"{}\"\n}}\n",
+        state.fen, state.depth, state.score, state.pv,
+    )
+}
+
+/// Read a state written by `to_json`
+///
+/// Only a flat object of numbers and of strings without escapes is understood, which is all that
+/// `to_json` writes. The keys may come in any order, and unknown keys are ignored.
+pub fn from_json(text: &str) -> Result<AnalysisState, String>
+{
+    let body = text.trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or("Expected a JSON object")?;
+
+    let mut state = AnalysisState::default();
+    let mut rest = body.trim();
+    while !rest.is_empty()
+    {
+        let (key, after_key) = rest.strip_prefix('"')
+            .and_then(|r| r.split_once('"'))
+            .ok_or_else(|| format!("Expected a key at \"{rest}\""))?;
+        let after_colon = after_key.trim_start()
+            .strip_prefix(':')
+            .ok_or_else(|| format!("Expected ':' after \"{key}\""))?
+            .trim_start();
+
+        let (value, after_value) = match after_colon.strip_prefix('"')
+        {
+            Some(string) => string.split_once('"').ok_or_else(|| format!("Unterminated string for \"{key}\""))?,
+            None => after_colon.split_at(after_colon.find(',').unwrap_or(after_colon.len())),
+        };
+        match key
+        {
+            "fen" => state.fen = value.to_owned(),
+            "depth" => state.depth = value.trim().parse().map_err(|_| format!("Invalid \"depth\": {value}"))?,
+            "score" => state.score = value.to_owned(),
+            "pv" => state.pv = value.to_owned(),
+            _ => { },
+        }
+
+        rest = after_value.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    if state.fen.is_empty() {
+        return Err("Missing \"fen\"".to_owned());
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+    use std::sync::atomic::Ordering;
+
+    use chess::Board;
+    use crate::search::Searcher;
+    use crate::stopconditions::StopConditions;
+
+    use super::*;
+
+    #[test]
+    fn test_sidecar_json()
+    {
+        let state = AnalysisState {
+            fen: "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_owned(),
+            depth: 14,
+            score: "cp -12".to_owned(),
+            pv: "f1b5 a7a6 b5a4".to_owned(),
+        };
+        assert_eq!(from_json(&to_json(&state)), Ok(state));
+
+        assert!(from_json("").is_err());
+        assert!(from_json("{}").is_err());
+        assert!(from_json("{\"fen\": \"8/8/8/8/8/8/8/8 w - - 0 1\", \"depth\": x}").is_err());
+        assert!(from_json("{\"fen\": \"8/8/8/8").is_err());
+    }
+
+    #[test]
+    fn test_autosave_during_search()
+    {
+        let position = Board::from_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let path = std::env::temp_dir().join(format!("autosave-test-{}.hash", std::process::id()));
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.movetime.store(2000, Ordering::Relaxed);
+        let mut searcher = Searcher::new(&stop_conditions);
+        searcher.resize_hash(1);
+        searcher.set_autosave(Some(path.clone()), Duration::from_secs(1));
+        searcher.search(position, 0);
+
+        let state = read_sidecar(&path);
+        let mut loaded = HashMap::new(1);
+        let count = snapshot::load(&mut loaded, &path);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(sidecar_path(&path)).unwrap();
+
+        let state = state.unwrap();
+        assert_eq!(Board::from_str(&state.fen).unwrap().get_hash(), position.get_hash());
+        assert!(state.depth >= 1 && !state.score.is_empty() && !state.pv.is_empty(), "{state:?}");
+
+        // The snapshot holds what the iterations before it stored about the root
+        assert!(count.unwrap() > 0);
+        assert!(loaded.probe(position.get_hash()).is_some(), "the root should be in the snapshot");
+    }
+}
@@ -0,0 +1,174 @@
+//! Export of the hash table as EPD, for offline analysis of what a long search found out
+//!
+//! The export walks the game tree breadth first from a root position, and writes one EPD line for
+//! every position found in the hash table. Positions that are not in the table are not expanded
+//! further, since the search only reaches a position through a parent that it also stores.
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, Write};
+
+use chess::{Board, MoveGen};
+use crate::hash::HashMap;
+use crate::san;
+use crate::score::BoundedScore;
+use super::Depth;
+
+/// Maximum number of positions to visit in one export, so that it finishes in reasonable time even
+/// with a huge hash table full of transpositions
+const MAX_EXPORT_POSITIONS: usize = 1_000_000;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExportStats
+{
+    /// Number of positions probed in the hash table
+    pub visited: usize,
+    /// Number of positions written to the EPD file
+    pub written: usize,
+    /// Whether the export stopped at `MAX_EXPORT_POSITIONS` before it had visited every position
+    pub truncated: bool,
+    /// Number of entries of at least the minimum depth in the whole table, reachable or not
+    pub deep_entries: usize,
+}
+
+/// Write all positions within `max_plies` of `root` that have hash entries of at least `min_depth`
+///
+/// Each line holds the position in EPD, followed by the opcodes `ce` (score in centipawns, from the
+/// side to move's point of view), `acd` (depth of the entry) and `bm` (best move, if any). Scores that
+/// are only bounds get a `c0` comment saying which kind of bound they are.
+pub fn export_tree(hashmap: &HashMap, root: &Board, min_depth: Depth, max_plies: usize, output: &mut impl Write) -> io::Result<ExportStats>
+{
+    let mut stats = ExportStats {
+        deep_entries: hashmap.iter_used().filter(|e| e.depth() >= min_depth).count(),
+        ..ExportStats::default()
+    };
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    visited.insert(root.get_hash());
+    queue.push_back((*root, 0));
+
+    while let Some((position, plies)) = queue.pop_front()
+    {
+        if stats.visited >= MAX_EXPORT_POSITIONS {
+            stats.truncated = true;
+            break;
+        }
+        stats.visited += 1;
+
+        let Some(probe) = hashmap.probe(position.get_hash()) else {
+            continue;
+        };
+
+        if probe.depth >= min_depth
+        {
+            write!(output, "{} ce {}; acd {};", epd(&position), probe.score.unwrap().epd_centipawns(), probe.depth)?;
+            // A hash collision could give us a move that isn't legal here
+            if let Some(best_move) = probe.best_move.filter(|&m| position.legal(m)) {
+                write!(output, " bm {};", san::to_san(&position, best_move))?;
+            }
+            match probe.score
+            {
+                BoundedScore::Exact(_) => { },
+                BoundedScore::LowerBound(_) => write!(output, " c0 \"lowerbound\";")?,
+                BoundedScore::UpperBound(_) => write!(output, " c0 \"upperbound\";")?,
+            }
+            writeln!(output)?;
+            stats.written += 1;
+        }
+
+        if plies < max_plies
+        {
+            for chess_move in MoveGen::new_legal(&position)
+            {
+                let child = position.make_move_new(chess_move);
+                if visited.insert(child.get_hash()) {
+                    queue.push_back((child, plies + 1));
+                }
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// The position as the first four fields of its FEN, which is how EPD describes a position
+fn epd(position: &Board) -> String
+{
+    position.to_string().split_ascii_whitespace().take(4).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use chess::ChessMove;
+    use crate::hash::HashEntry;
+    use crate::score::BoardScore;
+
+    use super::*;
+
+    fn export(hashmap: &HashMap, root: &Board, min_depth: Depth, max_plies: usize) -> (ExportStats, String)
+    {
+        let mut output = Vec::new();
+        let stats = export_tree(hashmap, root, min_depth, max_plies, &mut output).unwrap();
+        (stats, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_export_format()
+    {
+        let hashmap = HashMap::new(1);
+        let root = Board::default();
+        let e4 = ChessMove::from_str("e2e4").unwrap();
+        let after_e4 = root.make_move_new(e4);
+        let after_d4 = root.make_move_new(ChessMove::from_str("d2d4").unwrap());
+
+        hashmap.insert(&root, HashEntry::with_contents(root.get_hash(), Some(e4), BoundedScore::Exact(BoardScore::evaluation(30)), 6));
+        hashmap.insert(&after_e4, HashEntry::with_contents(after_e4.get_hash(), None, BoundedScore::LowerBound(BoardScore::evaluation(-25)), 5));
+        hashmap.insert(&after_d4, HashEntry::with_contents(after_d4.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 2));
+
+        let (stats, output) = export(&hashmap, &root, 3, 1);
+        assert_eq!(stats.visited, 21);
+        assert_eq!(stats.written, 2);
+        assert_eq!(stats.deep_entries, 2);
+        assert!(!stats.truncated);
+
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines, [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ce 30; acd 6; bm e4;",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - ce -25; acd 5; c0 \"lowerbound\";",
+        ]);
+
+        // The root alone when no plies are allowed
+        let (stats, output) = export(&hashmap, &root, 0, 0);
+        assert_eq!(stats.visited, 1);
+        assert_eq!(output.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_export_visits_transpositions_once()
+    {
+        let hashmap = HashMap::new(1);
+        let root = Board::from_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        // Kings only, so every position within reach transposes many times over
+        let mut positions = vec![root];
+        let mut frontier = vec![root];
+        for _ in 0..4 {
+            frontier = frontier.iter()
+                .flat_map(|p| MoveGen::new_legal(p).map(|m| p.make_move_new(m)).collect::<Vec<_>>())
+                .collect();
+            positions.extend(&frontier);
+        }
+        for position in &positions {
+            hashmap.insert(position, HashEntry::with_contents(position.get_hash(), None, BoundedScore::Exact(BoardScore::EVEN), 1));
+        }
+
+        let (stats, output) = export(&hashmap, &root, 0, 4);
+        let unique = output.lines().collect::<HashSet<_>>();
+        assert!(stats.written > 1);
+        assert_eq!(unique.len(), stats.written);
+        assert!(stats.visited < positions.len());
+    }
+}
@@ -0,0 +1,240 @@
+//! Late move reductions: quiet moves late in the move order are first searched to a reduced depth,
+//! and only searched again to full depth if the reduced search suggests that they are good after all
+//!
+//! How much to reduce comes from an `LmrTable`, which can be inspected and tested on its own. The
+//! search counts how often the reduced searches have to be repeated, by depth, in `LmrStats`. In
+//! adaptive mode, the base reduction is nudged between iterations to keep that rate within a band.
+
+use super::Depth;
+
+/// Moves are not reduced in nodes of lower depth than this
+pub const LMR_MIN_DEPTH: Depth = 3;
+
+/// The first moves searched in a node, up to but not including this move number, are never reduced
+pub const LMR_MIN_MOVE_NUMBER: usize = 4;
+
+/// Base reduction and divisor of the default table, in hundredths of a ply
+pub const DEFAULT_BASE: i32 = 75;
+pub const DEFAULT_DIVISOR: i32 = 225;
+
+/// Range within which adaptive mode may move the base reduction, and its step size
+const MIN_BASE: i32 = 0;
+const MAX_BASE: i32 = 150;
+const BASE_STEP: i32 = 25;
+
+/// Adaptive mode aims for a re-search rate in this range, in percent
+const TARGET_RESEARCH_PERCENT: (u64, u64) = (5, 15);
+
+/// Adaptive mode doesn't draw conclusions from fewer reductions than this
+const ADAPT_MIN_REDUCTIONS: u64 = 1000;
+
+const TABLE_SIZE: usize = 64;
+
+/// Depth ranges by which the reductions are counted, inclusive
+pub const DEPTH_BUCKETS: [(Depth, Depth); 4] = [(3, 4), (5, 6), (7, 9), (10, Depth::MAX)];
+
+/// Reductions by depth and move number, following `base + ln(depth) * ln(move number) / divisor`
+#[derive(Clone, Debug)]
+pub struct LmrTable
+{
+    base: i32,
+    divisor: i32,
+    reductions: [[Depth; TABLE_SIZE]; TABLE_SIZE],
+}
+
+impl LmrTable
+{
+    /// `base` and `divisor` are in hundredths of a ply
+    pub fn new(base: i32, divisor: i32) -> Self
+    {
+        debug_assert!(divisor > 0);
+        let mut reductions = [[0; TABLE_SIZE]; TABLE_SIZE];
+        for depth in 1..TABLE_SIZE
+        {
+            for move_number in 1..TABLE_SIZE
+            {
+                let scaled = (depth as f64).ln() * (move_number as f64).ln() * 100.0 / divisor as f64;
+                let reduction = base as f64 / 100.0 + scaled;
+                reductions[depth][move_number] = reduction.max(0.0) as Depth;
+            }
+        }
+
+        LmrTable {
+            base,
+            divisor,
+            reductions,
+        }
+    }
+
+    pub fn base(&self) -> i32
+    {
+        self.base
+    }
+
+    /// By how many plies to reduce the search of the move with the given number, counting from 1, in a
+    /// node of the given depth. The reduced search always has at least one ply left.
+    pub fn reduction(&self, depth: Depth, move_number: usize) -> Depth
+    {
+        if depth < LMR_MIN_DEPTH || move_number < LMR_MIN_MOVE_NUMBER {
+            return 0;
+        }
+
+        let reduction = self.reductions[(depth as usize).min(TABLE_SIZE - 1)][move_number.min(TABLE_SIZE - 1)];
+        reduction.min(depth - 2)
+    }
+
+    /// Move the base reduction one step towards the target re-search rate, judging by the reductions
+    /// counted so far. Reduce less if too many reduced searches had to be repeated, and more if
+    /// hardly any had.
+    pub fn adapt(&mut self, stats: &LmrStats)
+    {
+        let reduced = stats.total_reduced();
+        if reduced < ADAPT_MIN_REDUCTIONS {
+            return;
+        }
+
+        let research_percent = 100 * stats.total_researched() / reduced;
+        let base = if research_percent > TARGET_RESEARCH_PERCENT.1 {
+            self.base - BASE_STEP
+        } else if research_percent < TARGET_RESEARCH_PERCENT.0 {
+            self.base + BASE_STEP
+        } else {
+            return;
+        };
+
+        *self = LmrTable::new(base.clamp(MIN_BASE, MAX_BASE), self.divisor);
+    }
+}
+
+impl Default for LmrTable
+{
+    fn default() -> Self
+    {
+        LmrTable::new(DEFAULT_BASE, DEFAULT_DIVISOR)
+    }
+}
+
+/// How many moves were searched to a reduced depth, and how many of them had to be searched again to
+/// full depth, by the depth buckets in `DEPTH_BUCKETS`
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LmrStats
+{
+    pub reduced: [u64; DEPTH_BUCKETS.len()],
+    pub researched: [u64; DEPTH_BUCKETS.len()],
+}
+
+impl LmrStats
+{
+    pub fn record(&mut self, depth: Depth, researched: bool)
+    {
+        let bucket = DEPTH_BUCKETS.iter()
+            .position(|&(low, high)| (low..=high).contains(&depth))
+            .expect("reductions are only done at depths covered by the buckets");
+        self.reduced[bucket] += 1;
+        if researched {
+            self.researched[bucket] += 1;
+        }
+    }
+
+    pub fn total_reduced(&self) -> u64
+    {
+        self.reduced.iter().sum()
+    }
+
+    pub fn total_researched(&self) -> u64
+    {
+        self.researched.iter().sum()
+    }
+
+    /// A summary for an info string, such as `3-4: 12/340 5-6: 3/80 7-9: 0/0 10+: 0/0`
+    pub fn summary(&self) -> String
+    {
+        DEPTH_BUCKETS.iter()
+            .enumerate()
+            .map(|(i, &(low, high))| {
+                let range = if high == Depth::MAX { format!("{low}+") } else { format!("{low}-{high}") };
+                format!("{range}: {}/{}", self.researched[i], self.reduced[i])
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_lmr_table_values()
+    {
+        let table = LmrTable::default();
+        assert_eq!(table.reduction(3, 4), 1);
+        assert_eq!(table.reduction(8, 10), 2);
+        assert_eq!(table.reduction(20, 30), 5);
+        assert_eq!(table.reduction(63, 63), 8);
+        // Beyond the table, the last entry applies
+        assert_eq!(table.reduction(200, 200), 8);
+
+        // Early moves and shallow nodes are not reduced
+        assert_eq!(table.reduction(8, LMR_MIN_MOVE_NUMBER - 1), 0);
+        assert_eq!(table.reduction(LMR_MIN_DEPTH - 1, 30), 0);
+
+        // Without a base reduction, the first reducible move isn't reduced
+        let table = LmrTable::new(0, DEFAULT_DIVISOR);
+        assert_eq!(table.reduction(3, 4), 0);
+        assert_eq!(table.reduction(8, 10), 2);
+    }
+
+    #[test]
+    fn test_reduced_search_keeps_a_ply()
+    {
+        let table = LmrTable::new(MAX_BASE, 50);
+        for depth in LMR_MIN_DEPTH..=40 {
+            for move_number in 1..100 {
+                assert!(table.reduction(depth, move_number) <= depth - 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adapt_stays_in_range()
+    {
+        let mut table = LmrTable::default();
+
+        let mut too_many_researches = LmrStats::default();
+        too_many_researches.reduced[0] = ADAPT_MIN_REDUCTIONS;
+        too_many_researches.researched[0] = ADAPT_MIN_REDUCTIONS / 2;
+        for _ in 0..20 {
+            table.adapt(&too_many_researches);
+        }
+        assert_eq!(table.base(), MIN_BASE);
+
+        let mut no_researches = LmrStats::default();
+        no_researches.reduced[1] = ADAPT_MIN_REDUCTIONS;
+        for _ in 0..20 {
+            table.adapt(&no_researches);
+        }
+        assert_eq!(table.base(), MAX_BASE);
+
+        // Too few reductions to go by
+        let mut few = LmrStats::default();
+        few.reduced[0] = 10;
+        table.adapt(&few);
+        assert_eq!(table.base(), MAX_BASE);
+    }
+
+    #[test]
+    fn test_lmr_stats_buckets()
+    {
+        let mut stats = LmrStats::default();
+        stats.record(3, false);
+        stats.record(4, true);
+        stats.record(9, false);
+        stats.record(30, true);
+
+        assert_eq!(stats.reduced, [2, 0, 1, 1]);
+        assert_eq!(stats.researched, [1, 0, 0, 1]);
+        assert_eq!(stats.summary(), "3-4: 1/2 5-6: 0/0 7-9: 0/1 10+: 1/1");
+    }
+}
@@ -0,0 +1,148 @@
+//! Shared guards for the heuristics that reduce or prune moves in the tree search
+//!
+//! Every such heuristic must stay away from the root, from PV nodes, from positions in check, and
+//! (for pruning) from windows near mate scores. Rather than repeating those conditions in every
+//! heuristic, each node builds a `PruningContext` once, and the heuristics ask it for permission.
+
+use crate::score::BoardScore;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind
+{
+    Root,
+    /// A node searched with an open window, which may be part of the principal variation
+    Pv,
+    /// A node searched with a null window, which is only expected to fail high or low
+    NonPv,
+}
+
+impl NodeKind
+{
+    pub fn new(ply: usize, alpha: BoardScore, beta: BoardScore) -> Self
+    {
+        if ply == 0 {
+            NodeKind::Root
+        } else if beta.next_lower() == alpha {
+            NodeKind::NonPv
+        } else {
+            NodeKind::Pv
+        }
+    }
+}
+
+/// The facts about a node that pruning and reduction heuristics consult
+#[derive(Clone, Copy, Debug)]
+pub struct PruningContext
+{
+    pub node_kind: NodeKind,
+    pub in_check: bool,
+    /// Whether the static evaluation is better than two plies ago
+    pub improving: bool,
+    /// Whether alpha or beta is a mate score, so that pruning could hide a mate
+    pub near_mate_window: bool,
+}
+
+impl PruningContext
+{
+    pub fn new(node_kind: NodeKind, in_check: bool, improving: bool, alpha: BoardScore, beta: BoardScore) -> Self
+    {
+        PruningContext {
+            node_kind,
+            in_check,
+            improving,
+            near_mate_window: is_mate_bound(alpha) || is_mate_bound(beta),
+        }
+    }
+
+    /// Whether moves may be skipped without searching them, as in futility or move count pruning, or
+    /// the whole node may be cut off early, as in null move pruning
+    pub fn allows_pruning(&self) -> bool
+    {
+        self.node_kind == NodeKind::NonPv && !self.in_check && !self.near_mate_window
+    }
+
+    /// Whether moves may be searched to a reduced depth, as in late move reductions
+    pub fn allows_reductions(&self) -> bool
+    {
+        self.node_kind == NodeKind::NonPv && !self.in_check
+    }
+}
+
+/// Counts of the reductions and prunings done in a search, by the kind of node they happened in
+///
+/// Every heuristic records its decisions here, which lets tests check that the guards hold.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PruningStats
+{
+    pub total: u64,
+    pub at_root: u64,
+    pub in_pv: u64,
+    pub in_check: u64,
+}
+
+impl PruningStats
+{
+    pub fn record(&mut self, context: &PruningContext)
+    {
+        debug_assert!(context.node_kind == NodeKind::NonPv && !context.in_check, "Pruned in {context:?}");
+
+        self.total += 1;
+        match context.node_kind
+        {
+            NodeKind::Root => self.at_root += 1,
+            NodeKind::Pv => self.in_pv += 1,
+            NodeKind::NonPv => { },
+        }
+        if context.in_check {
+            self.in_check += 1;
+        }
+    }
+}
+
+/// Whether the score is a mate score, not counting the infinite bounds of an open window
+fn is_mate_bound(score: BoardScore) -> bool
+{
+    score.is_mate_score() && score != BoardScore::BEST_SCORE && score != BoardScore::WORST_SCORE
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_node_kind()
+    {
+        let alpha = BoardScore::evaluation(10);
+        assert_eq!(NodeKind::new(0, alpha, BoardScore::evaluation(11)), NodeKind::Root);
+        assert_eq!(NodeKind::new(3, alpha, BoardScore::evaluation(11)), NodeKind::NonPv);
+        assert_eq!(NodeKind::new(3, alpha, BoardScore::evaluation(50)), NodeKind::Pv);
+        assert_eq!(NodeKind::new(3, BoardScore::WORST_SCORE, BoardScore::BEST_SCORE), NodeKind::Pv);
+    }
+
+    #[test]
+    fn test_pruning_guards()
+    {
+        let alpha = BoardScore::evaluation(10);
+        let beta = BoardScore::evaluation(11);
+        let mate = BoardScore::MATE.increment_mate_plies();
+
+        let context = PruningContext::new(NodeKind::NonPv, false, true, alpha, beta);
+        assert!(context.allows_pruning());
+        assert!(context.allows_reductions());
+
+        for node_kind in [NodeKind::Root, NodeKind::Pv] {
+            let context = PruningContext::new(node_kind, false, true, alpha, beta);
+            assert!(!context.allows_pruning());
+            assert!(!context.allows_reductions());
+        }
+
+        let context = PruningContext::new(NodeKind::NonPv, true, false, alpha, beta);
+        assert!(!context.allows_pruning());
+        assert!(!context.allows_reductions());
+
+        let context = PruningContext::new(NodeKind::NonPv, false, true, mate.next_lower(), mate);
+        assert!(!context.allows_pruning());
+        assert!(context.allows_reductions());
+    }
+}
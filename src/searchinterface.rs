@@ -1,75 +1,489 @@
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
+use std::time;
 
-use crate::search::Searcher;
+use crate::clock::Instant;
+use crate::evaluation::SharedEvaluator;
+#[cfg(feature = "nnue")]
+use crate::evaluation::nnue::Network;
+use crate::hash::ProbeResult;
+use crate::output;
+use crate::overrides::EvalOverrides;
+use crate::params::EngineParams;
+use crate::san::PvNotation;
+use crate::search::{Depth, ExportStats, Searcher};
+use crate::stopconditions::StopConditions;
 
+/// How long to wait for the search thread before reporting that it doesn't respond. Stopping a search
+/// takes a few milliseconds, but maintenance like allocating a large hash table may take seconds.
+const RESPONSE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+/// How long quitting waits for the search thread to stop. A GUI that sends quit expects the engine to be
+/// gone at once, and may kill it otherwise.
+const QUIT_TIMEOUT: time::Duration = time::Duration::from_millis(250);
 
 pub struct SearchInterface
 {
-    join_handle: Option<thread::JoinHandle<()>>, // Actually always Some, except during drop
+    join_handle: Option<thread::JoinHandle<()>>, // None during drop, or if the thread could not be started
     stop_conditions: Box<StopConditions>,
     channel: mpsc::Sender<ThreadCommand>,
+    /// Evaluation that the search thread uses instead of the classical one, if any
+    evaluator: Option<SharedEvaluator>,
+    /// Whether maintenance work was queued behind the running search, which readyok has to wait for
+    maintenance_queued: bool,
 }
 
 impl SearchInterface
 {
     pub fn new() -> Self
+    {
+        Self::start(None)
+    }
+
+    /// Create a search interface whose searches evaluate positions with `evaluator`, see
+    /// `Searcher::with_evaluator`
+    ///
+    /// The evaluator is shared rather than owned, so that a search thread started in place of one that
+    /// has died still evaluates with it.
+    pub fn with_evaluator(evaluator: SharedEvaluator) -> Self
+    {
+        Self::start(Some(evaluator))
+    }
+
+    fn start(evaluator: Option<SharedEvaluator>) -> Self
     {
         let stop_conditions = Box::new(StopConditions::new());
-        // SAFETY: Artificially prolonging the lifetime of the borrow. This is sound so long as I make
-        // sure to join with the thread before dropping the box.
-        let borrowed_stop_conditions = unsafe { &*(&*stop_conditions as *const _) };
-        let (sender, receiver) = mpsc::channel();
-        let join_handle = thread::Builder::new()
-            .name("search thread".into())
-            .spawn(move || search_thread_mainloop(receiver, borrowed_stop_conditions))
-            .expect("failed to start thread");
+        let (join_handle, channel) = match spawn_search_thread(&stop_conditions, evaluator.clone())
+        {
+            Ok((join_handle, channel)) => (Some(join_handle), channel),
+            Err(e) => {
+                // A channel that nobody receives from makes the first command start the thread again,
+                // see `send`
+                output::error(format_args!("Could not start the search thread: {e}, trying again with the next command"));
+                (None, mpsc::channel().0)
+            }
+        };
 
         SearchInterface {
             stop_conditions,
-            join_handle: Some(join_handle),
-            channel: sender,
+            join_handle,
+            channel,
+            evaluator,
+            maintenance_queued: false,
+        }
+    }
+
+    /// Start searching `position`. `game_history` holds the hashes of the positions of the game so far,
+    /// as far as they are known, for detecting repetitions.
+    pub fn go(&mut self, position: &chess::Board, halfmove_clock: u32, game_history: &[u64], stop_conditions: StopConditions)
+    {
+        self.start_search(position, halfmove_clock, game_history, stop_conditions, Vec::new(), Vec::new());
+    }
+
+    /// Start a search that leaves out `excluded_moves` at the root, see `Searcher::set_excluded_moves`
+    pub fn go_excluding(
+        &mut self,
+        position: &chess::Board,
+        halfmove_clock: u32,
+        game_history: &[u64],
+        stop_conditions: StopConditions,
+        excluded_moves: Vec<chess::ChessMove>,
+    )
+    {
+        self.start_search(position, halfmove_clock, game_history, stop_conditions, excluded_moves, Vec::new());
+    }
+
+    /// Start a search that only considers `search_moves` at the root, see `Searcher::set_search_moves`
+    pub fn go_searchmoves(
+        &mut self,
+        position: &chess::Board,
+        halfmove_clock: u32,
+        game_history: &[u64],
+        stop_conditions: StopConditions,
+        search_moves: Vec<chess::ChessMove>,
+    )
+    {
+        self.start_search(position, halfmove_clock, game_history, stop_conditions, Vec::new(), search_moves);
+    }
+
+    fn start_search(
+        &mut self,
+        position: &chess::Board,
+        halfmove_clock: u32,
+        game_history: &[u64],
+        stop_conditions: StopConditions,
+        excluded_moves: Vec<chess::ChessMove>,
+        search_moves: Vec<chess::ChessMove>,
+    )
+    {
+        self.supersede();
+        // The search thread does the queued maintenance before it starts this search
+        self.maintenance_queued = false;
+
+        // Set new stop parameters
+        self.set_stop_now(false);
+        self.stop_conditions.superseded.store(false, Ordering::Release);
+        self.stop_conditions.assign(stop_conditions);
+
+        // Mark the search as running already now, rather than when the thread picks up the command,
+        // so that there is no window where a search is queued but is_running() returns false.
+        self.stop_conditions.set_running(true);
+
+        // Any maintenance still queued is done before the search starts, but the clock is already running
+        let go_time = Instant::now();
+
+        // Give new position to thread. Without a thread to search, the GUI still gets the bestmove it
+        // waits for.
+        if !self.send(ThreadCommand::Go(*position, halfmove_clock, game_history.to_vec(), go_time, excluded_moves, search_moves))
+        {
+            self.stop_conditions.set_running(false);
+            output::send("bestmove 0000");
         }
     }
 
-    pub fn go(&mut self, position: &chess::Board, stop_conditions: StopConditions)
+    /// Stop the running search, if any, for a new go command, and wait until it has finished
+    ///
+    /// A normal search answers with its best move as usual, but a ponder search that was neither hit
+    /// nor stopped was never asked for a move.
+    pub fn supersede(&mut self)
     {
-        // If search is running, get it to stop
         if self.is_running()
         {
+            let is_stopped = self.stop_conditions.stop_now.load(Ordering::Acquire);
+            if self.stop_conditions.ponder.load(Ordering::Acquire) && !is_stopped {
+                self.stop_conditions.superseded.store(true, Ordering::Release);
+            }
             self.set_stop_now(true);
+            self.wait_until_idle();
+        }
+    }
+
+    pub fn stop(&mut self)
+    {
+        self.set_stop_now(true);
+    }
+
+    /// The opponent played the expected move, so turn the ponder search into a normal search. Its time
+    /// limits count from now.
+    pub fn ponderhit(&mut self)
+    {
+        self.stop_conditions.ponderhit();
+    }
+
+    /// Clear the hash table
+    ///
+    /// If a search is running, the hash table gets cleared once it has finished.
+    pub fn clear_hash(&mut self)
+    {
+        if self.is_running() {
+            output::send("info string Search is running, hash will be cleared when it finishes");
+            self.maintenance_queued = true;
+        }
+        self.send(ThreadCommand::ClearHash);
+    }
+
+    /// Replace the hash table with an empty one of another size
+    ///
+    /// Like clearing, this happens once a running search has finished. Allocating a large table takes
+    /// a while, so GUIs should wait for `isready` before starting a search.
+    pub fn resize_hash(&mut self, megabytes: usize)
+    {
+        if self.is_running() {
+            output::send("info string Search is running, hash will be resized when it finishes");
+            self.maintenance_queued = true;
+        }
+        self.send(ThreadCommand::ResizeHash(megabytes));
+    }
+
+    /// Start a new game announced with ucinewgame: clear the hash table, and forget about the previous
+    /// searches
+    ///
+    /// If a search is running, this happens once it has finished.
+    pub fn new_game(&mut self)
+    {
+        if self.is_running() {
+            output::send("info string Search is running, hash will be cleared when it finishes");
+            self.maintenance_queued = true;
+        }
+        self.send(ThreadCommand::NewGame);
+    }
+
+    /// Prepare for a new game that was not announced with ucinewgame
+    ///
+    /// This is milder than an explicit new game: rather than clearing the hash table, the entries of the
+    /// previous game are aged so that they get purged first.
+    pub fn implicit_new_game(&mut self)
+    {
+        self.send(ThreadCommand::ImplicitNewGame);
+    }
+
+    /// Wait for the search thread to finish any queued maintenance work, such as clearing the hash
+    ///
+    /// If a search is running, this returns immediately, so as not to block until the search ends.
+    pub fn wait_until_ready(&mut self)
+    {
+        if self.is_running() {
+            return;
+        }
 
-            // Wait for it to stop
-            while self.is_running()
+        let (sender, receiver) = mpsc::channel();
+        if !self.send(ThreadCommand::Ping(sender)) {
+            return;
+        }
+        let mut waited = time::Duration::ZERO;
+        loop
+        {
+            match receiver.recv_timeout(RESPONSE_TIMEOUT)
             {
-                std::hint::spin_loop();
-                std::thread::yield_now();
+                Ok(()) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    waited += RESPONSE_TIMEOUT;
+                    output::send(format_args!("info string Search thread is still busy after {} s", waited.as_secs()));
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    output::send("info string Search thread didn't respond");
+                    break;
+                }
             }
         }
+    }
 
-        // Set new stop parameters
-        self.set_stop_now(false);
-        self.stop_conditions.assign(stop_conditions);
-        
-        // Give new position to thread
-        self.channel.send(ThreadCommand::Go(*position))
-            .expect("channel mustn't close");
+    /// Answer isready with readyok once the queued maintenance work is done
+    ///
+    /// During a search, readyok comes right away, unless maintenance like clearing the hash was queued
+    /// behind the search. Then the search thread answers once it has done that work, so that readyok
+    /// still means the hash is clear, and the commands that stop the search can be read meanwhile.
+    pub fn answer_isready(&mut self)
+    {
+        if self.is_running()
+        {
+            // Without a search thread to answer, nobody does the maintenance either
+            if !self.maintenance_queued || !self.send(ThreadCommand::ReadyOk) {
+                output::send("readyok");
+            }
+            return;
+        }
+
+        self.wait_until_ready();
+        output::send("readyok");
     }
 
-    pub fn stop(&mut self)
+    /// Look up a position in the hash table of the search thread
+    ///
+    /// Returns `None` if a search is running, since the search thread can't answer until it has
+    /// finished. Otherwise returns the hash table entry for the position, if there is one.
+    pub fn probe(&mut self, position: &chess::Board) -> Option<Option<ProbeResult>>
     {
-        self.set_stop_now(true);
+        if self.is_running() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.send(ThreadCommand::Probe(*position, sender));
+        // Without a search thread, there is no hash table to find the position in
+        Some(receiver.recv().unwrap_or(None))
+    }
+
+    /// Export the hash entries of at least `min_depth` for positions within `max_plies` of `position` to
+    /// an EPD file
+    ///
+    /// Returns `None` if a search is running, like `probe`.
+    pub fn export_tree(&mut self, position: &chess::Board, path: PathBuf, min_depth: Depth, max_plies: usize) -> Option<io::Result<ExportStats>>
+    {
+        if self.is_running() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.send(ThreadCommand::ExportTree(*position, path, min_depth, max_plies, sender));
+        Some(receiver.recv().unwrap_or_else(|_| Err(io::Error::other("search thread didn't respond"))))
+    }
+
+    /// Write a snapshot of the hash table to a file, and return the number of entries written
+    ///
+    /// Returns `None` if a search is running, like `probe`.
+    pub fn save_hash(&mut self, path: PathBuf) -> Option<io::Result<usize>>
+    {
+        if self.is_running() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.send(ThreadCommand::SaveHash(path, sender));
+        Some(receiver.recv().unwrap_or_else(|_| Err(io::Error::other("search thread didn't respond"))))
+    }
+
+    /// Load a snapshot written by `save_hash` into the hash table, and return the number of entries
+    /// loaded
+    ///
+    /// Returns `None` if a search is running, like `probe`.
+    pub fn load_hash(&mut self, path: PathBuf) -> Option<io::Result<usize>>
+    {
+        if self.is_running() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.send(ThreadCommand::LoadHash(path, sender));
+        Some(receiver.recv().unwrap_or_else(|_| Err(io::Error::other("search thread didn't respond"))))
+    }
+
+    /// Save snapshots of the hash table to `path` every `interval` while searching, or stop saving
+    /// them if `path` is `None`. Takes effect from the next search.
+    pub fn set_autosave(&mut self, path: Option<PathBuf>, interval: time::Duration)
+    {
+        self.send(ThreadCommand::SetAutosave(path, interval));
+    }
+
+    /// Set the notation of the PV in info lines. Takes effect from the next search.
+    pub fn set_pv_notation(&mut self, pv_notation: PvNotation)
+    {
+        self.send(ThreadCommand::SetPvNotation(pv_notation));
+    }
+
+    /// Turn adaptive tuning of late move reductions on or off. Takes effect from the next search.
+    pub fn set_adaptive_lmr(&mut self, adaptive_lmr: bool)
+    {
+        self.send(ThreadCommand::SetAdaptiveLmr(adaptive_lmr));
+    }
+
+    /// For the next search, play a move up to `margin` centipawns worse than the best one, picked by
+    /// `seed`, or the best move if `margin` is zero
+    pub fn set_opening_variety(&mut self, margin: i16, seed: u64)
+    {
+        self.send(ThreadCommand::SetOpeningVariety(margin, seed));
+    }
+
+    /// Turn the hanging piece term of the evaluation on or off. Takes effect from the next search.
+    pub fn set_hanging_piece_eval(&mut self, hanging_piece: bool)
+    {
+        self.send(ThreadCommand::SetHangingPieceEval(hanging_piece));
+    }
+
+    /// Evaluate and prune with tuned values. Takes effect from the next search.
+    pub fn set_params(&mut self, params: EngineParams)
+    {
+        self.send(ThreadCommand::SetParams(params));
+    }
+
+    /// Turn checking of the boards and hash moves in the search on or off. Takes effect from the next
+    /// search.
+    pub fn set_validate_boards(&mut self, validate_boards: bool)
+    {
+        self.send(ThreadCommand::SetValidateBoards(validate_boards));
+    }
+
+    /// Report the scores of info lines from White's point of view or not. Takes effect from the next
+    /// search.
+    pub fn set_report_white_relative(&mut self, report_white_relative: bool)
+    {
+        self.send(ThreadCommand::SetReportWhiteRelative(report_white_relative));
+    }
+
+    /// Report the expected wins, draws and losses with the scores of info lines or not. Takes effect
+    /// from the next search.
+    pub fn set_show_wdl(&mut self, show_wdl: bool)
+    {
+        self.send(ThreadCommand::SetShowWdl(show_wdl));
+    }
+
+    /// Evaluate with a network, or with the classical evaluation if `None`. Takes effect from the next
+    /// search.
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<Network>>)
+    {
+        self.send(ThreadCommand::SetNetwork(network));
+    }
+
+    /// Turn the extra info strings of UCI debug mode on or off. Takes effect from the next search.
+    pub fn set_debug(&mut self, debug: bool)
+    {
+        self.send(ThreadCommand::SetDebug(debug));
+    }
+
+    /// Search with this many threads from the next search on, see `Searcher::set_threads`
+    pub fn set_threads(&mut self, threads: usize)
+    {
+        self.send(ThreadCommand::SetThreads(threads));
+    }
+
+    /// Pin the scores of `overrides` to their positions, see `Searcher::set_overrides`. Takes effect
+    /// from the next search.
+    pub fn set_overrides(&mut self, overrides: EvalOverrides)
+    {
+        self.send(ThreadCommand::SetOverrides(Arc::new(overrides)));
+    }
+
+    /// Send a command to the search thread
+    ///
+    /// If the thread has died, a new one is started in its place and gets the command, so that the
+    /// engine keeps responding. The new thread starts out with a new hash table and default options,
+    /// but keeps the evaluator.
+    /// Returns whether the command could be sent.
+    fn send(&mut self, command: ThreadCommand) -> bool
+    {
+        let Err(mpsc::SendError(command)) = self.channel.send(command) else {
+            return true;
+        };
+
+        output::send("info string Search thread has stopped unexpectedly, restarting it");
+        if let Some(join_handle) = self.join_handle.take() {
+            // The thread has exited already, and any panic has been reported as it happened
+            let _ = join_handle.join();
+        }
+        match spawn_search_thread(&self.stop_conditions, self.evaluator.clone())
+        {
+            Ok((join_handle, channel)) => {
+                self.join_handle = Some(join_handle);
+                self.channel = channel;
+            }
+            Err(e) => {
+                output::error(format_args!("Could not restart the search thread: {e}"));
+                return false;
+            }
+        }
+
+        // The thread may have died in the middle of a search, and never marked it as finished
+        self.stop_conditions.set_running(matches!(command, ThreadCommand::Go(..)));
+        self.channel.send(command).is_ok()
     }
 
     fn is_running(&mut self) -> bool
     {
-        self.stop_conditions.is_running.load(Ordering::Acquire)
+        self.stop_conditions.is_running()
+    }
+
+    /// Block until the search thread has finished its search, without using the processor meanwhile
+    ///
+    /// Every `RESPONSE_TIMEOUT`, this reports that it is still waiting. If the search thread has died
+    /// in the middle of the search instead, the search is marked as finished, and the next command
+    /// starts a new thread, see `send`.
+    fn wait_until_idle(&mut self)
+    {
+        let mut waited = time::Duration::ZERO;
+        while !self.stop_conditions.wait_until_idle(RESPONSE_TIMEOUT)
+        {
+            if self.join_handle.as_ref().is_some_and(|join_handle| join_handle.is_finished())
+            {
+                output::send("info string Search thread has stopped unexpectedly during the search");
+                self.stop_conditions.set_running(false);
+                return;
+            }
+            waited += RESPONSE_TIMEOUT;
+            output::send(format_args!("info string Search has not stopped after {} s, still waiting", waited.as_secs()));
+        }
     }
 
     fn set_stop_now(&mut self, value: bool)
     {
-        self.stop_conditions.stop_now.store(value, Ordering::Release);
+        if value {
+            self.stop_conditions.stop();
+        } else {
+            self.stop_conditions.stop_now.store(false, Ordering::Release);
+        }
     }
 }
 
@@ -80,23 +494,94 @@ impl Drop for SearchInterface
         // Stop search on thread, if ongoing
         self.set_stop_now(true);
 
-        // Ask thread to exit
-        self.channel.send(ThreadCommand::Exit).expect("channel mustn't close");
+        // Ask thread to exit, unless it has died already
+        let _ = self.channel.send(ThreadCommand::Exit);
+
+        // A search thread that doesn't stop must not keep the engine from quitting. It still borrows
+        // the stop conditions, so they are leaked rather than dropped under it.
+        let has_died = self.join_handle.as_ref().is_none_or(|join_handle| join_handle.is_finished());
+        if !has_died && !self.stop_conditions.wait_until_idle(QUIT_TIMEOUT)
+        {
+            output::error(format_args!("The search did not stop within {} ms, quitting without it", QUIT_TIMEOUT.as_millis()));
+            Box::leak(std::mem::replace(&mut self.stop_conditions, Box::new(StopConditions::new())));
+            return;
+        }
 
-        // Wait for thread to terminate
-        let join_handle = self.join_handle.take().expect("no join handle?");
-        join_handle.join().expect("search thread panic");
+        // Wait for thread to terminate. If it panicked, that has been reported as it happened.
+        if let Some(join_handle) = self.join_handle.take() {
+            let _ = join_handle.join();
+        }
 
         // We can now proceed with dropping the channel and stop conditions box
     }
 }
 
-fn search_thread_mainloop(channel: mpsc::Receiver<ThreadCommand>, stop_conditions: &StopConditions)
+fn spawn_search_thread(
+    stop_conditions: &StopConditions,
+    evaluator: Option<SharedEvaluator>,
+) -> io::Result<(thread::JoinHandle<()>, mpsc::Sender<ThreadCommand>)>
 {
-    let mut searcher = Searcher::new(stop_conditions);
-    loop {
-        match channel.recv().expect("channel mustn't close") {
-            ThreadCommand::Go(position) => searcher.search(position),
+    // SAFETY: Artificially prolonging the lifetime of the borrow. This is sound so long as I make
+    // sure to join with the thread before dropping the box that SearchInterface keeps the stop
+    // conditions in.
+    let borrowed_stop_conditions = unsafe { &*(stop_conditions as *const _) };
+    let (sender, receiver) = mpsc::channel();
+    let join_handle = thread::Builder::new()
+        .name("search thread".into())
+        .spawn(move || search_thread_mainloop(receiver, borrowed_stop_conditions, evaluator))?;
+    Ok((join_handle, sender))
+}
+
+fn search_thread_mainloop(
+    channel: mpsc::Receiver<ThreadCommand>,
+    stop_conditions: &StopConditions,
+    evaluator: Option<SharedEvaluator>,
+)
+{
+    let mut searcher = match evaluator
+    {
+        Some(evaluator) => Searcher::with_evaluator(stop_conditions, evaluator),
+        None => Searcher::new(stop_conditions),
+    };
+    // The channel only closes when the SearchInterface is gone, and then there is nothing left to do.
+    // Whoever asked for an answer may have given up waiting, so failing to send it is fine.
+    while let Ok(command) = channel.recv() {
+        match command {
+            ThreadCommand::Go(position, halfmove_clock, game_history, go_time, excluded_moves, search_moves) => {
+                searcher.set_game_history(game_history);
+                searcher.set_go_time(go_time);
+                searcher.set_excluded_moves(excluded_moves);
+                searcher.set_search_moves(search_moves);
+                searcher.search(position, halfmove_clock);
+                stop_conditions.set_running(false);
+            }
+            ThreadCommand::ClearHash => searcher.clear_hash(),
+            ThreadCommand::ResizeHash(megabytes) => searcher.resize_hash(megabytes),
+            ThreadCommand::NewGame => searcher.new_game(),
+            ThreadCommand::ImplicitNewGame => searcher.implicit_new_game(),
+            ThreadCommand::Ping(sender) => { let _ = sender.send(()); }
+            ThreadCommand::ReadyOk => output::send("readyok"),
+            ThreadCommand::Probe(position, sender) => { let _ = sender.send(searcher.probe(&position)); }
+            ThreadCommand::ExportTree(position, path, min_depth, max_plies, sender) => {
+                let result = searcher.export_tree(&position, &path, min_depth, max_plies);
+                let _ = sender.send(result);
+            }
+            ThreadCommand::SaveHash(path, sender) => { let _ = sender.send(searcher.save_hash(&path)); }
+            ThreadCommand::LoadHash(path, sender) => { let _ = sender.send(searcher.load_hash(&path)); }
+            ThreadCommand::SetAutosave(path, interval) => searcher.set_autosave(path, interval),
+            ThreadCommand::SetPvNotation(pv_notation) => searcher.set_pv_notation(pv_notation),
+            ThreadCommand::SetAdaptiveLmr(adaptive_lmr) => searcher.set_adaptive_lmr(adaptive_lmr),
+            ThreadCommand::SetOpeningVariety(margin, seed) => searcher.set_opening_variety(margin, seed),
+            ThreadCommand::SetHangingPieceEval(hanging_piece) => searcher.set_hanging_piece_eval(hanging_piece),
+            ThreadCommand::SetParams(params) => searcher.set_params(params),
+            ThreadCommand::SetValidateBoards(validate_boards) => searcher.set_validate_boards(validate_boards),
+            ThreadCommand::SetReportWhiteRelative(report_white_relative) => searcher.set_report_white_relative(report_white_relative),
+            ThreadCommand::SetShowWdl(show_wdl) => searcher.set_show_wdl(show_wdl),
+            #[cfg(feature = "nnue")]
+            ThreadCommand::SetNetwork(network) => searcher.set_network(network),
+            ThreadCommand::SetDebug(debug) => searcher.set_debug(debug),
+            ThreadCommand::SetThreads(threads) => searcher.set_threads(threads),
+            ThreadCommand::SetOverrides(overrides) => searcher.set_overrides(overrides),
             ThreadCommand::Exit => break,
         }
     }
@@ -104,33 +589,234 @@ fn search_thread_mainloop(channel: mpsc::Receiver<ThreadCommand>, stop_condition
 
 enum ThreadCommand
 {
-    Go(chess::Board),
+    Go(chess::Board, u32, Vec<u64>, Instant, Vec<chess::ChessMove>, Vec<chess::ChessMove>),
+    ClearHash,
+    ResizeHash(usize),
+    NewGame,
+    ImplicitNewGame,
+    Ping(mpsc::Sender<()>),
+    ReadyOk,
+    Probe(chess::Board, mpsc::Sender<Option<ProbeResult>>),
+    ExportTree(chess::Board, PathBuf, Depth, usize, mpsc::Sender<io::Result<ExportStats>>),
+    SaveHash(PathBuf, mpsc::Sender<io::Result<usize>>),
+    LoadHash(PathBuf, mpsc::Sender<io::Result<usize>>),
+    SetAutosave(Option<PathBuf>, time::Duration),
+    SetPvNotation(PvNotation),
+    SetAdaptiveLmr(bool),
+    SetOpeningVariety(i16, u64),
+    SetHangingPieceEval(bool),
+    SetParams(EngineParams),
+    SetValidateBoards(bool),
+    SetReportWhiteRelative(bool),
+    SetShowWdl(bool),
+    #[cfg(feature = "nnue")]
+    SetNetwork(Option<Arc<Network>>),
+    SetDebug(bool),
+    SetThreads(usize),
+    SetOverrides(Arc<EvalOverrides>),
     Exit,
 }
 
-pub struct StopConditions
+#[cfg(test)]
+mod test
 {
-    pub is_running: AtomicBool,
-    pub stop_now: AtomicBool,
-    pub depth: AtomicU8,
-    pub movetime: AtomicU32,
-}
+    use std::str::FromStr;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
 
-impl StopConditions
-{
-    pub fn new() -> Self
+    use super::*;
+
+    #[test]
+    fn test_probe_after_search()
+    {
+        let position = chess::Board::from_str("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1").unwrap();
+        let mut search_interface = SearchInterface::new();
+
+        let stop_conditions = StopConditions::new();
+        *stop_conditions.depth.get_mut() = 8;
+        search_interface.go(&position, 0, &[], stop_conditions);
+        while search_interface.is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let root = search_interface.probe(&position)
+            .expect("search has finished")
+            .expect("root should be in the hash table");
+        assert!(root.depth >= 7, "root has depth {}", root.depth);
+
+        let first_move = root.best_move.expect("root should have a best move");
+        let child = search_interface.probe(&position.make_move_new(first_move))
+            .expect("search has finished")
+            .expect("position after the first PV move should be in the hash table");
+        assert!(child.depth >= 7, "child has depth {}", child.depth);
+    }
+
+    #[test]
+    fn test_search_thread_restarts()
     {
-        StopConditions {
-            stop_now: AtomicBool::new(false),
-            is_running: AtomicBool::new(false),
-            depth: AtomicU8::new(255),
-            movetime: AtomicU32::new(0),
+        let position = chess::Board::default();
+        let mut search_interface = SearchInterface::new();
+
+        // Make the thread go away behind the interface's back
+        search_interface.channel.send(ThreadCommand::Exit).unwrap();
+        while !search_interface.join_handle.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let stop_conditions = StopConditions::new();
+        *stop_conditions.depth.get_mut() = 3;
+        search_interface.go(&position, 0, &[], stop_conditions);
+        while search_interface.is_running() {
+            thread::sleep(Duration::from_millis(10));
         }
+        search_interface.wait_until_ready();
+
+        let root = search_interface.probe(&position)
+            .expect("search has finished")
+            .expect("the new thread should have searched the root");
+        assert_eq!(root.depth, 3);
     }
 
-    pub fn assign(&self, new: Self)
+    #[test]
+    fn test_export_tree_after_search()
     {
-        self.depth.store(new.depth.into_inner(), Ordering::Release);
-        self.movetime.store(new.movetime.into_inner(), Ordering::Release);
+        // Winning the queen on a8, after which the rook can't mate within the horizon
+        let position = chess::Board::from_str("q6k/8/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut search_interface = SearchInterface::new();
+
+        let stop_conditions = StopConditions::new();
+        *stop_conditions.depth.get_mut() = 10;
+        search_interface.go(&position, 0, &[], stop_conditions);
+        while search_interface.is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let path = std::env::temp_dir().join(format!("exporttree-test-{}.epd", std::process::id()));
+        let stats = search_interface.export_tree(&position, path.clone(), 0, 3)
+            .expect("search has finished")
+            .expect("export should succeed");
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.lines().count(), stats.written);
+
+        let depth_of = |position: &chess::Board| {
+            let epd = position.to_string().split_ascii_whitespace().take(4).collect::<Vec<_>>().join(" ");
+            let line = contents.lines()
+                .find(|l| l.starts_with(&format!("{epd} ce ")))
+                .unwrap_or_else(|| panic!("{epd} is missing from the export"));
+            let acd = line.split(';').find_map(|op| op.trim().strip_prefix("acd ")).unwrap();
+            (line.to_owned(), acd.parse::<Depth>().unwrap())
+        };
+
+        let (root_line, root_depth) = depth_of(&position);
+        assert_eq!(root_depth, 10);
+        assert!(root_line.contains("bm Rxa8"), "{root_line}");
+
+        // The positions along the PV were searched one ply shallower each
+        let mut pv_position = position;
+        for ply in 1..=3
+        {
+            let best_move = search_interface.probe(&pv_position)
+                .expect("search has finished")
+                .and_then(|probe| probe.best_move)
+                .expect("PV position should have a best move");
+            pv_position = pv_position.make_move_new(best_move);
+            let (line, depth) = depth_of(&pv_position);
+            assert!(depth >= 10 - ply, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_ponderhit_time_accounting()
+    {
+        let mut search_interface = SearchInterface::new();
+
+        let stop_conditions = StopConditions::new();
+        *stop_conditions.movetime.get_mut() = 1000;
+        *stop_conditions.ponder.get_mut() = true;
+        search_interface.go(&chess::Board::default(), 0, &[], stop_conditions);
+
+        // Longer than the movetime, so that counting from the start of pondering would stop the search
+        // right at ponderhit
+        thread::sleep(Duration::from_millis(1200));
+        assert!(search_interface.is_running(), "ponder search must not stop on its own");
+
+        let ponderhit_time = std::time::Instant::now();
+        search_interface.ponderhit();
+        while search_interface.is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The full budget counts from ponderhit, not from the start of pondering. A loaded machine may
+        // stop the search late, so the upper bound only catches a search that doesn't stop at all.
+        let elapsed = ponderhit_time.elapsed().as_millis();
+        assert!(elapsed >= 900, "search ran for only {elapsed} ms after ponderhit");
+        assert!(elapsed < 10_000, "search ran for {elapsed} ms after ponderhit");
+    }
+
+    /// Processor time that the calling thread has used so far, in clock ticks of 10 ms
+    #[cfg(target_os = "linux")]
+    fn thread_cpu_time() -> Duration
+    {
+        let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+        // The name of the thread is in parentheses and may contain spaces, so count the fields after it.
+        // The user and system times are the 14th and 15th fields.
+        let fields = stat.rsplit_once(')').unwrap().1.split_ascii_whitespace().collect::<Vec<_>>();
+        let ticks = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+        Duration::from_millis(10 * ticks)
+    }
+
+    /// A measurement rather than a check: it keeps every core busy, so it competes with the other tests
+    /// and is only meaningful on its own, with `cargo test test_go_handover_under_load -- --ignored`
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[ignore = "saturates every core, run on its own"]
+    fn test_go_handover_under_load()
+    {
+        // Keep every core busy, so that the search thread has to compete for the processor
+        let is_loaded = AtomicBool::new(true);
+        let (mut handovers, waited, cpu_time) = thread::scope(|scope| {
+            let cores = thread::available_parallelism().map_or(2, |cores| cores.get());
+            for _ in 0..cores
+            {
+                scope.spawn(|| {
+                    let mut counter = 0u64;
+                    while is_loaded.load(Ordering::Relaxed) {
+                        counter = std::hint::black_box(counter.wrapping_add(1));
+                    }
+                });
+            }
+
+            // Every go stops the search of the one before, and waits for its bestmove
+            let mut search_interface = SearchInterface::new();
+            let position = chess::Board::default();
+            search_interface.go(&position, 0, &[], StopConditions::new());
+            let mut handovers = Vec::new();
+            for _ in 0..10
+            {
+                thread::sleep(Duration::from_millis(50));
+                let starttime = Instant::now();
+                search_interface.go(&position, 0, &[], StopConditions::new());
+                handovers.push(starttime.elapsed());
+            }
+
+            // Then wait for a search to finish on its own, measuring the processor time that this
+            // thread uses meanwhile
+            let stop_conditions = StopConditions::new();
+            *stop_conditions.movetime.get_mut() = 1000;
+            search_interface.go(&position, 0, &[], stop_conditions);
+            let (starttime, cpu_starttime) = (Instant::now(), thread_cpu_time());
+            search_interface.wait_until_idle();
+            let measurements = (handovers, starttime.elapsed(), thread_cpu_time() - cpu_starttime);
+
+            is_loaded.store(false, Ordering::Relaxed);
+            measurements
+        });
+
+        handovers.sort();
+        assert!(handovers[handovers.len() / 2] < Duration::from_millis(20), "handovers took {handovers:?}");
+        // Waiting doesn't spin, so it takes no processor time of its own
+        assert!(waited >= Duration::from_millis(500), "waited {waited:?}");
+        assert!(cpu_time <= Duration::from_millis(50), "used {cpu_time:?} of processor time waiting {waited:?}");
     }
 }
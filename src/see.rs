@@ -0,0 +1,168 @@
+//! Static exchange evaluation: what a sequence of captures on a single square wins or loses, judged
+//! from the pieces attacking it rather than by searching
+//!
+//! The evaluation is of whatever piece stands on the square, so it applies to quiet moves as well as
+//! captures. After a move, the exchange gain for the opponent on the destination square is how much
+//! the move hangs. Pins are not taken into account.
+
+use chess::{BitBoard, Board, ChessMove, Color, Piece, Square};
+use crate::boardutil::PositionExt;
+use crate::values::exchange_value;
+
+/// How much material `chess_move` wins, or loses if negative, once the exchange on its destination
+/// square has played out. The move must be legal in `board`.
+///
+/// ```
+/// use std::str::FromStr;
+/// use engine::chess::{Board, ChessMove};
+///
+/// // The rook wins the undefended queen
+/// let board = Board::from_str("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+/// assert_eq!(engine::see(&board, ChessMove::from_str("d1d5").unwrap()), 900);
+///
+/// // The queen wins a rook, but the pawn takes her back
+/// let board = Board::from_str("4k3/8/2p5/3r4/8/8/8/3QK3 w - - 0 1").unwrap();
+/// assert_eq!(engine::see(&board, ChessMove::from_str("d1d5").unwrap()), -400);
+/// ```
+pub fn move_gain(board: &Board, chess_move: ChessMove) -> i16
+{
+    let captured = match board.piece_on(chess_move.get_dest())
+    {
+        Some(piece) => exchange_value(piece),
+        // En passant
+        None if board.is_capture(chess_move) => exchange_value(Piece::Pawn),
+        None => 0,
+    };
+    let promotion = chess_move.get_promotion().map_or(0, |piece| exchange_value(piece) - exchange_value(Piece::Pawn));
+    let after = board.make_move_new(chess_move);
+    captured + promotion - exchange_gain(&after, chess_move.get_dest())
+}
+
+/// How much material the side to move can win by capturing the piece on `square`, recapturing with
+/// the least valuable piece each time. Either side may stop capturing whenever it likes, so this is
+/// never negative, and it is zero if the square is empty or capturing doesn't pay.
+pub fn exchange_gain(board: &Board, square: Square) -> i16
+{
+    let Some(target) = board.piece_on(square) else {
+        return 0;
+    };
+
+    let mut occupied = *board.combined();
+    let mut side = board.side_to_move();
+    let Some((mut from, mut attacker)) = least_valuable_attacker(board, square, occupied, side) else {
+        return 0;
+    };
+
+    // gains[n] is the balance for the side making capture n + 1, if the exchange stops after it
+    let mut gains = [0; 32];
+    gains[0] = exchange_value(target);
+    let mut captures = 0;
+    loop
+    {
+        captures += 1;
+        // Speculatively, until it turns out whether the other side can recapture
+        gains[captures] = exchange_value(attacker) - gains[captures - 1];
+
+        // Removing the capturing piece may uncover a slider behind it
+        occupied ^= BitBoard::from_square(from);
+        side = !side;
+        match least_valuable_attacker(board, square, occupied, side)
+        {
+            Some((next_from, next_attacker)) => {
+                from = next_from;
+                attacker = next_attacker;
+            }
+            None => break,
+        }
+    }
+
+    // Going backwards, each side chooses between capturing and stopping. The last entry is for a
+    // recapture that turned out not to exist, and is skipped.
+    while captures > 1
+    {
+        captures -= 1;
+        gains[captures - 1] = -(-gains[captures - 1]).max(gains[captures]);
+    }
+
+    gains[0].max(0)
+}
+
+/// The least valuable piece of `color` among `occupied` that attacks `square`
+fn least_valuable_attacker(board: &Board, square: Square, occupied: BitBoard, color: Color) -> Option<(Square, Piece)>
+{
+    let own = board.color_combined(color) & occupied;
+    let diagonal = chess::get_bishop_moves(square, occupied);
+    let straight = chess::get_rook_moves(square, occupied);
+
+    let attacks = [
+        (Piece::Pawn, chess::get_pawn_attacks(square, !color, !chess::EMPTY)),
+        (Piece::Knight, chess::get_knight_moves(square)),
+        (Piece::Bishop, diagonal),
+        (Piece::Rook, straight),
+        (Piece::Queen, diagonal | straight),
+        (Piece::King, chess::get_king_moves(square)),
+    ];
+
+    attacks.into_iter()
+        .map(|(piece, attacks)| (piece, attacks & board.pieces(piece) & own))
+        .find(|&(_, attackers)| attackers != chess::EMPTY)
+        .map(|(piece, attackers)| (attackers.to_square(), piece))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn gain(fen: &str, square: &str) -> i16
+    {
+        exchange_gain(&Board::from_str(fen).unwrap(), Square::from_str(square).unwrap())
+    }
+
+    #[test]
+    fn test_exchange_gain()
+    {
+        // Undefended knight
+        assert_eq!(gain("4k3/8/8/3n4/8/8/3R4/4K3 w - - 0 1", "d5"), 300);
+        // Knight defended by a pawn, the rook would be lost for it
+        assert_eq!(gain("4k3/8/4p3/3n4/8/8/3R4/4K3 w - - 0 1", "d5"), 0);
+        // Rook defended by a pawn, but taking with a pawn still wins the exchange
+        assert_eq!(gain("4k3/8/4p3/3r4/2P5/8/8/4K3 w - - 0 1", "d5"), 400);
+        // Nothing attacks the square, or there is nothing on it
+        assert_eq!(gain("4k3/8/8/3n4/8/8/8/4K3 w - - 0 1", "d5"), 0);
+        assert_eq!(gain("4k3/8/8/8/8/8/3R4/4K3 w - - 0 1", "d5"), 0);
+    }
+
+    #[test]
+    fn test_move_gain()
+    {
+        let gain_of = |fen: &str, chess_move: &str| move_gain(&Board::from_str(fen).unwrap(), ChessMove::from_str(chess_move).unwrap());
+
+        // En passant, promotion, and a quiet move that hangs the knight to a pawn
+        assert_eq!(gain_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(gain_of("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(gain_of("4k3/8/4p3/8/8/2N5/8/4K3 w - - 0 1", "c3d5"), -300);
+        assert_eq!(gain_of("4k3/8/8/8/8/2N5/8/4K3 w - - 0 1", "c3d5"), 0);
+    }
+
+    #[test]
+    fn test_exchange_gain_x_rays()
+    {
+        // The queen behind the rook joins in once the rook has captured, so the pawn falls for free
+        assert_eq!(gain("4k3/3r4/8/3p4/8/8/3R4/3QK3 w - - 0 1", "d5"), 100);
+        // Without the queen, the rook would be lost for the pawn
+        assert_eq!(gain("4k3/3r4/8/3p4/8/8/3R4/4K3 w - - 0 1", "d5"), 0);
+    }
+
+    #[test]
+    fn test_exchange_gain_of_quiet_move()
+    {
+        // After Nd5 the knight hangs to the pawn on e6, as far as exchanges can tell. The pawn is
+        // actually pinned, which the exchange evaluation doesn't know about.
+        let position = Board::from_str("4k3/2r5/4pr2/8/1N6/8/8/4R2K w - - 0 1").unwrap()
+            .make_move_new(chess::ChessMove::from_str("b4d5").unwrap());
+        assert_eq!(exchange_gain(&position, Square::from_str("d5").unwrap()), 300);
+    }
+}
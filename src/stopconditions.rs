@@ -0,0 +1,216 @@
+//! The limits of a search, which the UCI client or another host sets and the search checks as it goes
+//!
+//! The search reads them without locking, so that a stop or a ponderhit from another thread takes
+//! effect within a few thousand nodes. Whoever runs the search marks it as running or finished, and
+//! others can wait for it to finish, see `set_running` and `wait_until_idle`. The other way round, a
+//! search that holds back its best move waits for a stop or a ponderhit, see `wait_while_held`.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time;
+
+pub struct StopConditions
+{
+    /// Whether a search has been started and hasn't finished yet, see `set_running`
+    is_running: Mutex<bool>,
+    /// Signalled when `is_running` turns false, for waiting on the search without spinning
+    idle: Condvar,
+    /// Signalled by `stop` and `ponderhit`, for a search that holds back its best move, see
+    /// `wait_while_held`
+    released: Condvar,
+    pub stop_now: AtomicBool,
+    pub depth: AtomicU8,
+    pub movetime: AtomicU32,
+    /// No new iteration is started after this many milliseconds. Zero means no limit.
+    pub soft_movetime: AtomicU32,
+    /// The best move is held back until this many milliseconds have passed, unless the search is
+    /// stopped. Zero means that it is sent as soon as the search is done.
+    pub min_movetime: AtomicU32,
+    /// With this set, the depth limit is a minimum rather than a maximum: the search goes on until it
+    /// has completed that depth and searched for this many milliseconds, whichever comes later, and
+    /// then stops, see `search::limits_reached`. Zero means that the depth limit is a maximum.
+    pub min_searchtime: AtomicU32,
+    /// The search stops after this many nodes. Zero means no limit.
+    pub nodes: AtomicU64,
+    /// The search stops once it has found a mate in this many moves or less. Zero means no limit.
+    pub mate: AtomicU32,
+    /// The search is pondering on the opponent's time. Time limits don't apply until ponderhit
+    /// clears this.
+    pub ponder: AtomicBool,
+    /// The search goes on until stop, and holds back the best move until then even if it has nothing
+    /// left to search
+    pub infinite: AtomicBool,
+    /// A new go replaced the running ponder search, which stops without sending bestmove. Set before
+    /// `stop_now`, so the search sees it once it sees that it must stop.
+    pub superseded: AtomicBool,
+}
+
+impl StopConditions
+{
+    pub fn new() -> Self
+    {
+        StopConditions {
+            stop_now: AtomicBool::new(false),
+            is_running: Mutex::new(false),
+            idle: Condvar::new(),
+            released: Condvar::new(),
+            depth: AtomicU8::new(255),
+            movetime: AtomicU32::new(0),
+            soft_movetime: AtomicU32::new(0),
+            min_movetime: AtomicU32::new(0),
+            min_searchtime: AtomicU32::new(0),
+            nodes: AtomicU64::new(0),
+            mate: AtomicU32::new(0),
+            ponder: AtomicBool::new(false),
+            infinite: AtomicBool::new(false),
+            superseded: AtomicBool::new(false),
+        }
+    }
+
+    /// Mark a search as started or finished, waking up whoever waits for it to finish
+    pub fn set_running(&self, is_running: bool)
+    {
+        *self.lock_is_running() = is_running;
+        if !is_running {
+            self.idle.notify_all();
+        }
+    }
+
+    pub fn is_running(&self) -> bool
+    {
+        *self.lock_is_running()
+    }
+
+    /// Wait until no search is running, for at most `timeout`. Returns whether that happened in time.
+    pub fn wait_until_idle(&self, timeout: time::Duration) -> bool
+    {
+        let is_running = self.lock_is_running();
+        let (is_running, _) = self.idle.wait_timeout_while(is_running, timeout, |is_running| *is_running)
+            .unwrap_or_else(PoisonError::into_inner);
+        !*is_running
+    }
+
+    /// Stop the running search, and wake it up if it is holding back its best move
+    pub fn stop(&self)
+    {
+        self.stop_now.store(true, Ordering::Release);
+        self.notify_released();
+    }
+
+    /// Turn a ponder search into a normal search, and wake it up if it is holding back its best move
+    pub fn ponderhit(&self)
+    {
+        self.ponder.store(false, Ordering::Release);
+        self.notify_released();
+    }
+
+    fn notify_released(&self)
+    {
+        // A waiting search checks its condition under the lock, so taking it here means that the search
+        // either sees the new flags or is already waiting when the notification comes
+        let _is_running = self.lock_is_running();
+        self.released.notify_all();
+    }
+
+    /// Wait while `is_held` returns true, until the search is stopped, or for at most `timeout` if
+    /// there is one. `is_held` is checked again after every `stop` and `ponderhit`.
+    pub fn wait_while_held(&self, timeout: Option<time::Duration>, is_held: impl Fn(&Self) -> bool)
+    {
+        let is_running = self.lock_is_running();
+        let condition = |_: &mut bool| is_held(self) && !self.stop_now.load(Ordering::Acquire);
+        match timeout
+        {
+            Some(timeout) => drop(self.released.wait_timeout_while(is_running, timeout, condition)),
+            None => drop(self.released.wait_while(is_running, condition)),
+        }
+    }
+
+    /// The flag stays meaningful even if a thread panicked while holding the lock, since it is only
+    /// ever assigned whole
+    fn lock_is_running(&self) -> MutexGuard<'_, bool>
+    {
+        self.is_running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn assign(&self, new: Self)
+    {
+        self.depth.store(new.depth.into_inner(), Ordering::Release);
+        self.movetime.store(new.movetime.into_inner(), Ordering::Release);
+        self.soft_movetime.store(new.soft_movetime.into_inner(), Ordering::Release);
+        self.min_movetime.store(new.min_movetime.into_inner(), Ordering::Release);
+        self.min_searchtime.store(new.min_searchtime.into_inner(), Ordering::Release);
+        self.nodes.store(new.nodes.into_inner(), Ordering::Release);
+        self.mate.store(new.mate.into_inner(), Ordering::Release);
+        self.ponder.store(new.ponder.into_inner(), Ordering::Release);
+        self.infinite.store(new.infinite.into_inner(), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_assign_copies_every_limit()
+    {
+        let new = StopConditions::new();
+        *new.depth.get_mut() = 20;
+        *new.movetime.get_mut() = 5000;
+        *new.soft_movetime.get_mut() = 2500;
+        *new.min_movetime.get_mut() = 100;
+        *new.min_searchtime.get_mut() = 300;
+        *new.nodes.get_mut() = 1_000_000;
+        *new.mate.get_mut() = 3;
+        *new.ponder.get_mut() = true;
+        *new.infinite.get_mut() = true;
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.assign(new);
+
+        // Naming every field makes a new one fail to compile here until it is added to `assign`. The
+        // flags that stop a running search are set by the UCI client on its own, not assigned by go.
+        let StopConditions {
+            is_running: _,
+            idle: _,
+            released: _,
+            stop_now,
+            depth,
+            movetime,
+            soft_movetime,
+            min_movetime,
+            min_searchtime,
+            nodes,
+            mate,
+            ponder,
+            infinite,
+            superseded,
+        } = stop_conditions;
+        assert_eq!(
+            (depth.into_inner(), movetime.into_inner(), soft_movetime.into_inner(), min_movetime.into_inner(), min_searchtime.into_inner()),
+            (20, 5000, 2500, 100, 300),
+        );
+        assert_eq!((nodes.into_inner(), mate.into_inner()), (1_000_000, 3));
+        assert_eq!((ponder.into_inner(), infinite.into_inner()), (true, true));
+        assert_eq!((stop_now.into_inner(), superseded.into_inner()), (false, false));
+    }
+
+    #[test]
+    fn test_stop_releases_held_search()
+    {
+        let stop_conditions = StopConditions::new();
+        stop_conditions.infinite.store(true, Ordering::Release);
+        let timeout = time::Duration::from_secs(60);
+
+        let starttime = time::Instant::now();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(time::Duration::from_millis(10));
+                stop_conditions.stop();
+            });
+            stop_conditions.wait_while_held(Some(timeout), |stop_conditions| stop_conditions.infinite.load(Ordering::Acquire));
+        });
+        assert!(starttime.elapsed() < timeout);
+        assert!(stop_conditions.stop_now.load(Ordering::Acquire));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::evaluation::GamePhase;
+use crate::rootmoves::BestMoveStability;
+use crate::score::BoardScore;
+
+#[cfg(test)]
+mod simulation;
+
+/// Time that is always left on the clock, to cover for communication delays with the GUI
+const MOVE_OVERHEAD: u32 = 50;
+
+/// Number of moves assumed to remain in the game if the GUI doesn't send movestogo
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// The time control for the side to move, as sent with the `go` command. All times in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeControl
+{
+    pub time_left: u32,
+    pub increment: u32,
+    pub moves_to_go: Option<u32>,
+}
+
+/// The time the search may spend on a move, in milliseconds
+///
+/// No new iteration is started after the `soft` limit, and the search is stopped at the `hard` limit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeAllocation
+{
+    pub soft: u32,
+    pub hard: u32,
+}
+
+/// Decide how much time to spend on the current move
+///
+/// The time left is divided evenly over the remaining moves, and then scaled by the game phase: the
+/// middlegame is where the most can be gained by thinking, while the opening is often well known and
+/// technical endgames are played on a long horizon anyway. Both limits are at least 1 ms, and the hard
+/// limit never exceeds the time left minus `MOVE_OVERHEAD`.
+pub fn allocate_time(time_control: &TimeControl, phase: GamePhase) -> TimeAllocation
+{
+    let time_left = time_control.time_left as u64;
+    let increment = time_control.increment as u64;
+    let moves_to_go = time_control.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1) as u64;
+
+    let phase_percent = match phase
+    {
+        GamePhase::Opening => 70,
+        GamePhase::Middlegame => 120,
+        GamePhase::Endgame => 90,
+    };
+
+    let base = time_left / moves_to_go + 3 * increment / 4;
+    let available = time_left.saturating_sub(MOVE_OVERHEAD as u64).max(1);
+
+    let hard = (3 * base * phase_percent / 100).min(available).max(1);
+    let soft = (base * phase_percent / 100).min(hard).max(1);
+
+    TimeAllocation {
+        soft: soft as u32,
+        hard: hard as u32,
+    }
+}
+
+/// The best move must have lasted this many iterations, and taken this percentage of the nodes, for
+/// the soft limit to be shortened by the corresponding percentage
+const STABILITY_SCALING: [(u32, u64, u64); 2] = [(6, 80, 40), (3, 60, 70)];
+
+/// Shorten the soft limit when the best move has been stable for a while, since more iterations are
+/// unlikely to change it. The hard limit stays as it is.
+pub fn scale_soft_limit(soft: u32, stability: &BestMoveStability) -> u32
+{
+    let percent = STABILITY_SCALING.iter()
+        .find(|&&(iterations, node_share, _)| {
+            stability.stable_iterations >= iterations && stability.node_share_percent >= node_share
+        })
+        .map_or(100, |&(_, _, percent)| percent);
+    (soft as u64 * percent / 100).max(1) as u32
+}
+
+/// When the score has dropped by at least this many centipawns since the previous search, the soft
+/// limit is lengthened to the corresponding percentage
+const SCORE_DROP_SCALING: [(i32, u64); 2] = [(80, 160), (30, 130)];
+
+/// When the score has risen by at least this many centipawns, and the best move has lasted at least
+/// `SCORE_RISE_STABLE_ITERATIONS` iterations, the soft limit is shortened to `SCORE_RISE_PERCENT`
+const SCORE_RISE_MIN: i32 = 50;
+const SCORE_RISE_STABLE_ITERATIONS: u32 = 3;
+const SCORE_RISE_PERCENT: u64 = 90;
+
+/// Percentage to scale the soft limit by, given the final score of the previous search and the score
+/// of the last completed iteration, both for the side to move now
+///
+/// A dropping score means that the opponent's last move was better than expected, and the position
+/// needs more thought before committing to a move. A rising score with a stable best move means that
+/// the opponent went wrong and the reply is clear, so a little time can be saved.
+pub fn score_trend_percent(previous: BoardScore, current: BoardScore, stability: &BestMoveStability) -> u64
+{
+    // Mate scores count as very large evaluations, so that losing a mate is a large drop
+    let change = current.epd_centipawns() - previous.epd_centipawns();
+
+    if let Some(&(_, percent)) = SCORE_DROP_SCALING.iter().find(|&&(drop, _)| -change >= drop) {
+        return percent;
+    }
+    if change >= SCORE_RISE_MIN && stability.stable_iterations >= SCORE_RISE_STABLE_ITERATIONS {
+        return SCORE_RISE_PERCENT;
+    }
+    100
+}
+
+/// Scale the soft limit by `score_trend_percent`, without passing the hard limit, which is a share of
+/// the time left on the clock. A `hard` of zero means that there is no hard limit.
+pub fn scale_for_score_trend(soft: u32, hard: u32, previous: BoardScore, current: BoardScore, stability: &BestMoveStability) -> u32
+{
+    let percent = score_trend_percent(previous, current, stability);
+    let hard = if hard != 0 { hard } else { u32::MAX };
+    (soft as u64 * percent / 100).clamp(1, hard as u64) as u32
+}
+
+/// The soft limit after the iterations so far: shortened by `scale_soft_limit` as the best move
+/// settles, and scaled by `scale_for_score_trend` once there is a `current` score to compare with the
+/// `previous` search
+pub fn scaled_soft_limit(soft: u32, hard: u32, stability: &BestMoveStability, previous: Option<BoardScore>, current: Option<BoardScore>) -> u32
+{
+    let soft = scale_soft_limit(soft, stability);
+    match (previous, current)
+    {
+        (Some(previous), Some(current)) => scale_for_score_trend(soft, hard, previous, current, stability),
+        _ => soft,
+    }
+}
+
+/// Largest variance of the soft limit, in percent either way
+pub const MAX_VARIANCE_PERCENT: u32 = 50;
+
+/// How to make the time spent on moves against the clock less predictable, for bots on online
+/// platforms, where answering instantly or thinking long tells the opponent about the position
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct MoveTimeVariance
+{
+    /// The soft limit varies by up to this percentage either way. Zero means no variance.
+    pub percent: u32,
+    /// No move is played in less than this many milliseconds, as far as the hard limit allows. Zero
+    /// means no floor.
+    pub floor: u32,
+}
+
+/// A time allocation with variance applied, see `vary_allocation`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VariedAllocation
+{
+    pub allocation: TimeAllocation,
+    /// The search holds back its best move until this many milliseconds have passed
+    pub floor: u32,
+}
+
+/// Vary the soft limit of a move played against the clock by a pseudo-random percentage drawn from
+/// `seed`, within `variance.percent` either way, and raise it to `variance.floor`
+///
+/// The same seed always gives the same allocation. The hard limit stays as it is, and neither the
+/// soft limit nor the floor goes beyond it, so the variance never costs more time than the clock
+/// allows.
+pub fn vary_allocation(allocation: TimeAllocation, variance: MoveTimeVariance, seed: u64) -> VariedAllocation
+{
+    let percent = variance.percent.min(MAX_VARIANCE_PERCENT) as i64;
+    let hard = allocation.hard as i64;
+
+    // In hundredths of a percent, from -percent to +percent
+    let jitter = (xorshift(seed) % (2 * percent as u64 * 100 + 1)) as i64 - percent * 100;
+    let soft = allocation.soft as i64 + allocation.soft as i64 * jitter / 10_000;
+    let floor = (variance.floor as i64).min(hard);
+
+    VariedAllocation {
+        allocation: TimeAllocation {
+            soft: soft.max(floor).clamp(1, hard) as u32,
+            hard: allocation.hard,
+        },
+        floor: floor as u32,
+    }
+}
+
+/// The next state of a xorshift64 generator, which must not be zero
+pub fn xorshift(mut state: u64) -> u64
+{
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_allocate_time_phases()
+    {
+        let time_control = TimeControl { time_left: 60_000, increment: 1000, moves_to_go: None };
+
+        let opening = allocate_time(&time_control, GamePhase::Opening);
+        let middlegame = allocate_time(&time_control, GamePhase::Middlegame);
+        let endgame = allocate_time(&time_control, GamePhase::Endgame);
+
+        assert_eq!(middlegame, TimeAllocation { soft: 3300, hard: 9900 });
+        assert!(opening.soft < endgame.soft);
+        assert!(endgame.soft < middlegame.soft);
+        assert!(opening.hard < endgame.hard);
+        assert!(endgame.hard < middlegame.hard);
+    }
+
+    #[test]
+    fn test_allocate_time_limits()
+    {
+        // The last move before the time control may use almost all of the time, but not all of it
+        let time_control = TimeControl { time_left: 1000, increment: 0, moves_to_go: Some(1) };
+        let allocation = allocate_time(&time_control, GamePhase::Middlegame);
+        assert_eq!(allocation.hard, 1000 - MOVE_OVERHEAD);
+        assert!(allocation.soft <= allocation.hard);
+
+        // Nearly flagging still gives a nonzero time, since zero means no limit
+        let time_control = TimeControl { time_left: 10, increment: 0, moves_to_go: None };
+        let allocation = allocate_time(&time_control, GamePhase::Endgame);
+        assert_eq!(allocation, TimeAllocation { soft: 1, hard: 1 });
+
+        // movestogo 0 is treated as 1
+        let time_control = TimeControl { time_left: 5000, increment: 0, moves_to_go: Some(0) };
+        let allocation = allocate_time(&time_control, GamePhase::Opening);
+        assert!(allocation.hard <= 5000 - MOVE_OVERHEAD);
+    }
+
+    #[test]
+    fn test_scale_soft_limit()
+    {
+        let stability = |stable_iterations, node_share_percent| BestMoveStability {
+            best_move: None,
+            stable_iterations,
+            node_share_percent,
+        };
+
+        assert_eq!(scale_soft_limit(1000, &stability(0, 0)), 1000);
+        assert_eq!(scale_soft_limit(1000, &stability(1, 100)), 1000);
+        assert_eq!(scale_soft_limit(1000, &stability(3, 60)), 700);
+        // Stable for long, but the other moves still take a lot of effort
+        assert_eq!(scale_soft_limit(1000, &stability(10, 50)), 1000);
+        assert_eq!(scale_soft_limit(1000, &stability(6, 80)), 400);
+        assert_eq!(scale_soft_limit(1, &stability(6, 80)), 1);
+    }
+
+    #[test]
+    fn test_score_trend_percent()
+    {
+        let stability = |stable_iterations| BestMoveStability {
+            best_move: None,
+            stable_iterations,
+            node_share_percent: 50,
+        };
+        let cp = BoardScore::evaluation;
+
+        // Small changes either way make no difference
+        assert_eq!(score_trend_percent(cp(20), cp(20), &stability(0)), 100);
+        assert_eq!(score_trend_percent(cp(20), cp(-9), &stability(0)), 100);
+        assert_eq!(score_trend_percent(cp(20), cp(60), &stability(5)), 100);
+
+        // Dropping scores get more time, the more they dropped
+        assert_eq!(score_trend_percent(cp(20), cp(-10), &stability(0)), 130);
+        assert_eq!(score_trend_percent(cp(20), cp(-60), &stability(5)), 160);
+        assert_eq!(score_trend_percent(BoardScore::MATE.increment_mate_plies(), cp(500), &stability(5)), 160);
+
+        // Rising scores only save time when the best move is stable
+        assert_eq!(score_trend_percent(cp(20), cp(70), &stability(2)), 100);
+        assert_eq!(score_trend_percent(cp(20), cp(70), &stability(3)), 90);
+    }
+
+    #[test]
+    fn test_scale_for_score_trend()
+    {
+        let stability = BestMoveStability::default();
+        let dropped = (BoardScore::evaluation(0), BoardScore::evaluation(-100));
+
+        assert_eq!(scale_for_score_trend(1000, 3000, dropped.0, dropped.1, &stability), 1600);
+        // Never beyond the hard limit
+        assert_eq!(scale_for_score_trend(1000, 1200, dropped.0, dropped.1, &stability), 1200);
+        assert_eq!(scale_for_score_trend(1000, 0, dropped.0, dropped.1, &stability), 1600);
+        assert_eq!(scale_for_score_trend(1000, 3000, dropped.1, dropped.0, &stability), 1000);
+    }
+
+    #[test]
+    fn test_vary_allocation()
+    {
+        let allocation = TimeAllocation { soft: 2000, hard: 6000 };
+        let variance = MoveTimeVariance { percent: 20, floor: 0 };
+
+        let softs = (1..=1000u64)
+            .map(|seed| vary_allocation(allocation, variance, seed).allocation.soft)
+            .collect::<Vec<_>>();
+        assert!(softs.iter().all(|soft| (1600..=2400).contains(soft)), "{softs:?}");
+        let (shortest, longest) = (softs.iter().min().unwrap(), softs.iter().max().unwrap());
+        assert!(*shortest < 1700 && *longest > 2300, "from {shortest} to {longest} ms");
+
+        // The same seed gives the same allocation
+        assert_eq!(vary_allocation(allocation, variance, 42), vary_allocation(allocation, variance, 42));
+
+        // Without variance, the allocation stays as it was
+        let unvaried = VariedAllocation { allocation, floor: 0 };
+        for seed in 1..100 {
+            assert_eq!(vary_allocation(allocation, MoveTimeVariance::default(), seed), unvaried);
+        }
+    }
+
+    #[test]
+    fn test_vary_allocation_limits()
+    {
+        // Never beyond the hard limit, however large the variance
+        let allocation = TimeAllocation { soft: 900, hard: 1000 };
+        let variance = MoveTimeVariance { percent: 1000, floor: 0 };
+        for seed in 1..1000 {
+            let varied = vary_allocation(allocation, variance, seed);
+            assert!(varied.allocation.soft <= 1000 && varied.allocation.hard == 1000, "{varied:?}");
+        }
+
+        // The floor raises the soft limit, but not beyond the hard limit
+        let variance = MoveTimeVariance { percent: 0, floor: 500 };
+        let varied = vary_allocation(TimeAllocation { soft: 100, hard: 3000 }, variance, 1);
+        assert_eq!(varied, VariedAllocation { allocation: TimeAllocation { soft: 500, hard: 3000 }, floor: 500 });
+        let varied = vary_allocation(TimeAllocation { soft: 100, hard: 300 }, variance, 1);
+        assert_eq!(varied, VariedAllocation { allocation: TimeAllocation { soft: 300, hard: 300 }, floor: 300 });
+
+        // Nearly flagging leaves nothing to vary
+        let time_control = TimeControl { time_left: 10, increment: 0, moves_to_go: None };
+        let allocation = allocate_time(&time_control, GamePhase::Endgame);
+        let varied = vary_allocation(allocation, MoveTimeVariance { percent: 50, floor: 1000 }, 7);
+        assert_eq!(varied.allocation, allocation);
+    }
+}
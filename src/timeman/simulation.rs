@@ -0,0 +1,195 @@
+//! Replays games against the clock with scripted searches, to check the time management as a whole:
+//! the time allocated to each move, the scaling of the soft limit between iterations, and the hard
+//! limit, over a game's worth of moves
+//!
+//! Each iteration of a scripted search takes longer than the one before, as in a real search, and
+//! reports the score and best move that the scenario gives it. Between iterations, the scripted search
+//! decides whether to go on the way `Searcher` does: the first iteration always completes, no further
+//! iteration starts after the soft limit, and an iteration still running at the hard limit is cut
+//! short and thrown away.
+
+use std::str::FromStr;
+
+use chess::{Board, ChessMove};
+use crate::rootmoves::RootMove;
+use super::*;
+
+/// The first iteration takes this many milliseconds, and every one after it 30% longer
+const FIRST_ITERATION_MILLIS: f64 = 1.0;
+const ITERATION_GROWTH: f64 = 1.3;
+
+/// More iterations than any scripted search has time to complete
+const MAX_ITERATIONS: usize = 60;
+
+/// Share of the nodes of an iteration that the best move takes, in percent
+const BEST_MOVE_NODE_SHARE: u64 = 80;
+
+/// What a scripted search reports after an iteration
+#[derive(Clone, Copy)]
+struct Iteration
+{
+    /// In centipawns, for the side to move
+    score: i16,
+    best_move: &'static str,
+}
+
+/// A move played by a scripted search
+#[derive(Clone, Copy, Debug)]
+struct PlayedMove
+{
+    millis: u32,
+    /// Score of the last completed iteration
+    score: BoardScore,
+    allocation: TimeAllocation,
+}
+
+fn iteration_millis(index: usize) -> u32
+{
+    (FIRST_ITERATION_MILLIS * ITERATION_GROWTH.powi(index as i32)).ceil() as u32
+}
+
+/// Root moves after an iteration, sorted with `best_move` first, for updating the stability
+fn root_moves(best_move: &str) -> [RootMove; 2]
+{
+    let board = Board::default();
+    let other_move = if best_move == "e2e4" { "d2d4" } else { "e2e4" };
+    [(best_move, BEST_MOVE_NODE_SHARE), (other_move, 100 - BEST_MOVE_NODE_SHARE)]
+        .map(|(chess_move, nodes)| RootMove { nodes, ..RootMove::new(&board, ChessMove::from_str(chess_move).unwrap()) })
+}
+
+/// Search one move on the clock of `time_control`, after a previous search that ended with the score
+/// `previous`. `script` gives the result of each iteration by its index.
+fn play_move(time_control: &TimeControl, previous: Option<BoardScore>, script: impl Fn(usize) -> Iteration) -> PlayedMove
+{
+    let allocation = allocate_time(time_control, GamePhase::Middlegame);
+    let mut stability = BestMoveStability::default();
+    let mut elapsed = 0;
+    let mut current = None;
+
+    for index in 0..MAX_ITERATIONS
+    {
+        let millis = iteration_millis(index);
+        if current.is_some()
+        {
+            let soft = scaled_soft_limit(allocation.soft, allocation.hard, &stability, previous, current);
+            if elapsed >= soft {
+                break;
+            }
+            if elapsed + millis >= allocation.hard {
+                elapsed = allocation.hard;
+                break;
+            }
+        }
+
+        elapsed += millis;
+        let Iteration { score, best_move } = script(index);
+        stability.update(&root_moves(best_move));
+        current = Some(BoardScore::evaluation(score));
+    }
+
+    PlayedMove {
+        millis: elapsed,
+        score: current.unwrap(),
+        allocation,
+    }
+}
+
+/// Play `moves` moves from a clock of `time_left` with `increment`, without movestogo. `script` gives
+/// the result of each iteration by the index of the move and of the iteration. Fails if the clock runs
+/// out.
+fn play_game(time_left: u32, increment: u32, moves: usize, script: impl Fn(usize, usize) -> Iteration) -> Vec<PlayedMove>
+{
+    let mut time_left = time_left;
+    let mut previous = None;
+    let mut played = Vec::new();
+
+    for move_index in 0..moves
+    {
+        let time_control = TimeControl { time_left, increment, moves_to_go: None };
+        let played_move = play_move(&time_control, previous, |iteration| script(move_index, iteration));
+        assert!(played_move.millis < time_left, "Lost on time at move {move_index}: {played_move:?} with {time_left} ms left");
+        assert!(played_move.millis <= played_move.allocation.hard.max(iteration_millis(0)), "{played_move:?}");
+
+        time_left = time_left - played_move.millis + increment;
+        previous = Some(played_move.score);
+        played.push(played_move);
+    }
+    played
+}
+
+fn steady(_move_index: usize, _iteration: usize) -> Iteration
+{
+    Iteration { score: 20, best_move: "e2e4" }
+}
+
+fn total_millis(played: &[PlayedMove]) -> u64
+{
+    played.iter().map(|played_move| played_move.millis as u64).sum()
+}
+
+#[test]
+fn test_steady_game()
+{
+    // A long game on a short clock without increment doesn't run out of time, but doesn't hoard it
+    // either
+    let played = play_game(60_000, 0, 120, steady);
+    assert!(total_millis(&played) > 30_000, "{} ms spent", total_millis(&played));
+
+    // With an increment, the clock lasts indefinitely
+    let played = play_game(10_000, 100, 500, steady);
+    assert!(played[499].millis >= 50, "{:?}", played[499]);
+}
+
+#[test]
+fn test_unstable_best_move_gets_more_time()
+{
+    let flip_flopping = |_move_index, iteration: usize| Iteration {
+        score: 20,
+        best_move: if iteration % 2 == 0 { "e2e4" } else { "d2d4" },
+    };
+
+    let stable = play_game(60_000, 0, 1, steady);
+    let unstable = play_game(60_000, 0, 1, flip_flopping);
+    assert!(unstable[0].millis > stable[0].millis, "{unstable:?} against {stable:?}");
+}
+
+#[test]
+fn test_score_drop_gets_more_time()
+{
+    // The opponent's 20th move turns out stronger than expected
+    let dropping = |move_index, _iteration| Iteration { score: if move_index < 20 { 20 } else { -80 }, best_move: "e2e4" };
+
+    let steady_game = play_game(60_000, 0, 60, steady);
+    let dropping_game = play_game(60_000, 0, 60, dropping);
+    assert_eq!(total_millis(&steady_game[..20]), total_millis(&dropping_game[..20]));
+    assert!(dropping_game[20].millis > steady_game[20].millis, "{:?} against {:?}", dropping_game[20], steady_game[20]);
+    // Once the new score is the expected one, the time goes back to normal
+    assert!(dropping_game[22].millis <= steady_game[22].millis, "{:?} against {:?}", dropping_game[22], steady_game[22]);
+}
+
+#[test]
+fn test_score_drop_near_the_end_of_the_clock()
+{
+    // Move after move, the score keeps dropping with little time left, and every move gets the longer
+    // soft limit, but the hard limit still keeps the clock from running out
+    let collapsing = |move_index: usize, _iteration| Iteration {
+        score: if move_index < 60 { 20 } else { 20 - 100 * (move_index as i16 - 59) },
+        best_move: "e2e4",
+    };
+
+    let steady_game = play_game(20_000, 0, 90, steady);
+    let collapsing_game = play_game(20_000, 0, 90, collapsing);
+    assert!(collapsing_game[60].millis >= steady_game[60].millis, "{:?} against {:?}", collapsing_game[60], steady_game[60]);
+    assert!(total_millis(&collapsing_game) > total_millis(&steady_game));
+}
+
+#[test]
+fn test_score_rise_with_stable_best_move_saves_time()
+{
+    // The opponent blunders on the 20th move, and the reply is clear
+    let rising = |move_index, _iteration| Iteration { score: if move_index < 20 { 20 } else { 300 }, best_move: "e2e4" };
+
+    let steady_game = play_game(60_000, 0, 21, steady);
+    let rising_game = play_game(60_000, 0, 21, rising);
+    assert!(rising_game[20].millis <= steady_game[20].millis, "{:?} against {:?}", rising_game[20], steady_game[20]);
+}
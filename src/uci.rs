@@ -1,14 +1,145 @@
+use std::fs;
 use std::io;
-use std::io::BufRead;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 use std::str::{FromStr, SplitAsciiWhitespace};
+use std::time;
 
+use crate::bench;
+use crate::book::{self, PlayedMove};
+use crate::boardutil::PositionExt;
+use crate::commandlog;
+use crate::epd::{self, EpdLimits};
+use crate::evaluation::{self, EvalTerms, GamePhase};
+use crate::fen::parse_fen;
+use crate::gameresult::{self, DrawReason, GameResult};
+use crate::hash::ProbeResult;
+use crate::matesolver::{self, MAX_MATE_MOVES};
+use crate::output;
+use crate::overrides::{self, EvalOverrides};
+use crate::params::{self, EngineParams};
+use crate::perft::{self, MAX_PERFT_DEPTH};
+use crate::rootmoves::canonical_root;
+use crate::san::{self, PvNotation};
+use crate::score::BoundedScore;
 use crate::search;
-use crate::searchinterface::{SearchInterface, StopConditions};
+use crate::searchinterface::SearchInterface;
+use crate::stopconditions::StopConditions;
+use crate::timeman::{self, MoveTimeVariance, TimeControl};
+use crate::version;
+
+mod options;
+
+use options::{OptionValue, UciOption};
+
+/// Upper limit on the length of an input line, in bytes
+///
+/// The longest legitimate input is a `position` command with a long move list. At five bytes per move
+/// this allows for well over a million moves, while keeping malicious input from growing the line
+/// buffer without bound.
+const MAX_LINE_LENGTH: usize = 8 * 1024 * 1024;
+
+/// Default of the MinimumThinkingTime option, in milliseconds. Shorter movetimes are raised to this.
+const DEFAULT_MINIMUM_THINKING_TIME: u32 = 10;
+const MAX_MINIMUM_THINKING_TIME: u32 = 5000;
+
+/// Largest value of the MoveTimeFloor option, in milliseconds
+const MAX_MOVE_TIME_FLOOR: u32 = 10_000;
+
+/// Largest value of the AutosaveInterval option, in minutes
+const MAX_AUTOSAVE_INTERVAL: u32 = 24 * 60;
+
+/// Whether a command is defined by the UCI protocol, or is an extension of this engine
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CommandKind
+{
+    Standard,
+    Extension,
+}
+
+/// Every command that the engine understands
+const COMMANDS: &[(&str, CommandKind)] = &[
+    ("uci", CommandKind::Standard),
+    ("debug", CommandKind::Standard),
+    ("isready", CommandKind::Standard),
+    ("setoption", CommandKind::Standard),
+    ("ucinewgame", CommandKind::Standard),
+    ("position", CommandKind::Standard),
+    ("go", CommandKind::Standard),
+    ("stop", CommandKind::Standard),
+    ("ponderhit", CommandKind::Standard),
+    ("quit", CommandKind::Standard),
+    ("d", CommandKind::Extension),
+    ("eval", CommandKind::Extension),
+    ("perft", CommandKind::Extension),
+    ("probe", CommandKind::Extension),
+    ("exporttree", CommandKind::Extension),
+    ("savehash", CommandKind::Extension),
+    ("loadhash", CommandKind::Extension),
+    ("bench", CommandKind::Extension),
+    ("analyse", CommandKind::Extension),
+    ("learn", CommandKind::Extension),
+    ("verifymate", CommandKind::Extension),
+    ("epd", CommandKind::Extension),
+    ("override", CommandKind::Extension),
+    ("dumplog", CommandKind::Extension),
+];
+
+/// Commands that wait for something on purpose, like the search thread or a file, and so aren't slow
+/// however long they take, see `commandlog`
+const BLOCKING_COMMANDS: &[&str] = &["isready", "bench", "perft", "analyse", "verifymate", "epd", "savehash", "loadhash", "exporttree", "quit"];
+
+fn command_kind(command: &str) -> Option<CommandKind>
+{
+    COMMANDS.iter().find(|(name, _)| *name == command).map(|&(_, kind)| kind)
+}
 
 pub struct UciClient
 {
-    stdin: io::StdinLock<'static>,
+    stdin: LineReader<io::StdinLock<'static>>,
     position: chess::Board,
+    /// Whether the last position command was valid. After one that wasn't, `position` is left over
+    /// from before it, and the commands that work on the position refuse to rather than use it.
+    position_valid: bool,
+    fullmove_number: u32,
+    /// Plies since the last capture or pawn move, for the fifty-move rule
+    halfmove_clock: u32,
+    /// Hashes of every position in the game so far, starting with the position given by the last
+    /// `position` command and ending with the current position
+    game_history: Vec<u64>,
+    /// The moves between the positions of `game_history`, `None` for a null move
+    game_moves: Vec<Option<chess::ChessMove>>,
+    /// The position given by the last `position` command, before its moves
+    game_start: chess::Board,
+    /// Lower limit on `go movetime`, in milliseconds
+    minimum_thinking_time: u32,
+    /// How much the time spent on moves against the clock varies
+    move_time_variance: MoveTimeVariance,
+    /// How many centipawns worse than the best move the move played in the opening may be
+    opening_variety: i16,
+    /// State of the pseudo-random numbers that vary the time spent on moves, and the moves played in
+    /// the opening
+    random_state: u64,
+    /// Where to save snapshots of the hash table while searching, empty if nowhere
+    autosave_path: String,
+    /// Minutes between snapshots of the hash table, 0 if none are saved
+    autosave_interval: u32,
+    /// Whether a position has been received, after which no autosave is offered anymore
+    received_position: bool,
+    /// Where `learn` keeps the weights of opening moves, empty if book learning is off
+    book_path: String,
+    /// Where `override` keeps the evaluation overrides, empty if they are off
+    overrides_path: String,
+    overrides: EvalOverrides,
+    /// Whether the GUI turned debug mode on, in which slow commands are reported
+    debug: bool,
+    /// The options that `uci` declares and `setoption` accepts
+    options: Vec<UciOption>,
+    /// The evaluation terms and parameters that the search was told to use, and the network, for `eval`
+    eval_terms: EvalTerms,
+    params: EngineParams,
+    #[cfg(feature = "nnue")]
+    network: Option<std::sync::Arc<evaluation::nnue::Network>>,
     search_interface: SearchInterface,
 }
 
@@ -17,69 +148,187 @@ impl UciClient
     pub fn new() -> UciClient
     {
         UciClient {
-            stdin: io::stdin().lock(),
+            stdin: LineReader::new(io::stdin().lock()),
             position: chess::Board::default(),
+            position_valid: true,
+            fullmove_number: 1,
+            halfmove_clock: 0,
+            game_history: Vec::new(),
+            game_moves: Vec::new(),
+            game_start: chess::Board::default(),
+            minimum_thinking_time: DEFAULT_MINIMUM_THINKING_TIME,
+            move_time_variance: MoveTimeVariance::default(),
+            // Zero would get the generator stuck
+            opening_variety: 0,
+            random_state: time::SystemTime::now().duration_since(time::UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1,
+            autosave_path: String::new(),
+            autosave_interval: 0,
+            received_position: false,
+            book_path: String::new(),
+            overrides_path: String::new(),
+            overrides: EvalOverrides::default(),
+            debug: false,
+            options: options::declared(),
+            eval_terms: EvalTerms::default(),
+            params: EngineParams::default(),
+            #[cfg(feature = "nnue")]
+            network: None,
             search_interface: SearchInterface::new(),
         }
     }
 
     pub fn main_loop(&mut self)
     {
-        let mut input = String::new();
         loop
         {
-            input.clear();
-            if let Err(e) = self.stdin.read_line(&mut input)
-            {
-                println!("ERROR: IO error {e}");
-                return;
-            }
-            if input.len() == 0 {
-                // EOF on input, exit
+            // Output has failed, so the GUI is no longer reading it, see `output`
+            if output::is_disconnected() {
+                self.command_stop();
                 return;
             }
 
-            let mut command_words = input.trim_ascii().split_ascii_whitespace();
+            let input = match self.stdin.read_line()
+            {
+                Ok(InputLine::Line(input)) => input,
+                Ok(InputLine::TooLong) => {
+                    output::error(format_args!("Input line longer than {MAX_LINE_LENGTH} bytes, ignoring it"));
+                    continue;
+                }
+                Ok(InputLine::Eof) => {
+                    // The GUI has closed the pipe, most likely because it crashed. Don't leave a search
+                    // burning CPU for nobody.
+                    self.command_stop();
+                    return;
+                }
+                Err(e) => {
+                    output::error(format_args!("IO error {e}"));
+                    return;
+                }
+            };
+            output::log_input(&input);
+
+            let mut command_words = input.split_ascii_whitespace();
             let command =  command_words.next();
             if let Some(command) = command
             {
-                match command
+                match command_kind(command)
                 {
-                    "uci" => self.command_uci(),
-                    "ucinewgame" => self.command_ucinewgame(),
-                    "position" => self.command_position(command_words),
-                    "d" => self.command_d(),
-                    "isready" => self.command_isready(),
-
-                    "go" => self.command_go(command_words),
-                    "stop" => self.command_stop(),
-
-                    "quit" => {
-                        return;
+                    // The protocol says to ignore unknown input, which strict mode does to the letter
+                    None => {
+                        if !output::is_strict() {
+                            output::send(format_args!("Unknown command: {command}"));
+                        }
+                        continue;
                     }
-                    _ => {
-                        println!("Unknown command: {command}");
+                    Some(CommandKind::Extension) if output::is_strict() => {
+                        output::send("info string unsupported in strict mode");
+                        continue;
                     }
+                    Some(_) => { },
+                }
+
+                let may_block = BLOCKING_COMMANDS.contains(&command);
+                let (keep_going, slow) = commandlog::run(&input, may_block, || self.dispatch(command, command_words));
+                if let Some(slow) = slow.filter(|_| self.debug) {
+                    output::send(slow);
+                }
+                if !keep_going {
+                    return;
                 }
             }
+            // Blank lines are ignored
         }
     }
 
+    /// Handle a command, which is in `COMMANDS`. Returns false for quit.
+    fn dispatch(&mut self, command: &str, command_words: SplitAsciiWhitespace) -> bool
+    {
+        match command
+        {
+            "uci" => self.command_uci(),
+            "debug" => self.command_debug(command_words),
+            "ucinewgame" => self.command_ucinewgame(),
+            "position" => self.command_position(command_words),
+            "d" => self.command_d(),
+            "eval" => self.command_eval(),
+            "perft" => self.command_perft(command_words),
+            "probe" => self.command_probe(command_words),
+            "exporttree" => self.command_exporttree(command_words),
+            "savehash" => self.command_savehash(command_words),
+            "loadhash" => self.command_loadhash(command_words),
+            "bench" => { self.command_bench(command_words); }
+            "analyse" => self.command_analyse(command_words),
+            "learn" => self.command_learn(command_words),
+            "verifymate" => self.command_verifymate(command_words),
+            "epd" => self.command_epd(command_words),
+            "override" => self.command_override(command_words),
+            "dumplog" => self.command_dumplog(),
+            "isready" => self.command_isready(),
+            "setoption" => self.command_setoption(command_words),
+
+            "go" => self.command_go(command_words),
+            "stop" => self.command_stop(),
+            "ponderhit" => self.command_ponderhit(),
+
+            "quit" => {
+                // Stop a running search right away rather than when the search interface is dropped
+                self.command_stop();
+                return false;
+            }
+            _ => unreachable!("{command} is in COMMANDS but not handled"),
+        }
+        true
+    }
+
     fn command_uci(&mut self)
     {
-        println!("id name Christoffer Engine 1.0");
-        println!("id author Christoffer Cronström");
-        println!("uciok");
+        output::send(format_args!("id name {}", version::version()));
+        output::send("id author Christoffer Cronström");
+        output::send(format_args!("info string build {}", version::build_info()));
+        let mut batch = output::Batch::new();
+        for option in &self.options {
+            batch.line(option);
+        }
+        batch.send();
+        output::send("uciok");
+    }
+
+    fn command_debug(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        match arguments.next()
+        {
+            Some("on") => {
+                self.debug = true;
+                self.search_interface.set_debug(true);
+            }
+            Some("off") => {
+                self.debug = false;
+                self.search_interface.set_debug(false);
+            }
+            Some(other) => output::error(format_args!("Expected on or off after debug, got {other}")),
+            None => output::error("Expected on or off after debug"),
+        }
     }
 
     fn command_ucinewgame(&mut self)
     {
-        // TODO: Clear the hash table
+        // Clearing a large hash table takes a while. The search thread does it before anything else,
+        // and isready waits for it, so GUIs that follow the protocol don't lose time on the clock.
+        self.search_interface.new_game();
+
+        // The next position command starts a new game, no need to detect it
+        self.game_history.clear();
+        self.game_moves.clear();
     }
 
     fn command_position(&mut self, mut arguments: SplitAsciiWhitespace)
     {
+        // Until the whole command turns out valid
+        self.position_valid = false;
+
         let mut result_position;
+        let mut result_fullmove_number = 1;
+        let mut result_halfmove_clock = 0;
 
         // Parse 'startpos' or 'fen <fen_str>'
         match arguments.next()
@@ -99,35 +348,72 @@ impl UciClient
                     moves_str = "";
                 }
 
-                match chess::Board::from_str(fen_str)
+                match parse_fen(fen_str)
                 {
                     Ok(board) => {
                         result_position = board;
                     }
                     Err(e) => {
-                        println!("ERROR: {e}");
+                        output::error(format_args!("{e}"));
                         return;
                     }
                 }
+                // The chess crate doesn't keep track of the move number or the halfmove clock, so
+                // parse them separately. Some GUIs send 0 as the move number, treat that as 1.
+                result_fullmove_number = fen_str.split_ascii_whitespace()
+                    .nth(5)
+                    .and_then(|n| u32::from_str(n).ok())
+                    .unwrap_or(1)
+                    .max(1);
+                result_halfmove_clock = fen_str.split_ascii_whitespace()
+                    .nth(4)
+                    .and_then(|n| u32::from_str(n).ok())
+                    .unwrap_or(0);
+
                 arguments = moves_str.split_ascii_whitespace();
             }
 
             _ => {
-                println!("ERROR: Expected 'startpos' or 'fen'");
+                output::error("Expected 'startpos' or 'fen'");
                 return;
             }
         }
 
+        let result_start = canonical_root(&result_position);
+        let mut result_history = vec![result_start.get_hash()];
+        let mut result_moves = Vec::new();
+
         // Optionally parse moves
         match arguments.next()
         {
             Some("moves") => {
                 for move_str in arguments {
+                    // Analysis front-ends pass with the null move, to see what the other side would do
+                    if move_str == "0000" {
+                        match result_position.null_move()
+                        {
+                            Some(new_position) => {
+                                if result_position.side_to_move() == chess::Color::Black {
+                                    result_fullmove_number += 1;
+                                }
+                                result_halfmove_clock += 1;
+                                result_position = new_position;
+                                result_history.push(canonical_root(&result_position).get_hash());
+                                result_moves.push(None);
+                            }
+                            None => {
+                                output::error("Illegal null move 0000, the side to move is in check");
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+
                     let next_move = match chess::ChessMove::from_str(move_str)
                     {
                         Ok(m) => m,
                         Err(e) => {
-                            println!("ERROR: Invalid move \"{move_str}\": {e}");
+                            output::error(format_args!("Invalid move \"{move_str}\": {e}"));
                             return;
                         }
                     };
@@ -138,21 +424,31 @@ impl UciClient
                     for legal_move in movegen {
                         if next_move == legal_move {
                             legal_move_found = true;
+                            if result_position.side_to_move() == chess::Color::Black {
+                                result_fullmove_number += 1;
+                            }
+                            if result_position.resets_halfmove_clock(next_move) {
+                                result_halfmove_clock = 0;
+                            } else {
+                                result_halfmove_clock += 1;
+                            }
                             let new_position = result_position.make_move_new(next_move);
                             result_position = new_position;
+                            result_history.push(canonical_root(&result_position).get_hash());
+                            result_moves.push(Some(next_move));
                             break;
                         }
                     }
 
                     if !legal_move_found {
-                        println!("ERROR: Illegal move {move_str}");
+                        output::error(format_args!("Illegal move {move_str}"));
                         return;
                     }
                 }
             }
 
             Some(w) => {
-                println!("ERROR: Unexpected word \"{w}\", expected \"moves\" or end of string");
+                output::error(format_args!("Unexpected word \"{w}\", expected \"moves\" or end of string"));
                 return;
             }
 
@@ -161,11 +457,55 @@ impl UciClient
             }
         }
 
-        assert!(result_position.is_sane());
-        self.position = result_position;
+        debug_assert!(result_position.is_sane());
+        // Equivalent positions must hash the same, whether they were given as a FEN or as moves
+        self.position = canonical_root(&result_position);
+        self.position_valid = true;
+        self.fullmove_number = result_fullmove_number;
+        self.halfmove_clock = result_halfmove_clock;
+
+        // Not all GUIs send ucinewgame, so also detect new games from the position history
+        if !is_same_game(&self.game_history, &result_history) {
+            output::send("info string New game detected");
+            self.search_interface.implicit_new_game();
+        }
+        self.game_history = result_history;
+        self.game_moves = result_moves;
+        self.game_start = result_start;
+
+        if !self.received_position
+        {
+            self.received_position = true;
+            self.offer_autosave();
+        }
+    }
+
+    /// If the autosave is of the current position, tell the GUI that the analysis can continue from it
+    ///
+    /// Only done for the first position, which after a crash is the one that the GUI sends again.
+    fn offer_autosave(&self)
+    {
+        if self.autosave_path.is_empty() {
+            return;
+        }
+
+        // Without a sidecar there is no autosave to speak of
+        let path = Path::new(&self.autosave_path);
+        let Ok(state) = search::autosave::read_sidecar(path) else {
+            return;
+        };
+        let is_same_position = parse_fen(&state.fen)
+            .is_ok_and(|board| canonical_root(&board).get_hash() == self.position.get_hash());
+        if is_same_position && path.exists()
+        {
+            output::send(format_args!(
+                "info string Autosave of this position found, depth {} score {} for White pv {}. Send \"loadhash {}\" to continue from it.",
+                state.depth, state.score, state.pv, self.autosave_path,
+            ));
+        }
     }
 
-    fn command_d(&self)
+    fn command_d(&mut self)
     {
         use chess::Color::*;
         use chess::File::*;
@@ -217,71 +557,1699 @@ impl UciClient
         }
         display_str.push_str("\n");
 
+        let fen = self.position.to_string();
         display_str.push_str("info string FEN: ");
-        display_str.push_str(&self.position.to_string());
+        display_str.push_str(&fen);
         display_str.push_str("\n");
 
+        // The castling rights and the en passant square as the FEN has them, and the clocks of the game
+        let fen_fields = fen.split_ascii_whitespace().collect::<Vec<_>>();
+        display_str.push_str(&format!("info string Castling: {}, en passant: {}\n", fen_fields[2], fen_fields[3]));
+        display_str.push_str(&format!("info string Halfmove clock: {}, fullmove number: {}\n", self.halfmove_clock, self.fullmove_number));
+
+        display_str.push_str(&format!("info string Hash: {:016x}\n", self.position.get_hash()));
+        let hash_entry = match self.search_interface.probe(&self.position)
+        {
+            None => "unknown while the search is running".to_owned(),
+            Some(None) => "none".to_owned(),
+            Some(Some(probe)) => describe_probe(&probe),
+        };
+        display_str.push_str(&format!("info string Hash entry: {hash_entry}\n"));
+
+        if let Some(result) = gameresult::game_result(&self.position, self.halfmove_clock, &self.game_history) {
+            display_str.push_str(&format!("info string Game over: {result}\n"));
+        }
+
 
-        print!("{}", display_str);
+        output::send(display_str.trim_end());
     }
 
-    fn command_isready(&mut self)
+    /// Non-standard command: print the static evaluation of the current position, term by term, from
+    /// White's point of view and from the side to move's, followed by the evaluation that the search
+    /// uses for the position
+    fn command_eval(&self)
     {
-        println!("readyok");
+        if !self.position_valid {
+            output::send("info string no valid position set");
+            return;
+        }
+
+        let side_to_move = self.position.side_to_move();
+        let white = |centipawns: i16| if side_to_move == chess::Color::White { centipawns } else { -centipawns };
+        let breakdown = evaluation::breakdown(&self.position, self.eval_terms, &self.params);
+
+        let mut batch = output::Batch::new();
+        batch.line(format_args!("info string eval {:<16}{:>8}{:>9}", "term", "white", "to move"));
+        for &(name, centipawns) in &breakdown.terms {
+            batch.line(format_args!("info string eval {name:<16}{:>8}{centipawns:>9}", white(centipawns)));
+        }
+        let sum = breakdown.terms.iter().map(|&(_, centipawns)| centipawns).sum::<i16>();
+        batch.line(format_args!("info string eval {:<16}{:>8}{sum:>9}", "sum", white(sum)));
+        batch.line(format_args!("info string eval {:<16}{:>7}%", "scaled to", breakdown.scale_percent));
+
+        // A loaded network replaces the classical evaluation in the search
+        #[cfg(feature = "nnue")]
+        let score = match &self.network
+        {
+            Some(network) => {
+                batch.line(format_args!("info string eval classical {}, replaced by the network", breakdown.score));
+                network.evaluate_board(&self.position)
+            }
+            None => breakdown.score,
+        };
+        #[cfg(not(feature = "nnue"))]
+        let score = breakdown.score;
+        batch.line(format_args!(
+            "info string eval {:<16}{:>8}{:>9}",
+            "evaluation",
+            score.to_white_relative(side_to_move).to_string(),
+            score.to_string(),
+        ));
+        batch.send();
     }
 
-    fn command_go(&mut self, mut arguments: SplitAsciiWhitespace)
+    /// Non-standard command: `perft <depth>` counts the leaf nodes of the tree of legal moves from the
+    /// current position, for checking the position handling against known counts
+    ///
+    /// `perft <depth> divide` first lists the count under each root move, followed by the total, as
+    /// Stockfish's `go perft` does, so that the two can be diffed to find the move that is miscounted.
+    fn command_perft(&mut self, mut arguments: SplitAsciiWhitespace)
     {
-        let mut stop_conditions = StopConditions::new();
+        if !self.position_valid {
+            output::send("info string no valid position set");
+            return;
+        }
+        let depth = match arguments.next().map(u32::from_str)
+        {
+            Some(Ok(depth)) if depth <= MAX_PERFT_DEPTH => depth,
+            _ => {
+                output::error(format_args!("Expected a depth of 0 to {MAX_PERFT_DEPTH}"));
+                return;
+            }
+        };
+
+        let divide = match arguments.next()
+        {
+            None => false,
+            Some("divide") if depth >= 1 => true,
+            Some("divide") => {
+                output::error(format_args!("Expected a depth of 1 to {MAX_PERFT_DEPTH} to divide"));
+                return;
+            }
+            Some(word) => {
+                output::error(format_args!("Unexpected word \"{word}\", expected \"divide\" or end of string"));
+                return;
+            }
+        };
+
+        let starttime = time::Instant::now();
+        let nodes = if divide {
+            let counts = perft::divide(&self.position, depth);
+            let nodes = counts.iter().map(|&(_, count)| count).sum();
+            let mut batch = output::Batch::new();
+            for (m, count) in counts {
+                batch.line(format_args!("{m}: {count}"));
+            }
+            batch.line("");
+            batch.line(format_args!("Nodes searched: {nodes}"));
+            batch.send();
+            nodes
+        } else {
+            perft::perft(&self.position, depth)
+        };
+        let millis = starttime.elapsed().as_millis();
+        let nps = (nodes as u128 * 1000) / millis.max(1);
+        output::send(format_args!("info string perft depth {depth} nodes {nodes} time {millis} nps {nps}"));
+    }
+
+    /// Non-standard command: look up a position in the hash table, for tools that inspect what the
+    /// engine knows
+    ///
+    /// `probe [fen]` probes the given position, or the current position if no FEN is given.
+    fn command_probe(&mut self, arguments: SplitAsciiWhitespace)
+    {
+        let fen_str = arguments.remainder().unwrap_or("").trim();
+        let position = if fen_str.is_empty() {
+            self.position
+        } else {
+            match parse_fen(fen_str)
+            {
+                Ok(board) => canonical_root(&board),
+                Err(e) => {
+                    output::error(format_args!("{e}"));
+                    return;
+                }
+            }
+        };
+
+        match self.search_interface.probe(&position)
+        {
+            None => output::send("info string Search is running, can't probe the hash table"),
+            Some(None) => output::send(format_args!("info string probe miss hash {:016x}", position.get_hash())),
+            Some(Some(probe)) => output::send(format_args!("info string probe hit hash {:016x} {}", position.get_hash(), describe_probe(&probe))),
+        }
+    }
+
+    /// Non-standard command: write what the hash table knows about the positions near the current
+    /// position to an EPD file, for offline analysis
+    ///
+    /// `exporttree <file> <min_depth> <max_plies>` exports the entries of at least `min_depth` for
+    /// positions up to `max_plies` from the current position.
+    fn command_exporttree(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        let (Some(path), Some(min_depth_str), Some(max_plies_str), None) =
+            (arguments.next(), arguments.next(), arguments.next(), arguments.next())
+        else {
+            output::error("Usage: exporttree <file> <min_depth> <max_plies>");
+            return;
+        };
+
+        let min_depth = match search::Depth::from_str(min_depth_str)
+        {
+            Ok(d) => d,
+            Err(e) => {
+                output::error(format_args!("Invalid depth \"{min_depth_str}\": {e}"));
+                return;
+            }
+        };
+        let max_plies = match usize::from_str(max_plies_str)
+        {
+            Ok(p) => p,
+            Err(e) => {
+                output::error(format_args!("Invalid number of plies \"{max_plies_str}\": {e}"));
+                return;
+            }
+        };
+
+        match self.search_interface.export_tree(&self.position, path.into(), min_depth, max_plies)
+        {
+            None => output::send("info string Search is running, can't export the hash table"),
+            Some(Err(e)) => output::error(format_args!("Could not write \"{path}\": {e}")),
+            Some(Ok(stats)) => {
+                output::send(format_args!(
+                    "info string exported {} of {} positions visited, {} entries in the table have depth {min_depth} or more",
+                    stats.written, stats.visited, stats.deep_entries,
+                ));
+                if stats.truncated {
+                    output::send(format_args!("info string export stopped after {} positions, decrease max_plies for a complete export", stats.visited));
+                }
+            }
+        }
+    }
+
+    /// Non-standard command: `savehash <file>` writes a snapshot of the hash table to the file
+    fn command_savehash(&mut self, arguments: SplitAsciiWhitespace)
+    {
+        let path = arguments.remainder().unwrap_or("").trim();
+        if path.is_empty() {
+            output::error("Usage: savehash <file>");
+            return;
+        }
+
+        match self.search_interface.save_hash(path.into())
+        {
+            None => output::send("info string Search is running, can't save the hash table"),
+            Some(Err(e)) => output::error(format_args!("Could not write \"{path}\": {e}")),
+            Some(Ok(entries)) => output::send(format_args!("info string saved {entries} hash entries to \"{path}\"")),
+        }
+    }
+
+    /// Non-standard command: `loadhash <file>` loads a snapshot written by `savehash` or the autosave
+    /// into the hash table, see `hash::snapshot::load`
+    fn command_loadhash(&mut self, arguments: SplitAsciiWhitespace)
+    {
+        let path = arguments.remainder().unwrap_or("").trim();
+        if path.is_empty() {
+            output::error("Usage: loadhash <file>");
+            return;
+        }
+
+        let starttime = time::Instant::now();
+        match self.search_interface.load_hash(path.into())
+        {
+            None => output::send("info string Search is running, can't load the hash table"),
+            Some(Err(e)) => output::error(format_args!("Could not load \"{path}\": {e}")),
+            Some(Ok(entries)) => {
+                let millis = starttime.elapsed().as_millis();
+                output::send(format_args!("info string loaded {entries} hash entries from \"{path}\" in {millis} ms"));
+            }
+        }
+    }
+
+    /// Run `bench` from the command line, as `engine bench [arguments]`, and tell whether it succeeded
+    pub fn command_line_bench(&mut self, arguments: &str) -> bool
+    {
+        self.command_bench(arguments.split_ascii_whitespace())
+    }
+
+    /// Non-standard command: `bench [depth] [--record <file>] [--compare <file>]`, or
+    /// `bench [depth] --save-baseline <file>` and `bench --gate <file>` for the regression gate, or
+    /// `bench [depth] --dispatch` to time the ways of calling the evaluation, see `bench::dispatch`, or
+    /// `bench [depth] --prefetch <megabytes>` to time the search with and without the speculative
+    /// prefetch, see `bench::prefetch`, or `bench --inserts <megabytes>` to time storing into an empty
+    /// and into a full hash table, see `bench::inserts`, or `bench --hanging` to time the evaluation
+    /// with and without the hanging piece term, see `bench::hanging`
+    ///
+    /// Every position is searched to the depth with a fresh hash table and no time limits, so the
+    /// total of the nodes, printed last as `Nodes searched: <nodes>`, is the same from run to run of
+    /// the same build. Returns false on errors, and when the gate finds a regression.
+    fn command_bench(&mut self, mut arguments: SplitAsciiWhitespace) -> bool
+    {
+        let mut depth = bench::DEFAULT_BENCH_DEPTH;
+        let mut record_path = None;
+        let mut compare_path = None;
+        let mut baseline_path = None;
+        let mut gate_path = None;
+        let mut dispatch = false;
+        let mut hanging = false;
+        let mut prefetch_megabytes = None;
+        let mut inserts_megabytes = None;
 
         loop
         {
-           match arguments.next()
-           {
-                Some("depth") => {
-                    let depth_str = arguments.next().unwrap_or("");
-                    let depth_parsed = search::Depth::from_str(depth_str);
-                    match depth_parsed
+            match arguments.next()
+            {
+                Some("--record") => record_path = arguments.next(),
+                Some("--compare") => compare_path = arguments.next(),
+                Some("--save-baseline") => baseline_path = arguments.next(),
+                Some("--gate") => gate_path = arguments.next(),
+                Some("--dispatch") => dispatch = true,
+                Some("--hanging") => hanging = true,
+                Some(option @ ("--prefetch" | "--inserts")) => {
+                    let megabytes_str = arguments.next().unwrap_or("");
+                    let megabytes = match usize::from_str(megabytes_str)
                     {
-                        Ok(d) => {
-                            *stop_conditions.depth.get_mut() = d;
-                        }
-                        Err(e) => {
-                            println!("ERROR: Invalid depth \"{depth_str}\": {e}");
-                            return;
+                        Ok(megabytes) if (1..=search::MAX_HASH_MEGABYTES).contains(&megabytes) => megabytes,
+                        _ => {
+                            output::error(format_args!("Invalid hash size \"{megabytes_str}\" after {option}, expected megabytes"));
+                            return false;
                         }
+                    };
+                    if option == "--prefetch" {
+                        prefetch_megabytes = Some(megabytes);
+                    } else {
+                        inserts_megabytes = Some(megabytes);
                     }
                 }
-
-                Some("movetime") => {
-                    let movetime_str = arguments.next().unwrap_or("");
-                    let movetime_parsed = u32::from_str(movetime_str);
-                    match movetime_parsed
+                Some(depth_str) => {
+                    match search::Depth::from_str(depth_str)
                     {
-                        Ok(t) => {
-                            *stop_conditions.movetime.get_mut() = t;
-                        }
+                        Ok(d) => depth = d,
                         Err(e) => {
-                            println!("ERROR: Invalid movetime \"{movetime_str}\": {e}");
-                            return;
+                            output::error(format_args!("Invalid depth \"{depth_str}\": {e}"));
+                            return false;
                         }
                     }
                 }
-
                 None => break,
+            }
+        }
 
-                Some(other) => {
-                    println!("ERROR: Unknown specifier \"{other}\"");
-                    return;
+        if let Some(path) = baseline_path {
+            return self.bench_save_baseline(depth, path);
+        }
+        if let Some(path) = gate_path {
+            return self.bench_gate(path);
+        }
+        if dispatch {
+            self.bench_dispatch(depth);
+            return true;
+        }
+        if hanging {
+            self.bench_hanging();
+            return true;
+        }
+        if let Some(megabytes) = prefetch_megabytes {
+            self.bench_prefetch(depth, megabytes);
+            return true;
+        }
+        if let Some(megabytes) = inserts_megabytes {
+            self.bench_inserts(megabytes);
+            return true;
+        }
+
+        // Read the file to compare against first, so that errors are found before a lengthy bench
+        let compare_records = match compare_path
+        {
+            Some(path) => {
+                let parsed = fs::read_to_string(path)
+                    .map_err(|e| format!("Could not read \"{path}\": {e}"))
+                    .and_then(|text| bench::from_csv(&text));
+                match parsed
+                {
+                    Ok(records) => Some(records),
+                    Err(e) => {
+                        output::error(format_args!("{e}"));
+                        return false;
+                    }
                 }
-           }
+            }
+            None => None,
+        };
+
+        let starttime = time::Instant::now();
+        let records = bench::run_bench(depth);
+        let time = starttime.elapsed().as_millis() as u64;
+        let nodes = bench::total_nodes(&records);
+        let nps = if time != 0 { (1000 * nodes) / time } else { 0 };
+        let mut batch = output::Batch::new();
+        for r in bench::last_iterations(&records) {
+            batch.line(format_args!(
+                "info string bench position {} depth {} nodes {} time {} fen {}",
+                r.position, r.depth, r.nodes, r.time, bench::BENCH_POSITIONS[r.position],
+            ));
+        }
+        batch.line(format_args!("info string bench depth {depth} nodes {nodes} time {time} nps {nps}"));
+        batch.line(format_args!("Nodes searched: {nodes}"));
+        batch.send();
+
+        if let Some(path) = record_path
+        {
+            match fs::write(path, bench::to_csv(&records))
+            {
+                Ok(()) => output::send(format_args!("info string bench recorded to \"{path}\"")),
+                Err(e) => {
+                    output::error(format_args!("Could not write \"{path}\": {e}"));
+                    return false;
+                }
+            }
+        }
+
+        if let Some(compare_records) = compare_records
+        {
+            let differences = bench::compare(&compare_records, &records);
+            let mut batch = output::Batch::new();
+            for difference in &differences
+            {
+                let format_nodes = |n: Option<u64>| n.map_or("-".to_owned(), |n| n.to_string());
+                let highlight = if difference.is_significant() { " <--" } else { "" };
+                batch.line(format_args!(
+                    "info string position {} depth {}: {} -> {} nodes{highlight}",
+                    difference.position,
+                    difference.depth,
+                    format_nodes(difference.old_nodes),
+                    format_nodes(difference.new_nodes),
+                ));
+            }
+            batch.line(format_args!("info string {} iterations differ from the recorded bench", differences.len()));
+            batch.send();
         }
-        self.search_interface.go(&self.position, stop_conditions);
+
+        true
     }
 
-    fn command_stop(&mut self)
+    /// Time the bench with each way of calling the evaluation, and how much longer than calling it
+    /// directly each takes
+    fn bench_dispatch(&mut self, depth: search::Depth)
     {
-        self.search_interface.stop();
+        let timings = bench::dispatch::measure(depth);
+        let direct = &timings[0];
+        let mut batch = output::Batch::new();
+        for timing in &timings {
+            batch.line(format_args!(
+                "info string bench dispatch {} depth {depth} nodes {} time {} nps {} extra time {:+.1}%",
+                timing.dispatch.name(), timing.nodes, timing.time, timing.nps(), timing.extra_time_percent(direct),
+            ));
+        }
+        batch.send();
+    }
+
+    /// Time the bench with a hash table of `megabytes`, without and with the speculative prefetch
+    fn bench_prefetch(&mut self, depth: search::Depth, megabytes: usize)
+    {
+        let [without, with] = bench::prefetch::measure(depth, megabytes);
+        let mut batch = output::Batch::new();
+        for timing in [without, with] {
+            batch.line(format_args!(
+                "info string bench prefetch {} moves depth {depth} hash {megabytes} nodes {} time {} nps {}",
+                timing.prefetch_moves, timing.nodes, timing.time, timing.nps(),
+            ));
+        }
+        let gain = if without.nps() != 0 { 100.0 * (with.nps() as f64 / without.nps() as f64 - 1.0) } else { 0.0 };
+        batch.line(format_args!("info string bench prefetch gains {gain:+.1}% nps"));
+        batch.send();
+    }
+
+    /// Time storing new positions into an empty and into a full hash table of `megabytes`
+    fn bench_inserts(&mut self, megabytes: usize)
+    {
+        let timing = bench::inserts::measure(megabytes);
+        let per_insert = |nanoseconds: u64| nanoseconds as f64 / timing.inserts.max(1) as f64;
+        output::send(format_args!(
+            "info string bench inserts hash {megabytes} stores {} free {:.1} ns purging {:.1} ns extra time {:+.1}%",
+            timing.inserts, per_insert(timing.free), per_insert(timing.purging), timing.extra_time_percent(),
+        ));
+    }
+
+    /// Time the evaluation of the bench positions without and with the hanging piece term
+    fn bench_hanging(&mut self)
+    {
+        let timing = bench::hanging::measure();
+        let per_evaluation = |nanoseconds: u64| nanoseconds as f64 / timing.evaluations.max(1) as f64;
+        output::send(format_args!(
+            "info string bench hanging evaluations {} without {:.1} ns with {:.1} ns extra time {:+.1}%",
+            timing.evaluations, per_evaluation(timing.without), per_evaluation(timing.with), timing.extra_time_percent(),
+        ));
+    }
+
+    /// Measure the metrics of the regression gate, see `bench::gate`, and write them to `path`
+    fn bench_save_baseline(&mut self, depth: search::Depth, path: &str) -> bool
+    {
+        let settings = bench::gate::GateSettings { bench_depth: depth, ..Default::default() };
+        let metrics = bench::gate::measure(settings);
+        match fs::write(path, bench::gate::to_json(&metrics))
+        {
+            Ok(()) => {
+                output::send(format_args!("info string baseline recorded to \"{path}\""));
+                true
+            }
+            Err(e) => {
+                output::error(format_args!("Could not write \"{path}\": {e}"));
+                false
+            }
+        }
+    }
+
+    /// Measure the metrics of the regression gate with the settings of the baseline in `path`, and
+    /// compare them with the baseline
+    fn bench_gate(&mut self, path: &str) -> bool
+    {
+        let parsed = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read \"{path}\": {e}"))
+            .and_then(|text| bench::gate::from_json(&text));
+        let baseline = match parsed
+        {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                output::error(format_args!("{e}"));
+                return false;
+            }
+        };
+
+        let current = bench::gate::measure(baseline.settings);
+        let regressions = bench::gate::regressions(&baseline, &current);
+
+        let mut batch = output::Batch::new();
+        batch.line(format_args!(
+            "info string gate bench nodes {} tactics solved {} of {} time to depth {:?}",
+            current.bench_nodes, current.tactics_solved, bench::gate::TACTICS.len(), current.time_to_depth,
+        ));
+        for regression in &regressions {
+            batch.line(format_args!("info string regression: {regression}"));
+        }
+        let verdict = if regressions.is_empty() { "passed" } else { "failed" };
+        batch.line(format_args!("info string gate {verdict} against \"{path}\""));
+        batch.send();
+
+        regressions.is_empty()
+    }
+
+    /// Non-standard command: `analyse exclude <move>... [movetime]`
+    ///
+    /// Searches the current position without the listed moves at the root, to find the best
+    /// alternative to them. Without a movetime, the search runs until `stop`.
+    fn command_analyse(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        if !self.position_valid {
+            output::send("info string no valid position set");
+            return;
+        }
+        if arguments.next() != Some("exclude") {
+            output::error("Expected 'exclude'");
+            return;
+        }
+
+        let mut stop_conditions = StopConditions::new();
+        let mut excluded_moves = Vec::new();
+        for word in arguments
+        {
+            // Moves never start with a digit, so a number is the movetime
+            if word.starts_with(|c: char| c.is_ascii_digit())
+            {
+                match u32::from_str(word)
+                {
+                    Ok(t) => *stop_conditions.movetime.get_mut() = self.clamp_movetime(t),
+                    Err(e) => {
+                        output::error(format_args!("Invalid movetime \"{word}\": {e}"));
+                        return;
+                    }
+                }
+                continue;
+            }
+
+            match chess::ChessMove::from_str(word)
+            {
+                Ok(m) if self.position.legal(m) => excluded_moves.push(m),
+                Ok(_) => {
+                    output::error(format_args!("Illegal move {word}"));
+                    return;
+                }
+                Err(e) => {
+                    output::error(format_args!("Invalid move \"{word}\": {e}"));
+                    return;
+                }
+            }
+        }
+
+        if excluded_moves.is_empty() {
+            output::error("Expected moves to exclude");
+            return;
+        }
+        if chess::MoveGen::new_legal(&self.position).all(|m| excluded_moves.contains(&m)) {
+            output::error("Every legal move is excluded");
+            return;
+        }
+
+        self.report_game_over();
+        self.search_interface.go_excluding(&self.position, self.halfmove_clock, &self.game_history, stop_conditions, excluded_moves);
     }
 
+    /// Non-standard command: `learn <1-0|0-1|1/2-1/2>`
+    ///
+    /// Learns from the result of the game given by the last position command, into the book set by the
+    /// BookLearning option, see `book`.
+    fn command_learn(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        let result = match arguments.next()
+        {
+            Some("1-0") => GameResult::WhiteWins,
+            Some("0-1") => GameResult::BlackWins,
+            // The GUI doesn't say why the game was drawn
+            Some("1/2-1/2") => GameResult::Draw(DrawReason::Adjudication),
+            Some(other) => {
+                output::error(format_args!("Invalid result \"{other}\", expected 1-0, 0-1 or 1/2-1/2"));
+                return;
+            }
+            None => {
+                output::error("Expected a result after learn: 1-0, 0-1 or 1/2-1/2");
+                return;
+            }
+        };
+        if self.book_path.is_empty() {
+            output::error("Book learning is off, set BookLearning to the path of a book first");
+            return;
+        }
+        if self.game_moves.contains(&None) {
+            output::error("The game has a null move, so it isn't a game to learn from");
+            return;
+        }
+
+        let mut position = self.game_start;
+        let moves = self.game_moves.iter()
+            .flatten()
+            .map(|&chess_move| {
+                let played = PlayedMove { position, chess_move };
+                position = position.make_move_new(chess_move);
+                played
+            })
+            .collect::<Vec<_>>();
+
+        match book::learn(Path::new(&self.book_path), &moves, result)
+        {
+            Ok(learned) => output::send(format_args!("info string Learned {learned} moves from {result} into {}", self.book_path)),
+            Err(e) => output::error(format_args!("Could not learn into {}: {e}", self.book_path)),
+        }
+    }
+
+    /// Non-standard command: `verifymate <moves>`
+    ///
+    /// Proves which first moves of the current position force mate within the given number of moves,
+    /// for checking a composed problem's claim of mate in that many, see `matesolver`. The keys that
+    /// mate in exactly that many moves, the ones that mate sooner and the moves that don't mate are
+    /// listed in SAN, and more than one key is reported as dual solutions. Like bench, the proof runs
+    /// on this thread, and commands wait for it.
+    fn command_verifymate(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        if !self.position_valid {
+            output::send("info string no valid position set");
+            return;
+        }
+        let moves = match arguments.next().map(u32::from_str)
+        {
+            Some(Ok(n)) if (1..=MAX_MATE_MOVES).contains(&n) => n,
+            _ => {
+                output::error(format_args!("Expected the number of moves to mate in, 1 to {MAX_MATE_MOVES}"));
+                return;
+            }
+        };
+
+        let mut exact = Vec::new();
+        let mut shorter = Vec::new();
+        let mut no_mate = Vec::new();
+        for candidate in matesolver::verify_mate(&self.position, moves)
+        {
+            let name = san::to_san(&self.position, candidate.chess_move);
+            match candidate.mate_moves
+            {
+                Some(n) if n == moves => exact.push(name),
+                Some(n) => shorter.push(format!("{name} in {n}")),
+                None => no_mate.push(name),
+            }
+        }
+
+        let list = |names: &[String]| if names.is_empty() { "none".to_owned() } else { names.join(" ") };
+        let result = match (exact.len(), shorter.len())
+        {
+            (0, 0) => format!("no mate in {moves}"),
+            (1, 0) => format!("unique key {}", exact[0]),
+            (0, _) => format!("short: mates in fewer than {moves} moves"),
+            (keys, short) => format!("dual solutions: {} key moves", keys + short),
+        };
+
+        let mut batch = output::Batch::new();
+        batch.line(format_args!("info string verifymate exact {}", list(&exact)));
+        batch.line(format_args!("info string verifymate shorter {}", list(&shorter)));
+        batch.line(format_args!("info string verifymate nomate {}", list(&no_mate)));
+        batch.line(format_args!("info string verifymate result {result}"));
+        batch.send();
+    }
+
+    /// Non-standard command: `epd <file> [depth <D>] [mintime <T>] [maxtime <H>]`
+    ///
+    /// Searches each position of an EPD file with the limits of `go depth <D> mintime <T> maxtime <H>`,
+    /// and checks the move found against its `bm` opcode, see `epd`. Like bench, the searches run on
+    /// this thread with the default options, and commands wait for them. Lines that aren't valid EPD
+    /// are reported and skipped.
+    fn command_epd(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        let Some(path) = arguments.next() else {
+            output::error("Usage: epd <file> [depth <D>] [mintime <T>] [maxtime <H>]");
+            return;
+        };
+
+        let mut limits = EpdLimits { depth: search::Depth::MAX, min_searchtime: 0, max_searchtime: 0 };
+        while let Some(limit_str) = arguments.next()
+        {
+            let value_str = arguments.next().unwrap_or("");
+            let parsed = match limit_str
+            {
+                "depth" => search::Depth::from_str(value_str).map(|d| limits.depth = d),
+                "mintime" => u32::from_str(value_str).map(|t| limits.min_searchtime = t),
+                "maxtime" => u32::from_str(value_str).map(|t| limits.max_searchtime = t),
+                _ => {
+                    output::error(format_args!("Unknown limit \"{limit_str}\", expected depth, mintime or maxtime"));
+                    return;
+                }
+            };
+            if let Err(e) = parsed {
+                output::error(format_args!("Invalid {limit_str} \"{value_str}\": {e}"));
+                return;
+            }
+        }
+        // Without any limit, the first hard puzzle would search forever
+        if limits.depth == search::Depth::MAX && limits.max_searchtime == 0 {
+            output::error("Expected a depth or a maxtime to search each position to");
+            return;
+        }
+
+        let text = match fs::read_to_string(path)
+        {
+            Ok(text) => text,
+            Err(e) => {
+                output::error(format_args!("Could not read \"{path}\": {e}"));
+                return;
+            }
+        };
+
+        let starttime = time::Instant::now();
+        let (mut solved, mut total) = (0, 0);
+        for (line_idx, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+        {
+            let position = match epd::parse(line)
+            {
+                Ok(position) => position,
+                Err(e) => {
+                    output::error(format_args!("Line {}: {e}", line_idx + 1));
+                    continue;
+                }
+            };
+
+            let solution = epd::solve(&position, limits);
+            total += 1;
+            solved += solution.solved as usize;
+
+            let name = position.id.unwrap_or_else(|| format!("line {}", line_idx + 1));
+            let best_moves = position.best_moves.iter().map(|&m| san::to_san(&position.position, m)).collect::<Vec<_>>();
+            let found = solution.best_move.map_or("none".to_owned(), |m| san::to_san(&position.position, m));
+            output::send(format_args!(
+                "info string epd {name} bm {} found {found} depth {} time {} {}",
+                best_moves.join(" "), solution.depth, solution.time, if solution.solved { "solved" } else { "failed" },
+            ));
+        }
+
+        let millis = starttime.elapsed().as_millis();
+        output::send(format_args!("info string epd solved {solved} of {total} in {millis} ms"));
+    }
+
+    /// Non-standard command: `override set <fen> <centipawns>`, `override clear <fen>` or
+    /// `override list`
+    ///
+    /// Pins evaluations to positions that the engine misjudges, see `overrides`. The score is from the
+    /// point of view of the side to move in the position. The overrides are kept in the file set by the
+    /// EvalOverrides option, and take effect from the next search.
+    fn command_override(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        let subcommand = arguments.next();
+        let rest = arguments.remainder().unwrap_or("").trim();
+        match subcommand
+        {
+            Some("list") => {
+                let mut batch = output::Batch::new();
+                for o in self.overrides.list() {
+                    batch.line(format_args!("info string override {} {}", o.centipawns, o.fen));
+                }
+                batch.line(format_args!("info string {} evaluation overrides", self.overrides.len()));
+                batch.send();
+                return;
+            }
+            Some("set" | "clear") if self.overrides_path.is_empty() => {
+                output::error("Evaluation overrides are off, set EvalOverrides to the path of a file first");
+                return;
+            }
+            Some("set") => {
+                let (fen, centipawns_str) = rest.rsplit_once(char::is_whitespace).unwrap_or(("", rest));
+                let centipawns = match overrides::parse_centipawns(centipawns_str)
+                {
+                    Ok(centipawns) => centipawns,
+                    Err(e) => {
+                        output::error(e);
+                        return;
+                    }
+                };
+                match parse_fen(fen)
+                {
+                    Ok(position) => { self.overrides.set(&position, centipawns); }
+                    Err(e) => {
+                        output::error(e);
+                        return;
+                    }
+                }
+            }
+            Some("clear") => {
+                match parse_fen(rest)
+                {
+                    Ok(position) if self.overrides.clear(&position) => { },
+                    Ok(_) => {
+                        output::send(format_args!("info string No evaluation override for {rest}"));
+                        return;
+                    }
+                    Err(e) => {
+                        output::error(e);
+                        return;
+                    }
+                }
+            }
+            _ => {
+                output::error("Expected 'set', 'clear' or 'list'");
+                return;
+            }
+        }
+
+        if let Err(e) = self.overrides.write(Path::new(&self.overrides_path)) {
+            output::error(format_args!("Could not write the overrides to {}: {e}", self.overrides_path));
+        }
+        self.search_interface.set_overrides(self.overrides.clone());
+    }
+
+    /// Non-standard command: `dumplog` prints the last commands received, with how long they took, see
+    /// `commandlog`
+    fn command_dumplog(&mut self)
+    {
+        let mut batch = output::Batch::new();
+        for line in commandlog::dump() {
+            batch.line(line);
+        }
+        batch.send();
+    }
+
+    fn command_isready(&mut self)
+    {
+        self.search_interface.answer_isready();
+    }
+
+    fn command_setoption(&mut self, mut arguments: SplitAsciiWhitespace)
+    {
+        // Parse 'name <name> [value <value>]'. Both the name and the value may contain spaces.
+        if arguments.next() != Some("name") {
+            output::error("Expected 'name'");
+            return;
+        }
+
+        let mut name_words = Vec::new();
+        let mut value_words = Vec::new();
+        let mut parsing_value = false;
+        for word in arguments {
+            if !parsing_value && word == "value" {
+                parsing_value = true;
+            } else if parsing_value {
+                value_words.push(word);
+            } else {
+                name_words.push(word);
+            }
+        }
+        let name = name_words.join(" ");
+        let value = value_words.join(" ");
+
+        let Some(&option) = options::find(&self.options, &name) else {
+            output::send(format_args!("info string Unknown option \"{name}\", ignoring it"));
+            return;
+        };
+        match option.parse(&value)
+        {
+            Ok(value) => self.set_option(option, value),
+            Err(e) => output::error(e),
+        }
+    }
+
+    /// Act on the value of an option, which `UciOption::parse` has checked
+    fn set_option(&mut self, option: UciOption, value: OptionValue)
+    {
+        // Only declared in builds with the `tune` feature
+        if let (Some(param), OptionValue::Spin(value)) = (params::find(option.name), &value)
+        {
+            *(param.field)(&mut self.params) = *value as i16;
+            self.search_interface.set_params(self.params);
+            return;
+        }
+
+        match (option.name, value)
+        {
+            ("Hash", OptionValue::Spin(megabytes)) => self.search_interface.resize_hash(megabytes as usize),
+
+            ("Clear Hash", OptionValue::Button) => self.search_interface.clear_hash(),
+
+            ("Threads", OptionValue::Spin(threads)) => self.search_interface.set_threads(threads as usize),
+
+            // The GUI decides when to ponder, and bestmove always names the move to ponder on
+            ("Ponder", OptionValue::Check(_)) => { },
+
+            ("PVNotation", OptionValue::Combo(notation)) => {
+                let pv_notation = if notation == "san" { PvNotation::San } else { PvNotation::Uci };
+                self.search_interface.set_pv_notation(pv_notation);
+            }
+
+            ("MinimumThinkingTime", OptionValue::Spin(millis)) => self.minimum_thinking_time = millis as u32,
+
+            ("MoveTimeVariance", OptionValue::Spin(percent)) => self.move_time_variance.percent = percent as u32,
+
+            ("MoveTimeFloor", OptionValue::Spin(floor)) => self.move_time_variance.floor = floor as u32,
+
+            ("OpeningVariety", OptionValue::Spin(margin)) => self.opening_variety = margin as i16,
+
+            ("AdaptiveLMR", OptionValue::Check(adaptive_lmr)) => self.search_interface.set_adaptive_lmr(adaptive_lmr),
+
+            #[cfg(feature = "nnue")]
+            ("EvalFile", OptionValue::String(path)) => {
+                use std::sync::Arc;
+                use crate::evaluation::nnue::Network;
+
+                // Without a network, or if it doesn't load, the classical evaluation is used
+                self.network = None;
+                if !path.is_empty()
+                {
+                    match Network::load(Path::new(&path))
+                    {
+                        Ok(network) => {
+                            output::send(format_args!("info string loaded network {path}"));
+                            self.network = Some(Arc::new(network));
+                        }
+                        Err(e) => output::error(format_args!("{e}, using the classical evaluation")),
+                    }
+                }
+                self.search_interface.set_network(self.network.clone());
+            }
+
+            ("HangingPieceEval", OptionValue::Check(hanging_piece)) => {
+                self.eval_terms.hanging_piece = hanging_piece;
+                self.search_interface.set_hanging_piece_eval(hanging_piece);
+            }
+
+            ("ValidateBoards", OptionValue::Check(validate_boards)) => self.search_interface.set_validate_boards(validate_boards),
+
+            ("ReportWhiteRelative", OptionValue::Check(white_relative)) => self.search_interface.set_report_white_relative(white_relative),
+
+            ("UCI_ShowWDL", OptionValue::Check(show_wdl)) => self.search_interface.set_show_wdl(show_wdl),
+
+            ("AutosaveInterval", OptionValue::Spin(minutes)) => {
+                self.autosave_interval = minutes as u32;
+                self.update_autosave();
+            }
+
+            ("StrictUci", OptionValue::Check(strict)) => output::set_strict(strict),
+
+            ("AutosavePath", OptionValue::String(path)) => {
+                self.autosave_path = path;
+                self.update_autosave();
+            }
+
+            ("BookLearning", OptionValue::String(path)) => self.book_path = path,
+
+            ("Debug Log File", OptionValue::String(path)) => {
+                let path = (!path.is_empty()).then(|| PathBuf::from(path));
+                if let Err(e) = output::set_traffic_log(path.as_deref()) {
+                    output::error(format_args!("Could not open the debug log file: {e}, logging is off"));
+                }
+            }
+
+            ("EvalOverrides", OptionValue::String(path)) => {
+                self.overrides_path = path;
+                self.overrides = EvalOverrides::default();
+                if !self.overrides_path.is_empty()
+                {
+                    match EvalOverrides::read(Path::new(&self.overrides_path))
+                    {
+                        Ok(overrides) => {
+                            output::send(format_args!("info string Loaded {} evaluation overrides from {}", overrides.len(), self.overrides_path));
+                            self.overrides = overrides;
+                        }
+                        Err(e) => {
+                            // Overrides set from now on would replace the file, so leave it alone
+                            output::error(format_args!("Could not read the overrides from {}: {e}, overrides are off", self.overrides_path));
+                            self.overrides_path.clear();
+                        }
+                    }
+                }
+                self.search_interface.set_overrides(self.overrides.clone());
+            }
+
+            (name, value) => unreachable!("option {name} has no handler for {value:?}"),
+        }
+    }
+
+    fn command_go(&mut self, arguments: SplitAsciiWhitespace)
+    {
+        // The GUI waits for a bestmove to every go, and must not get one for the stale position
+        if !self.position_valid
+        {
+            self.search_interface.supersede();
+            output::send("info string no valid position set");
+            output::send("bestmove 0000");
+            return;
+        }
+        let Some((stop_conditions, search_moves)) = self.parse_go(arguments) else {
+            return;
+        };
+        self.report_game_over();
+        self.random_state = timeman::xorshift(self.random_state);
+        self.search_interface.set_opening_variety(self.opening_variety_margin(), self.random_state);
+        if search_moves.is_empty() {
+            self.search_interface.go(&self.position, self.halfmove_clock, &self.game_history, stop_conditions);
+        } else {
+            self.search_interface.go_searchmoves(&self.position, self.halfmove_clock, &self.game_history, stop_conditions, search_moves);
+        }
+    }
+
+    /// The stop conditions of a `go` command and the moves of its searchmoves, if any, or `None` if its
+    /// arguments are invalid
+    fn parse_go(&mut self, mut arguments: SplitAsciiWhitespace) -> Option<(StopConditions, Vec<chess::ChessMove>)>
+    {
+        let mut stop_conditions = StopConditions::new();
+        let mut search_moves = Vec::new();
+        let mut time_left = [None, None];
+        let mut increment = [None, None];
+        let mut moves_to_go = None;
+
+        loop
+        {
+           match arguments.next()
+           {
+                Some("depth") => {
+                    let depth_str = arguments.next().unwrap_or("");
+                    let depth_parsed = search::Depth::from_str(depth_str);
+                    match depth_parsed
+                    {
+                        Ok(d) => {
+                            *stop_conditions.depth.get_mut() = d;
+                        }
+                        Err(e) => {
+                            output::error(format_args!("Invalid depth \"{depth_str}\": {e}"));
+                            return None;
+                        }
+                    }
+                }
+
+                Some("movetime") => {
+                    let movetime_str = arguments.next().unwrap_or("");
+                    let movetime_parsed = u32::from_str(movetime_str);
+                    match movetime_parsed
+                    {
+                        Ok(t) => {
+                            *stop_conditions.movetime.get_mut() = self.clamp_movetime(t);
+                        }
+                        Err(e) => {
+                            output::error(format_args!("Invalid movetime \"{movetime_str}\": {e}"));
+                            return None;
+                        }
+                    }
+                }
+
+                // Extensions for puzzle batches: `go depth <D> mintime <T> maxtime <H>` searches until it
+                // has reached both depth D and T milliseconds, but no longer than H milliseconds, see
+                // `StopConditions::min_searchtime`. The epd command searches with the same limits.
+                Some(limit_str @ ("mintime" | "maxtime")) => {
+                    let value_str = arguments.next().unwrap_or("");
+                    if output::is_strict() {
+                        continue;
+                    }
+                    match u32::from_str(value_str)
+                    {
+                        Ok(t) if limit_str == "mintime" => *stop_conditions.min_searchtime.get_mut() = t,
+                        Ok(t) => *stop_conditions.movetime.get_mut() = self.clamp_movetime(t),
+                        Err(e) => {
+                            output::error(format_args!("Invalid {limit_str} \"{value_str}\": {e}"));
+                            return None;
+                        }
+                    }
+                }
+
+                Some("nodes") => {
+                    let nodes_str = arguments.next().unwrap_or("");
+                    match u64::from_str(nodes_str)
+                    {
+                        Ok(n) => {
+                            *stop_conditions.nodes.get_mut() = n;
+                        }
+                        Err(e) => {
+                            output::error(format_args!("Invalid nodes \"{nodes_str}\": {e}"));
+                            return None;
+                        }
+                    }
+                }
+
+                Some("mate") => {
+                    let mate_str = arguments.next().unwrap_or("");
+                    match u32::from_str(mate_str)
+                    {
+                        Ok(m) => {
+                            *stop_conditions.mate.get_mut() = m;
+                        }
+                        Err(e) => {
+                            output::error(format_args!("Invalid mate \"{mate_str}\": {e}"));
+                            return None;
+                        }
+                    }
+                }
+
+                Some("ponder") => {
+                    *stop_conditions.ponder.get_mut() = true;
+                }
+
+                Some("infinite") => {
+                    *stop_conditions.infinite.get_mut() = true;
+                }
+
+                Some(clock_str @ ("wtime" | "btime" | "winc" | "binc" | "movestogo")) => {
+                    let value_str = arguments.next().unwrap_or("");
+                    // Some GUIs send negative times when the engine is out of time
+                    let value = match i64::from_str(value_str)
+                    {
+                        Ok(v) => v.clamp(0, u32::MAX as i64) as u32,
+                        Err(e) => {
+                            output::error(format_args!("Invalid {clock_str} \"{value_str}\": {e}"));
+                            return None;
+                        }
+                    };
+                    match clock_str
+                    {
+                        "wtime" => time_left[chess::Color::White.to_index()] = Some(value),
+                        "btime" => time_left[chess::Color::Black.to_index()] = Some(value),
+                        "winc" => increment[chess::Color::White.to_index()] = Some(value),
+                        "binc" => increment[chess::Color::Black.to_index()] = Some(value),
+                        "movestogo" => moves_to_go = Some(value),
+                        _ => unreachable!(),
+                    }
+                }
+
+                // The moves run to the end of the command
+                Some("searchmoves") => {
+                    for word in arguments.by_ref()
+                    {
+                        match chess::ChessMove::from_str(word)
+                        {
+                            Ok(m) if self.position.legal(m) => search_moves.push(m),
+                            Ok(_) => {
+                                output::error(format_args!("Illegal move {word}"));
+                                return None;
+                            }
+                            Err(e) => {
+                                output::error(format_args!("Invalid move \"{word}\": {e}"));
+                                return None;
+                            }
+                        }
+                    }
+                    if search_moves.is_empty() {
+                        output::error("Expected moves after searchmoves");
+                        return None;
+                    }
+                }
+
+                None => break,
+
+                // The UCI protocol asks engines to ignore what they don't understand, so that a GUI sending
+                // a newer specifier still gets a search with the limits that were understood. A number
+                // after the word is taken to be its argument.
+                Some(other) => {
+                    match arguments.clone().next().filter(|word| i64::from_str(word).is_ok())
+                    {
+                        Some(value_str) => {
+                            arguments.next();
+                            output::send(format_args!("info string Ignoring unknown go specifier \"{other} {value_str}\""));
+                        }
+                        None => output::send(format_args!("info string Ignoring unknown go specifier \"{other}\"")),
+                    }
+                }
+           }
+        }
+
+        // Clock times are only used if no explicit movetime was given
+        let side_to_move = self.position.side_to_move().to_index();
+        if let Some(time_left) = time_left[side_to_move]
+        {
+            if *stop_conditions.movetime.get_mut() == 0
+            {
+                let time_control = TimeControl {
+                    time_left,
+                    increment: increment[side_to_move].unwrap_or(0),
+                    moves_to_go,
+                };
+                let phase = evaluation::game_phase(&self.position, self.fullmove_number);
+                let allocation = timeman::allocate_time(&time_control, phase);
+
+                // Only moves against the clock vary, and only if the MoveTimeVariance options ask for it
+                self.random_state = timeman::xorshift(self.random_state);
+                let varied = timeman::vary_allocation(allocation, self.move_time_variance, self.random_state);
+                *stop_conditions.movetime.get_mut() = varied.allocation.hard;
+                *stop_conditions.soft_movetime.get_mut() = varied.allocation.soft;
+                *stop_conditions.min_movetime.get_mut() = varied.floor;
+            }
+        }
+
+        Some((stop_conditions, search_moves))
+    }
+
+    /// The margin of the OpeningVariety option while the game is still in the opening, and zero after
+    fn opening_variety_margin(&self) -> i16
+    {
+        match evaluation::game_phase(&self.position, self.fullmove_number)
+        {
+            GamePhase::Opening => self.opening_variety,
+            GamePhase::Middlegame | GamePhase::Endgame => 0,
+        }
+    }
+
+    /// Tell the GUI when the game is already over in the position to search
+    ///
+    /// The search goes ahead anyway: a draw by the fifty-move rule or by repetition only happens once
+    /// it is claimed, and a position without legal moves gets "bestmove 0000".
+    fn report_game_over(&self)
+    {
+        if let Some(result) = gameresult::game_result(&self.position, self.halfmove_clock, &self.game_history) {
+            output::send(format_args!("info string Game over: {result}"));
+        }
+    }
+
+    /// Pass the Autosave options on to the search, which saves once both are set
+    fn update_autosave(&mut self)
+    {
+        let path = (!self.autosave_path.is_empty()).then(|| PathBuf::from(&self.autosave_path));
+        let interval = time::Duration::from_secs(60 * self.autosave_interval as u64);
+        self.search_interface.set_autosave(path, interval);
+    }
+
+    /// Raise a movetime below the MinimumThinkingTime option up to it. This includes `movetime 0`,
+    /// which would otherwise mean no limit at all.
+    fn clamp_movetime(&self, movetime: u32) -> u32
+    {
+        let minimum = self.minimum_thinking_time.max(1);
+        if movetime < minimum {
+            output::send(format_args!("info string movetime {movetime} is below the minimum thinking time, using {minimum}"));
+            minimum
+        } else {
+            movetime
+        }
+    }
+
+    fn command_stop(&mut self)
+    {
+        self.search_interface.stop();
+    }
+
+    fn command_ponderhit(&mut self)
+    {
+        self.search_interface.ponderhit();
+    }
+
+}
+
+/// Whether a `position` command continues the game that was tracked so far
+///
+/// Extending the game with more moves, or going back to an earlier position of it (as when jumping
+/// around in analysis), counts as the same game. Anything else, including going back to the very
+/// first position of a game that has progressed, is taken to be a new game.
+fn is_same_game(old_history: &[u64], new_history: &[u64]) -> bool
+{
+    if old_history.is_empty() {
+        // Nothing tracked yet
+        return true;
+    }
+
+    let common_length = old_history.len().min(new_history.len());
+    if old_history[..common_length] != new_history[..common_length] {
+        return false;
+    }
+
+    !(new_history.len() == 1 && old_history.len() > 1)
+}
+
+/// The contents of a hash entry as `probe` and `d` print them
+fn describe_probe(probe: &ProbeResult) -> String
+{
+    let bound = match probe.score
+    {
+        BoundedScore::Exact(_) => "exact",
+        BoundedScore::LowerBound(_) => "lowerbound",
+        BoundedScore::UpperBound(_) => "upperbound",
+    };
+    let best_move = probe.best_move.map_or("none".to_owned(), |m| m.to_string());
+    format!("depth {} bound {bound} score {} bestmove {best_move} age {} slot {}", probe.depth, probe.score.unwrap(), probe.age, probe.slot)
+}
+
+enum InputLine
+{
+    /// A line of input, with the line ending and surrounding whitespace removed
+    Line(String),
+    /// The line exceeded the maximum line length and has been skipped
+    TooLong,
+    Eof,
+}
+
+/// Reads input line by line, tolerating the quirks of various GUIs and wrappers
+///
+/// Lines may end in LF or CRLF, the first line may start with a UTF-8 byte order mark, and invalid
+/// UTF-8 is replaced rather than treated as an error. Lines longer than the maximum line length are
+/// skipped in their entirety.
+struct LineReader<R>
+{
+    reader: R,
+    buffer: Vec<u8>,
+    is_first_line: bool,
+    max_line_length: usize,
+}
+
+impl<R: BufRead> LineReader<R>
+{
+    fn new(reader: R) -> Self
+    {
+        LineReader {
+            reader,
+            buffer: Vec::new(),
+            is_first_line: true,
+            max_line_length: MAX_LINE_LENGTH,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<InputLine>
+    {
+        self.buffer.clear();
+        let limit = self.max_line_length as u64 + 1;
+        let bytes_read = (&mut self.reader).take(limit).read_until(b'\n', &mut self.buffer)?;
+        if bytes_read == 0 {
+            return Ok(InputLine::Eof);
+        }
+
+        if self.buffer.last() != Some(&b'\n') && bytes_read as u64 == limit
+        {
+            // The line got cut off, drop the rest of it
+            self.buffer.clear();
+            self.skip_rest_of_line()?;
+            self.is_first_line = false;
+            return Ok(InputLine::TooLong);
+        }
+
+        let mut line = String::from_utf8_lossy(&self.buffer).into_owned();
+        if self.is_first_line
+        {
+            self.is_first_line = false;
+            if let Some(stripped) = line.strip_prefix('\u{feff}') {
+                line = stripped.to_owned();
+            }
+        }
+
+        Ok(InputLine::Line(line.trim_ascii().to_owned()))
+    }
+
+    fn skip_rest_of_line(&mut self) -> io::Result<()>
+    {
+        loop
+        {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                // EOF, will be reported on the next read
+                return Ok(());
+            }
+            if let Some(newline_idx) = available.iter().position(|&b| b == b'\n') {
+                self.reader.consume(newline_idx + 1);
+                return Ok(());
+            }
+            let nbr_bytes = available.len();
+            self.reader.consume(nbr_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn read_all_lines(input: &[u8], max_line_length: usize) -> Vec<Option<String>>
+    {
+        let mut reader = LineReader::new(input);
+        reader.max_line_length = max_line_length;
+
+        let mut result = Vec::new();
+        loop
+        {
+            match reader.read_line().unwrap()
+            {
+                InputLine::Line(line) => result.push(Some(line)),
+                InputLine::TooLong => result.push(None),
+                InputLine::Eof => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_reader_line_endings_and_bom()
+    {
+        let input = "\u{feff}uci\r\n\r\n\nisready\r\n  go depth 3  \nquit";
+        let lines = read_all_lines(input.as_bytes(), MAX_LINE_LENGTH);
+        assert_eq!(lines, [
+            Some("uci".to_owned()),
+            Some("".to_owned()),
+            Some("".to_owned()),
+            Some("isready".to_owned()),
+            Some("go depth 3".to_owned()),
+            Some("quit".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn test_line_reader_too_long()
+    {
+        let input = format!("isready\n{}\nisready\n{}", "x".repeat(100), "y".repeat(100));
+        let lines = read_all_lines(input.as_bytes(), 50);
+        assert_eq!(lines, [Some("isready".to_owned()), None, Some("isready".to_owned()), None]);
+
+        // Exactly at the limit is fine
+        let input = format!("{}\n", "x".repeat(50));
+        let lines = read_all_lines(input.as_bytes(), 50);
+        assert_eq!(lines, [Some("x".repeat(50))]);
+    }
+
+    #[test]
+    fn test_implicit_new_game_detection()
+    {
+        let mut uci = UciClient::new();
+        let mut history_after = |command: &str| {
+            uci.command_position(command.split_ascii_whitespace());
+            uci.game_history.clone()
+        };
+
+        let start = history_after("startpos");
+        let opening = history_after("startpos moves e2e4 e7e5 g1f3");
+        let continued = history_after("startpos moves e2e4 e7e5 g1f3 b8c6 f1b5");
+        let back = history_after("startpos moves e2e4 e7e5");
+        let other_game = history_after("startpos moves d2d4 d7d5");
+        let fen = history_after("fen rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2 moves g1f3");
+
+        // Continuing a game
+        assert!(is_same_game(&[], &start));
+        assert!(is_same_game(&start, &opening));
+        assert!(is_same_game(&opening, &continued));
+
+        // Jumping around in analysis
+        assert!(is_same_game(&continued, &back));
+        assert!(is_same_game(&back, &continued));
+        assert!(is_same_game(&continued, &continued));
+
+        // A new game
+        assert!(!is_same_game(&continued, &start));
+        assert!(!is_same_game(&continued, &other_game));
+        assert!(!is_same_game(&continued, &fen));
+    }
+
+    #[test]
+    fn test_invalid_position_is_not_kept()
+    {
+        let mut uci = UciClient::new();
+        uci.command_position("startpos moves e2e4".split_ascii_whitespace());
+        assert!(uci.position_valid);
+
+        for command in [
+            "fen 8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "startpos moves e2e5",
+            "fen 4k3/8/8/8/8/8/8/4K3 w - - 0 1 moves e1e3",
+            "startpos nonsense",
+            "nonsense",
+        ] {
+            uci.command_position(command.split_ascii_whitespace());
+            assert!(!uci.position_valid, "{command}");
+        }
+
+        uci.command_position("startpos".split_ascii_whitespace());
+        assert!(uci.position_valid);
+    }
+
+    #[test]
+    fn test_every_option_has_a_handler()
+    {
+        let mut uci = UciClient::new();
+        for option in options::declared()
+        {
+            // Setting every option to its default leaves the engine as it was
+            let value = match option.kind
+            {
+                options::OptionType::Check { default } => default.to_string(),
+                options::OptionType::Spin { default, .. } => default.to_string(),
+                options::OptionType::Combo { default, .. } => default.to_owned(),
+                options::OptionType::Button => String::new(),
+                options::OptionType::String { default } => default.to_owned(),
+            };
+            uci.command_setoption(format!("name {} value {value}", option.name.to_ascii_uppercase()).split_ascii_whitespace());
+        }
+        assert_eq!(uci.options, options::declared());
+        assert_eq!(uci.params, EngineParams::default());
+        assert_eq!(uci.minimum_thinking_time, DEFAULT_MINIMUM_THINKING_TIME);
+        assert!(uci.book_path.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "tune")]
+    fn test_tuning_parameters()
+    {
+        let mut uci = UciClient::new();
+        uci.command_setoption("name PawnValue value 95".split_ascii_whitespace());
+        uci.command_setoption("name futilitymargin value 150".split_ascii_whitespace());
+        assert_eq!(uci.params, EngineParams { pawn_value: 95, futility_margin: 150, ..EngineParams::default() });
+
+        // Out of range, the parameter keeps its value
+        uci.command_setoption("name PawnValue value 20".split_ascii_whitespace());
+        assert_eq!(uci.params.pawn_value, 95);
+    }
+
+    #[test]
+    fn test_null_moves()
+    {
+        let mut uci = UciClient::new();
+        let after_e4_d4 = chess::Board::from_str("rnbqkbnr/pppppppp/8/8/3PP3/8/PPP2PPP/RNBQKBNR b KQkq - 0 2").unwrap();
+
+        // In the middle of the moves, where Black passes
+        uci.command_position("startpos moves e2e4 0000 d2d4".split_ascii_whitespace());
+        assert!(uci.position_valid);
+        assert_eq!(uci.position.get_hash(), after_e4_d4.get_hash());
+        assert_eq!((uci.fullmove_number, uci.halfmove_clock), (2, 0));
+        assert_eq!(uci.game_history.len(), 4);
+        assert_eq!(uci.game_moves[1], None);
+
+        // At the end, leaving White to move again
+        uci.command_position("startpos moves e2e4 e7e5 g1f3 0000".split_ascii_whitespace());
+        assert!(uci.position_valid);
+        assert_eq!(uci.position.side_to_move(), chess::Color::White);
+        assert_eq!((uci.fullmove_number, uci.halfmove_clock), (3, 2));
+        assert!(uci.position.piece_on(chess::Square::F3).is_some());
+
+        // Not out of check
+        uci.command_position("fen 4k3/8/8/8/8/8/8/R3K2r w - - 0 1 moves 0000".split_ascii_whitespace());
+        assert!(!uci.position_valid);
+        uci.command_position("fen 4k3/8/8/8/8/8/8/R3K3 w - - 0 1 moves a1a8 0000".split_ascii_whitespace());
+        assert!(!uci.position_valid);
+    }
+
+    #[test]
+    fn test_equivalent_positions_resume_search()
+    {
+        use std::sync::atomic::Ordering;
+
+        // The pawn on f4 is pinned, so the chess crate's en passant square after e2e4 is meaningless
+        let start = chess::Board::from_str("8/8/8/8/R4p1k/8/4P3/4K3 w - - 0 1").unwrap();
+        let from_moves = start.make_move_new(chess::ChessMove::from_str("e2e4").unwrap());
+
+        let mut uci = UciClient::new();
+        let mut position_after = |command: &str| {
+            uci.command_position(command.split_ascii_whitespace());
+            uci.position
+        };
+        let parsed_moves = position_after("fen 8/8/8/8/R4p1k/8/4P3/4K3 w - - 0 1 moves e2e4");
+        let parsed_fen = position_after("fen 8/8/8/8/R3Pp1k/8/8/4K3 b - - 0 1");
+        let parsed_fen_with_square = position_after("fen 8/8/8/8/R3Pp1k/8/8/4K3 b - e3 0 1");
+        assert_eq!(parsed_moves.get_hash(), parsed_fen.get_hash());
+        assert_eq!(parsed_fen_with_square.get_hash(), parsed_fen.get_hash());
+
+        let stop_conditions = StopConditions::new();
+        stop_conditions.depth.store(5, Ordering::Relaxed);
+        let mut searcher = search::Searcher::new(&stop_conditions);
+        searcher.search(from_moves, 0);
+
+        // Switching to the FEN picks up where the first search left off
+        stop_conditions.depth.store(7, Ordering::Relaxed);
+        let result = searcher.search(parsed_fen, 0);
+        let depths = result.iterations.iter().map(|i| i.depth).collect::<Vec<_>>();
+        assert_eq!(depths, [5, 6, 7]);
+    }
+
+    #[test]
+    fn test_minimum_thinking_time()
+    {
+        let mut uci = UciClient::new();
+        assert_eq!(uci.clamp_movetime(0), DEFAULT_MINIMUM_THINKING_TIME);
+        assert_eq!(uci.clamp_movetime(1), DEFAULT_MINIMUM_THINKING_TIME);
+        assert_eq!(uci.clamp_movetime(500), 500);
+
+        // Even without a minimum, movetime 0 must not turn into an unlimited search
+        uci.command_setoption("name MinimumThinkingTime value 0".split_ascii_whitespace());
+        assert_eq!(uci.clamp_movetime(0), 1);
+        assert_eq!(uci.clamp_movetime(1), 1);
+    }
+
+    #[test]
+    fn test_move_time_variance()
+    {
+        let mut uci = UciClient::new();
+        uci.command_position("startpos".split_ascii_whitespace());
+        let go = |uci: &mut UciClient, arguments: &str| {
+            let (stop_conditions, _) = uci.parse_go(arguments.split_ascii_whitespace()).unwrap();
+            let soft = stop_conditions.soft_movetime.into_inner() as u64;
+            (soft, stop_conditions.movetime.into_inner(), stop_conditions.min_movetime.into_inner())
+        };
+        let clock = "wtime 60000 btime 60000";
+
+        // Off by default, so every move gets the same time
+        let (soft, hard, _) = go(&mut uci, clock);
+        for _ in 0..20 {
+            assert_eq!(go(&mut uci, clock), (soft, hard, 0));
+        }
+
+        uci.command_setoption("name MoveTimeVariance value 30".split_ascii_whitespace());
+        uci.command_setoption("name MoveTimeFloor value 300".split_ascii_whitespace());
+        let varied = (0..20).map(|_| go(&mut uci, clock)).collect::<Vec<_>>();
+        assert!(varied.iter().any(|&(varied_soft, _, _)| varied_soft != soft), "{varied:?}");
+        for &(varied_soft, varied_hard, floor) in &varied {
+            assert!((soft * 70 / 100..=soft * 130 / 100).contains(&varied_soft), "{varied_soft} varied from {soft}");
+            assert_eq!((varied_hard, floor), (hard, 300));
+        }
+
+        // Fixed move times and searches without a clock, as for analysis, don't vary
+        assert_eq!(go(&mut uci, "movetime 1000"), (0, 1000, 0));
+        assert_eq!(go(&mut uci, "depth 5"), (0, 0, 0));
+        assert_eq!(go(&mut uci, ""), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_opening_variety_margin()
+    {
+        let mut uci = UciClient::new();
+        uci.command_position("startpos".split_ascii_whitespace());
+        assert_eq!(uci.opening_variety_margin(), 0);
+
+        uci.command_setoption("name OpeningVariety value 30".split_ascii_whitespace());
+        assert_eq!(uci.opening_variety_margin(), 30);
+        uci.command_position("startpos moves e2e4 e7e5 g1f3 b8c6 f1b5 a7a6".split_ascii_whitespace());
+        assert_eq!(uci.opening_variety_margin(), 30);
+
+        // Not once the opening is over, by move number or by trades
+        uci.command_position("fen r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 11".split_ascii_whitespace());
+        assert_eq!(uci.opening_variety_margin(), 0);
+        uci.command_position("fen 4k3/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/4K3 w - - 0 5".split_ascii_whitespace());
+        assert_eq!(uci.opening_variety_margin(), 0);
+    }
+
+    #[test]
+    fn test_go_searchmoves()
+    {
+        let mut uci = UciClient::new();
+        uci.command_position("startpos".split_ascii_whitespace());
+        let search_moves = |uci: &mut UciClient, arguments: &str| {
+            uci.parse_go(arguments.split_ascii_whitespace()).map(|(_, moves)| moves.iter().map(|m| m.to_string()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(search_moves(&mut uci, "depth 5"), Some(vec![]));
+        assert_eq!(search_moves(&mut uci, "depth 5 searchmoves e2e4 d2d4"), Some(vec!["e2e4".to_owned(), "d2d4".to_owned()]));
+        assert_eq!(search_moves(&mut uci, "searchmoves g1f3"), Some(vec!["g1f3".to_owned()]));
+        assert_eq!(search_moves(&mut uci, "searchmoves"), None);
+        assert_eq!(search_moves(&mut uci, "searchmoves e2e5"), None);
+        assert_eq!(search_moves(&mut uci, "searchmoves e2e4 depth 5"), None);
+    }
+
+    #[test]
+    fn test_go_unknown_specifiers()
+    {
+        let mut uci = UciClient::new();
+        uci.command_position("startpos".split_ascii_whitespace());
+        let limits = |uci: &mut UciClient, arguments: &str| {
+            let (stop_conditions, search_moves) = uci.parse_go(arguments.split_ascii_whitespace()).unwrap();
+            (stop_conditions.depth.into_inner(), stop_conditions.nodes.into_inner(), stop_conditions.movetime.into_inner() > 0, search_moves.len())
+        };
+
+        // Unknown words are skipped with their numeric argument, wherever they are
+        assert_eq!(limits(&mut uci, "depth 5 nodes 1000"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "frobnicate depth 5 nodes 1000"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "depth 5 frobnicate 12 nodes 1000"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "depth 5 frobnicate -3 nodes 1000"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "depth 5 nodes 1000 frobnicate"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "frobnicate 7 depth 5 frobnicate 12 nodes 1000 frobnicate 3"), (5, 1000, false, 0));
+        assert_eq!(limits(&mut uci, "wtime 1000 btime 1000 frobnicate winc 10 binc 10"), (0, 0, true, 0));
+        assert_eq!(limits(&mut uci, "frobnicate"), (0, 0, false, 0));
+
+        // A word after an unknown one is not taken as its argument
+        assert_eq!(limits(&mut uci, "frobnicate depth 5"), (5, 0, false, 0));
+        assert_eq!(limits(&mut uci, "frobnicate infinite depth 5"), (5, 0, false, 0));
+        assert_eq!(limits(&mut uci, "frobnicate searchmoves e2e4 d2d4"), (0, 0, false, 2));
+
+        // Invalid arguments of known specifiers are still errors
+        assert!(uci.parse_go("frobnicate 12 depth five".split_ascii_whitespace()).is_none());
+    }
+
+    #[test]
+    fn test_blocking_commands()
+    {
+        for command in BLOCKING_COMMANDS {
+            assert!(command_kind(command).is_some(), "{command}");
+        }
+    }
+
+    #[test]
+    fn test_position_with_very_long_move_list()
+    {
+        // Shuffle the knights back and forth, returning to the start position every four moves
+        let mut command = String::from("startpos moves");
+        for _ in 0..2500 {
+            command.push_str(" g1f3 g8f6 f3g1 f6g8");
+        }
+
+        let mut uci = UciClient::new();
+        uci.position = chess::Board::from_str("8/8/8/4k3/8/8/8/4K3 w - - 0 1").unwrap();
+        uci.command_position(command.split_ascii_whitespace());
+        assert_eq!(uci.position.get_hash(), chess::Board::default().get_hash());
+    }
 }
@@ -0,0 +1,236 @@
+//! The options of the engine, in one table that both the `uci` command declares and `setoption` looks
+//! names up in, so that the two can't drift apart
+//!
+//! The table only describes the options. The UCI client acts on the values that `UciOption::parse` has
+//! checked against the type and the limits of the option.
+//!
+//! Builds with the `tune` feature also declare the parameters of `params::TUNING_PARAMS`, see
+//! `declared`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::params;
+use crate::search;
+use crate::timeman;
+use super::{DEFAULT_MINIMUM_THINKING_TIME, MAX_AUTOSAVE_INTERVAL, MAX_MINIMUM_THINKING_TIME, MAX_MOVE_TIME_FLOOR};
+
+/// The type of an option, with its default and limits, as the UCI protocol defines them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OptionType
+{
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: &'static str, vars: &'static [&'static str] },
+    Button,
+    /// An empty default is declared as `<empty>`, as GUIs expect
+    String { default: &'static str },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UciOption
+{
+    pub name: &'static str,
+    pub kind: OptionType,
+}
+
+/// The value of a `setoption` command, checked against the option
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OptionValue
+{
+    Check(bool),
+    Spin(i64),
+    /// The variant as the option declares it, whatever the case it was given in
+    Combo(&'static str),
+    Button,
+    /// `<empty>` is the empty string
+    String(String),
+}
+
+/// Every option of the engine, in the order that `uci` declares them
+pub const OPTIONS: &[UciOption] = &[
+    UciOption {
+        name: "Hash",
+        kind: OptionType::Spin { default: search::DEFAULT_HASH_MEGABYTES as i64, min: 1, max: search::MAX_HASH_MEGABYTES as i64 },
+    },
+    UciOption { name: "Threads", kind: OptionType::Spin { default: 1, min: 1, max: search::MAX_THREADS as i64 } },
+    UciOption { name: "Ponder", kind: OptionType::Check { default: false } },
+    UciOption { name: "Clear Hash", kind: OptionType::Button },
+    UciOption { name: "UCI_ShowWDL", kind: OptionType::Check { default: false } },
+    // Non-standard option: SAN in the PV is for humans reading the output in a terminal
+    UciOption { name: "PVNotation", kind: OptionType::Combo { default: "uci", vars: &["uci", "san"] } },
+    UciOption {
+        name: "MinimumThinkingTime",
+        kind: OptionType::Spin { default: DEFAULT_MINIMUM_THINKING_TIME as i64, min: 0, max: MAX_MINIMUM_THINKING_TIME as i64 },
+    },
+    // For bots on online platforms, where the time spent on a move tells the opponent something
+    UciOption { name: "MoveTimeVariance", kind: OptionType::Spin { default: 0, min: 0, max: timeman::MAX_VARIANCE_PERCENT as i64 } },
+    UciOption { name: "MoveTimeFloor", kind: OptionType::Spin { default: 0, min: 0, max: MAX_MOVE_TIME_FLOOR as i64 } },
+    // For playing many games against the same opponent, which would otherwise all open the same way
+    UciOption { name: "OpeningVariety", kind: OptionType::Spin { default: 0, min: 0, max: search::MAX_OPENING_VARIETY as i64 } },
+    // Experimental: tunes the late move reductions during the search
+    UciOption { name: "AdaptiveLMR", kind: OptionType::Check { default: false } },
+    // Experimental: an evaluation term for comparing strength with and without it
+    UciOption { name: "HangingPieceEval", kind: OptionType::Check { default: false } },
+    // Debugging: checks every board in the search, also in builds without debug assertions
+    UciOption { name: "ValidateBoards", kind: OptionType::Check { default: false } },
+    // Debugging: scores in info lines from White's point of view, rather than the side to move's
+    UciOption { name: "ReportWhiteRelative", kind: OptionType::Check { default: false } },
+    // For long analysis: snapshots of the hash table that survive a crash, see `loadhash`
+    UciOption { name: "AutosaveInterval", kind: OptionType::Spin { default: 0, min: 0, max: MAX_AUTOSAVE_INTERVAL as i64 } },
+    UciOption { name: "AutosavePath", kind: OptionType::String { default: "" } },
+    #[cfg(feature = "nnue")]
+    UciOption { name: "EvalFile", kind: OptionType::String { default: "" } },
+    // For tournament wrappers that reject any output outside the protocol
+    UciOption { name: "StrictUci", kind: OptionType::Check { default: false } },
+    // For selfplay: the path of a book that learns from the results of games, see `learn`
+    UciOption { name: "BookLearning", kind: OptionType::String { default: "" } },
+    // For analysis: the path of a file of evaluations pinned to positions, see `override`
+    UciOption { name: "EvalOverrides", kind: OptionType::String { default: "" } },
+    // Debugging: records the conversation with the GUI in a file, see `output::set_traffic_log`
+    UciOption { name: "Debug Log File", kind: OptionType::String { default: "" } },
+];
+
+/// The options that the engine declares: `OPTIONS`, followed by the tuning parameters in builds with
+/// the `tune` feature
+pub fn declared() -> Vec<UciOption>
+{
+    let tuning = params::TUNING_PARAMS.iter()
+        .filter(|_| cfg!(feature = "tune"))
+        .map(|param| UciOption {
+            name: param.name,
+            kind: OptionType::Spin { default: param.default_value() as i64, min: param.min as i64, max: param.max as i64 },
+        });
+    OPTIONS.iter().copied().chain(tuning).collect()
+}
+
+/// The option called `name` among `options`. Option names are not case sensitive.
+pub fn find<'a>(options: &'a [UciOption], name: &str) -> Option<&'a UciOption>
+{
+    options.iter().find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+impl UciOption
+{
+    /// Check the value of a `setoption` command against the type and the limits of the option
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String>
+    {
+        let name = self.name;
+        match self.kind
+        {
+            OptionType::Check { .. } => {
+                match value.to_ascii_lowercase().as_str()
+                {
+                    "true" => Ok(OptionValue::Check(true)),
+                    "false" => Ok(OptionValue::Check(false)),
+                    _ => Err(format!("Invalid {name} \"{value}\", expected \"true\" or \"false\"")),
+                }
+            }
+            OptionType::Spin { min, max, .. } => {
+                match i64::from_str(value)
+                {
+                    Ok(number) if (min..=max).contains(&number) => Ok(OptionValue::Spin(number)),
+                    _ => Err(format!("Invalid {name} \"{value}\", expected {min} to {max}")),
+                }
+            }
+            OptionType::Combo { vars, .. } => {
+                match vars.iter().find(|var| var.eq_ignore_ascii_case(value))
+                {
+                    Some(var) => Ok(OptionValue::Combo(var)),
+                    None => {
+                        let expected = vars.iter().map(|var| format!("\"{var}\"")).collect::<Vec<_>>();
+                        Err(format!("Invalid {name} \"{value}\", expected {}", expected.join(" or ")))
+                    }
+                }
+            }
+            OptionType::Button => Ok(OptionValue::Button),
+            OptionType::String { .. } => {
+                Ok(OptionValue::String(if value == "<empty>" { String::new() } else { value.to_owned() }))
+            }
+        }
+    }
+}
+
+/// The `option` line that declares the option to the GUI
+impl fmt::Display for UciOption
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "option name {} type ", self.name)?;
+        match self.kind
+        {
+            OptionType::Check { default } => write!(f, "check default {default}"),
+            OptionType::Spin { default, min, max } => write!(f, "spin default {default} min {min} max {max}"),
+            OptionType::Combo { default, vars } => {
+                write!(f, "combo default {default}")?;
+                vars.iter().try_for_each(|var| write!(f, " var {var}"))
+            }
+            OptionType::Button => write!(f, "button"),
+            OptionType::String { default } => write!(f, "string default {}", if default.is_empty() { "<empty>" } else { default }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_declarations()
+    {
+        let declaration = |name| find(OPTIONS, name).unwrap().to_string();
+        assert_eq!(
+            declaration("Hash"),
+            format!("option name Hash type spin default {} min 1 max {}", search::DEFAULT_HASH_MEGABYTES, search::MAX_HASH_MEGABYTES),
+        );
+        assert_eq!(declaration("Ponder"), "option name Ponder type check default false");
+        assert_eq!(declaration("Clear Hash"), "option name Clear Hash type button");
+        assert_eq!(declaration("PVNotation"), "option name PVNotation type combo default uci var uci var san");
+        assert_eq!(declaration("AutosavePath"), "option name AutosavePath type string default <empty>");
+        assert_eq!(declaration("Debug Log File"), "option name Debug Log File type string default <empty>");
+    }
+
+    #[test]
+    fn test_names()
+    {
+        assert_eq!(find(OPTIONS, "clear hash").map(|option| option.name), Some("Clear Hash"));
+        assert_eq!(find(OPTIONS, "NoSuchOption"), None);
+
+        // Also the tuning parameters, whether this build declares them or not
+        let all = OPTIONS.iter().map(|option| option.name).chain(params::TUNING_PARAMS.iter().map(|param| param.name)).collect::<Vec<_>>();
+        for (i, name) in all.iter().enumerate() {
+            assert!(all[..i].iter().all(|other| !other.eq_ignore_ascii_case(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_tuning_options()
+    {
+        let declared = declared();
+        assert_eq!(&declared[..OPTIONS.len()], OPTIONS);
+        let pawn_value = find(&declared, "PawnValue");
+        if cfg!(feature = "tune") {
+            assert_eq!(pawn_value.unwrap().to_string(), "option name PawnValue type spin default 100 min 50 max 200");
+        } else {
+            assert_eq!(pawn_value, None);
+        }
+    }
+
+    #[test]
+    fn test_parse()
+    {
+        let parse = |name, value| find(OPTIONS, name).unwrap().parse(value);
+        assert_eq!(parse("Threads", "4"), Ok(OptionValue::Spin(4)));
+        assert_eq!(parse("Threads", "0"), Err(format!("Invalid Threads \"0\", expected 1 to {}", search::MAX_THREADS)));
+        assert!(parse("Hash", "99999999999999999999").is_err());
+        assert!(parse("Hash", "").is_err());
+        assert_eq!(parse("AdaptiveLMR", "TRUE"), Ok(OptionValue::Check(true)));
+        assert_eq!(parse("AdaptiveLMR", "maybe"), Err("Invalid AdaptiveLMR \"maybe\", expected \"true\" or \"false\"".to_owned()));
+        assert_eq!(parse("PVNotation", "SAN"), Ok(OptionValue::Combo("san")));
+        assert_eq!(parse("PVNotation", ""), Err("Invalid PVNotation \"\", expected \"uci\" or \"san\"".to_owned()));
+        assert_eq!(parse("Clear Hash", "anything"), Ok(OptionValue::Button));
+        assert_eq!(parse("BookLearning", "<empty>"), Ok(OptionValue::String(String::new())));
+        assert_eq!(parse("BookLearning", "my book.bin"), Ok(OptionValue::String("my book.bin".to_owned())));
+    }
+}
@@ -0,0 +1,78 @@
+//! Piece values, shared by everything that weighs material
+//!
+//! There are two sets. `piece_value` is what the evaluation counts material as by default, and is the
+//! one to tune, see `params::EngineParams`. `exchange_value` is what the static exchange evaluation
+//! weighs captures by. It stays fixed, so that tuning the evaluation doesn't change which exchanges
+//! look winning.
+
+use chess::Piece;
+
+/// Material value of a piece in the evaluation, in centipawns. The king is never traded, so it
+/// counts for nothing.
+pub const fn piece_value(piece: Piece) -> i16
+{
+    match piece
+    {
+        Piece::Pawn => 100,
+        Piece::Knight => 300,
+        Piece::Bishop => 300,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+/// Value of a piece in exchanges, in centipawns. The king can never actually be captured, so its
+/// value only needs to exceed anything it could win by capturing.
+pub const fn exchange_value(piece: Piece) -> i16
+{
+    match piece
+    {
+        Piece::Pawn => 100,
+        Piece::Knight => 300,
+        Piece::Bishop => 300,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 10_000,
+    }
+}
+
+/// How much the material balance in the evaluation changes when a pawn promotes to `piece`
+// Nothing weighs promotions yet, see "Quiescent search: promotion" in TODO.md
+#[allow(dead_code)]
+pub const fn promotion_delta(piece: Piece) -> i16
+{
+    piece_value(piece) - piece_value(Piece::Pawn)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn assert_standard_ordering(value: fn(Piece) -> i16)
+    {
+        assert!(value(Piece::Pawn) < value(Piece::Knight));
+        assert!(value(Piece::Knight) <= value(Piece::Bishop));
+        assert!(value(Piece::Bishop) < value(Piece::Rook));
+        assert!(value(Piece::Rook) < value(Piece::Queen));
+    }
+
+    #[test]
+    fn test_standard_ordering()
+    {
+        assert_standard_ordering(piece_value);
+        assert_standard_ordering(exchange_value);
+
+        // Trading down into a capture of the king must never look good in an exchange
+        assert!(exchange_value(Piece::King) > 2 * exchange_value(Piece::Queen));
+    }
+
+    #[test]
+    fn test_promotion_delta()
+    {
+        assert_eq!(promotion_delta(Piece::Queen), 800);
+        assert_eq!(promotion_delta(Piece::Knight), 200);
+        assert_eq!(promotion_delta(Piece::Pawn), 0);
+    }
+}
@@ -0,0 +1,42 @@
+//! Identification of the build, so that results can be traced back to the build that produced them
+
+/// Package name and version from Cargo
+const NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Output of `git describe` at build time, if the engine was built from a git checkout
+const GIT_DESCRIBE: Option<&str> = option_env!("ENGINE_GIT_DESCRIBE");
+
+/// Cargo profile of the build, such as "release" or "debug"
+const BUILD_PROFILE: &str = env!("ENGINE_BUILD_PROFILE");
+
+/// Enabled Cargo features separated by spaces, or "none"
+const FEATURES: &str = env!("ENGINE_FEATURES");
+
+/// Instruction set extensions of the build among popcnt, bmi2 and avx2, separated by spaces, or "none"
+const CPU_FEATURES: &str = env!("ENGINE_CPU_FEATURES");
+
+/// Name and version of the engine, with a short description of the build: the git revision it was built
+/// from if known, and the features and instruction set extensions that are enabled, so that the logs of
+/// different builds, such as those of a bench, can be told apart
+pub fn version() -> String
+{
+    let description = [GIT_DESCRIBE, Some(FEATURES), Some(CPU_FEATURES)]
+        .into_iter()
+        .flatten()
+        .filter(|part| *part != "none")
+        .collect::<Vec<_>>();
+    match description.is_empty()
+    {
+        true => format!("{NAME} {VERSION}"),
+        false => format!("{NAME} {VERSION} ({})", description.join(", ")),
+    }
+}
+
+/// How the engine was built: the profile, whether debug assertions are on, the enabled features and the
+/// instruction set extensions
+pub fn build_info() -> String
+{
+    let assertions = if cfg!(debug_assertions) { "on" } else { "off" };
+    format!("profile {BUILD_PROFILE} debug-assertions {assertions} features {FEATURES} cpu {CPU_FEATURES}")
+}
@@ -0,0 +1,196 @@
+//! The engine for web pages and Node.js, as a WebAssembly module
+//!
+//! The module is built with `wasm-pack build -- --no-default-features`, and tested with
+//! `wasm-pack test --node -- --no-default-features --test wasm`.
+//!
+//! JavaScript gives the module a single thread, so `go` searches until it is done before it returns.
+//! Meanwhile it calls the info callback with each line that a UCI engine would print, and every
+//! `YIELD_INTERVAL` with the nodes searched so far. That is the host's chance to call `stop`.
+//!
+//! ```js
+//! const engine = new Engine();
+//! engine.set_position("startpos", ["e2e4", "e7e5"]);
+//! const bestmove = engine.go("movetime 1000", line => console.log(line));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use chess::{Board, ChessMove};
+use wasm_bindgen::prelude::*;
+use crate::boardutil::PositionExt;
+use crate::clock::{Clock, Instant};
+use crate::fen::parse_fen;
+use crate::output;
+use crate::rootmoves::canonical_root;
+use crate::search::{self, Depth, Searcher};
+use crate::stopconditions::StopConditions;
+
+/// A running search calls the info callback at least this often, so that the host can stop it
+const YIELD_INTERVAL: Duration = Duration::from_millis(50);
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// The clock of the host, `performance.now()`, which browsers and Node.js both have
+pub struct PerformanceClock;
+
+impl Clock for PerformanceClock
+{
+    fn now(&self) -> Duration
+    {
+        Duration::from_secs_f64(performance_now() / 1000.0)
+    }
+}
+
+/// An engine for JavaScript, which keeps its position and its hash table from one search to the next
+#[wasm_bindgen]
+pub struct Engine
+{
+    // Declared before the stop conditions that it borrows, so that it is dropped first
+    searcher: RefCell<Searcher<'static>>,
+    stop_conditions: Box<StopConditions>,
+    position: Cell<Board>,
+    /// Number of plies since the last capture or pawn move
+    halfmove_clock: Cell<u32>,
+    /// Hashes of the positions of the game up to and including `position`, for avoiding repetitions
+    game_history: RefCell<Vec<u64>>,
+}
+
+#[wasm_bindgen]
+impl Engine
+{
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Engine
+    {
+        let stop_conditions = Box::new(StopConditions::new());
+        // SAFETY: Artificially prolonging the lifetime of the borrow. The box keeps the stop conditions
+        // in place when the engine moves, and the searcher is dropped before the box.
+        let borrowed_stop_conditions = unsafe { &*(&*stop_conditions as *const StopConditions) };
+        let position = Board::default();
+        Engine {
+            searcher: RefCell::new(Searcher::new(borrowed_stop_conditions)),
+            stop_conditions,
+            position: Cell::new(position),
+            halfmove_clock: Cell::new(0),
+            game_history: RefCell::new(vec![canonical_root(&position).get_hash()]),
+        }
+    }
+
+    /// Set the position to search: `fen` is a FEN or "startpos", and `moves` are the moves played from
+    /// there in UCI notation, like "e2e4" or "e7e8q"
+    pub fn set_position(&self, fen: &str, moves: Vec<String>) -> Result<(), JsError>
+    {
+        let mut position = match fen
+        {
+            "startpos" => Board::default(),
+            fen => parse_fen(fen).map_err(|e| JsError::new(&e))?,
+        };
+        let mut halfmove_clock = fen.split_ascii_whitespace()
+            .nth(4)
+            .and_then(|n| u32::from_str(n).ok())
+            .unwrap_or(0);
+        let mut game_history = vec![canonical_root(&position).get_hash()];
+
+        for move_str in &moves
+        {
+            let next_move = ChessMove::from_str(move_str).ok()
+                .filter(|&next_move| position.legal(next_move))
+                .ok_or_else(|| JsError::new(&format!("Illegal move {move_str}")))?;
+            if position.resets_halfmove_clock(next_move) {
+                halfmove_clock = 0;
+            } else {
+                halfmove_clock += 1;
+            }
+            position = position.make_move_new(next_move);
+            game_history.push(canonical_root(&position).get_hash());
+        }
+
+        self.position.set(canonical_root(&position));
+        self.halfmove_clock.set(halfmove_clock);
+        *self.game_history.borrow_mut() = game_history;
+        Ok(())
+    }
+
+    /// Search the position, and return the best move in UCI notation, or `undefined` if there is no
+    /// legal move
+    ///
+    /// `limit` is "depth <plies>" or "movetime <milliseconds>". `info_callback` gets each line that a
+    /// UCI engine would print, the final `bestmove` included, and an `info nodes` line every
+    /// `YIELD_INTERVAL`. It may call `stop`, but not `go`.
+    pub fn go(&self, limit: &str, info_callback: &js_sys::Function) -> Result<Option<String>, JsError>
+    {
+        let (depth, movetime) = parse_limit(limit).map_err(|e| JsError::new(&e))?;
+        let Ok(mut searcher) = self.searcher.try_borrow_mut() else {
+            return Err(JsError::new("A search is running already"));
+        };
+
+        let mut stop_conditions = StopConditions::new();
+        *stop_conditions.depth.get_mut() = depth;
+        *stop_conditions.movetime.get_mut() = movetime;
+        self.stop_conditions.assign(stop_conditions);
+        self.stop_conditions.stop_now.store(false, Ordering::Release);
+
+        // An exception thrown by the callback is the host's business, and doesn't stop the search
+        let callback = info_callback.clone();
+        output::set_sink(Some(Box::new(move |line| {
+            let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(line));
+        })));
+        let callback = info_callback.clone();
+        let starttime = Instant::now();
+        let mut next_yield = YIELD_INTERVAL;
+        search::set_yield_hook(Some(Box::new(move |nodes| {
+            let elapsed = starttime.elapsed();
+            if elapsed >= next_yield
+            {
+                next_yield = elapsed + YIELD_INTERVAL;
+                let line = format!("info nodes {nodes} time {}", elapsed.as_millis());
+                let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(&line));
+            }
+        })));
+
+        searcher.set_game_history(self.game_history.borrow().clone());
+        let result = searcher.search(self.position.get(), self.halfmove_clock.get());
+
+        search::set_yield_hook(None);
+        output::set_sink(None);
+        Ok(result.best_move.map(|best_move| best_move.to_string()))
+    }
+
+    /// Stop the running search, which then returns the best move it has found so far. While `go` runs,
+    /// only the info callback gets to call this.
+    pub fn stop(&self)
+    {
+        self.stop_conditions.stop();
+    }
+}
+
+impl Default for Engine
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// The depth and movetime limits of `go`, where `Depth::MAX` and zero mean no limit
+fn parse_limit(limit: &str) -> Result<(Depth, u32), String>
+{
+    let invalid = || format!("Expected \"depth <plies>\" or \"movetime <milliseconds>\", got \"{limit}\"");
+    match limit.split_ascii_whitespace().collect::<Vec<_>>()[..]
+    {
+        ["depth", depth] => Ok((Depth::from_str(depth).map_err(|_| invalid())?, 0)),
+        // Without a limit, the search would never return
+        ["movetime", movetime] => match u32::from_str(movetime)
+        {
+            Ok(movetime) if movetime > 0 => Ok((Depth::MAX, movetime)),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
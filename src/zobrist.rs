@@ -0,0 +1,272 @@
+//! The Zobrist hash of the position after a move, without making the move
+//!
+//! The chess crate hashes a board by XORing together a key for every piece on its square, keys for
+//! the castling rights of either side, a key for the file of a possible en passant capture, and a key
+//! for black to move. It keeps the keys to itself, so `Keys` recovers them from the hashes of boards
+//! that differ in one of these things only. With the keys, `zobrist_after` updates the hash of a
+//! position for a move, which is cheaper than building the board after the move only to hash it.
+//!
+//! Some keys can only be recovered relative to a reference: every board has both kings, and castling
+//! rights are always some rights, if only none. A move changes the hash by the key from before XOR the
+//! key after, in which the reference cancels out.
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use chess::{BitBoard, Board, CastleRights, ChessMove, Color, File, Piece, Rank, Square};
+use crate::boardutil::PositionExt;
+
+/// The square that the king keys are relative to
+const KING_REFERENCE: Square = Square::D4;
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+/// The hash of `board` after `chess_move`, the same as `board.make_move_new(chess_move).get_hash()`
+///
+/// The move must be legal.
+pub fn zobrist_after(board: &Board, chess_move: ChessMove) -> u64
+{
+    let keys = KEYS.get_or_init(Keys::recover);
+    let us = board.side_to_move();
+    let them = !us;
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
+    let moved = board.piece_on(source).expect("there is no piece to move");
+
+    let mut hash = board.get_hash() ^ keys.black_to_move;
+
+    // Whatever en passant capture there was, it can't be made anymore
+    if let Some(square) = board.en_passant() {
+        hash ^= keys.en_passant[them.to_index()][square.get_file().to_index()];
+    }
+
+    hash ^= keys.piece(us, moved, source) ^ keys.piece(us, chess_move.get_promotion().unwrap_or(moved), dest);
+    if let Some(captured) = board.piece_on(dest) {
+        hash ^= keys.piece(them, captured, dest);
+    } else if moved == Piece::Pawn && source.get_file() != dest.get_file() {
+        // En passant, the captured pawn stands beside the source square
+        hash ^= keys.piece(them, Piece::Pawn, Square::make_square(source.get_rank(), dest.get_file()));
+    }
+
+    if board.is_castling(chess_move)
+    {
+        let (rook_source, rook_dest) = if dest.get_file().to_index() > source.get_file().to_index() {
+            (File::H, File::F)
+        } else {
+            (File::A, File::D)
+        };
+        hash ^= keys.piece(us, Piece::Rook, Square::make_square(source.get_rank(), rook_source))
+            ^ keys.piece(us, Piece::Rook, Square::make_square(source.get_rank(), rook_dest));
+    }
+
+    // Moving the king or a rook from its square loses castling rights, and so does losing a rook
+    let our_rights = board.my_castle_rights();
+    let their_rights = board.their_castle_rights();
+    hash ^= keys.castling(us, our_rights) ^ keys.castling(us, our_rights.remove(CastleRights::square_to_castle_rights(us, source)));
+    hash ^= keys.castling(them, their_rights) ^ keys.castling(them, their_rights.remove(CastleRights::square_to_castle_rights(them, dest)));
+
+    // The chess crate only allows en passant after a double push if an enemy pawn stands beside it
+    if moved == Piece::Pawn && source.get_rank().to_index().abs_diff(dest.get_rank().to_index()) == 2
+    {
+        let enemy_pawns = board.pieces(Piece::Pawn) & board.color_combined(them);
+        let beside = [dest.left(), dest.right()].into_iter().flatten();
+        if beside.any(|square| enemy_pawns & BitBoard::from_square(square) != chess::EMPTY) {
+            hash ^= keys.en_passant[us.to_index()][dest.get_file().to_index()];
+        }
+    }
+
+    hash
+}
+
+/// The Zobrist keys of the chess crate
+struct Keys
+{
+    /// By color, piece and square. The keys of kings are relative to a king on `KING_REFERENCE`.
+    pieces: [[[u64; 64]; 6]; 2],
+    /// By color and `CastleRights::to_index`, relative to no castling rights
+    castling: [[u64; 4]; 2],
+    /// By the color of the pawn that can be captured en passant, and its file
+    en_passant: [[u64; 8]; 2],
+    black_to_move: u64,
+}
+
+impl Keys
+{
+    fn piece(&self, color: Color, piece: Piece, square: Square) -> u64
+    {
+        self.pieces[color.to_index()][piece.to_index()][square.to_index()]
+    }
+
+    fn castling(&self, color: Color, rights: CastleRights) -> u64
+    {
+        self.castling[color.to_index()][rights.to_index()]
+    }
+
+    /// Recover the keys from the hashes of boards that differ by one key
+    fn recover() -> Keys
+    {
+        let mut keys = Keys {
+            pieces: [[[0; 64]; 6]; 2],
+            castling: [[0; 4]; 2],
+            en_passant: [[0; 8]; 2],
+            black_to_move: 0,
+        };
+
+        let kings = [(Square::A1, Piece::King, Color::White), (Square::H8, Piece::King, Color::Black)];
+        keys.black_to_move = hash_of(&kings, Color::White, "-") ^ hash_of(&kings, Color::Black, "-");
+
+        for color in chess::ALL_COLORS
+        {
+            for square in chess::ALL_SQUARES
+            {
+                // The kings stand in corners, out of the way of the piece. With the other side to move,
+                // a piece that gives check still makes a legal board.
+                let kings = if square == Square::A1 || square == Square::H8 {
+                    [(Square::A8, Piece::King, Color::White), (Square::H1, Piece::King, Color::Black)]
+                } else {
+                    kings
+                };
+                for piece in [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+                {
+                    let is_back_rank = square.get_rank() == Rank::First || square.get_rank() == Rank::Eighth;
+                    if piece == Piece::Pawn && is_back_rank {
+                        continue;
+                    }
+                    let with_piece = [kings[0], kings[1], (square, piece, color)];
+                    keys.pieces[color.to_index()][piece.to_index()][square.to_index()] =
+                        hash_of(&with_piece, !color, "-") ^ hash_of(&kings, !color, "-");
+                }
+
+                // The other king waits in a corner away from both squares
+                let corner = [Square::A1, Square::H1, Square::A8, Square::H8].into_iter()
+                    .find(|&corner| distance(corner, square) > 1)
+                    .unwrap();
+                let king_on = |king_square| [(king_square, Piece::King, color), (corner, Piece::King, !color)];
+                keys.pieces[color.to_index()][Piece::King.to_index()][square.to_index()] =
+                    hash_of(&king_on(square), Color::White, "-") ^ hash_of(&king_on(KING_REFERENCE), Color::White, "-");
+            }
+
+            let castling_position = [
+                (Square::E1, Piece::King, Color::White),
+                (Square::A1, Piece::Rook, Color::White),
+                (Square::H1, Piece::Rook, Color::White),
+                (Square::E8, Piece::King, Color::Black),
+                (Square::A8, Piece::Rook, Color::Black),
+                (Square::H8, Piece::Rook, Color::Black),
+            ];
+            for (rights, letters) in [(CastleRights::KingSide, "K"), (CastleRights::QueenSide, "Q"), (CastleRights::Both, "KQ")]
+            {
+                let letters = if color == Color::White { letters.to_owned() } else { letters.to_lowercase() };
+                keys.castling[color.to_index()][rights.to_index()] =
+                    hash_of(&castling_position, Color::White, &letters) ^ hash_of(&castling_position, Color::White, "-");
+            }
+
+            // A double push with an enemy pawn beside it, against the same pawns without en passant
+            let (second_rank, fourth_rank) = match color
+            {
+                Color::White => (Rank::Second, Rank::Fourth),
+                Color::Black => (Rank::Seventh, Rank::Fifth),
+            };
+            for file in chess::ALL_FILES
+            {
+                let beside = File::from_index(if file == File::A { 1 } else { file.to_index() - 1 });
+                let enemy_pawn = (Square::make_square(fourth_rank, beside), Piece::Pawn, !color);
+                let kings = [(Square::E1, Piece::King, Color::White), (Square::E8, Piece::King, Color::Black)];
+                let before = [kings[0], kings[1], (Square::make_square(second_rank, file), Piece::Pawn, color), enemy_pawn];
+                let after = [kings[0], kings[1], (Square::make_square(fourth_rank, file), Piece::Pawn, color), enemy_pawn];
+
+                let double_push = ChessMove::new(Square::make_square(second_rank, file), Square::make_square(fourth_rank, file), None);
+                let pushed = board_of(&before, color, "-").make_move_new(double_push);
+                keys.en_passant[color.to_index()][file.to_index()] = pushed.get_hash() ^ hash_of(&after, !color, "-");
+            }
+        }
+
+        keys
+    }
+}
+
+/// The number of king moves between two squares
+fn distance(a: Square, b: Square) -> usize
+{
+    let files = a.get_file().to_index().abs_diff(b.get_file().to_index());
+    let ranks = a.get_rank().to_index().abs_diff(b.get_rank().to_index());
+    files.max(ranks)
+}
+
+fn hash_of(pieces: &[(Square, Piece, Color)], side_to_move: Color, castling: &str) -> u64
+{
+    board_of(pieces, side_to_move, castling).get_hash()
+}
+
+/// The board with `pieces`, through a FEN, since that is how the chess crate takes castling rights
+fn board_of(pieces: &[(Square, Piece, Color)], side_to_move: Color, castling: &str) -> Board
+{
+    let mut placement = String::new();
+    for rank in chess::ALL_RANKS.into_iter().rev()
+    {
+        let mut empty = 0;
+        for file in chess::ALL_FILES
+        {
+            let square = Square::make_square(rank, file);
+            match pieces.iter().find(|(on, _, _)| *on == square)
+            {
+                Some(&(_, piece, color)) => {
+                    if empty > 0 {
+                        placement += &empty.to_string();
+                        empty = 0;
+                    }
+                    placement += &piece.to_string(color);
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            placement += &empty.to_string();
+        }
+        if rank != Rank::First {
+            placement.push('/');
+        }
+    }
+
+    let side = if side_to_move == Color::White { "w" } else { "b" };
+    let fen = format!("{placement} {side} {castling} - 0 1");
+    Board::from_str(&fen).unwrap_or_else(|e| panic!("{fen} is not a valid board: {e}"))
+}
+
+#[cfg(test)]
+mod test
+{
+    use chess::MoveGen;
+
+    use super::*;
+
+    /// Check every move in the tree below `position`
+    fn check_tree(position: &Board, depth: u32)
+    {
+        for chess_move in MoveGen::new_legal(position)
+        {
+            let new_position = position.make_move_new(chess_move);
+            assert_eq!(zobrist_after(position, chess_move), new_position.get_hash(), "{chess_move} in {position}");
+            if depth > 1 {
+                check_tree(&new_position, depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zobrist_after_matches_make_move()
+    {
+        // Castling, en passant and promotions, with and without captures, come up in these trees
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+        for fen in positions {
+            check_tree(&Board::from_str(fen).unwrap(), 3);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Runs `bench` twice and checks that its node signature is the same both times, that the dispatch
+//! and prefetch benches search the same nodes whichever way they time, and that the inserts and
+//! hanging piece benches report their timings
+
+mod common;
+
+use common::{field, Engine};
+
+/// The position lines and the node signature of a bench
+fn bench(engine: &mut Engine, depth: u32) -> (Vec<String>, u64)
+{
+    engine.send(&format!("bench {depth}"));
+    let lines = engine.wait_for("Nodes searched: ");
+    let positions = lines.iter()
+        .filter(|(_, line)| line.starts_with("info string bench position "))
+        .map(|(_, line)| line.clone())
+        .collect();
+    let (_, signature) = lines.last().unwrap();
+    (positions, signature.trim_start_matches("Nodes searched: ").parse().unwrap())
+}
+
+#[test]
+fn test_bench_signature()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    let (positions, signature) = bench(&mut engine, 2);
+    assert_eq!(positions.len(), 40);
+    assert!(positions[0].starts_with("info string bench position 0 depth 2 nodes "), "{}", positions[0]);
+    assert!(positions[39].contains(" fen "), "{}", positions[39]);
+    let nodes = positions.iter()
+        .map(|line| {
+            let mut words = line.split_ascii_whitespace();
+            words.find(|&w| w == "nodes").and_then(|_| words.next()).unwrap().parse::<u64>().unwrap()
+        })
+        .sum::<u64>();
+    assert_eq!(nodes, signature);
+
+    // Neither the hash table left by the first bench nor the time it took changes the second
+    let (_, rerun_signature) = bench(&mut engine, 2);
+    assert_eq!(rerun_signature, signature);
+}
+
+#[test]
+fn test_bench_dispatch()
+{
+    let mut engine = Engine::start();
+    engine.send("bench 1 --dispatch");
+    let lines = engine.wait_for("info string bench dispatch dyn ");
+    let nodes: Vec<u64> = lines.iter()
+        .filter(|(_, line)| line.starts_with("info string bench dispatch "))
+        .map(|(_, line)| field(line, "nodes").unwrap())
+        .collect();
+    assert_eq!(nodes.len(), 3);
+    assert!(nodes.iter().all(|&n| n == nodes[0] && n > 0), "{nodes:?}");
+}
+
+#[test]
+fn test_bench_prefetch()
+{
+    let mut engine = Engine::start();
+    engine.send("bench 1 --prefetch 16");
+    let lines = engine.wait_for("info string bench prefetch gains ");
+    let nodes: Vec<u64> = lines.iter()
+        .filter(|(_, line)| line.starts_with("info string bench prefetch ") && line.contains(" nodes "))
+        .map(|(_, line)| field(line, "nodes").unwrap())
+        .collect();
+    assert_eq!(nodes.len(), 2);
+    assert!(nodes[0] == nodes[1] && nodes[0] > 0, "{nodes:?}");
+
+    engine.send("bench 1 --prefetch lots");
+    engine.wait_for("ERROR: Invalid hash size \"lots\"");
+}
+
+#[test]
+fn test_bench_inserts()
+{
+    let mut engine = Engine::start();
+    engine.send("bench --inserts 1");
+    let lines = engine.wait_for("info string bench inserts ");
+    let (_, line) = lines.last().unwrap();
+    assert!(field::<u64>(line, "stores").unwrap() > 0, "{line}");
+    assert!(line.contains(" ns purging ") && line.contains(" extra time "), "{line}");
+
+    engine.send("bench --inserts 0");
+    engine.wait_for("ERROR: Invalid hash size \"0\" after --inserts");
+}
+
+#[test]
+fn test_bench_hanging()
+{
+    let mut engine = Engine::start();
+    engine.send("bench --hanging");
+    let lines = engine.wait_for("info string bench hanging ");
+    let (_, line) = lines.last().unwrap();
+    assert_eq!(field::<usize>(line, "evaluations"), Some(40_000), "{line}");
+    assert!(line.contains(" ns with ") && line.contains(" extra time "), "{line}");
+}
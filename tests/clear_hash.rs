@@ -0,0 +1,73 @@
+//! Presses the Clear Hash button, as GUIs offer it, after a search and during one
+
+mod common;
+
+use common::{field, Engine};
+
+/// An engine with a 1 MB hash table, which a short search fills noticeably
+fn start_engine() -> Engine
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("setoption name Hash value 1");
+    engine.send("position startpos");
+    engine
+}
+
+/// The hashfull of the last info line with a depth, up to the bestmove
+fn final_hashfull(engine: &mut Engine) -> u32
+{
+    let lines = engine.wait_for("bestmove");
+    let info = lines.iter()
+        .map(|(_, line)| line)
+        .filter(|line| line.starts_with("info depth"))
+        .next_back()
+        .expect("no info line before bestmove");
+    field(info, "hashfull").expect("no hashfull")
+}
+
+/// Check that nothing is left in the table: not the start position, and not enough for the next
+/// search to report any hashfull
+fn assert_hash_empty(engine: &mut Engine)
+{
+    engine.send("d");
+    engine.wait_for("info string Hash entry: none");
+    engine.send("go depth 1");
+    assert_eq!(final_hashfull(engine), 0);
+}
+
+#[test]
+fn test_clear_hash_after_search()
+{
+    let mut engine = start_engine();
+    engine.send("go depth 7");
+    let hashfull = final_hashfull(&mut engine);
+    assert!(hashfull > 0, "hashfull {hashfull}");
+
+    // isready waits until the table is clear
+    engine.send("setoption name Clear Hash");
+    engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(lines.iter().any(|(_, line)| line.starts_with("info string hash cleared")), "{lines:?}");
+    assert_hash_empty(&mut engine);
+}
+
+#[test]
+fn test_clear_hash_during_search()
+{
+    let mut engine = start_engine();
+    engine.send("go infinite");
+    engine.wait_for("info depth 5 ");
+    // The table is cleared once the search has finished, and readyok waits for that
+    engine.send("setoption name Clear Hash");
+    engine.send("isready");
+    engine.send("stop");
+    let lines = engine.wait_for("readyok");
+    let index = |prefix: &str| lines.iter().position(|(_, line)| line.starts_with(prefix));
+    assert!(index("info string Search is running, hash will be cleared when it finishes").is_some(), "{lines:?}");
+    let bestmove = index("bestmove").expect("readyok before bestmove");
+    let cleared = index("info string hash cleared").expect("readyok before the hash was cleared");
+    assert!(bestmove < cleared, "{lines:?}");
+    assert_hash_empty(&mut engine);
+}
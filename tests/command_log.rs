@@ -0,0 +1,39 @@
+//! Runs quick commands in debug mode, and checks that none is reported as slow and that `dumplog` lists
+//! them
+
+mod common;
+
+use common::Engine;
+
+const QUICK_COMMANDS: &[&str] = &[
+    "ucinewgame",
+    "position startpos moves e2e4 e7e5 g1f3",
+    "setoption name MoveTimeFloor value 0",
+    "d",
+];
+
+#[test]
+fn test_quick_commands_are_not_slow()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("debug on");
+    for command in QUICK_COMMANDS {
+        engine.send(command);
+    }
+    engine.send("isready");
+    let mut lines = engine.wait_for("readyok");
+    engine.send("dumplog");
+    engine.send("isready");
+    lines.extend(engine.wait_for("readyok"));
+
+    assert!(!lines.iter().any(|(_, line)| line.starts_with("info string slow command")), "{lines:?}");
+    for command in QUICK_COMMANDS
+    {
+        let logged = format!("\"{command}\" took ");
+        assert!(lines.iter().any(|(_, line)| line.starts_with("info string log ") && line.contains(&logged)), "{command}: {lines:?}");
+    }
+    // The dump happens while dumplog runs
+    assert!(lines.iter().any(|(_, line)| line.ends_with("\"dumplog\" still running")), "{lines:?}");
+}
@@ -0,0 +1,125 @@
+//! Runs the engine binary behind pipes, the way a GUI does
+
+// Each test file uses its own part of this
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for any line before deciding that it is stuck in a buffer
+const LINE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Engine
+{
+    process: Child,
+    stdin: ChildStdin,
+    /// Lines of output, timestamped as soon as they come out of the pipe
+    lines: mpsc::Receiver<(Instant, String)>,
+    reader: Option<thread::JoinHandle<()>>,
+}
+
+impl Engine
+{
+    pub fn start() -> Engine
+    {
+        Engine::spawn(Command::new(env!("CARGO_BIN_EXE_engine")))
+    }
+
+    /// Start the engine with at most `megabytes` of address space, so that large allocations fail the
+    /// way they do on a machine without enough memory
+    #[cfg(unix)]
+    pub fn start_with_memory_limit(megabytes: usize) -> Engine
+    {
+        let mut command = Command::new("sh");
+        command.args(["-c", &format!("ulimit -v {} && exec \"$0\"", megabytes * 1024), env!("CARGO_BIN_EXE_engine")]);
+        Engine::spawn(command)
+    }
+
+    fn spawn(mut command: Command) -> Engine
+    {
+        let mut process = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start the engine");
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send((Instant::now(), line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Engine {
+            process,
+            stdin,
+            lines,
+            reader: Some(reader),
+        }
+    }
+
+    /// Send a command, and return when it was sent
+    pub fn send(&mut self, command: &str) -> Instant
+    {
+        writeln!(self.stdin, "{command}").unwrap();
+        self.stdin.flush().unwrap();
+        Instant::now()
+    }
+
+    /// Send raw bytes, which need not be valid UTF-8 or end in a newline
+    pub fn send_bytes(&mut self, bytes: &[u8])
+    {
+        self.stdin.write_all(bytes).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// Ask the engine to quit, and wait for it to exit
+    pub fn quit(mut self) -> ExitStatus
+    {
+        let _ = writeln!(self.stdin, "quit");
+        self.process.wait().unwrap()
+    }
+
+    /// The lines up to and including the first one starting with `prefix`
+    pub fn wait_for(&mut self, prefix: &str) -> Vec<(Instant, String)>
+    {
+        let mut lines = Vec::new();
+        loop {
+            let (time, line) = self.lines.recv_timeout(LINE_TIMEOUT)
+                .unwrap_or_else(|_| panic!("no \"{prefix}\" from the engine, last lines {lines:?}"));
+            let done = line.starts_with(prefix);
+            lines.push((time, line));
+            if done {
+                return lines;
+            }
+        }
+    }
+}
+
+impl Drop for Engine
+{
+    fn drop(&mut self)
+    {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.process.wait();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// The value following `key` in a line of space separated words, such as the time in an info line
+pub fn field<T: std::str::FromStr>(line: &str, key: &str) -> Option<T>
+{
+    let mut words = line.split_ascii_whitespace();
+    words.find(|&w| w == key)?;
+    words.next()?.parse().ok()
+}
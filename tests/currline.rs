@@ -0,0 +1,43 @@
+//! Checks the `info currline` lines that debug mode prints during long searches, which show where a
+//! search that seems stuck is spending its time
+
+mod common;
+
+use std::str::FromStr;
+
+use chess::{Board, ChessMove, MoveGen};
+use common::Engine;
+
+#[test]
+fn test_currline_in_debug_mode()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("debug on");
+    engine.send("position startpos moves e2e4");
+    engine.send("go movetime 2000");
+    let lines = engine.wait_for("bestmove");
+
+    let position = Board::default().make_move_new(ChessMove::from_str("e2e4").unwrap());
+    let root_moves = MoveGen::new_legal(&position).collect::<Vec<_>>();
+    let currlines = lines.iter()
+        .filter_map(|(_, line)| line.strip_prefix("info currline "))
+        .collect::<Vec<_>>();
+    assert!(!currlines.is_empty(), "no currline in {lines:?}");
+
+    for currline in currlines
+    {
+        let moves = currline.split_ascii_whitespace()
+            .map(|m| ChessMove::from_str(m).unwrap_or_else(|_| panic!("invalid move {m} in \"{currline}\"")))
+            .collect::<Vec<_>>();
+        assert!(!moves.is_empty(), "empty currline");
+        assert!(root_moves.contains(&moves[0]), "{} is not a root move, in \"{currline}\"", moves[0]);
+    }
+
+    // Without debug mode, there are none
+    engine.send("debug off");
+    engine.send("go movetime 1500");
+    let lines = engine.wait_for("bestmove");
+    assert!(lines.iter().all(|(_, line)| !line.starts_with("info currline")), "{lines:?}");
+}
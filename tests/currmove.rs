@@ -0,0 +1,44 @@
+//! Checks the `info currmove` lines of long searches, which show GUIs that the search is making
+//! progress between iterations
+
+mod common;
+
+use std::time::Duration;
+
+use chess::{Board, ChessMove, MoveGen};
+use common::{field, Engine};
+
+/// The time into the search from which root moves are announced, as in the search
+const CURRMOVE_MIN_TIME: Duration = Duration::from_millis(2000);
+
+#[test]
+fn test_currmove_in_long_searches()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+    let go_sent = engine.send("go movetime 4000");
+    let lines = engine.wait_for("bestmove");
+
+    let root_moves = MoveGen::new_legal(&Board::default()).collect::<Vec<_>>();
+    let currmoves = lines.iter()
+        .filter(|(_, line)| line.contains(" currmove "))
+        .collect::<Vec<_>>();
+    assert!(!currmoves.is_empty(), "no currmove in {lines:?}");
+
+    for (time, line) in currmoves
+    {
+        assert!(time.duration_since(go_sent) >= CURRMOVE_MIN_TIME, "\"{line}\" after {:?}", time.duration_since(go_sent));
+        assert!(line.starts_with("info depth "), "{line}");
+        let currmove: ChessMove = field(line, "currmove").expect("no currmove");
+        assert!(root_moves.contains(&currmove), "{line}");
+        let number: usize = field(line, "currmovenumber").expect("no currmovenumber");
+        assert!((1..=root_moves.len()).contains(&number), "{line}");
+    }
+
+    // Short searches don't announce their root moves
+    engine.send("go movetime 500");
+    let lines = engine.wait_for("bestmove");
+    assert!(lines.iter().all(|(_, line)| !line.contains(" currmove ")), "{lines:?}");
+}
@@ -0,0 +1,50 @@
+//! Checks the info strings that debug mode adds to a search, and that `debug off` silences them again
+
+mod common;
+
+use common::Engine;
+
+const DIAGNOSTICS: &[&str] = &[
+    "info string time allocated ",
+    "info string root hash entry ",
+    "info string hash depth ",
+    "info string search stopped by ",
+];
+
+/// The lines of the search of `go`, from the start position
+fn search(engine: &mut Engine, go: &str) -> Vec<String>
+{
+    engine.send("position startpos");
+    engine.send(go);
+    engine.wait_for("bestmove").into_iter().map(|(_, line)| line).collect()
+}
+
+#[test]
+fn test_debug_diagnostics()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("debug on");
+
+    let lines = search(&mut engine, "go depth 4");
+    for diagnostic in DIAGNOSTICS {
+        assert!(lines.iter().any(|line| line.starts_with(diagnostic)), "no \"{diagnostic}\" in {lines:?}");
+    }
+    assert!(lines.contains(&"info string time allocated soft limit none hard limit none".to_owned()), "{lines:?}");
+    assert!(lines.contains(&"info string search stopped by depth".to_owned()), "{lines:?}");
+    assert!(lines.contains(&"info string root hash entry none".to_owned()), "{lines:?}");
+    let hash_depths = lines.iter().filter(|line| line.starts_with("info string hash depth ")).count();
+    assert_eq!(hash_depths, 4, "{lines:?}");
+
+    // The second search finds the first in the hash table, and stops on its node limit
+    let lines = search(&mut engine, "go nodes 5000");
+    assert!(lines.iter().any(|line| line.starts_with("info string root hash entry move ")), "{lines:?}");
+    assert!(lines.contains(&"info string search stopped by nodes".to_owned()), "{lines:?}");
+
+    engine.send("debug off");
+    let lines = search(&mut engine, "go depth 4");
+    for diagnostic in DIAGNOSTICS {
+        assert!(!lines.iter().any(|line| line.starts_with(diagnostic)), "\"{diagnostic}\" without debug mode in {lines:?}");
+    }
+}
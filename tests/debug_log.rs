@@ -0,0 +1,46 @@
+//! Records the conversation with the engine in a file with the Debug Log File option, as for finding out
+//! what went wrong inside a GUI
+
+mod common;
+
+use std::fs;
+
+use common::Engine;
+
+#[test]
+fn test_debug_log_file()
+{
+    let path = std::env::temp_dir().join(format!("debug-log-test-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send(&format!("setoption name Debug Log File value {}", path.display()));
+    engine.send("position startpos");
+    engine.send("go depth 3");
+    let lines = engine.wait_for("bestmove");
+    let (_, bestmove) = lines.last().unwrap();
+
+    // Turning the log off leaves out what follows
+    engine.send("setoption name Debug Log File value <empty>");
+    engine.send("isready");
+    engine.wait_for("readyok");
+
+    // Every line is in the log as soon as it has been sent
+    let log = fs::read_to_string(&path).expect("no log file");
+    let _ = fs::remove_file(&path);
+    let entries = log.lines()
+        .map(|line| {
+            let (timestamp, entry) = line.split_once(' ').unwrap_or_else(|| panic!("\"{line}\" in {log}"));
+            assert!(timestamp.parse::<f64>().is_ok(), "\"{line}\" in {log}");
+            entry
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(entries.first(), Some(&"< position startpos"), "{log}");
+    assert!(entries.contains(&"< go depth 3"), "{log}");
+    assert!(entries.iter().any(|entry| entry.starts_with("> info depth 3 ")), "{log}");
+    assert!(entries.contains(&format!("> {bestmove}").as_str()), "{log}");
+    assert_eq!(entries.last(), Some(&"< setoption name Debug Log File value <empty>"), "{log}");
+    assert!(!entries.iter().any(|entry| entry.contains("readyok")), "{log}");
+}
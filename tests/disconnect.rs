@@ -0,0 +1,75 @@
+//! Closes the pipes of a running engine, as happens when the GUI crashes, and checks that it shuts down
+//! promptly and cleanly instead of searching on for nobody
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the engine may take to exit after the GUI has gone away
+const MAX_EXIT_TIME: Duration = Duration::from_secs(5);
+
+/// Start the engine on an infinite search, and return once it is under way
+fn start_infinite_search() -> (Child, ChildStdin, BufReader<ChildStdout>)
+{
+    let mut process = Command::new(env!("CARGO_BIN_EXE_engine"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start the engine");
+    let mut stdin = process.stdin.take().unwrap();
+    let mut stdout = BufReader::new(process.stdout.take().unwrap());
+
+    // Debug mode reports the current line every second, so that there is output to fail on. A go
+    // without limits searches until stopped.
+    writeln!(stdin, "uci\ndebug on\nposition startpos\ngo").unwrap();
+    stdin.flush().unwrap();
+    let mut line = String::new();
+    while !line.starts_with("info depth") {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "the engine exited before searching");
+    }
+    (process, stdin, stdout)
+}
+
+fn wait_with_timeout(process: &mut Child) -> ExitStatus
+{
+    let start = Instant::now();
+    loop {
+        if let Some(status) = process.try_wait().unwrap() {
+            return status;
+        }
+        if start.elapsed() > MAX_EXIT_TIME {
+            let _ = process.kill();
+            panic!("the engine was still running {MAX_EXIT_TIME:?} after the GUI went away");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_exit_when_both_pipes_close()
+{
+    let (mut process, stdin, stdout) = start_infinite_search();
+    drop(stdin);
+    drop(stdout);
+
+    let status = wait_with_timeout(&mut process);
+    assert!(status.success(), "the engine exited with {status}");
+}
+
+#[test]
+fn test_stop_when_output_pipe_closes()
+{
+    let (mut process, mut stdin, stdout) = start_infinite_search();
+    drop(stdout);
+
+    // The search notices when its output fails, and the next command finds the engine shutting down
+    thread::sleep(Duration::from_millis(1500));
+    let _ = writeln!(stdin, "isready");
+    let _ = stdin.flush();
+
+    let status = wait_with_timeout(&mut process);
+    assert!(status.success(), "the engine exited with {status}");
+}
@@ -0,0 +1,45 @@
+//! Checks what the `d` command shows of the position beyond the board, for debugging the hash table
+
+mod common;
+
+use common::Engine;
+
+/// The lines of `d`, up to the hash entry, which is the last line
+fn display(engine: &mut Engine) -> Vec<String>
+{
+    engine.send("d");
+    engine.wait_for("info string Hash entry: ").into_iter().map(|(_, line)| line).collect()
+}
+
+#[test]
+fn test_display_position_details()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // White can take en passant on f6
+    engine.send("position startpos moves e2e4 d7d5 e4e5 f7f5");
+    let lines = display(&mut engine);
+    assert!(lines.contains(&"info string Castling: KQkq, en passant: f6".to_owned()), "{lines:?}");
+    assert!(lines.contains(&"info string Halfmove clock: 0, fullmove number: 3".to_owned()), "{lines:?}");
+    let hash = lines.iter().find_map(|line| line.strip_prefix("info string Hash: ")).expect("no hash");
+    assert!(hash.len() == 16 && u64::from_str_radix(hash, 16).is_ok(), "{hash}");
+    assert_eq!(lines.last().unwrap(), "info string Hash entry: none");
+
+    // After a search, the position has an entry, which probe shows the same way
+    engine.send("go depth 4");
+    engine.wait_for("bestmove");
+    let lines = display(&mut engine);
+    let entry = lines.last().unwrap().strip_prefix("info string Hash entry: ").unwrap();
+    assert!(entry.starts_with("depth ") && entry.contains(" bound exact score cp "), "{entry}");
+    engine.send("probe");
+    let probe = engine.wait_for("info string probe hit ");
+    assert_eq!(probe.last().unwrap().1, format!("info string probe hit hash {hash} {entry}"));
+
+    // Without castling rights, and after a quiet move
+    engine.send("position fen 4k3/8/8/8/8/8/8/4K2R w K - 7 40 moves e1f1");
+    let lines = display(&mut engine);
+    assert!(lines.contains(&"info string Castling: -, en passant: -".to_owned()), "{lines:?}");
+    assert!(lines.contains(&"info string Halfmove clock: 8, fullmove number: 40".to_owned()), "{lines:?}");
+}
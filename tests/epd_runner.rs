@@ -0,0 +1,37 @@
+//! Runs the `epd` command over a small file of puzzles, with the limits of puzzle batches: each
+//! position needs a few plies and a few milliseconds, and may take a few seconds at most
+
+mod common;
+
+use common::Engine;
+
+const PUZZLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/puzzles.epd");
+
+#[test]
+fn test_epd_runner()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    engine.send(&format!("epd {PUZZLES} depth 3 mintime 10 maxtime 5000"));
+    let lines = engine.wait_for("info string epd solved");
+    let results = lines.iter()
+        .filter_map(|(_, line)| line.strip_prefix("info string epd "))
+        .collect::<Vec<_>>();
+
+    assert_eq!(results.len(), 3, "{lines:?}");
+    assert!(results[0].starts_with("back rank mate bm Ra8# found Ra8#"), "{}", results[0]);
+    assert!(results[0].ends_with(" solved"), "{}", results[0]);
+    assert!(results[1].starts_with("hanging queen bm Rxd5 found Rxd5"), "{}", results[1]);
+    assert!(results[2].starts_with("solved 2 of 2 in "), "{}", results[2]);
+
+    // The line with an illegal best move is skipped
+    assert!(lines.iter().any(|(_, line)| line.starts_with("ERROR: Line 3: ")), "{lines:?}");
+
+    // Without a limit, a search could go on forever
+    engine.send(&format!("epd {PUZZLES} mintime 10"));
+    engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(lines.iter().any(|(_, line)| line.starts_with("ERROR: Expected a depth or a maxtime")), "{lines:?}");
+}
@@ -0,0 +1,35 @@
+//! Runs `eval` and checks that it breaks the evaluation down from both points of view
+
+mod common;
+
+use common::Engine;
+
+/// The white and side to move columns of the row of the `eval` output named `name`
+fn row(lines: &[(std::time::Instant, String)], name: &str) -> Option<(String, String)>
+{
+    let prefix = format!("info string eval {name} ");
+    let (_, line) = lines.iter().find(|(_, line)| line.starts_with(&prefix))?;
+    let columns = line[prefix.len()..].split("  ").map(str::trim).filter(|c| !c.is_empty()).collect::<Vec<_>>();
+    Some((columns[0].to_owned(), columns[1].to_owned()))
+}
+
+#[test]
+fn test_eval()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // Black to move, against a bishop for two pawns that is trapped on a7
+    engine.send("position fen 4k3/B1p5/1p6/8/8/8/8/4K3 b - - 0 1");
+    engine.send("eval");
+    let lines = engine.wait_for("info string eval evaluation ");
+    assert_eq!(row(&lines, "material"), Some(("100".to_owned(), "-100".to_owned())));
+    assert_eq!(row(&lines, "evaluation"), Some(("cp -20".to_owned(), "cp 20".to_owned())));
+    assert_eq!(row(&lines, "hanging piece"), None);
+
+    engine.send("setoption name HangingPieceEval value true");
+    engine.send("eval");
+    let lines = engine.wait_for("info string eval evaluation ");
+    assert!(row(&lines, "hanging piece").is_some());
+}
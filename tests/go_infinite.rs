@@ -0,0 +1,37 @@
+//! Searches with `go infinite`, the way GUIs do in analysis mode, and checks that the best move only
+//! comes after stop
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::Engine;
+
+/// Mate in one, which the search has proven long before it is stopped
+const MATE_IN_ONE_FEN: &str = "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1";
+
+/// How long `readyok` may take during a search, which is far less than the search itself
+const READYOK_MAX_DELAY: Duration = Duration::from_millis(200);
+
+#[test]
+fn test_go_infinite_waits_for_stop()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send(&format!("position fen {MATE_IN_ONE_FEN}"));
+    engine.send("go infinite");
+    engine.wait_for("info depth");
+
+    // isready doesn't wait for the search, which only ends with stop
+    thread::sleep(Duration::from_millis(500));
+    let isready_sent = engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(!lines.iter().any(|(_, line)| line.starts_with("bestmove")), "{lines:?}");
+    let (readyok_time, _) = lines.last().unwrap();
+    assert!(readyok_time.duration_since(isready_sent) < READYOK_MAX_DELAY, "readyok after {:?}", readyok_time.duration_since(isready_sent));
+
+    engine.send("stop");
+    engine.wait_for("bestmove b1b8");
+}
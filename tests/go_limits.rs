@@ -0,0 +1,55 @@
+//! Searches with several limits in one `go`, and checks that the search stops at whichever comes first,
+//! as the reason that debug mode reports tells
+
+mod common;
+
+use common::Engine;
+
+const MOVETIME: u128 = 300;
+/// How long after the movetime bestmove may come, which covers the stopping and a slow machine
+const MOVETIME_TOLERANCE: u128 = 500;
+
+/// Search from the start position with `go`, and return the reason the search stopped for and how long
+/// it took in milliseconds
+fn stop_reason(engine: &mut Engine, go: &str) -> (String, u128)
+{
+    engine.send("position startpos");
+    let go_sent = engine.send(go);
+    let lines = engine.wait_for("bestmove");
+    let (bestmove_time, _) = lines.last().unwrap();
+    let reason = lines.iter()
+        .find_map(|(_, line)| line.strip_prefix("info string search stopped by "))
+        .unwrap_or_else(|| panic!("no stop reason in {lines:?}"));
+    (reason.to_owned(), bestmove_time.duration_since(go_sent).as_millis())
+}
+
+#[test]
+fn test_go_limits()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("debug on");
+
+    // The depth is reached long before the movetime
+    let (reason, time) = stop_reason(&mut engine, "go depth 3 movetime 60000");
+    assert_eq!(reason, "depth");
+    assert!(time < 60_000, "took {time} ms");
+
+    // The movetime runs out long before the depth is reached
+    let (reason, time) = stop_reason(&mut engine, &format!("go depth 60 movetime {MOVETIME}"));
+    assert_eq!(reason, "time limit");
+    assert!(time >= MOVETIME && time < MOVETIME + MOVETIME_TOLERANCE, "took {time} ms");
+
+    // Either limit on its own
+    let (reason, time) = stop_reason(&mut engine, &format!("go movetime {MOVETIME}"));
+    assert_eq!(reason, "time limit");
+    assert!(time >= MOVETIME && time < MOVETIME + MOVETIME_TOLERANCE, "took {time} ms");
+    let (reason, _) = stop_reason(&mut engine, "go depth 4");
+    assert_eq!(reason, "depth");
+
+    // The node limit comes first too, before a depth and a movetime that are far off
+    let (reason, time) = stop_reason(&mut engine, "go depth 60 movetime 60000 nodes 20000");
+    assert_eq!(reason, "nodes");
+    assert!(time < 60_000, "took {time} ms");
+}
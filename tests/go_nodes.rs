@@ -0,0 +1,49 @@
+//! Searches with `go nodes`, the way fixed-node matches do, and checks that the search answers close to
+//! the node count asked for
+
+mod common;
+
+use common::Engine;
+
+const NODE_LIMIT: u64 = 100_000;
+/// How far past the limit the search may go, unwinding the nodes it was in when it reached the limit
+const NODE_TOLERANCE: u64 = 500;
+
+/// The node count of the last info line with a depth, up to the bestmove
+fn final_nodes(engine: &mut Engine) -> u64
+{
+    let lines = engine.wait_for("bestmove");
+    let info = lines.iter()
+        .map(|(_, line)| line)
+        .filter(|line| line.starts_with("info depth"))
+        .next_back()
+        .expect("no info line before bestmove");
+    let mut words = info.split_ascii_whitespace();
+    words.find(|&w| w == "nodes").and_then(|_| words.next()).unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_go_nodes()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+    engine.send(&format!("go nodes {NODE_LIMIT}"));
+
+    let nodes = final_nodes(&mut engine);
+    assert!(nodes >= NODE_LIMIT && nodes <= NODE_LIMIT + NODE_TOLERANCE, "searched {nodes} nodes");
+}
+
+#[test]
+fn test_go_nodes_zero_is_unlimited()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+    engine.send("go nodes 0 depth 3");
+
+    let lines = engine.wait_for("bestmove");
+    assert!(lines.iter().any(|(_, line)| line.starts_with("info depth 3 ")), "{lines:?}");
+}
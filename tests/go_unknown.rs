@@ -0,0 +1,29 @@
+//! Sends `go` with specifiers the engine doesn't know, as a GUI for a newer protocol might, and checks
+//! that the engine still searches with the limits it understood
+
+mod common;
+
+use common::Engine;
+
+#[test]
+fn test_go_with_unknown_specifiers()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+
+    // Without a search, the engine would lose on time
+    engine.send("go wtime 1000 btime 1000 frobnicate winc 10 binc 10");
+    let lines = engine.wait_for("bestmove");
+    assert!(lines.iter().any(|(_, line)| line == "info string Ignoring unknown go specifier \"frobnicate\""), "{lines:?}");
+    assert!(lines.iter().any(|(_, line)| line.starts_with("info depth ")), "{lines:?}");
+
+    // The numeric argument of an unknown word is skipped with it, and the depth still applies
+    engine.send("go frobnicate 12 depth 3 wibble");
+    let lines = engine.wait_for("bestmove");
+    assert!(lines.iter().any(|(_, line)| line == "info string Ignoring unknown go specifier \"frobnicate 12\""), "{lines:?}");
+    assert!(lines.iter().any(|(_, line)| line == "info string Ignoring unknown go specifier \"wibble\""), "{lines:?}");
+    assert!(lines.iter().any(|(_, line)| line.starts_with("info depth 3 ")), "{lines:?}");
+    assert!(!lines.iter().any(|(_, line)| line.starts_with("info depth 4 ")), "{lines:?}");
+}
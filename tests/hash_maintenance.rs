@@ -0,0 +1,78 @@
+//! The start of a game as GUIs send it: a new hash size and a new game, followed quickly by the first
+//! search on a short clock. Preparing a large hash table takes a while, and that must not make the
+//! engine lose on time.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{field, Engine};
+
+const HASH_MEGABYTES: usize = 1024;
+const CLOCK: Duration = Duration::from_secs(1);
+
+/// Search on a one second clock, and return the reported time of the search, how long it was reported
+/// to wait for maintenance to finish, and how long the move took in all
+fn first_move(engine: &mut Engine) -> (u128, u128, Duration)
+{
+    engine.send("position startpos");
+    let go_sent = engine.send(&format!("go wtime {0} btime {0}", CLOCK.as_millis()));
+    let lines = engine.wait_for("bestmove");
+    let (bestmove_time, _) = lines.last().unwrap();
+
+    let delay = lines.iter()
+        .find(|(_, l)| l.starts_with("info string search started"))
+        .map_or(0, |(_, l)| field(l, "started").unwrap());
+    let final_info = &lines[lines.len() - 2].1;
+    let time = field(final_info, "time").expect("final info line has a time");
+    (time, delay, bestmove_time.duration_since(go_sent))
+}
+
+#[test]
+fn test_hash_maintenance_before_go()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // Without waiting for isready, the maintenance is still going on when go arrives
+    engine.send(&format!("setoption name Hash value {HASH_MEGABYTES}"));
+    engine.send("ucinewgame");
+    let (time, delay, total) = first_move(&mut engine);
+    assert!(total < CLOCK, "the move took {total:?}");
+    assert!(time + delay <= total.as_millis() + 1, "reported time {time} ms and delay {delay} ms, but the move took {total:?}");
+
+    // With isready, the maintenance is done before go, and the search starts right away
+    engine.send(&format!("setoption name Hash value {HASH_MEGABYTES}"));
+    engine.send("ucinewgame");
+    engine.send("isready");
+    let ready = engine.wait_for("readyok");
+    assert!(ready.iter().any(|(_, l)| l.starts_with(&format!("info string hash resized to {HASH_MEGABYTES} MB"))), "{ready:?}");
+    let (time, delay, total) = first_move(&mut engine);
+    assert_eq!(delay, 0);
+    assert!(total < CLOCK, "the move took {total:?}");
+    assert!(time <= total.as_millis());
+}
+
+#[test]
+fn test_new_game_clears_hash()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    engine.send("position startpos");
+    engine.send("go depth 5");
+    engine.wait_for("bestmove");
+    engine.send("probe");
+    engine.wait_for("info string probe hit ");
+
+    // readyok only comes once the table is clear, and nothing of the previous game is left in it
+    engine.send("ucinewgame");
+    engine.send("isready");
+    let ready = engine.wait_for("readyok");
+    assert!(ready.iter().any(|(_, l)| l.starts_with("info string hash cleared")), "{ready:?}");
+    engine.send("position startpos");
+    engine.send("probe");
+    engine.wait_for("info string probe miss ");
+}
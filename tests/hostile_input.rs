@@ -0,0 +1,171 @@
+//! Feeds the engine input that buggy or hostile GUIs could send, and checks that it neither aborts nor
+//! stops answering `isready`. Run with `cargo test --release` to check the release binary, which
+//! aborts on panics.
+
+mod common;
+
+use std::str::FromStr;
+
+use common::Engine;
+use engine::chess::{Board, ChessMove};
+
+/// Each script is sent as is, followed by `isready`
+const SCRIPTS: &[&str] = &[
+    // Malformed and illegal positions
+    "position\nposition foo\nposition fen\nposition fen moves\nposition startpos moves\n",
+    "position fen 4k3/8/8/8/8/8/8/4K3\n",
+    "position fen 4k3/8/8/8/8/8/8/8/4K3 w - - 0 1\n",
+    "position fen 9/8/8/8/8/8/8/8 w - - 0 1\n",
+    "position fen 4k3/8/8/8/8/8/8/4K3 w - z9 0 1\n",
+    "position fen 8/8/8/8/8/8/8/8 w - - 0 1\n",
+    "position fen kkkkkkkk/8/8/8/8/8/8/KKKKKKKK w - - 0 1\n",
+    "position fen 4k3/4R3/8/8/8/8/8/4K3 w - - 0 1\n",
+    "position fen 4k3/8/8/8/8/8/8/4K3 w KQkq - 0 1 moves e1g1\n",
+    "position fen 4k3/8/8/8/8/8/8/4K3 w - - -5 -1\n",
+    "position fen 4k3/8/8/4P3/8/8/8/4K3 w - d6\ngo depth 3\nposition fen 4k3/8/8/3pP3/8/8/8/4K3  w  -  d6 \ngo depth 3\n",
+    "position startpos moves e2e4 e2e4\nposition startpos moves zzzz\nposition startpos moves e7e8q\n",
+    "position startpos foo bar\n",
+    "position startpos moves 0000 0000 0000\ngo depth 2\nposition fen 4k3/8/8/8/8/8/8/R3K2r w - - 0 1 moves 0000\nposition startpos moves e2e4 0000 00000\n",
+    "probe\nprobe 8/8/8/8/8/8/8/8 w\nprobe garbage\n",
+    // Searches of positions without moves, and odd limits
+    "position fen 7k/5QQ1/8/8/8/8/8/K7 b - - 0 1\ngo depth 5\n",
+    "position fen 7k/8/6QK/8/8/8/8/8 b - - 0 1\ngo movetime 10\n",
+    "position startpos\ngo depth 0\n",
+    "position startpos\ngo depth 255 movetime 1\n",
+    "position startpos\ngo depth 999\ngo depth -1\ngo movetime x\ngo wtime\ngo foo\n",
+    "position startpos\ngo wtime -1000 btime -1000 winc -5 binc -5 movestogo 0\n",
+    "position startpos\ngo wtime 4294967295 btime 4294967295 movestogo 4294967295 movetime 1\n",
+    "position startpos\ngo movetime 0\n",
+    "position startpos\ngo nodes x\ngo mate -1\ngo nodes 0 mate 0 movetime 10\ngo nodes 1\n",
+    // Commands out of order
+    "stop\nponderhit\nstop\n",
+    "position startpos\ngo ponder movetime 10\nstop\nponderhit\n",
+    "position startpos\ngo depth 30\ngo depth 1\nstop\n",
+    "position startpos\ngo depth 30\nsetoption name Clear Hash\nucinewgame\nprobe\nexporttree x 1 1\nstop\n",
+    // Options
+    "setoption\nsetoption name\nsetoption name value\nsetoption foo\n",
+    "setoption name Hash value 0\nsetoption name Hash value -1\nsetoption name Hash value 99999999999999999999\n",
+    "setoption name Hash value 1\nsetoption name Hash value abc\nsetoption name PVNotation value\n",
+    "setoption name MinimumThinkingTime value 99999\nsetoption name AdaptiveLMR value maybe\nsetoption name HangingPieceEval\n",
+    "setoption name MoveTimeVariance value 51\nsetoption name MoveTimeFloor value -1\nsetoption name MoveTimeVariance value 50\nsetoption name MoveTimeFloor value 10000\nposition startpos\ngo wtime 100 btime 100\n",
+    "setoption name Threads value 0\nsetoption name Threads value 257\nsetoption name Threads value 4\nposition startpos\ngo depth 30\nsetoption name Hash value 2\nstop\n",
+    "setoption name ValidateBoards value yes\nsetoption name ValidateBoards value true\nposition startpos\ngo depth 3\n",
+    "debug\ndebug maybe\ndebug on\nposition startpos\ngo depth 3\ndebug off\n",
+    "setoption name StrictUci value maybe\nsetoption name StrictUci value true\nd\nfoo\nsetoption name StrictUci value false\n",
+    // Non-standard commands with bad arguments
+    "exporttree\nexporttree /nonexistent/dir/file.epd 1 1\nexporttree x y z\nexporttree x 1 1 1\n",
+    "savehash\nsavehash /nonexistent/dir/file.hash\nloadhash\nloadhash /nonexistent/file.hash\nloadhash /dev/null\n",
+    "setoption name AutosaveInterval value -1\nsetoption name AutosaveInterval value 1\nsetoption name AutosavePath value /nonexistent/dir/file.hash\nposition startpos\ngo depth 3\n",
+    "position startpos\ngo searchmoves\ngo searchmoves e2e5\ngo searchmoves zz\ngo depth 2 searchmoves a2a3\n",
+    "position startpos\ngo depth 1 mintime x\ngo maxtime -1\ngo mintime 10 maxtime\n",
+    "bench -1\nbench 1 --compare /nonexistent/file.csv\nbench --gate /nonexistent/file.json\n",
+    "analyse\nanalyse exclude\nanalyse exclude zzzz\nanalyse exclude e2e5\nanalyse exclude e2e4 99x\nanalyse include e2e4\n",
+    "position fen 7k/8/8/8/8/8/8/K7 w - - 0 1\nanalyse exclude a1a2 a1b1 a1b2 10\n",
+    "learn\nlearn 1-0\nsetoption name BookLearning value /nonexistent/dir/file.book\nposition startpos moves e2e4\nlearn 2-0\nlearn 0-1\n",
+    "perft\nperft x\nperft -1\nperft 11\nperft 99999999999\nposition startpos moves e2e5\nperft 1\nposition startpos\nperft 0\nperft 2\nperft 0 divide\nperft 2 divid\nperft 2 divide extra\nperft 1 divide\n",
+    "dumplog\ndumplog extra words\ndebug on\ndumplog\n",
+    "eval extra words\nposition startpos moves e2e5\neval\nposition fen 8/8/8/8/8/8/8/K6k w - - 0 1\neval\n",
+    "verifymate\nverifymate 0\nverifymate 99\nverifymate x\nposition fen kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1\nverifymate 2\n",
+    "override\noverride list\noverride set startpos 10\nsetoption name EvalOverrides value /nonexistent/dir/file.txt\noverride set 8/8/8/8/8/8/8/K6k w - - 0 1 99999\noverride set 8/8/8/8/8/8/8/K6k w - - 0 1 x\noverride clear x\noverride set 8/8/8/8/8/8/8/K6k w - - 0 1 50\n",
+    // Junk
+    "\n\n   \n\t\n\r\n",
+    "uci uci uci\nisready isready\nucinewgame now\n",
+];
+
+#[test]
+fn test_hostile_scripts()
+{
+    for script in SCRIPTS
+    {
+        let mut engine = Engine::start();
+        engine.send_bytes(script.as_bytes());
+        engine.send("isready");
+        engine.wait_for("readyok");
+        let status = engine.quit();
+        assert!(status.success(), "exit status {status} after {script:?}");
+    }
+}
+
+/// Hash sizes that there isn't enough memory for fall back to the table there was, and the engine keeps
+/// working
+#[test]
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn test_hash_beyond_memory()
+{
+    let mut engine = Engine::start_with_memory_limit(1024);
+    engine.send("setoption name Hash value 65536");
+    engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(
+        lines.iter().any(|(_, line)| line.starts_with("info string Could not allocate 65536 MB for the hash table, using ")),
+        "{lines:?}",
+    );
+
+    engine.send("position startpos");
+    engine.send("go depth 3");
+    engine.wait_for("bestmove");
+    let status = engine.quit();
+    assert!(status.success(), "exit status {status}");
+}
+
+#[test]
+fn test_binary_junk()
+{
+    let mut engine = Engine::start();
+    engine.send_bytes(b"\xff\xfe\x00\x01position startpos\x80\n");
+    engine.send_bytes(&[0xc3; 10_000]);
+    engine.send_bytes(b"\n\xef\xbb\xbfisready\n");
+    engine.wait_for("readyok");
+
+    // A line of far more than the maximum length, cut into pieces
+    for _ in 0..20 {
+        engine.send_bytes(&[b'a'; 1024 * 1024]);
+    }
+    engine.send_bytes(b"\n");
+    engine.send("isready");
+    engine.wait_for("readyok");
+
+    let status = engine.quit();
+    assert!(status.success(), "exit status {status}");
+}
+
+/// A legal game of `plies` moves in which both sides only shuffle their knights, and the position it
+/// ends in
+fn shuffling_game(plies: usize) -> (Vec<ChessMove>, Board)
+{
+    let cycle = ["g1f3", "g8f6", "b1c3", "b8c6", "f3e5", "f6e4", "e5f3", "e4f6", "f3g1", "f6g8", "c3b1", "c6b8"];
+    let mut position = Board::default();
+    let moves = cycle.iter()
+        .cycle()
+        .take(plies)
+        .map(|move_str| {
+            let chess_move = ChessMove::from_str(move_str).unwrap();
+            assert!(position.legal(chess_move), "{move_str} in {position}");
+            position = position.make_move_new(chess_move);
+            chess_move
+        })
+        .collect();
+    (moves, position)
+}
+
+#[test]
+fn test_long_position_line()
+{
+    let (moves, position) = shuffling_game(10_000);
+    let moves = moves.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ");
+
+    let mut engine = Engine::start();
+    engine.send(&format!("position startpos moves {moves}"));
+    engine.send("d");
+    let lines = engine.wait_for("info string Halfmove clock: ");
+    assert_eq!(lines.last().unwrap().1, "info string Halfmove clock: 10000, fullmove number: 5001");
+
+    // Long drawn by the fifty-move rule and by repetition, but the search goes ahead anyway
+    engine.send("go depth 4");
+    let lines = engine.wait_for("bestmove");
+    let best_move = lines.last().unwrap().1.split_ascii_whitespace().nth(1).unwrap().to_owned();
+    assert!(ChessMove::from_str(&best_move).is_ok_and(|m| position.legal(m)), "bestmove {best_move} in {position}");
+
+    let status = engine.quit();
+    assert!(status.success(), "exit status {status}");
+}
@@ -0,0 +1,59 @@
+//! The engine tells which build it is, both to GUIs and on the command line
+
+mod common;
+
+use std::process::Command;
+
+use common::Engine;
+
+#[test]
+fn test_uci_id_has_version()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    let lines = engine.wait_for("uciok");
+
+    let (_, id_name) = lines.iter().find(|(_, l)| l.starts_with("id name ")).expect("no id name line");
+    assert!(id_name.contains(env!("CARGO_PKG_VERSION")), "{id_name}");
+    assert!(lines.iter().any(|(_, l)| l.starts_with("info string build profile ")), "{lines:?}");
+
+    let status = engine.quit();
+    assert!(status.success());
+}
+
+#[test]
+fn test_version_flag()
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_engine"))
+        .arg("--version")
+        .output()
+        .expect("failed to start the engine");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let first_line = stdout.lines().next().unwrap_or("");
+    assert!(first_line.starts_with(&format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))), "{stdout}");
+
+    // The build line tells builds for different CPUs apart
+    let build_line = stdout.lines().nth(1).unwrap_or("");
+    assert!(build_line.starts_with("profile ") && build_line.contains(" features ") && build_line.contains(" cpu "), "{stdout}");
+}
+
+#[test]
+fn test_uci_declares_options()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    let lines = engine.wait_for("uciok");
+    let options = lines.iter().filter(|(_, l)| l.starts_with("option name ")).collect::<Vec<_>>();
+    assert!(options.iter().any(|(_, l)| l.starts_with("option name Hash type spin default ")), "{lines:?}");
+    assert!(options.iter().any(|(_, l)| l == "option name Clear Hash type button"), "{lines:?}");
+
+    // Every declared option is accepted, and anything else is ignored with a warning
+    engine.send("setoption name clear hash");
+    engine.send("setoption name NoSuchOption value 1");
+    engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(lines.iter().any(|(_, l)| l == "info string Unknown option \"NoSuchOption\", ignoring it"), "{lines:?}");
+    assert!(!lines.iter().any(|(_, l)| l.contains("clear hash")), "{lines:?}");
+}
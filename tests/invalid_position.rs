@@ -0,0 +1,37 @@
+//! Sends positions that are rejected, and checks that the following go doesn't search the position
+//! from before them
+
+mod common;
+
+use common::Engine;
+
+/// White's king is in check and can only take the rook on b2
+const FORCED_FEN: &str = "7k/8/8/8/8/8/1r6/K6r w - - 0 1";
+
+#[test]
+fn test_go_after_rejected_position()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos moves e2e4");
+
+    for rejected in [
+        "position fen 8/8/8/8/8/8/8/4K3 w - - 0 1",
+        "position fen 4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+        "position startpos moves e2e5",
+    ] {
+        engine.send(rejected);
+        engine.send("go depth 3");
+        let lines = engine.wait_for("bestmove");
+        let lines = lines.iter().map(|(_, line)| line.as_str()).collect::<Vec<_>>();
+        assert_eq!(lines.last(), Some(&"bestmove 0000"), "{rejected}: {lines:?}");
+        assert!(lines.contains(&"info string no valid position set"), "{rejected}: {lines:?}");
+        assert!(!lines.iter().any(|line| line.starts_with("info depth")), "{rejected}: {lines:?}");
+    }
+
+    // A valid position is searched again
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 3");
+    engine.wait_for("bestmove a1b2");
+}
@@ -0,0 +1,39 @@
+//! Searches positions with known mate distances, and checks the `score mate` of the final info line,
+//! which counts moves rather than plies, and is negative for the side getting mated
+
+mod common;
+
+use common::Engine;
+
+/// Morphy's mate in two: 1. Ra6 bxa6 2. b7#
+const MATE_IN_2: &str = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+/// Black to move gets mated by the queen on White's third move, and not sooner
+const MATED_IN_3: &str = "k7/8/8/1K6/8/8/8/Q7 b - - 0 1";
+
+/// The final info line of a search of `fen`, the one right before bestmove
+fn final_info(engine: &mut Engine, fen: &str) -> String
+{
+    engine.send(&format!("position fen {fen}"));
+    engine.send("go depth 8");
+    let lines = engine.wait_for("bestmove");
+    let [.., (_, info), _] = lines.as_slice() else {
+        panic!("no info line before bestmove for {fen}: {lines:?}");
+    };
+    info.clone()
+}
+
+#[test]
+fn test_mate_scores()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    let info = final_info(&mut engine, MATE_IN_2);
+    assert!(info.contains(" score mate 2 "), "{info}");
+    assert!(info.contains(" pv a1a6 "), "{info}");
+
+    engine.send("ucinewgame");
+    let info = final_info(&mut engine, MATED_IN_3);
+    assert!(info.contains(" score mate -3 "), "{info}");
+}
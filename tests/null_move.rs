@@ -0,0 +1,26 @@
+//! Searches positions reached with the null move `0000`, as analysis front-ends send them
+
+mod common;
+
+use common::Engine;
+
+#[test]
+fn test_search_after_null_move()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // Black passes, and White mates on the back rank
+    engine.send("position fen 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1 moves g1f1 0000");
+    engine.send("go depth 3");
+    let lines = engine.wait_for("bestmove");
+    assert_eq!(lines.last().unwrap().1.split_ascii_whitespace().nth(1), Some("a1a8"), "{lines:?}");
+
+    // Passes in the middle too, after which the rest of the moves are played as usual, so that
+    // White has the scholar's mate
+    engine.send("position startpos moves e2e4 0000 d1h5 0000 f1c4 0000");
+    engine.send("go depth 3");
+    let lines = engine.wait_for("bestmove");
+    assert_eq!(lines.last().unwrap().1.split_ascii_whitespace().nth(1), Some("h5f7"), "{lines:?}");
+}
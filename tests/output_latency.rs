@@ -0,0 +1,39 @@
+//! Measures how long `bestmove` takes to arrive after the search has finished. Output that sits in a
+//! buffer would show up here as a delay, or as a `bestmove` that never arrives at all.
+
+mod common;
+
+use std::time::Duration;
+
+use common::Engine;
+
+/// The longest acceptable delay between the final info line and `bestmove`. Both are sent right after
+/// each other, so this only needs to tell a flushed `bestmove` from one that waits in a buffer for more
+/// output or input, with room for the scheduling of the engine and of this test on a loaded machine.
+const MAX_BESTMOVE_LATENCY: Duration = Duration::from_millis(100);
+
+#[test]
+fn test_bestmove_latency()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("isready");
+    engine.wait_for("readyok");
+
+    // Fast searches, as in bullet games, where a delayed bestmove costs the most
+    for go in ["go depth 1", "go depth 4", "go movetime 50"]
+    {
+        engine.send("position startpos moves e2e4");
+        engine.send(go);
+        let lines = engine.wait_for("bestmove");
+
+        // The search is complete when it prints its final info line, right before bestmove
+        let [.., (info_time, info), (bestmove_time, _)] = lines.as_slice() else {
+            panic!("no info line before bestmove for \"{go}\": {lines:?}");
+        };
+        assert!(info.starts_with("info depth"), "{info}");
+        let latency = bestmove_time.duration_since(*info_time);
+        assert!(latency < MAX_BESTMOVE_LATENCY, "bestmove took {latency:?} to arrive after \"{go}\"");
+    }
+}
@@ -0,0 +1,56 @@
+//! Runs `perft` on positions given in the ways GUIs give them, and checks the counts
+
+mod common;
+
+use common::Engine;
+
+/// The node count of the perft line, up to it
+fn perft(engine: &mut Engine, position: &str, depth: u32) -> u64
+{
+    engine.send(position);
+    engine.send(&format!("perft {depth}"));
+    let lines = engine.wait_for("info string perft ");
+    let (_, line) = lines.last().unwrap();
+    let mut words = line.split_ascii_whitespace();
+    words.find(|&w| w == "nodes").and_then(|_| words.next()).unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_perft()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    assert_eq!(perft(&mut engine, "position startpos", 4), 197_281);
+    // Kiwipete, and the start position after 1. e4 e5 2. Nf3, which has 29 moves for Black
+    assert_eq!(perft(&mut engine, "position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2), 2_039);
+    assert_eq!(perft(&mut engine, "position startpos moves e2e4 e7e5 g1f3", 1), 29);
+}
+
+#[test]
+fn test_perft_divide()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // After 1. e4 e5 2. Nf3, in the format of Stockfish's `go perft`
+    engine.send("position startpos moves e2e4 e7e5 g1f3");
+    engine.send("perft 2 divide");
+    let lines = engine.wait_for("Nodes searched: ");
+    let counts = lines.iter()
+        .filter_map(|(_, line)| line.split_once(": "))
+        .filter(|&(m, _)| m != "Nodes searched")
+        .map(|(m, count)| (m.to_owned(), count.parse::<u64>().unwrap()))
+        .collect::<Vec<_>>();
+    let (_, total) = lines.last().unwrap();
+    let total: u64 = total.trim_start_matches("Nodes searched: ").parse().unwrap();
+
+    assert_eq!(counts.len(), 29);
+    assert_eq!(counts.iter().map(|(_, count)| count).sum::<u64>(), total);
+    assert_eq!(total, perft(&mut engine, "position startpos moves e2e4 e7e5 g1f3", 2));
+    // Moves are sorted, so that the lists of two engines line up
+    assert!(counts.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(counts.contains(&("b8c6".to_owned(), 27)), "{counts:?}");
+}
@@ -0,0 +1,136 @@
+//! Sends go while a search is running, in the orders that GUIs do, and checks that every go that isn't
+//! a superseded ponder search gets exactly one bestmove
+
+mod common;
+
+use common::Engine;
+
+/// White's king is in check and can only take the rook on b2
+const FORCED_FEN: &str = "7k/8/8/8/8/8/1r6/K6r w - - 0 1";
+const FORCED_BESTMOVE: &str = "bestmove a1b2";
+
+/// Ponder on the reply to 1. e4, as if the opponent was thinking about it
+fn start_pondering(engine: &mut Engine)
+{
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos moves e2e4 e7e5");
+    engine.send("go ponder wtime 60000 btime 60000");
+    engine.wait_for("info depth");
+}
+
+/// The bestmove lines up to the next one for the forced position, without the ponder moves, checking
+/// that no more follow it
+fn bestmoves_until_forced(engine: &mut Engine) -> Vec<String>
+{
+    let mut lines = engine.wait_for(FORCED_BESTMOVE);
+    engine.send("isready");
+    lines.extend(engine.wait_for("readyok"));
+
+    let bestmoves = lines.into_iter()
+        .map(|(_, line)| line)
+        .filter(|line| line.starts_with("bestmove"))
+        .map(|line| line.split(" ponder ").next().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(bestmoves.last().map(String::as_str), Some(FORCED_BESTMOVE), "{bestmoves:?}");
+    bestmoves
+}
+
+#[test]
+fn test_bestmove_names_ponder_move()
+{
+    // The GUI ponders on the reply that bestmove names, after the move, and ponderhit makes it count
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+    engine.send("go depth 5");
+    let lines = engine.wait_for("bestmove");
+    let (_, bestmove) = lines.last().unwrap();
+    let words = bestmove.split_ascii_whitespace().collect::<Vec<_>>();
+    let [_, best_move, "ponder", ponder_move] = words[..] else {
+        panic!("no ponder move in \"{bestmove}\"");
+    };
+
+    engine.send(&format!("position startpos moves {best_move} {ponder_move}"));
+    engine.send("go ponder wtime 60000 btime 60000");
+    engine.wait_for("info depth");
+    engine.send("isready");
+    let lines = engine.wait_for("readyok");
+    assert!(!lines.iter().any(|(_, line)| line.starts_with("bestmove")), "{lines:?}");
+    engine.send("ponderhit");
+    engine.wait_for("bestmove");
+}
+
+#[test]
+fn test_go_supersedes_ponder()
+{
+    // The opponent played another move, and the GUI goes on without stop or ponderhit
+    let mut engine = Engine::start();
+    start_pondering(&mut engine);
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 5");
+
+    assert_eq!(bestmoves_until_forced(&mut engine), [FORCED_BESTMOVE]);
+}
+
+#[test]
+fn test_stop_ponder_then_go()
+{
+    // The opponent played another move, and the GUI stops the ponder search first. The move it
+    // answers with is thrown away, but it must come.
+    let mut engine = Engine::start();
+    start_pondering(&mut engine);
+    engine.send("stop");
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 5");
+
+    let bestmoves = bestmoves_until_forced(&mut engine);
+    assert_eq!(bestmoves.len(), 2, "{bestmoves:?}");
+}
+
+#[test]
+fn test_go_after_ponderhit()
+{
+    // The opponent played the expected move, but the GUI starts a new search instead of waiting for
+    // the one that ponderhit turned into a normal search. That one answers, and then the new one.
+    let mut engine = Engine::start();
+    start_pondering(&mut engine);
+    engine.send("ponderhit");
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 5");
+
+    let bestmoves = bestmoves_until_forced(&mut engine);
+    assert_eq!(bestmoves.len(), 2, "{bestmoves:?}");
+}
+
+#[test]
+fn test_go_supersedes_normal_search()
+{
+    // A search without limits is stopped by the next go, and answers before the new one starts
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+    engine.send("position startpos");
+    engine.send("go");
+    engine.wait_for("info depth");
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 5");
+
+    let bestmoves = bestmoves_until_forced(&mut engine);
+    assert_eq!(bestmoves.len(), 2, "{bestmoves:?}");
+}
+
+#[test]
+fn test_ponder_superseded_twice()
+{
+    // Each new ponder search replaces the last, and only the final normal search answers
+    let mut engine = Engine::start();
+    start_pondering(&mut engine);
+    engine.send("position startpos moves d2d4 d7d5");
+    engine.send("go ponder wtime 60000 btime 60000");
+    engine.send(&format!("position fen {FORCED_FEN}"));
+    engine.send("go depth 5");
+
+    assert_eq!(bestmoves_until_forced(&mut engine), [FORCED_BESTMOVE]);
+}
@@ -0,0 +1,153 @@
+//! Lists the public API of the library, so that making an internal item public, or changing the
+//! signature of a public one, takes a deliberate change here too
+
+use std::fs;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use engine::prelude::*;
+
+/// The `pub` declarations of the files that make up the API: all of those in `lib.rs`, and those at
+/// the top level of the modules that `lib.rs` makes public
+const API: [(&str, &[&str]); 5] = [
+    ("src/lib.rs", &[
+        "pub mod clock;",
+        "pub mod geometry;",
+        "pub mod san;",
+        "pub mod wasm;",
+        "pub use chess;",
+        "pub use evaluation::EvalBreakdown;",
+        "pub use evaluation::Evaluator;",
+        "pub use evaluation::SharedEvaluator;",
+        "pub use gameresult::game_result;",
+        "pub use gameresult::DrawReason;",
+        "pub use gameresult::GameResult;",
+        "pub use perft::perft;",
+        "pub use rootmoves::canonical_root;",
+        "pub use score::BoardScore;",
+        "pub use search::Searcher;",
+        "pub use searchinterface::SearchInterface;",
+        "pub use see::move_gain as see;",
+        "pub use stopconditions::StopConditions;",
+        "pub use version::build_info;",
+        "pub use version::version;",
+        "pub mod prelude",
+        "pub use chess::{Board, ChessMove, Color, Piece, Square};",
+        "pub use crate::gameresult::{game_result, DrawReason, GameResult};",
+        "pub use crate::geometry;",
+        "pub use crate::perft::perft;",
+        "pub use crate::rootmoves::canonical_root;",
+        "pub use crate::san::{from_san, to_san};",
+        "pub use crate::see::move_gain as see;",
+        "pub use crate::{evaluate, trace, BoardScore, EvalBreakdown, Evaluator, SharedEvaluator};",
+        "pub use crate::{Searcher, StopConditions};",
+        "pub use crate::SearchInterface;",
+        "pub fn evaluate(board: &Board) -> BoardScore",
+        "pub fn trace(board: &Board) -> EvalBreakdown",
+        // Hidden, for the engine binary
+        "pub mod binary",
+        "pub fn bench(arguments: &str) -> bool",
+        "pub fn uci()",
+    ]),
+    ("src/clock.rs", &[
+        "pub trait Clock: Send + Sync",
+        "pub fn set_clock(clock: &'static dyn Clock) -> bool",
+        "pub struct Instant(Duration);",
+    ]),
+    ("src/geometry.rs", &[
+        "pub fn distance(a: Square, b: Square) -> u8",
+        "pub fn manhattan_distance(a: Square, b: Square) -> u8",
+        "pub fn file_mask(square: Square) -> BitBoard",
+        "pub fn rank_mask(square: Square) -> BitBoard",
+        "pub fn diagonal_mask(square: Square) -> BitBoard",
+        "pub fn anti_diagonal_mask(square: Square) -> BitBoard",
+        "pub fn front_span(color: Color, square: Square) -> BitBoard",
+        "pub fn passed_pawn_mask(color: Color, square: Square) -> BitBoard",
+        "pub fn is_in_front(color: Color, pawn_square: Square, square: Square) -> bool",
+        "pub fn relative_square(color: Color, square: Square) -> Square",
+        "pub fn king_zone(square: Square) -> BitBoard",
+    ]),
+    ("src/san.rs", &[
+        "pub enum PvNotation",
+        "pub fn to_san(position: &Board, chess_move: ChessMove) -> String",
+        "pub fn from_san(position: &Board, san: &str) -> Result<ChessMove, String>",
+        "pub fn format_pv(position: &Board, moves: &[ChessMove], notation: PvNotation) -> String",
+    ]),
+    // Only on WebAssembly
+    ("src/wasm.rs", &[
+        "pub struct PerformanceClock;",
+        "pub struct Engine",
+    ]),
+];
+
+#[test]
+fn test_public_api()
+{
+    for (path, expected) in API
+    {
+        let source = fs::read_to_string(format!("{}/{path}", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let declarations = source.lines()
+            // Everything in lib.rs, but only the top level elsewhere, which leaves out test modules
+            .filter(|line| path == "src/lib.rs" || !line.starts_with(' '))
+            .map(str::trim)
+            .filter(|line| line.starts_with("pub ") || line.starts_with("pub("))
+            .collect::<Vec<_>>();
+        assert_eq!(declarations, expected, "{path}");
+    }
+}
+
+#[test]
+fn test_prelude()
+{
+    let position = Board::default();
+    let e4 = from_san(&position, "e4").unwrap();
+    assert_eq!(to_san(&position, e4), "e4");
+    assert_eq!(perft(&position, 1), 20);
+    assert_eq!(see(&position, e4), 0);
+    assert_eq!(evaluate(&position), trace(&position).score);
+    assert_eq!(game_result(&position, 0, &[canonical_root(&position).get_hash()]), None);
+    assert_eq!(geometry::distance(Square::E1, Square::E8), 7);
+    assert!(!engine::version().is_empty() && !engine::build_info().is_empty());
+}
+
+/// Counts nothing but material, as an example of an evaluator of one's own
+struct MaterialOnly;
+
+impl Evaluator for MaterialOnly
+{
+    fn evaluate(&self, board: &Board) -> BoardScore
+    {
+        let values = [(Piece::Pawn, 100), (Piece::Knight, 300), (Piece::Bishop, 300), (Piece::Rook, 500), (Piece::Queen, 900)];
+        let up = board.side_to_move();
+        let count = |piece, color| (board.pieces(piece) & board.color_combined(color)).popcnt() as i16;
+        BoardScore::evaluation(values.iter().map(|&(piece, value)| value * (count(piece, up) - count(piece, !up))).sum())
+    }
+}
+
+#[test]
+fn test_plugged_in_evaluator()
+{
+    // Quiet positions are scored by the plugged in evaluator. Here the classical evaluation also
+    // credits White's queenside majority.
+    let position = Board::from_str("4k3/pp3ppp/8/8/8/8/PPP2PPP/4K3 w - - 0 1").unwrap();
+    let stop_conditions = StopConditions::new();
+    stop_conditions.depth.store(1, Ordering::Relaxed);
+    let mut searcher = Searcher::with_evaluator(&stop_conditions, Arc::new(MaterialOnly));
+    let result = searcher.search(position, 0);
+    assert_eq!(result.iterations.last().unwrap().score.unwrap(), BoardScore::evaluation(100));
+    assert_ne!(evaluate(&position), BoardScore::evaluation(100));
+
+    // The search still finds mates
+    let position = Board::from_str("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+    stop_conditions.depth.store(4, Ordering::Relaxed);
+    let mut searcher = Searcher::with_evaluator(&stop_conditions, Arc::new(MaterialOnly));
+    let result = searcher.search(position, 0);
+    assert_eq!(result.best_move, Some(ChessMove::from_str("b1b8").unwrap()));
+    assert!(result.iterations.last().unwrap().score.unwrap().is_mate_score());
+
+    // A search thread takes the evaluator shared, so that a thread started in place of one that has
+    // died gets it too
+    let evaluator: SharedEvaluator = Arc::new(MaterialOnly);
+    drop(SearchInterface::with_evaluator(evaluator));
+}
@@ -0,0 +1,32 @@
+//! Quits in the middle of a search, as a GUI closing down does, and checks that the engine exits at once
+//! and cleanly
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::Engine;
+
+/// How soon after quit the process must have exited
+const QUIT_MAX_DELAY: Duration = Duration::from_millis(500);
+
+#[test]
+fn test_quit_during_search()
+{
+    // Deep into an infinite search, and right after it started
+    for wait_for in ["info depth 8 ", "info depth 1 "]
+    {
+        let mut engine = Engine::start();
+        engine.send("uci");
+        engine.wait_for("uciok");
+        engine.send("position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        engine.send("go infinite");
+        engine.wait_for(wait_for);
+
+        let quit_sent = Instant::now();
+        let status = engine.quit();
+        let delay = quit_sent.elapsed();
+        assert!(status.success(), "exit status {status}");
+        assert!(delay < QUIT_MAX_DELAY, "exited {delay:?} after quit");
+    }
+}
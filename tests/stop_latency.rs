@@ -0,0 +1,40 @@
+//! Stops searches of a complex middlegame partway, and checks that the best move follows at once, since
+//! match managers count the time until it against the engine
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::Engine;
+
+/// Kiwipete, with many captures and checks in every line
+const MIDDLEGAME_FEN: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+/// How long bestmove may take after stop. A few milliseconds are enough to return from the search, but
+/// the engine and this test share the machine with the rest of the test run. A search that doesn't stop
+/// at once keeps going for the rest of the iteration, which takes far longer.
+const STOP_MAX_DELAY: Duration = Duration::from_millis(250);
+
+#[test]
+fn test_stop_latency()
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    engine.wait_for("uciok");
+
+    // Stopping at different points of the search, from early iterations to deep ones
+    for search_time in [100, 250, 1000]
+    {
+        engine.send("ucinewgame");
+        engine.send(&format!("position fen {MIDDLEGAME_FEN}"));
+        engine.send("go infinite");
+        thread::sleep(Duration::from_millis(search_time));
+
+        let stop_sent = engine.send("stop");
+        let lines = engine.wait_for("bestmove");
+        let (bestmove_time, bestmove) = lines.last().unwrap();
+        let delay = bestmove_time.duration_since(stop_sent);
+        assert!(delay < STOP_MAX_DELAY, "\"{bestmove}\" {delay:?} after stop, {search_time} ms into the search");
+    }
+}
@@ -0,0 +1,60 @@
+//! Runs the same dialogue with and without the StrictUci option, and checks that strict mode only
+//! sends lines that the UCI protocol defines
+
+mod common;
+
+use common::Engine;
+
+/// Line prefixes that the UCI protocol defines for output of the engine
+const UCI_PREFIXES: &[&str] = &["id ", "option ", "uciok", "readyok", "info ", "bestmove "];
+
+/// Commands with mistakes, unknown commands, and extensions of this engine
+const DIALOGUE: &[&str] = &[
+    "setoption name Hash value abc",
+    "setoption name NoSuchOption value 1",
+    "position startpos moves e2e4 e2e4",
+    "position startpos moves e2e4",
+    "d",
+    "probe",
+    "savehash",
+    "foo bar",
+    "go depth 3",
+];
+
+/// All output from the start of the dialogue to the end
+fn transcript(strict: bool) -> Vec<String>
+{
+    let mut engine = Engine::start();
+    engine.send("uci");
+    let mut lines = engine.wait_for("uciok");
+    if strict {
+        engine.send("setoption name StrictUci value true");
+    }
+    for command in DIALOGUE {
+        engine.send(command);
+    }
+    lines.extend(engine.wait_for("bestmove"));
+    engine.send("isready");
+    lines.extend(engine.wait_for("readyok"));
+
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+#[test]
+fn test_strict_uci_output()
+{
+    let is_conformant = |line: &String| UCI_PREFIXES.iter().any(|prefix| line.starts_with(prefix));
+
+    // Without strict mode, the mistakes and extensions give output of their own
+    let lines = transcript(false);
+    assert!(lines.iter().any(|line| !is_conformant(line)), "{lines:?}");
+    assert!(lines.iter().any(|line| line.starts_with("ERROR: ")), "{lines:?}");
+
+    let lines = transcript(true);
+    let violations = lines.iter().filter(|line| !is_conformant(line)).collect::<Vec<_>>();
+    assert!(violations.is_empty(), "{violations:?}");
+    assert!(lines.iter().any(|line| line.starts_with("info string ERROR: ")), "{lines:?}");
+    let refused = lines.iter().filter(|line| *line == "info string unsupported in strict mode").count();
+    assert_eq!(refused, 3, "{lines:?}");
+    assert!(lines.iter().any(|line| line.starts_with("bestmove ")));
+}
@@ -0,0 +1,73 @@
+//! The WebAssembly module, under Node.js
+//!
+//! Run with `wasm-pack test --node -- --no-default-features --test wasm`.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use engine::wasm::Engine;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// An info callback for `go` that collects the lines it gets, and calls `on_line` with each
+fn collecting_callback(on_line: impl Fn(&str) + 'static) -> (Closure<dyn FnMut(String)>, Rc<RefCell<Vec<String>>>)
+{
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let collected = Rc::clone(&lines);
+    let callback = Closure::new(move |line: String| {
+        on_line(&line);
+        collected.borrow_mut().push(line);
+    });
+    (callback, lines)
+}
+
+#[wasm_bindgen_test]
+fn test_mate_in_two()
+{
+    let engine = Engine::new();
+    engine.set_position("4kb1r/p2n1ppp/4q3/4p1B1/4P3/1Q6/PPP2PPP/2KR4 w k - 0 1", Vec::new()).unwrap();
+
+    let (callback, lines) = collecting_callback(|_| { });
+    let best_move = engine.go("depth 5", callback.as_ref().unchecked_ref()).unwrap();
+
+    // Qb8+ Nxb8 Rd8#
+    assert_eq!(best_move.as_deref(), Some("b3b8"));
+    let lines = lines.borrow();
+    assert!(lines.iter().any(|line| line.contains(" score mate 2 ")), "{lines:?}");
+    assert!(lines.last().is_some_and(|line| line.starts_with("bestmove b3b8")), "{lines:?}");
+}
+
+#[wasm_bindgen_test]
+fn test_position_with_moves()
+{
+    let engine = Engine::new();
+    // Fool's mate, after which White has no move
+    let moves = ["f2f3", "e7e5", "g2g4", "d8h4"].map(str::to_owned).to_vec();
+    engine.set_position("startpos", moves).unwrap();
+    let (callback, _) = collecting_callback(|_| { });
+    assert_eq!(engine.go("depth 3", callback.as_ref().unchecked_ref()).unwrap(), None);
+
+    assert!(engine.set_position("startpos", vec!["e2e5".to_owned()]).is_err());
+    assert!(engine.set_position("not a fen", Vec::new()).is_err());
+    assert!(engine.go("nodes 1000", callback.as_ref().unchecked_ref()).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_stop_from_callback()
+{
+    let engine = Rc::new(Engine::new());
+    let stopping = Rc::clone(&engine);
+    let (callback, lines) = collecting_callback(move |line| {
+        if line.starts_with("info nodes ") {
+            stopping.stop();
+        }
+    });
+
+    // Without the stop, this would take far longer than the test may
+    let best_move = engine.go("movetime 600000", callback.as_ref().unchecked_ref()).unwrap();
+    assert!(best_move.is_some());
+    let lines = lines.borrow();
+    assert!(lines.iter().any(|line| line.starts_with("info nodes ")), "{lines:?}");
+}